@@ -0,0 +1,126 @@
+//! Cost of the CSMA strategy itself, on a transceiver that does nothing but loop back bytes.
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::VecDeque,
+    hint::black_box,
+    rc::Rc,
+};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use kiri_csma::{
+    Clock, Config, CsmaFrameInProgress, CsmaStrategy, ReadError, SendReceiveResult, Transceiver,
+};
+use kiri_protocol::{Address, Frame, Writer};
+use rand::rngs::mock::StepRng;
+
+const SIZES: [usize; 3] = [8, 64, 256];
+
+/// Clock that moves on a tick every time it is looked at, such that backoffs expire without waiting.
+struct TickingClock(Cell<u64>);
+
+impl Clock for &TickingClock {
+    type Instant = u64;
+    type Duration = u64;
+
+    fn now(&self) -> u64 {
+        let now = self.0.get();
+        self.0.set(now + 1);
+        now
+    }
+}
+
+struct BenchConfig;
+
+impl Config<&TickingClock> for BenchConfig {
+    const BUS_MIN_IDLE_DURATION: u64 = 1;
+    const BUS_MAX_IDLE_DURATION: u64 = 5;
+    const ECHO_BYTE_TIMEOUT: u64 = 1_000;
+    const ECHO_FRAME_TIMEOUT: u64 = 100_000;
+}
+
+/// Transceiver without a bus, that only hands back the bytes written or queued to it.
+///
+/// Clones share the same bytes, such that a bench can queue frames of other nodes.
+#[derive(Clone, Default)]
+struct NullTransceiver {
+    bus: Rc<RefCell<VecDeque<u8>>>,
+}
+
+impl Transceiver for NullTransceiver {
+    type Error = ();
+
+    fn handle_interrupts(&self) {}
+
+    fn bus_is_idle(&self) -> bool {
+        self.bus.borrow().is_empty()
+    }
+
+    fn write(&mut self, byte: u8) -> nb::Result<(), Self::Error> {
+        self.bus.borrow_mut().push_back(byte);
+        Ok(())
+    }
+
+    fn read(&mut self) -> nb::Result<u8, ReadError<Self::Error>> {
+        self.bus
+            .borrow_mut()
+            .pop_front()
+            .ok_or(nb::Error::WouldBlock)
+    }
+}
+
+fn frame(len: usize) -> Frame {
+    Writer::package(Address::new(1), Address::new(2), &vec![0x55; len]).unwrap()
+}
+
+/// Polling `send_or_receive` until our frame looped back completely.
+fn send(c: &mut Criterion) {
+    let clock = TickingClock(Cell::new(0));
+    let mut strategy =
+        CsmaStrategy::new::<BenchConfig>(NullTransceiver::default(), &clock, StepRng::new(0, 7919));
+
+    let mut group = c.benchmark_group("send");
+    for len in SIZES {
+        let frame = frame(len);
+        group.throughput(Throughput::Bytes(frame.as_slice().len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(len), &frame, |b, frame| {
+            b.iter(|| {
+                let mut frame = CsmaFrameInProgress::new(black_box(frame.clone()));
+                while !matches!(
+                    strategy.send_or_receive(&mut frame),
+                    Ok(SendReceiveResult::SendComplete)
+                ) {}
+            })
+        });
+    }
+    group.finish();
+}
+
+/// Polling `receive` until a frame of another node came in.
+fn receive(c: &mut Criterion) {
+    let clock = TickingClock(Cell::new(0));
+    let transceiver = NullTransceiver::default();
+    let bus = transceiver.bus.clone();
+    let mut strategy = CsmaStrategy::new::<BenchConfig>(transceiver, &clock, StepRng::new(0, 7919));
+
+    let mut group = c.benchmark_group("receive");
+    for len in SIZES {
+        let frame = frame(len);
+        group.throughput(Throughput::Bytes(frame.as_slice().len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(len), &frame, |b, frame| {
+            b.iter(|| {
+                // Queueing the bytes is part of the measurement, but negligible next to the strategy.
+                bus.borrow_mut().extend(frame.as_slice());
+                loop {
+                    if let Ok(frame) = strategy.receive() {
+                        break black_box(frame.contents.len());
+                    }
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, send, receive);
+criterion_main!(benches);
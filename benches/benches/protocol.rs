@@ -0,0 +1,77 @@
+//! Throughput of packaging and decoding frames of representative sizes.
+
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use kiri_protocol::{iter::FrameIter, Address, Frame, Reader, Writer, MAX_MESSAGE_LEN};
+use rand::{rngs::StdRng, RngCore, SeedableRng};
+
+/// From a sensor reading up to the largest message.
+const SIZES: [usize; 4] = [8, 64, 256, MAX_MESSAGE_LEN];
+
+/// How many frames a stream consists of in `frame_iter`.
+const STREAM_FRAMES: usize = 16;
+
+fn payload(len: usize) -> Vec<u8> {
+    let mut payload = vec![0u8; len];
+    StdRng::seed_from_u64(len as u64).fill_bytes(&mut payload);
+    payload
+}
+
+fn frame(len: usize) -> Frame {
+    Writer::package(Address::new(1), Address::new(2), &payload(len)).unwrap()
+}
+
+fn package(c: &mut Criterion) {
+    let mut group = c.benchmark_group("package");
+    for len in SIZES {
+        let payload = payload(len);
+        group.throughput(Throughput::Bytes(len as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(len), &payload, |b, payload| {
+            b.iter(|| Writer::package(Address::new(1), Address::new(2), black_box(payload)))
+        });
+    }
+    group.finish();
+}
+
+fn feed(c: &mut Criterion) {
+    let mut group = c.benchmark_group("feed");
+    for len in SIZES {
+        let frame = frame(len);
+        let mut reader = Reader::new();
+        group.throughput(Throughput::Bytes(frame.as_slice().len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(len), &frame, |b, frame| {
+            b.iter(|| {
+                let mut received = 0;
+                for byte in frame.as_slice() {
+                    if let Ok(Some(frame)) = reader.feed(black_box(*byte)) {
+                        received += frame.contents.len();
+                    }
+                }
+                assert_eq!(received, len);
+            })
+        });
+    }
+    group.finish();
+}
+
+/// Decoding a captured stream of frames at once, as the host tools do.
+fn frame_iter(c: &mut Criterion) {
+    let mut group = c.benchmark_group("frame_iter");
+    for len in SIZES {
+        let stream: Vec<u8> = (0..STREAM_FRAMES)
+            .flat_map(|_| frame(len).as_slice().to_vec())
+            .collect();
+        group.throughput(Throughput::Bytes(stream.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(len), &stream, |b, stream| {
+            b.iter(|| {
+                let frames = FrameIter::new(black_box(stream).iter().copied());
+                assert_eq!(frames.filter(Result::is_ok).count(), STREAM_FRAMES);
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, package, feed, frame_iter);
+criterion_main!(benches);
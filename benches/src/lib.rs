@@ -0,0 +1,4 @@
+//! Benchmarks of encoding, decoding and the CSMA strategy, see the `benches` directory.
+//!
+//! Run with `cargo bench`, and compare against a baseline using `cargo bench -- --save-baseline <name>` before
+//! and `cargo bench -- --baseline <name>` after a change.
@@ -0,0 +1,173 @@
+-- Wireshark dissector for kiri bus captures, as written by the simulator bus tap.
+--
+-- Interface 0 (LINKTYPE_USER0) carries single bus bytes followed by a flags byte.
+-- Interface 1 (LINKTYPE_USER1) carries complete COBS encoded frames, including the trailing marker.
+--
+-- Generated from the definitions in `kiri-protocol` by `kiri gen-dissector`, do not edit.
+-- Install by copying into your Wireshark personal plugins directory.
+
+local kiri_byte = Proto("kiri_byte", "Kiri bus byte")
+local kiri = Proto("kiri", "Kiri frame")
+
+local f_byte = ProtoField.uint8("kiri_byte.value", "Value", base.HEX)
+local f_error = ProtoField.bool("kiri_byte.error", "Garbled", 8, nil, 0x01)
+kiri_byte.fields = { f_byte, f_error }
+
+local f_magic = ProtoField.bytes("kiri.magic", "Magic")
+local f_bus = ProtoField.uint8("kiri.bus", "Bus", base.DEC)
+local f_src = ProtoField.uint32("kiri.src", "Source", base.HEX)
+local f_dst = ProtoField.uint32("kiri.dst", "Destination", base.HEX)
+local f_len = ProtoField.uint16("kiri.len", "Length", base.DEC, nil, 0xFFC0)
+local f_hop_limit = ProtoField.uint8("kiri.hop_limit", "Hop limit", base.DEC, nil, 0x38)
+local f_sequence = ProtoField.uint8("kiri.sequence", "Sequence", base.DEC, nil, 0x06)
+local f_has_options = ProtoField.bool("kiri.has_options", "Has options", 8, nil, 0x01)
+local f_len_high = ProtoField.uint8("kiri.len_high", "Length, high bits", base.DEC)
+local f_options = ProtoField.bytes("kiri.options", "Options")
+local f_option = ProtoField.uint8("kiri.option", "Option", base.DEC, {
+    [1] = "Priority",
+    [2] = "TTL",
+    [3] = "Fragment",
+    [4] = "Authentication tag",
+    [5] = "Compressed",
+    [6] = "Management",
+    [7] = "Acknowledgement",
+})
+local f_option_value = ProtoField.bytes("kiri.option.value", "Value")
+local f_payload = ProtoField.bytes("kiri.payload", "Payload")
+local f_padding = ProtoField.bytes("kiri.padding", "Padding")
+local f_crc = ProtoField.uint16("kiri.crc", "CRC", base.HEX)
+kiri.fields = {
+    f_magic, f_bus, f_src, f_dst, f_len, f_hop_limit, f_sequence, f_has_options, f_len_high,
+    f_options, f_option, f_option_value, f_payload, f_padding, f_crc,
+}
+
+function kiri_byte.dissector(buf, pinfo, tree)
+    pinfo.cols.protocol = "KIRI"
+    local subtree = tree:add(kiri_byte, buf())
+    subtree:add(f_byte, buf(0, 1))
+    subtree:add(f_error, buf(1, 1))
+end
+
+-- Undo the COBS encoding, dropping the trailing marker.
+local function cobs_decode(bytes)
+    local out = ByteArray.new()
+    local i = 0
+    local len = bytes:len()
+    while i < len do
+        local code = bytes:get_index(i)
+        if code == 0 then
+            break
+        end
+        i = i + 1
+        for _ = 1, code - 1 do
+            if i >= len then
+                return nil
+            end
+            out:append(bytes:subset(i, 1))
+            i = i + 1
+        end
+        if code < 0xFF and i < len and bytes:get_index(i) ~= 0 then
+            out:append(ByteArray.new("00"))
+        end
+    end
+    return out
+end
+
+-- Checksum with polynomial 0x1021, over everything from the magic word up to the checksum.
+local function checksum(tvb, len)
+    local crc = 0xFFFF
+    for i = 0, len - 1 do
+        local byte = tvb(i, 1):uint()
+        crc = bit.bxor(crc, byte)
+        for _ = 1, 8 do
+            if bit.band(crc, 1) ~= 0 then
+                crc = bit.bxor(bit.rshift(crc, 1), 0x8408)
+            else
+                crc = bit.rshift(crc, 1)
+            end
+        end
+    end
+    return bit.bxor(crc, 0xFFFF)
+end
+
+local function dissect_options(tvb, tree)
+    local block_len = tvb(0, 1):uint()
+    local options = tree:add(f_options, tvb(0, block_len + 1))
+    local i = 1
+    while i + 1 < block_len + 1 do
+        local option_len = tvb(i + 1, 1):uint()
+        local option = options:add(f_option, tvb(i, 1))
+        if option_len > 0 then
+            option:add(f_option_value, tvb(i + 2, option_len))
+        end
+        i = i + 2 + option_len
+    end
+    return block_len + 1
+end
+
+function kiri.dissector(buf, pinfo, tree)
+    pinfo.cols.protocol = "KIRI"
+
+    local decoded = cobs_decode(buf:bytes())
+    if decoded == nil or decoded:len() < 14 then
+        tree:add(kiri, buf(), "Malformed kiri frame")
+        return
+    end
+
+    local tvb = decoded:tvb("Decoded frame")
+    local subtree = tree:add(kiri, tvb())
+    subtree:add(f_magic, tvb(0, 2))
+    local kind = tvb(1, 1):uint()
+    if kind ~= 0x49 and kind ~= 0x4C then
+        subtree:add_expert_info(PI_MALFORMED, PI_ERROR, "Invalid magic word")
+        return
+    end
+    -- The bus is XOR-ed into the first byte of the magic word.
+    subtree:add(f_bus, tvb(0, 1), bit.bxor(tvb(0, 1):uint(), 0x6B))
+
+    subtree:add(f_src, tvb(2, 4))
+    subtree:add(f_dst, tvb(6, 4))
+    subtree:add(f_len, tvb(10, 2))
+    subtree:add(f_hop_limit, tvb(11, 1))
+    subtree:add(f_sequence, tvb(11, 1))
+    subtree:add(f_has_options, tvb(11, 1))
+
+    local len = bit.rshift(bit.band(tvb(10, 2):uint(), 0xFFC0), 6)
+    local start = 12
+    if kind == 0x4C then
+        subtree:add(f_len_high, tvb(start, 1))
+        len = len + bit.lshift(tvb(start, 1):uint(), 10)
+        start = start + 1
+    end
+
+    local crc_start = tvb:len() - 2
+    if start + len > crc_start then
+        subtree:add_expert_info(PI_MALFORMED, PI_ERROR, "Length exceeds the frame")
+        return
+    end
+    local payload_start = start
+    if bit.band(tvb(11, 1):uint(), 0x1) ~= 0 then
+        payload_start = start + dissect_options(tvb(start, len), subtree)
+    end
+    if payload_start < start + len then
+        subtree:add(f_payload, tvb(payload_start, start + len - payload_start))
+    end
+    if start + len < crc_start then
+        subtree:add(f_padding, tvb(start + len, crc_start - start - len))
+    end
+
+    local crc = subtree:add(f_crc, tvb(crc_start, 2))
+    local expected = checksum(tvb, crc_start)
+    if expected == tvb(crc_start, 2):uint() then
+        crc:append_text(" [correct]")
+    else
+        crc:add_expert_info(PI_CHECKSUM, PI_ERROR, string.format("Incorrect, should be 0x%04x", expected))
+    end
+
+    pinfo.cols.info = string.format("%08x -> %08x, %d bytes, seq=%d, hops=%d", tvb(2, 4):uint(), tvb(6, 4):uint(), len, bit.rshift(bit.band(tvb(11, 1):uint(), 0x6), 1), bit.rshift(bit.band(tvb(11, 1):uint(), 0x38), 3))
+end
+
+local encaps = wtap_encaps or wtap
+local wtap_table = DissectorTable.get("wtap_encap")
+wtap_table:add(encaps.USER0, kiri_byte)
+wtap_table:add(encaps.USER1, kiri)
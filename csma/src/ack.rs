@@ -0,0 +1,181 @@
+//! Acknowledging frames on the frames that answer them, or by themselves if no answer follows in time, see
+//! `DelayedAcks`.
+//!
+//! Acknowledgements carry the sequence number of the frame they acknowledge in the `options::ACK` option, such that
+//! request and response traffic does not take twice as many frames.
+
+use kiri_protocol::{Address, Frame, FrameBuilder, FrameRef, WriteError};
+
+use crate::Clock;
+
+/// Acknowledgement of the frame of `dst` with sequence number `sequence`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ack {
+    pub dst: Address,
+    pub sequence: u8,
+}
+
+impl Ack {
+    /// Package the acknowledgement in a frame of its own from `src`, without payload.
+    pub fn package(&self, src: Address) -> Result<Frame, WriteError> {
+        FrameBuilder::new(src, self.dst).ack(self.sequence).build()
+    }
+}
+
+/// Acknowledgements of up to `P` peers, held back for up to `delay` to be piggybacked on the next frame to the peer.
+///
+/// Frames with a sequence number that are addressed to us alone are acknowledged, see `received`. The
+/// acknowledgement is taken along by the next frame to the peer using `take` and `FrameBuilder::ack`, or sent in a
+/// frame of its own once `poll` yields it.
+#[derive(Debug)]
+pub struct DelayedAcks<C: Clock, const P: usize> {
+    delay: C::Duration,
+    /// The sequence number to acknowledge of each peer, and when to acknowledge it by itself.
+    pending: heapless::LinearMap<Address, (u8, C::Instant), P>,
+}
+
+impl<C: Clock, const P: usize> DelayedAcks<C, P> {
+    pub fn new(delay: C::Duration) -> Self {
+        Self {
+            delay,
+            pending: heapless::LinearMap::new(),
+        }
+    }
+
+    /// Hold back the acknowledgement of `frame`, received at `now`.
+    ///
+    /// A later frame of the same peer replaces the sequence number to acknowledge, but not when to acknowledge it,
+    /// such that a peer that keeps sending is still acknowledged. Yields the acknowledgement to send right away if
+    /// acknowledgements of `P` other peers are already held back.
+    pub fn received(&mut self, frame: &FrameRef, now: C::Instant) -> Option<Ack> {
+        let src = frame.header.address_src;
+        let sequence = frame.sequence();
+        if sequence == 0 || src.is_group() || frame.header.address_dst.is_group() {
+            return None;
+        }
+
+        if let Some((pending, _)) = self.pending.get_mut(&src) {
+            *pending = sequence;
+            return None;
+        }
+        match self.pending.insert(src, (sequence, now + self.delay)) {
+            Ok(_) => None,
+            Err(_) => Some(Ack { dst: src, sequence }),
+        }
+    }
+
+    /// The acknowledgement to piggyback on a frame to `dst`, if one is held back.
+    pub fn take(&mut self, dst: Address) -> Option<Ack> {
+        self.pending
+            .remove(&dst)
+            .map(|(sequence, _)| Ack { dst, sequence })
+    }
+
+    /// An acknowledgement that was held back for `delay` already, to be sent in a frame of its own.
+    pub fn poll(&mut self, now: C::Instant) -> Option<Ack> {
+        let dst = self
+            .pending
+            .iter()
+            .filter(|(_, (_, due))| *due <= now)
+            .map(|(dst, _)| *dst)
+            .next()?;
+        self.take(dst)
+    }
+
+    /// When `poll` yields the next acknowledgement, if any is held back.
+    pub fn next_poll_at(&self) -> Option<C::Instant> {
+        self.pending
+            .values()
+            .map(|(_, due)| *due)
+            .reduce(|a, b| if b < a { b } else { a })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use kiri_protocol::{FrameOwned, Reader, Writer};
+
+    use super::*;
+
+    struct TestClock;
+
+    impl Clock for TestClock {
+        type Instant = u64;
+        type Duration = u64;
+
+        fn now(&self) -> u64 {
+            0
+        }
+    }
+
+    fn decode(frame: &Frame) -> FrameOwned {
+        let mut reader = Reader::new();
+        frame
+            .as_slice()
+            .iter()
+            .find_map(|b| match reader.feed(*b) {
+                Ok(Some(frame)) => frame.try_into().ok(),
+                _ => None,
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn piggyback_or_delay() {
+        let (ours, a, b) = (Address::new(1), Address::new(2), Address::new(3));
+        let request = |src, sequence| {
+            decode(&Writer::package_with_sequence(src, ours, sequence, b"request").unwrap())
+        };
+        let mut acks = DelayedAcks::<TestClock, 1>::new(10);
+
+        // Of several requests of a peer, only the last one is acknowledged.
+        assert_eq!(acks.received(&(&request(a, 1)).into(), 1), None);
+        assert_eq!(acks.received(&(&request(a, 2)).into(), 2), None);
+        assert_eq!(acks.next_poll_at(), Some(11));
+        assert_eq!(acks.poll(10), None);
+
+        // Requests of peers beyond `P` are acknowledged right away.
+        let ack = acks.received(&(&request(b, 3)).into(), 5).unwrap();
+        let frame = decode(&ack.package(ours).unwrap());
+        assert_eq!(FrameRef::from(&frame).ack(), Some(3));
+        assert_eq!(FrameRef::from(&frame).payload(), Ok(&[][..]));
+
+        // The acknowledgement is piggybacked on the response.
+        let ack = acks.take(a).unwrap();
+        let response = FrameBuilder::new(ours, a)
+            .ack(ack.sequence)
+            .payload(b"response")
+            .build()
+            .unwrap();
+        let response = decode(&response);
+        assert_eq!(FrameRef::from(&response).ack(), Some(2));
+        assert_eq!(FrameRef::from(&response).payload(), Ok(&b"response"[..]));
+        assert_eq!(acks.take(a), None);
+
+        // Without a response in time, the acknowledgement is sent by itself.
+        assert_eq!(acks.received(&(&request(a, 3)).into(), 20), None);
+        assert_eq!(acks.poll(29), None);
+        assert_eq!(
+            acks.poll(30),
+            Some(Ack {
+                dst: a,
+                sequence: 3
+            })
+        );
+        assert_eq!(acks.next_poll_at(), None);
+
+        // Frames without a sequence number, or to or from a group, are not acknowledged.
+        let datagram = decode(&Writer::package(a, ours, b"datagram").unwrap());
+        assert_eq!(acks.received(&(&datagram).into(), 40), None);
+        for (src, dst) in [
+            (a, Address::group(1)),
+            (a, Address::management(1).unwrap()),
+            (a, Address::broadcast()),
+            (Address::group(1), ours),
+        ] {
+            let frame = Writer::package_with_sequence(src, dst, 1, b"group").unwrap();
+            assert_eq!(acks.received(&(&decode(&frame)).into(), 40), None);
+        }
+        assert_eq!(acks.next_poll_at(), None);
+    }
+}
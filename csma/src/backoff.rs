@@ -0,0 +1,224 @@
+//! Sources of the random time to wait once the bus became idle, see `BackoffSource`.
+
+use kiri_protocol::Address;
+use rand::{
+    distributions::{Distribution, Uniform},
+    RngCore,
+};
+
+use crate::Clock;
+
+/// Decides how long to wait once the bus became idle, before starting to send.
+pub trait BackoffSource<C: Clock> {
+    /// Next time to wait, within `min..max` from `Config`.
+    fn backoff(
+        &mut self,
+        min: C::Duration,
+        max: C::Duration,
+        rng: &mut impl RngCore,
+    ) -> C::Duration;
+
+    /// Learn how busy the bus is, from `0.0` to `1.0`, which is told before every `backoff`.
+    ///
+    /// See `CsmaStrategy::utilization`.
+    fn observe_utilization(&mut self, _utilization: f32) {}
+}
+
+/// Sample every backoff uniformly from the random number generator of the strategy.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct UniformBackoff;
+
+impl<C: Clock> BackoffSource<C> for UniformBackoff {
+    fn backoff(
+        &mut self,
+        min: C::Duration,
+        max: C::Duration,
+        rng: &mut impl RngCore,
+    ) -> C::Duration {
+        Uniform::new(min, max).sample(rng)
+    }
+}
+
+/// Widen the window of `Config` as the bus gets busier, up to `K` times as wide when it is never idle.
+///
+/// A wider window spreads the nodes that wait for the bus over more time, which leads to fewer collisions when
+/// many of them have something to send. A quiet bus uses the window of `Config` as is, to not wait longer than needed.
+///
+/// The window `min..max` is widened by a factor `n` to `n` consecutive copies of it, of which one is picked at random.
+///
+/// Compare both using `KIRI_BACKOFF=adaptive` in the simulation. With saturated traffic it avoids about four in five
+/// collisions there, but takes about a tenth longer to deliver all messages, as collisions are detected within a byte.
+/// It pays off on buses where collisions are more expensive, i.e. with transceivers that detect them late.
+#[derive(Debug, Clone, Copy)]
+pub struct AdaptiveBackoff<const K: u32 = 4> {
+    factor: u32,
+}
+
+impl<const K: u32> AdaptiveBackoff<K> {
+    pub const fn new() -> Self {
+        Self { factor: 1 }
+    }
+
+    /// How many times as wide the window currently is.
+    pub fn factor(&self) -> u32 {
+        self.factor
+    }
+}
+
+impl<const K: u32> Default for AdaptiveBackoff<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C: Clock, const K: u32> BackoffSource<C> for AdaptiveBackoff<K> {
+    fn backoff(
+        &mut self,
+        min: C::Duration,
+        max: C::Duration,
+        rng: &mut impl RngCore,
+    ) -> C::Duration {
+        let copy = Uniform::new(0, self.factor).sample(rng);
+        (0..copy).fold(Uniform::new(min, max).sample(rng), |backoff, _| {
+            backoff + max
+        })
+    }
+
+    fn observe_utilization(&mut self, utilization: f32) {
+        let widening = (utilization.clamp(0.0, 1.0) * (K.max(1) - 1) as f32 + 0.5) as u32;
+        self.factor = 1 + widening;
+    }
+}
+
+/// Cycle through `K` backoffs that are sampled up front, to avoid the cost of sampling at runtime.
+///
+/// The schedule is sampled for a given window, and ignores the one of `Config`.
+#[derive(Debug, Clone)]
+pub struct ScheduleBackoff<D, const K: usize = 16> {
+    schedule: [D; K],
+    next: usize,
+}
+
+impl<D: Copy + Default + rand::distributions::uniform::SampleUniform, const K: usize>
+    ScheduleBackoff<D, K>
+{
+    /// Sample a schedule within `min..max` from `rng`.
+    pub fn new(min: D, max: D, rng: &mut impl RngCore) -> Self {
+        let distribution = Uniform::new(min, max);
+        let mut schedule = [D::default(); K];
+        for backoff in schedule.iter_mut() {
+            *backoff = distribution.sample(rng);
+        }
+        Self { schedule, next: 0 }
+    }
+
+    /// Derive a schedule within `min..max` from `address`, which differs per node without needing a random source.
+    pub fn from_address(address: Address, min: D, max: D) -> Self {
+        Self::new(min, max, &mut XorShift::from_address(address))
+    }
+}
+
+impl<C: Clock, const K: usize> BackoffSource<C> for ScheduleBackoff<C::Duration, K> {
+    fn backoff(
+        &mut self,
+        _min: C::Duration,
+        _max: C::Duration,
+        _rng: &mut impl RngCore,
+    ) -> C::Duration {
+        let backoff = self.schedule[self.next];
+        self.next = (self.next + 1) % K;
+        backoff
+    }
+}
+
+/// Small generator to derive a schedule from an address.
+struct XorShift(u32);
+
+impl XorShift {
+    fn from_address(address: Address) -> Self {
+        // Spread the bits of the address, such that neighbouring addresses end up with different schedules.
+        let seed = address.to_primitive().wrapping_mul(0x9E37_79B9) ^ 0x5A5A_5A5A;
+        Self(seed.max(1))
+    }
+}
+
+impl RngCore for XorShift {
+    fn next_u32(&mut self) -> u32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        self.0
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        (u64::from(self.next_u32()) << 32) | u64::from(self.next_u32())
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(4) {
+            chunk.copy_from_slice(&self.next_u32().to_le_bytes()[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestClock;
+
+    impl Clock for TestClock {
+        type Instant = u64;
+        type Duration = u64;
+
+        fn now(&self) -> u64 {
+            0
+        }
+    }
+
+    #[test]
+    fn adaptive_widens_under_load() {
+        let mut rng = rand::rngs::mock::StepRng::new(0, 0x1234_5678_9ABC_DEF1);
+        let mut backoff = AdaptiveBackoff::<4>::new();
+        let mut sample = |backoff: &mut AdaptiveBackoff<4>, utilization| {
+            BackoffSource::<TestClock>::observe_utilization(backoff, utilization);
+            let samples: [u64; 64] = core::array::from_fn(|_| {
+                BackoffSource::<TestClock>::backoff(backoff, 10, 20, &mut rng)
+            });
+            (backoff.factor(), samples.iter().copied().max().unwrap())
+        };
+
+        let (factor, max) = sample(&mut backoff, 0.0);
+        assert_eq!(factor, 1);
+        assert!(max < 20);
+
+        let (factor, max) = sample(&mut backoff, 1.0);
+        assert_eq!(factor, 4);
+        assert!((60..80).contains(&max));
+
+        assert_eq!(sample(&mut backoff, 0.5).0, 3);
+        assert_eq!(sample(&mut backoff, 0.1).0, 1);
+    }
+
+    #[test]
+    fn schedule_from_address() {
+        let mut rng = rand::rngs::mock::StepRng::new(0, 1);
+        let mut a = ScheduleBackoff::<u64, 4>::from_address(Address::new(1), 10, 20);
+        let b = ScheduleBackoff::<u64, 4>::from_address(Address::new(2), 10, 20);
+        assert_ne!(a.schedule, b.schedule);
+        assert!(a.schedule.iter().all(|backoff| (10..20).contains(backoff)));
+
+        let schedule = a.schedule;
+        for expected in schedule.iter().chain(schedule.iter()) {
+            assert_eq!(
+                BackoffSource::<TestClock>::backoff(&mut a, 0, 1, &mut rng),
+                *expected
+            );
+        }
+    }
+}
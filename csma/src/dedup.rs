@@ -0,0 +1,89 @@
+use kiri_protocol::{Address, FrameRef};
+
+/// Remembers the last sequence number seen of up to `S` senders, to drop frames that are received twice.
+///
+/// Senders that have not been heard from for the longest time are forgotten first.
+/// Frames with sequence number `0` are never deemed duplicates. Use `S = 0` to disable the filter entirely.
+#[derive(Debug, Default)]
+pub struct DuplicateFilter<const S: usize> {
+    /// Least recently seen sender first.
+    seen: heapless::Vec<(Address, u8), S>,
+}
+
+impl<const S: usize> DuplicateFilter<S> {
+    pub fn new() -> Self {
+        Self {
+            seen: heapless::Vec::new(),
+        }
+    }
+
+    /// Register a received frame, yielding whether it is a duplicate of the previous frame of its sender.
+    pub fn is_duplicate(&mut self, frame: &FrameRef) -> bool {
+        let src = frame.header.address_src;
+        let sequence = frame.sequence();
+        if S == 0 || sequence == 0 {
+            return false;
+        }
+
+        let last = match self.seen.iter().position(|(address, _)| *address == src) {
+            Some(i) => Some(self.seen.remove(i).1),
+            None => {
+                if self.seen.is_full() {
+                    self.seen.remove(0);
+                }
+                None
+            }
+        };
+
+        // Can not fail, as there is room for at least one more sender now.
+        let _ = self.seen.push((src, sequence));
+        last == Some(sequence)
+    }
+
+    /// Forget all senders.
+    pub fn clear(&mut self) {
+        self.seen.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use kiri_protocol::{Reader, Writer};
+
+    use super::*;
+
+    fn check<const S: usize>(filter: &mut DuplicateFilter<S>, src: u32, sequence: u8) -> bool {
+        let frame =
+            Writer::package_with_sequence(Address::new(src), Address::new(0), sequence, b"hi")
+                .unwrap();
+        let mut reader = Reader::new();
+        let mut result = false;
+        for b in frame.as_slice() {
+            if let Ok(Some(frame)) = reader.feed(*b) {
+                result = filter.is_duplicate(&frame);
+            }
+        }
+        result
+    }
+
+    #[test]
+    fn drop_duplicates() {
+        let mut filter = DuplicateFilter::<2>::new();
+        assert!(!check(&mut filter, 1, 1));
+        assert!(check(&mut filter, 1, 1));
+        assert!(!check(&mut filter, 1, 2));
+        assert!(!check(&mut filter, 1, 0));
+        assert!(!check(&mut filter, 1, 0));
+
+        // Sender 1 is forgotten as soon as a third sender shows up.
+        assert!(!check(&mut filter, 2, 1));
+        assert!(!check(&mut filter, 3, 1));
+        assert!(!check(&mut filter, 1, 0));
+        assert!(!check(&mut filter, 1, 1));
+        assert!(check(&mut filter, 3, 1));
+
+        let mut disabled = DuplicateFilter::<0>::new();
+        assert!(!check(&mut disabled, 1, 1));
+        assert!(!check(&mut disabled, 1, 1));
+    }
+}
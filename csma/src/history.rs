@@ -0,0 +1,130 @@
+//! Headers of the frames that were recently on the bus, to reconstruct what happened before a fault.
+//!
+//! A strategy records them once it is given room for them using `CsmaStrategy::with_history`, and yields them from
+//! `CsmaStrategy::history`. The `management` protocol can dump them, see `management::Message::HistoryQuery`.
+
+use kiri_protocol::{Address, Header};
+
+use crate::DropReason;
+
+/// What became of a frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FrameOutcome {
+    /// Our frame was confirmed to be sent on the bus.
+    Sent,
+    /// Our frame was overwritten by another sender, and will be sent again.
+    Collided,
+    /// Our frame was not looped back in time, and will be sent again.
+    EchoTimeout,
+    /// A frame of another node was received.
+    Received,
+    /// A frame of another node was dropped. Its header is not verified by the checksum for some reasons.
+    Dropped(DropReason),
+}
+
+/// The header of a frame, and what became of it at `at`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameRecord<I> {
+    pub src: Address,
+    pub dst: Address,
+    /// Length of the contents, including any options.
+    pub len: usize,
+    pub at: I,
+    pub outcome: FrameOutcome,
+}
+
+impl<I> FrameRecord<I> {
+    pub fn new(header: &Header, len: usize, at: I, outcome: FrameOutcome) -> Self {
+        Self {
+            src: header.address_src,
+            dst: header.address_dst,
+            len,
+            at,
+            outcome,
+        }
+    }
+}
+
+/// The last `H` frame records, overwriting the oldest one when full. Records nothing if `H` is zero.
+///
+/// Unlike a `heapless::HistoryBuffer`, this can be zero-sized, such that strategies without history pay nothing.
+#[derive(Debug)]
+pub struct FrameHistory<I, const H: usize> {
+    records: [Option<FrameRecord<I>>; H],
+    /// Where the next record goes, which is the oldest one once all are taken.
+    next: usize,
+}
+
+impl<I: Copy, const H: usize> FrameHistory<I, H> {
+    pub fn new() -> Self {
+        Self {
+            records: [None; H],
+            next: 0,
+        }
+    }
+
+    /// Whether records are kept at all, such that callers can skip decoding headers otherwise.
+    pub const fn is_enabled(&self) -> bool {
+        H > 0
+    }
+
+    pub fn record(&mut self, record: FrameRecord<I>) {
+        if self.is_enabled() {
+            self.records[self.next] = Some(record);
+            self.next = (self.next + 1) % H;
+        }
+    }
+
+    /// The records, oldest first.
+    pub fn iter(&self) -> impl Iterator<Item = &FrameRecord<I>> {
+        let (newest, oldest) = self.records.split_at(self.next);
+        oldest.iter().chain(newest).flatten()
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.iter().flatten().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::new();
+    }
+}
+
+impl<I: Copy, const H: usize> Default for FrameHistory<I, H> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(at: u64) -> FrameRecord<u64> {
+        FrameRecord {
+            src: Address::new(1),
+            dst: Address::new(2),
+            len: 5,
+            at,
+            outcome: FrameOutcome::Sent,
+        }
+    }
+
+    #[test]
+    fn keeps_last() {
+        let mut history = FrameHistory::<u64, 3>::new();
+        for at in 0..5 {
+            history.record(record(at));
+        }
+        let ats: heapless::Vec<u64, 3> = history.iter().map(|record| record.at).collect();
+        assert_eq!(ats, [2, 3, 4]);
+
+        let mut disabled = FrameHistory::<u64, 0>::new();
+        disabled.record(record(0));
+        assert!(disabled.is_empty());
+    }
+}
@@ -1,19 +1,41 @@
 #![no_std]
 
+pub mod ack;
+pub mod backoff;
+pub mod dedup;
 pub(crate) mod fmt;
+pub mod history;
+pub mod management;
+pub mod persist;
+pub mod queue;
+pub mod retry;
+pub mod shared;
+pub mod split;
+pub mod ticks;
+pub mod timing;
+pub mod transitions;
+#[cfg(feature = "std")]
+pub mod udp;
+pub mod utilization;
+
+#[cfg(feature = "std")]
+pub use udp::SystemClock;
 
 use core::{
     fmt::Debug,
-    marker::PhantomData,
     ops::{Add, Sub},
 };
 
-use kiri_protocol::{Frame, FrameOwned, FrameRef, ReadResult, Reader};
-use rand::{
-    distributions::{uniform::SampleUniform, Uniform},
-    prelude::Distribution,
-    RngCore,
+use backoff::{BackoffSource, UniformBackoff};
+use dedup::DuplicateFilter;
+use history::{FrameHistory, FrameOutcome, FrameRecord};
+use kiri_protocol::{
+    Address, AddressClass, Frame, FrameError, FrameRef, Header, ReadResult, Reader, MAX_FRAME_LEN,
 };
+use queue::{QueueResult, TxQueue};
+use rand::{distributions::uniform::SampleUniform, RngCore};
+use transitions::{Action, Guard, Input, StateKind};
+use utilization::UtilizationEstimator;
 
 pub enum ReadError<E> {
     /// An unrecoverable underlying error.
@@ -26,6 +48,60 @@ pub enum ReadError<E> {
     FrameError,
 }
 
+/// Why an incoming frame was dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DropReason {
+    /// The transceiver reported a broken frame, i.e. a framing or parity error.
+    FrameError,
+    /// The frame did not fit the buffer of the reader.
+    Overflow,
+    /// See `FrameError::Cobs`.
+    Cobs,
+    /// See `FrameError::Magic`.
+    Magic,
+    /// See `FrameError::Header`.
+    Header,
+    /// See `FrameError::Size`.
+    Size,
+    /// See `FrameError::Checksum`.
+    Checksum,
+    /// The frame was received before, see `DuplicateFilter`.
+    Duplicate,
+    /// The frame was not addressed to us, see `CsmaStrategy::listen_for`.
+    Skipped,
+    /// The frame claims to be sent from a group address, which no node can send from, see `AddressClass::is_group`.
+    Source,
+}
+
+impl DropReason {
+    /// The reason corresponding to an error of the reader, if any.
+    pub fn from_read_result(result: &ReadResult<'_>) -> Option<Self> {
+        FrameError::from_read_result(result).map(Self::from)
+    }
+}
+
+impl From<FrameError> for DropReason {
+    fn from(error: FrameError) -> Self {
+        match error {
+            FrameError::Overflow => DropReason::Overflow,
+            FrameError::Cobs => DropReason::Cobs,
+            FrameError::Magic => DropReason::Magic,
+            FrameError::Header => DropReason::Header,
+            FrameError::Size => DropReason::Size,
+            FrameError::Checksum => DropReason::Checksum,
+        }
+    }
+}
+
+/// Error yielded by `CsmaStrategy::receive_verbose`.
+#[derive(Debug, PartialEq)]
+pub enum ReceiveError<E> {
+    /// An unrecoverable underlying error.
+    UnderlyingError(E),
+    /// An incoming frame was dropped. Keep receiving to get the next frame.
+    Dropped(DropReason),
+}
+
 impl<E> From<E> for ReadError<E> {
     fn from(e: E) -> Self {
         ReadError::UnderlyingError(e)
@@ -47,6 +123,38 @@ pub trait Transceiver {
 
     /// Read a byte from the bus, if available.
     fn read(&mut self) -> nb::Result<u8, ReadError<Self::Error>>;
+
+    /// Prepare to transmit a frame, i.e. assert the RS485 driver enable (DE) pin.
+    ///
+    /// Yield `WouldBlock` until the driver is ready to transmit, to wait for the turnaround time of the transceiver.
+    /// Not necessary for transceivers that control the driver in hardware.
+    fn start_transmit(&mut self) -> nb::Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Transmission of the frame has stopped, i.e. release the RS485 driver enable (DE) pin.
+    ///
+    /// Called once the last byte has been received back, or when transmission has been aborted.
+    fn end_transmit(&mut self) {}
+
+    /// Re-initialise the transceiver, because it appears to be wedged.
+    ///
+    /// Called when the strategy got stuck in a state, if `Config::RESET_ON_STATE_TIMEOUT` is set.
+    fn reset(&mut self) {}
+
+    /// Recover from errors, i.e. flush the FIFOs and clear sticky error flags.
+    ///
+    /// Called after `Config::RECOVER_AFTER_ERRORS` consecutive frame errors or overruns.
+    fn recover(&mut self) {}
+
+    /// Stop receiving until the bus is idle again, i.e. using the mute mode of the USART, to avoid waking up
+    /// for every byte of a frame that is of no interest.
+    ///
+    /// Yield whether the transceiver was muted, in which case the end of the frame is not received either.
+    /// Called when skipping a frame, see `CsmaStrategy::listen_for`.
+    fn mute_until_idle(&mut self) -> bool {
+        false
+    }
 }
 
 pub trait Clock {
@@ -57,7 +165,13 @@ pub trait Clock {
         + Debug
         + Clone
         + Copy;
-    type Duration: PartialEq + PartialOrd + SampleUniform;
+    type Duration: PartialEq
+        + PartialOrd
+        + Add<Self::Duration, Output = Self::Duration>
+        + SampleUniform
+        + Default
+        + Clone
+        + Copy;
 
     fn now(&self) -> Self::Instant;
 }
@@ -65,8 +179,88 @@ pub trait Clock {
 pub trait Config<C: Clock> {
     const BUS_MIN_IDLE_DURATION: C::Duration;
     const BUS_MAX_IDLE_DURATION: C::Duration;
+
+    /// How long to wait for a sent byte to be looped back, before considering the frame lost.
+    const ECHO_BYTE_TIMEOUT: C::Duration;
+    /// How long sending and confirming a complete frame may take, before considering the frame lost.
+    const ECHO_FRAME_TIMEOUT: C::Duration;
+
+    /// After how many consecutive frame errors or overruns to `Transceiver::recover`.
+    const RECOVER_AFTER_ERRORS: u32 = 3;
+
+    /// Whether to `Transceiver::reset` after the strategy got stuck in a state.
+    const RESET_ON_STATE_TIMEOUT: bool = false;
+
+    /// How long the strategy may stay in `state`, before it gives up and waits for the bus to be idle again.
+    ///
+    /// Defaults to no limit.
+    fn max_dwell_duration(_state: &CsmaStrategyState<C>) -> Option<C::Duration> {
+        None
+    }
+
+    /// How often to sample whether the bus is busy, see `CsmaStrategy::utilization`.
+    const UTILIZATION_INTERVAL: C::Duration = Self::BUS_MIN_IDLE_DURATION;
+
+    /// How long to keep quiet after a frame of our own was sent, before contending for the bus again.
+    ///
+    /// Set this to at least the idle time other nodes detect the end of a frame by, such that our consecutive frames
+    /// are not seen as one. Defaults to no gap.
+    const POST_SEND_GAP: Option<C::Duration> = None;
+
+    /// How many `JAM_BYTE`s to send once our frame turned out to collide, before backing off.
+    ///
+    /// Makes sure the other sender notices the collision as well and aborts, instead of finishing a frame that
+    /// nobody can receive. Defaults to backing off right away.
+    const JAM_LEN: u8 = 0;
+}
+
+/// Byte that is sent to jam the bus after a collision, see `Config::JAM_LEN`.
+///
+/// Being the COBS marker, it ends the broken frame at every receiver, such that they are ready for the next one.
+pub const JAM_BYTE: u8 = 0;
+
+/// Configuration of a `CsmaStrategy` that can be decided at runtime, i.e. depending on the baud rate.
+///
+/// See `Config` for the meaning of every field, and to decide them at compile time instead.
+pub struct CsmaConfig<C: Clock> {
+    pub bus_min_idle: C::Duration,
+    pub bus_max_idle: C::Duration,
+    pub echo_byte_timeout: C::Duration,
+    pub echo_frame_timeout: C::Duration,
+    pub recover_after_errors: u32,
+    pub reset_on_state_timeout: bool,
+    pub max_dwell_duration: fn(&CsmaStrategyState<C>) -> Option<C::Duration>,
+    pub utilization_interval: C::Duration,
+    pub post_send_gap: Option<C::Duration>,
+    pub jam_len: u8,
+}
+
+impl<C: Clock> CsmaConfig<C> {
+    /// The configuration decided at compile time by `CONF`.
+    pub fn from_config<CONF: Config<C>>() -> Self {
+        Self {
+            bus_min_idle: CONF::BUS_MIN_IDLE_DURATION,
+            bus_max_idle: CONF::BUS_MAX_IDLE_DURATION,
+            echo_byte_timeout: CONF::ECHO_BYTE_TIMEOUT,
+            echo_frame_timeout: CONF::ECHO_FRAME_TIMEOUT,
+            recover_after_errors: CONF::RECOVER_AFTER_ERRORS,
+            reset_on_state_timeout: CONF::RESET_ON_STATE_TIMEOUT,
+            max_dwell_duration: CONF::max_dwell_duration,
+            utilization_interval: CONF::UTILIZATION_INTERVAL,
+            post_send_gap: CONF::POST_SEND_GAP,
+            jam_len: CONF::JAM_LEN,
+        }
+    }
+}
+
+impl<C: Clock> Clone for CsmaConfig<C> {
+    fn clone(&self) -> Self {
+        *self
+    }
 }
 
+impl<C: Clock> Copy for CsmaConfig<C> {}
+
 #[derive(Debug)]
 pub struct GreedyFrameInProgress {
     frame: Frame,
@@ -75,7 +269,7 @@ pub struct GreedyFrameInProgress {
 
 impl GreedyFrameInProgress {
     pub fn first(&self) -> Option<u8> {
-        return self.frame.0.get(self.ptr).copied();
+        self.frame.0.get(self.ptr).copied()
     }
 
     pub fn pop_first(&mut self) {
@@ -129,7 +323,7 @@ impl<T: Transceiver> GreedyStrategy<T> {
     pub fn receive(&mut self) -> nb::Result<FrameRef<'_>, ReadError<T::Error>> {
         let b = self.transceiver.read()?;
         match self.reader.feed(b) {
-            ReadResult::FrameOK(fr) => Ok(fr),
+            Ok(Some(fr)) => Ok(fr),
             _ => nb::Result::Err(nb::Error::WouldBlock),
         }
     }
@@ -140,9 +334,14 @@ pub enum CsmaStrategyState<C: Clock> {
     /// The bus is not idle, and before deciding to act we first must wait for a new frame.
     WaitForBusIdle,
     /// Bus is now idle, but needs to wait a bit before we can start chattering.
-    BusIdleCooldown { ready_at: C::Instant },
+    BusIdleCooldown {
+        started_at: C::Instant,
+        ready_at: C::Instant,
+    },
     /// Clear any FIFO in queue. Peripheral should have processed all bytes by now.
     StartSend,
+    /// Waiting for the transceiver to enable its driver.
+    EnablingDriver,
     /// We are sending a frame and hence the frame that we receive must correspond to our own frame.
     Sending,
     /// We have sent the last byte of the frame to the transceiver, and are awaiting it to come back
@@ -150,33 +349,192 @@ pub enum CsmaStrategyState<C: Clock> {
     ///
     /// We will need to resend the frame if it does not end up back here.
     ConfirmingSendWithoutErrors,
+    /// Our frame was sent, and we keep quiet until `until` before contending for the bus again, see
+    /// `Config::POST_SEND_GAP`.
+    PostSendGap { until: C::Instant },
+    /// Our frame collided, and we keep the driver enabled to send `left` more `JAM_BYTE`s, see `Config::JAM_LEN`.
+    Jamming { left: u8 },
+}
+
+impl<C: Clock> CsmaStrategyState<C> {
+    /// The state without its data, as listed in `transitions::TRANSITIONS`.
+    pub fn kind(&self) -> StateKind {
+        match self {
+            CsmaStrategyState::WaitForBusIdle => StateKind::WaitForBusIdle,
+            CsmaStrategyState::BusIdleCooldown { .. } => StateKind::BusIdleCooldown,
+            CsmaStrategyState::StartSend => StateKind::StartSend,
+            CsmaStrategyState::EnablingDriver => StateKind::EnablingDriver,
+            CsmaStrategyState::Sending => StateKind::Sending,
+            CsmaStrategyState::ConfirmingSendWithoutErrors => {
+                StateKind::ConfirmingSendWithoutErrors
+            }
+            CsmaStrategyState::PostSendGap { .. } => StateKind::PostSendGap,
+            CsmaStrategyState::Jamming { .. } => StateKind::Jamming,
+        }
+    }
+}
+
+/// Notable things happening in a `CsmaStrategy`, reported to its `Observer`.
+#[derive(Debug)]
+pub enum Event<'a, C: Clock> {
+    /// The strategy moved to a new state.
+    StateChanged(&'a CsmaStrategyState<C>),
+    /// Our frame was looped back completely, and hence was sent on the bus.
+    FrameSent,
+    /// A frame from another node was received.
+    FrameReceived,
+    /// Our frame was overwritten by another sender.
+    CollisionDetected,
+    /// Our frame was not looped back in time.
+    EchoTimeout,
+    /// The strategy stayed in a state for longer than allowed by `Config::max_dwell_duration`.
+    StateTimeout,
+    /// A frame that was not addressed to us is being skipped, see `CsmaStrategy::listen_for`.
+    ///
+    /// Nothing needs to be done until the bus is idle again, i.e. to enter a low power mode.
+    FrameSkipped,
+}
+
+/// Hook into the events of a `CsmaStrategy`, i.e. to drive LEDs or tracing.
+pub trait Observer<C: Clock> {
+    fn on_event(&mut self, event: Event<'_, C>);
 }
 
-#[derive(Default)]
-pub struct Stats {
+/// The default observer, which ignores all events.
+impl<C: Clock> Observer<C> for () {
+    fn on_event(&mut self, _event: Event<'_, C>) {}
+}
+
+/// The observer and the backoff source of a `CsmaStrategy`, which are replaced by `CsmaStrategy::with_observer` and
+/// `CsmaStrategy::with_backoff`.
+///
+/// Implemented for `(observer, backoff)` pairs, such that the strategy takes a single parameter for both. Defaults to
+/// `((), UniformBackoff)`, which ignores all events and samples the backoff uniformly.
+pub trait Hooks<C: Clock> {
+    type Observer: Observer<C>;
+    type Backoff: BackoffSource<C>;
+
+    fn observer(&mut self) -> &mut Self::Observer;
+
+    fn backoff(&mut self) -> &mut Self::Backoff;
+
+    fn into_parts(self) -> (Self::Observer, Self::Backoff);
+}
+
+impl<C: Clock, O: Observer<C>, B: BackoffSource<C>> Hooks<C> for (O, B) {
+    type Observer = O;
+    type Backoff = B;
+
+    fn observer(&mut self) -> &mut O {
+        &mut self.0
+    }
+
+    fn backoff(&mut self) -> &mut B {
+        &mut self.1
+    }
+
+    fn into_parts(self) -> (O, B) {
+        self
+    }
+}
+
+/// Counters kept by the strategy, in durations `D` of the clock.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Stats<D> {
     pub frame_errors: u64,
+    /// Frames that were aborted because the sent bytes did not loop back in time.
+    pub echo_timeouts: u64,
+    /// Frames of our own that were confirmed to be sent on the bus.
+    pub frames_sent: u64,
+    /// Frames from other nodes that were received.
+    pub frames_received: u64,
+    /// Bytes written to the transceiver.
+    pub bytes_sent: u64,
+    /// Bytes read from the transceiver, including our own looped back bytes.
+    pub bytes_received: u64,
+    /// Frames of our own that were overwritten by another sender.
+    pub collisions: u64,
+    /// Frames that needed to be sent again after a (partial) attempt.
+    pub retransmissions: u64,
+    /// Cumulative time spent waiting for the random backoff to expire.
+    pub backoff_time: D,
+    /// Received frames that were dropped because of a checksum mismatch.
+    pub crc_failures: u64,
+    /// Times the strategy stayed in a state for longer than allowed by `Config::max_dwell_duration`.
+    pub state_timeouts: u64,
+    /// Times the transceiver was asked to recover, see `Transceiver::recover`.
+    pub recoveries: u64,
+    /// Received frames that were dropped as duplicates, see `DuplicateFilter`.
+    pub duplicates_dropped: u64,
+    /// Frames of our own that could not be sent before their deadline.
+    pub frames_expired: u64,
+    /// Received frames that were skipped, as they were not addressed to us.
+    pub frames_skipped: u64,
+}
+
+impl<D: Default> Stats<D> {
+    /// Set all counters back to zero.
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
 }
 
 /// Carrier Sense Multiple Access strategy implementation.
-pub struct CsmaStrategy<T: Transceiver, C: Clock, R: RngCore, CONF: Config<C>> {
+///
+/// Incoming frames are buffered in a reader of `N` bytes, see `kiri_protocol::max_naked_len`.
+/// Duplicate frames of the last `D` senders are dropped, see `DuplicateFilter`.
+/// Events are reported to, and the time to wait once the bus became idle is decided by, the hooks `K`, see `Hooks`.
+/// The headers of the last `H` frames are kept for post-mortems, see `with_history`.
+/// Only frames on bus `BUS` are received, see `with_bus`.
+pub struct CsmaStrategy<
+    T: Transceiver,
+    C: Clock,
+    R: RngCore,
+    const N: usize = MAX_FRAME_LEN,
+    const D: usize = 8,
+    K: Hooks<C> = ((), UniformBackoff),
+    const H: usize = 0,
+    const BUS: u8 = 0,
+> {
     transceiver: T,
     clock: C,
     rng: R,
-    reader: Reader,
+    reader: Reader<N, BUS>,
+    duplicates: DuplicateFilter<D>,
     state: CsmaStrategyState<C>,
-    stats: Stats,
-    _conf: PhantomData<CONF>,
+    /// When we entered the current state.
+    state_entered_at: C::Instant,
+    stats: Stats<C::Duration>,
+    /// When we started sending the current frame.
+    send_started_at: Option<C::Instant>,
+    /// When we last made progress while waiting for a byte to loop back.
+    echo_progress_at: Option<C::Instant>,
+    /// Frame errors and overruns since the last frame that was sent or received.
+    consecutive_errors: u32,
+    hooks: K,
+    config: CsmaConfig<C>,
+    /// Addresses to receive frames for, if not all, see `listen_for`.
+    listening: Option<heapless::Vec<Address, MAX_LISTEN_ADDRESSES>>,
+    utilization: UtilizationEstimator<C>,
+    history: FrameHistory<C::Instant, H>,
 }
 
+/// How many addresses a strategy can listen for at most, see `CsmaStrategy::listen_for`.
+pub const MAX_LISTEN_ADDRESSES: usize = 4;
+
+/// Tried to listen for more than `MAX_LISTEN_ADDRESSES` addresses.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct TooManyAddresses;
+
 #[derive(Debug)]
-pub struct CsmaFrameInProgress {
-    frame: Frame,
+pub struct CsmaFrameInProgress<const N: usize = MAX_FRAME_LEN> {
+    frame: Frame<N>,
     send_ptr: usize,
     receive_ptr: usize,
 }
 
-impl CsmaFrameInProgress {
-    pub fn new(frame: Frame) -> Self {
+impl<const N: usize> CsmaFrameInProgress<N> {
+    pub fn new(frame: Frame<N>) -> Self {
         Self {
             frame,
             send_ptr: 0,
@@ -197,6 +555,17 @@ impl CsmaFrameInProgress {
         self.send_ptr += 1;
     }
 
+    /// Whether some bytes have been sent since the frame was last reset.
+    pub fn is_started(&self) -> bool {
+        self.send_ptr > 0
+    }
+
+    /// Whether some bytes that have been sent have not yet been looped back.
+    pub fn awaiting_echo(&self) -> bool {
+        self.send_ptr > self.receive_ptr
+    }
+
+    #[allow(clippy::result_unit_err)]
     pub fn feed_as_check(&mut self, b: u8) -> Result<bool, ()> {
         match self.frame.as_slice().get(self.receive_ptr) {
             Some(by) if *by == b => {
@@ -208,154 +577,653 @@ impl CsmaFrameInProgress {
     }
 }
 
-pub enum SendReceiveResult {
+/// Outcome of `send_or_receive`, where `F` is what a received frame has been turned into.
+#[allow(clippy::large_enum_variant)]
+pub enum SendReceiveResult<F> {
     SendComplete,
-    Received(FrameOwned),
+    Received(F),
+    /// The frame could not be sent before its deadline, see `CsmaStrategy::send_or_receive_before`.
+    ///
+    /// The frame has been reset, such that it can be sent anew.
+    Expired,
+}
+
+impl<T: Transceiver, C: Clock, R: RngCore> CsmaStrategy<T, C, R> {
+    /// Create a strategy configured at compile time by `CONF`.
+    ///
+    /// Use `CsmaStrategy::new_with_config` for other buffer sizes.
+    pub fn new<CONF: Config<C>>(transceiver: T, clock: C, rng: R) -> Self {
+        Self::new_with_config(transceiver, clock, rng, CsmaConfig::from_config::<CONF>())
+    }
 }
 
-impl<T: Transceiver, C: Clock, R: RngCore, CONF: Config<C>> CsmaStrategy<T, C, R, CONF> {
-    pub fn new(transceiver: T, clock: C, rng: R) -> Self {
+impl<T: Transceiver, C: Clock, R: RngCore, const N: usize, const D: usize>
+    CsmaStrategy<T, C, R, N, D>
+{
+    /// Create a strategy configured at runtime, see `CsmaConfig::from_config` to start from a `Config`.
+    pub fn new_with_config(transceiver: T, clock: C, rng: R, config: CsmaConfig<C>) -> Self {
+        let state_entered_at = clock.now();
         Self {
             transceiver,
             clock,
             rng,
-            reader: Reader::new(),
+            reader: Reader::default(),
+            duplicates: DuplicateFilter::new(),
             state: CsmaStrategyState::WaitForBusIdle,
+            state_entered_at,
             stats: Stats::default(),
-            _conf: PhantomData::default(),
+            send_started_at: None,
+            echo_progress_at: None,
+            consecutive_errors: 0,
+            hooks: ((), UniformBackoff),
+            utilization: UtilizationEstimator::new(config.utilization_interval),
+            config,
+            listening: None,
+            history: FrameHistory::new(),
+        }
+    }
+}
+
+impl<
+        T: Transceiver,
+        C: Clock,
+        R: RngCore,
+        const N: usize,
+        const D: usize,
+        K: Hooks<C>,
+        const H: usize,
+        const BUS: u8,
+    > CsmaStrategy<T, C, R, N, D, K, H, BUS>
+{
+    /// Report events of this strategy to an observer.
+    pub fn with_observer<O2: Observer<C>>(
+        self,
+        observer: O2,
+    ) -> CsmaStrategy<T, C, R, N, D, (O2, K::Backoff), H, BUS> {
+        self.rebuild(
+            |hooks| (observer, hooks.into_parts().1),
+            |reader| reader,
+            |history| history,
+        )
+    }
+
+    /// Decide the time to wait once the bus became idle with `backoff`, instead of sampling it uniformly.
+    pub fn with_backoff<B2: BackoffSource<C>>(
+        self,
+        backoff: B2,
+    ) -> CsmaStrategy<T, C, R, N, D, (K::Observer, B2), H, BUS> {
+        self.rebuild(
+            |hooks| (hooks.into_parts().0, backoff),
+            |reader| reader,
+            |history| history,
+        )
+    }
+
+    /// Record the headers of the last `H2` frames that were sent, received or dropped, see `history`.
+    ///
+    /// Every record takes up to about 40 bytes, depending on the clock. Headers of frames that are sent are decoded
+    /// for this, which is skipped without history.
+    pub fn with_history<const H2: usize>(self) -> CsmaStrategy<T, C, R, N, D, K, H2, BUS> {
+        self.rebuild(|hooks| hooks, |reader| reader, |_| FrameHistory::new())
+    }
+
+    /// Only receive frames on bus `BUS2`, such that frames leaking over from other buses are dropped.
+    ///
+    /// Frames to send are meant for that bus by building them with `FrameBuilder::bus`. Frames that were only
+    /// partially received are forgotten.
+    pub fn with_bus<const BUS2: u8>(self) -> CsmaStrategy<T, C, R, N, D, K, H, BUS2> {
+        let mut strategy = self.rebuild(|hooks| hooks, |_| Reader::default(), |history| history);
+        if strategy.listening.is_some() {
+            strategy.reader.peek_headers(true);
         }
+        strategy
     }
 
-    pub fn stats(&self) -> &Stats {
+    /// The same strategy, of which the parts that the parameters of its type depend on are replaced.
+    fn rebuild<K2: Hooks<C>, const H2: usize, const BUS2: u8>(
+        self,
+        hooks: impl FnOnce(K) -> K2,
+        reader: impl FnOnce(Reader<N, BUS>) -> Reader<N, BUS2>,
+        history: impl FnOnce(FrameHistory<C::Instant, H>) -> FrameHistory<C::Instant, H2>,
+    ) -> CsmaStrategy<T, C, R, N, D, K2, H2, BUS2> {
+        CsmaStrategy {
+            transceiver: self.transceiver,
+            clock: self.clock,
+            rng: self.rng,
+            reader: reader(self.reader),
+            duplicates: self.duplicates,
+            state: self.state,
+            state_entered_at: self.state_entered_at,
+            stats: self.stats,
+            send_started_at: self.send_started_at,
+            echo_progress_at: self.echo_progress_at,
+            consecutive_errors: self.consecutive_errors,
+            hooks: hooks(self.hooks),
+            config: self.config,
+            listening: self.listening,
+            utilization: self.utilization,
+            history: history(self.history),
+        }
+    }
+
+    /// The headers of the last frames on the bus and what became of them, see `with_history`.
+    pub fn history(&self) -> &FrameHistory<C::Instant, H> {
+        &self.history
+    }
+
+    /// Record what became of a frame of our own, if history is kept.
+    fn record_own<const F: usize>(
+        &mut self,
+        frame: &CsmaFrameInProgress<F>,
+        outcome: FrameOutcome,
+    ) {
+        if !self.history.is_enabled() {
+            return;
+        }
+        if let (Some(header), Some(len)) = (frame.frame.header(), frame.frame.contents_len()) {
+            let record = FrameRecord::new(&header, len, self.clock.now(), outcome);
+            self.history.record(record);
+        }
+    }
+
+    /// The observer that events are reported to.
+    pub fn observer(&mut self) -> &mut K::Observer {
+        self.hooks.observer()
+    }
+
+    /// Move to `state` because `guard` holds, which must be listed in `transitions::TRANSITIONS`.
+    fn set_state(&mut self, guard: Guard, state: CsmaStrategyState<C>) {
+        debug_assert!(
+            transitions::allows(self.state.kind(), guard, state.kind()),
+            "Transition from {:?} to {:?} on {:?} is not listed",
+            self.state.kind(),
+            state.kind(),
+            guard
+        );
+        self.state = state;
+        self.state_entered_at = self.clock.now();
+        self.hooks
+            .observer()
+            .on_event(Event::StateChanged(&self.state));
+    }
+
+    pub fn stats(&self) -> &Stats<C::Duration> {
         &self.stats
     }
 
-    /// Handle sending of bytes on bus, if the bus is clear.
-    fn handle_send(&mut self, frame: &mut CsmaFrameInProgress) -> nb::Error<T::Error> {
-        use CsmaStrategyState::*;
-        match &self.state {
-            WaitForBusIdle => {
-                if self.transceiver.bus_is_idle() {
-                    let distribution =
-                        Uniform::new(CONF::BUS_MIN_IDLE_DURATION, CONF::BUS_MAX_IDLE_DURATION);
-                    let idle_duration = distribution.sample(&mut self.rng);
-                    let ready_at = self.clock.now() + idle_duration;
-                    self.state = BusIdleCooldown { ready_at };
-                }
+    /// Set all statistics back to zero.
+    pub fn reset_stats(&mut self) {
+        self.stats.reset();
+    }
+
+    pub fn config(&self) -> &CsmaConfig<C> {
+        &self.config
+    }
+
+    /// Change the configuration, i.e. after changing the baud rate.
+    pub fn config_mut(&mut self) -> &mut CsmaConfig<C> {
+        &mut self.config
+    }
+
+    /// Fraction of time the bus was not idle recently, from `0.0` to `1.0`, i.e. to adapt the rate of sending.
+    ///
+    /// Estimated over the last `UTILIZATION_WINDOW` samples, which are taken every `Config::UTILIZATION_INTERVAL`
+    /// while the strategy is polled.
+    pub fn utilization(&self) -> f32 {
+        self.utilization.utilization()
+    }
+
+    /// Sample whether the bus is busy, given whether a byte was just received.
+    fn observe_bus(&mut self, received: bool) {
+        if self.utilization.interval() != self.config.utilization_interval {
+            self.utilization
+                .set_interval(self.config.utilization_interval);
+        }
+        let busy = received || !self.transceiver.bus_is_idle();
+        self.utilization.observe(self.clock.now(), busy);
+    }
+
+    /// Reset a frame so that it is sent again, counting it as a retransmission if it was sent in part.
+    fn restart_frame<const F: usize>(&mut self, frame: &mut CsmaFrameInProgress<F>) {
+        if frame.send_ptr > 0 {
+            self.stats.retransmissions += 1;
+        }
+        frame.reset();
+    }
+
+    /// Register a frame error or overrun, asking the transceiver to recover if they keep occurring.
+    ///
+    /// Takes the fields separately, such that it can be used while a frame is borrowed from the reader.
+    fn note_error(
+        config: &CsmaConfig<C>,
+        transceiver: &mut T,
+        stats: &mut Stats<C::Duration>,
+        consecutive_errors: &mut u32,
+    ) {
+        *consecutive_errors += 1;
+        if *consecutive_errors >= config.recover_after_errors {
+            warn!("Recovering transceiver");
+            stats.recoveries += 1;
+            *consecutive_errors = 0;
+            transceiver.recover();
+        }
+    }
+
+    /// Only receive frames addressed to one of `addresses`, or to broadcast and management addresses.
+    ///
+    /// Multicast groups are joined by including their address, see `Address::group`.
+    /// Other frames are skipped as soon as their header arrives, without buffering or checking the rest of them.
+    pub fn listen_for(&mut self, addresses: &[Address]) -> Result<(), TooManyAddresses> {
+        let addresses = heapless::Vec::from_slice(addresses).map_err(|()| TooManyAddresses)?;
+        self.listening = Some(addresses);
+        self.reader.peek_headers(true);
+        Ok(())
+    }
+
+    /// Receive all frames again, see `listen_for`.
+    pub fn listen_to_all(&mut self) {
+        self.listening = None;
+        self.reader.peek_headers(false);
+    }
+
+    /// The header of the frame being received, if it turned out not to be addressed to us.
+    fn unwanted_frame(&mut self) -> Option<Header> {
+        match (&self.listening, self.reader.take_header()) {
+            (Some(addresses), Some(header)) => {
+                let dst = header.address_dst;
+                let unwanted = match dst.class() {
+                    AddressClass::Broadcast | AddressClass::Management => false,
+                    AddressClass::Unicast | AddressClass::Dynamic | AddressClass::Multicast => {
+                        !addresses.contains(&dst)
+                    }
+                };
+                unwanted.then_some(header)
             }
-            BusIdleCooldown { ready_at } => {
-                if !self.transceiver.bus_is_idle() {
-                    self.state = WaitForBusIdle;
-                } else if self.clock.now() >= *ready_at {
-                    self.state = StartSend;
-                }
+            _ => None,
+        }
+    }
+
+    /// Feed a byte from another sender to the reader, yielding any completed frame or why it was dropped.
+    fn feed_reader(&mut self, b: u8) -> Result<Option<FrameRef<'_>>, DropReason> {
+        // Check the header decoded by the previous byte, as the reader is borrowed once it yields a frame.
+        if let Some(header) = self.unwanted_frame() {
+            trace!("Skipping frame");
+            let len = *header.len as usize;
+            let outcome = FrameOutcome::Dropped(DropReason::Skipped);
+            let record = FrameRecord::new(&header, len, self.clock.now(), outcome);
+            self.history.record(record);
+            self.stats.frames_skipped += 1;
+            self.hooks.observer().on_event(Event::FrameSkipped);
+            if self.transceiver.mute_until_idle() {
+                self.reader.clear();
+            } else {
+                // This byte is part of the skipped frame as well, unless it ends it.
+                self.reader.skip();
+                let _ = self.reader.feed(b);
             }
-            StartSend => {
-                if !self.transceiver.bus_is_idle() {
-                    self.state = WaitForBusIdle;
+            return Err(DropReason::Skipped);
+        }
+
+        // Frames are only checked completely at the COBS marker, after which their header is gone if they are broken.
+        let peeked = match b == JAM_BYTE && self.history.is_enabled() {
+            true => self.reader.peek_header(),
+            false => None,
+        };
+
+        let now = self.clock.now();
+        match self.reader.feed(b) {
+            Ok(Some(fr)) => {
+                self.consecutive_errors = 0;
+                let outcome = if fr.header.address_src.is_group() {
+                    Err(DropReason::Source)
+                } else if self.duplicates.is_duplicate(&fr) {
+                    self.stats.duplicates_dropped += 1;
+                    Err(DropReason::Duplicate)
                 } else {
-                    self.reader.clear();
-                    self.state = Sending;
-                }
-            }
-            Sending => {
-                let b = match frame.peek_for_send() {
-                    None => {
-                        self.state = ConfirmingSendWithoutErrors;
-                        return nb::Error::WouldBlock;
-                    }
-                    Some(b) => b,
+                    Ok(())
                 };
+                let record = FrameRecord::new(
+                    &fr.header,
+                    fr.contents.len(),
+                    now,
+                    match outcome {
+                        Ok(()) => FrameOutcome::Received,
+                        Err(reason) => FrameOutcome::Dropped(reason),
+                    },
+                );
+                self.history.record(record);
+                outcome?;
 
-                if let nb::Result::Err(e) = self.transceiver.write(b) {
-                    return e;
+                self.stats.frames_received += 1;
+                self.hooks.observer().on_event(Event::FrameReceived);
+                Ok(Some(fr))
+            }
+            Ok(None) => Ok(None),
+            Err(e) => {
+                if let Some(header) = peeked {
+                    let len = *header.len as usize;
+                    let outcome = FrameOutcome::Dropped(e.into());
+                    self.history
+                        .record(FrameRecord::new(&header, len, now, outcome));
+                }
+                match e {
+                    FrameError::Checksum => self.stats.crc_failures += 1,
+                    FrameError::Overflow => Self::note_error(
+                        &self.config,
+                        &mut self.transceiver,
+                        &mut self.stats,
+                        &mut self.consecutive_errors,
+                    ),
+                    _ => (),
                 }
+                Err(e.into())
+            }
+        }
+    }
+
+    /// Whether we have been in the current state for longer than allowed.
+    fn state_timed_out(&self) -> bool {
+        (self.config.max_dwell_duration)(&self.state)
+            .is_some_and(|max| self.clock.now() >= self.state_entered_at + max)
+    }
+
+    /// Whether the frame we are sending is not looped back in time, i.e. because our transceiver is broken.
+    fn echo_timed_out<const F: usize>(&self, frame: &CsmaFrameInProgress<F>) -> bool {
+        let now = self.clock.now();
+        let frame_timed_out = self
+            .send_started_at
+            .is_some_and(|at| now >= at + self.config.echo_frame_timeout);
+        let byte_timed_out = frame.awaiting_echo()
+            && self
+                .echo_progress_at
+                .is_some_and(|at| now >= at + self.config.echo_byte_timeout);
+        frame_timed_out || byte_timed_out
+    }
+
+    /// When polling is needed next, at the latest, such that callers can sleep in between.
+    ///
+    /// Yields `None` if nothing happens until the transceiver signals activity, like a received byte or the bus
+    /// becoming idle. Poll right away after such an interrupt, or after having handed a new frame to send.
+    pub fn next_poll_at(&self) -> Option<C::Instant> {
+        use CsmaStrategyState::*;
+        let now = self.clock.now();
+        let at = match &self.state {
+            WaitForBusIdle => None,
+            BusIdleCooldown { ready_at, .. } => Some(*ready_at),
+            PostSendGap { until } => Some(*until),
+            StartSend | EnablingDriver | Sending | Jamming { .. } => Some(now),
+            ConfirmingSendWithoutErrors => earliest(
+                self.send_started_at
+                    .map(|at| at + self.config.echo_frame_timeout),
+                self.echo_progress_at
+                    .map(|at| at + self.config.echo_byte_timeout),
+            ),
+        };
+        let timeout_at =
+            (self.config.max_dwell_duration)(&self.state).map(|max| self.state_entered_at + max);
+        earliest(at, timeout_at)
+    }
 
-                frame.notify_send();
-                if frame.peek_for_send().is_none() {
-                    self.state = ConfirmingSendWithoutErrors;
+    /// Take a step on `input` and carry out its actions, along with those of the steps on their outcomes.
+    ///
+    /// Yields the result of polling if we are done for now, or `None` if polling should proceed.
+    fn run<const F: usize, U>(
+        &mut self,
+        frame: &mut CsmaFrameInProgress<F>,
+        input: Input<C>,
+        on_receive: impl FnOnce(FrameRef<'_>) -> U,
+    ) -> Option<nb::Result<SendReceiveResult<U>, T::Error>> {
+        let mut input = Some(input);
+        let mut on_receive = Some(on_receive);
+        let mut result = None;
+        while let Some(current) = input.take() {
+            for action in transitions::step(&self.state, current, &self.config) {
+                match action {
+                    Action::Enter(guard, state) => self.set_state(guard, state),
+                    Action::DrawBackoff => {
+                        let source = self.hooks.backoff();
+                        source.observe_utilization(self.utilization.utilization());
+                        let backoff = source.backoff(
+                            self.config.bus_min_idle,
+                            self.config.bus_max_idle,
+                            &mut self.rng,
+                        );
+                        input = Some(Input::BackoffDrawn {
+                            now: self.clock.now(),
+                            backoff,
+                        });
+                    }
+                    Action::CountBackoff(duration) => {
+                        self.stats.backoff_time = self.stats.backoff_time + duration
+                    }
+                    Action::EnableDriver => match self.transceiver.start_transmit() {
+                        Ok(()) => input = Some(Input::DriverEnabled),
+                        Err(e) => return Some(Err(e)),
+                    },
+                    Action::DisableDriver => self.transceiver.end_transmit(),
+                    Action::WriteFrame => {
+                        if let Some(b) = frame.peek_for_send() {
+                            if let Err(e) = self.transceiver.write(b) {
+                                return Some(Err(e));
+                            }
+                            self.stats.bytes_sent += 1;
+                            if !frame.awaiting_echo() {
+                                self.echo_progress_at = Some(self.clock.now());
+                            }
+                            frame.notify_send();
+                        }
+                        input = Some(Input::FrameByteWritten {
+                            written: frame.peek_for_send().is_none(),
+                        });
+                    }
+                    Action::WriteJam => {
+                        if let Err(e) = self.transceiver.write(JAM_BYTE) {
+                            return Some(Err(e));
+                        }
+                        self.stats.bytes_sent += 1;
+                        input = Some(Input::JamByteWritten);
+                    }
+                    Action::CheckEcho(b) => {
+                        input = Some(match frame.feed_as_check(b) {
+                            Ok(complete) => Input::Echoed {
+                                now: self.clock.now(),
+                                complete,
+                            },
+                            // Mismatch between sending and loopback frames.
+                            Err(()) => Input::EchoMismatch(b),
+                        })
+                    }
+                    Action::StartEchoTimer => self.send_started_at = Some(self.clock.now()),
+                    Action::EchoProgress => self.echo_progress_at = Some(self.clock.now()),
+                    Action::StopEchoTimers => {
+                        self.send_started_at = None;
+                        self.echo_progress_at = None;
+                    }
+                    Action::ClearReader => self.reader.clear(),
+                    Action::Discard(b) => {
+                        let _ = self.reader.feed(b);
+                    }
+                    Action::Receive(b) => {
+                        // The frame is not sent by us, and thus should be reported back to our caller.
+                        if let (Ok(Some(incoming_frame)), Some(on_receive)) =
+                            (self.feed_reader(b), on_receive.take())
+                        {
+                            return Some(Ok(SendReceiveResult::Received(on_receive(
+                                incoming_frame,
+                            ))));
+                        }
+                    }
+                    Action::Sent => {
+                        self.stats.frames_sent += 1;
+                        self.consecutive_errors = 0;
+                        self.hooks.observer().on_event(Event::FrameSent);
+                        self.record_own(frame, FrameOutcome::Sent);
+                        result = Some(Ok(SendReceiveResult::SendComplete));
+                    }
+                    Action::CountFrameError => {
+                        trace!("Frame error");
+                        self.stats.frame_errors += 1;
+                    }
+                    Action::NoteError => Self::note_error(
+                        &self.config,
+                        &mut self.transceiver,
+                        &mut self.stats,
+                        &mut self.consecutive_errors,
+                    ),
+                    Action::Collided => {
+                        self.stats.collisions += 1;
+                        self.hooks.observer().on_event(Event::CollisionDetected);
+                        self.record_own(frame, FrameOutcome::Collided);
+                    }
+                    Action::EchoTimedOut => {
+                        trace!("Echo timeout");
+                        self.stats.echo_timeouts += 1;
+                        self.hooks.observer().on_event(Event::EchoTimeout);
+                        self.record_own(frame, FrameOutcome::EchoTimeout);
+                    }
+                    Action::StateTimedOut => {
+                        warn!("State timeout");
+                        self.stats.state_timeouts += 1;
+                        self.hooks.observer().on_event(Event::StateTimeout);
+                    }
+                    Action::RestartFrame => self.restart_frame(frame),
+                    Action::ResetTransceiver => self.transceiver.reset(),
+                    Action::Yield => result = Some(Err(nb::Error::WouldBlock)),
                 }
             }
-            ConfirmingSendWithoutErrors => (),
         }
-        nb::Error::WouldBlock
+        result
+    }
+
+    /// Handle sending of bytes on bus, if the bus is clear.
+    fn handle_send<const F: usize>(
+        &mut self,
+        frame: &mut CsmaFrameInProgress<F>,
+    ) -> nb::Error<T::Error> {
+        let input = Input::Poll {
+            now: self.clock.now(),
+            bus_idle: self.transceiver.bus_is_idle(),
+            written: frame.peek_for_send().is_none(),
+        };
+        match self.run(frame, input, |_| ()) {
+            Some(Err(e)) => e,
+            _ => nb::Error::WouldBlock,
+        }
     }
 
     /// Try to send a frame, but the strategy is open to receive a frame as well.
     ///
-    /// Keep polling this function until `SendReceiveResult::SendComplete`.
-    pub fn send_or_receive(
+    /// Keep polling this function until `SendReceiveResult::SendComplete`. Received frames are borrowed from the
+    /// reader of the strategy, such that they are not copied.
+    pub fn send_or_receive<const F: usize>(
         &mut self,
-        frame: &mut CsmaFrameInProgress,
-    ) -> nb::Result<SendReceiveResult, T::Error> {
-        use CsmaStrategyState::*;
+        frame: &mut CsmaFrameInProgress<F>,
+    ) -> nb::Result<SendReceiveResult<FrameRef<'_>>, T::Error> {
+        self.poll_send_borrowed(frame, None)
+    }
 
-        self.transceiver.handle_interrupts();
+    /// Like `send_or_receive`, but hands any received frame to `on_receive` without copying it.
+    ///
+    /// The result of `on_receive` is yielded as `SendReceiveResult::Received`.
+    pub fn send_or_receive_with<const F: usize, U>(
+        &mut self,
+        frame: &mut CsmaFrameInProgress<F>,
+        on_receive: impl FnOnce(FrameRef<'_>) -> U,
+    ) -> nb::Result<SendReceiveResult<U>, T::Error> {
+        self.poll_send(frame, None, on_receive)
+    }
 
-        // Handle incoming bytes during our sending process.
-        match self.transceiver.read() {
-            Ok(b) => match &self.state {
-                Sending | ConfirmingSendWithoutErrors => {
-                    trace!("Received(S) {}", b);
-
-                    // Frame must correspond with the frame we are trying to send.
-                    match frame.feed_as_check(b) {
-                        Ok(true) => {
-                            self.state = WaitForBusIdle;
-                            return Ok(SendReceiveResult::SendComplete);
-                        }
-                        Ok(false) => (), // Continue with sending.
-                        Err(_) => {
-                            // Mismatch between sending and loopback frames.
-                            trace!("Frame error");
-                            self.stats.frame_errors += 1;
+    /// Like `send_or_receive`, but gives up on the frame if it did not start to be sent before `deadline`.
+    ///
+    /// Yields `SendReceiveResult::Expired` in that case, i.e. to drop stale data when the bus is congested.
+    /// A frame that is being sent at the deadline is finished.
+    pub fn send_or_receive_before<const F: usize>(
+        &mut self,
+        frame: &mut CsmaFrameInProgress<F>,
+        deadline: C::Instant,
+    ) -> nb::Result<SendReceiveResult<FrameRef<'_>>, T::Error> {
+        self.poll_send_borrowed(frame, Some(deadline))
+    }
+
+    /// Send the frames in `queue` one after another, in the order described at `TxQueue`.
+    ///
+    /// Receives frames like `send_or_receive_with` meanwhile, or like `receive` if the queue is empty. Keep polling
+    /// this function for as long as frames are to be sent or received.
+    pub fn send_or_receive_queued<const Q: usize, const F: usize, U>(
+        &mut self,
+        queue: &mut TxQueue<Q, F>,
+        on_receive: impl FnOnce(FrameRef<'_>) -> U,
+    ) -> nb::Result<QueueResult<U>, T::Error> {
+        let (handle, frame) = match queue.next_mut() {
+            Some(next) => next,
+            None => {
+                return self
+                    .receive()
+                    .map(|frame| QueueResult::Received(on_receive(frame)))
+            }
+        };
 
-                            // Reset the current sending frame so that it is resent.
-                            frame.reset();
+        match self.poll_send(frame, None, on_receive)? {
+            SendReceiveResult::SendComplete => {
+                queue.remove(handle);
+                Ok(QueueResult::Sent(handle))
+            }
+            SendReceiveResult::Received(received) => Ok(QueueResult::Received(received)),
+            // Can not happen without a deadline.
+            SendReceiveResult::Expired => Err(nb::Error::WouldBlock),
+        }
+    }
 
-                            // Forget the current incoming frame.
-                            self.reader.clear();
+    /// Like `poll_send`, but yields a received frame borrowed from the reader.
+    fn poll_send_borrowed<const F: usize>(
+        &mut self,
+        frame: &mut CsmaFrameInProgress<F>,
+        deadline: Option<C::Instant>,
+    ) -> nb::Result<SendReceiveResult<FrameRef<'_>>, T::Error> {
+        Ok(match self.poll_send(frame, deadline, |_| ())? {
+            SendReceiveResult::SendComplete => SendReceiveResult::SendComplete,
+            SendReceiveResult::Expired => SendReceiveResult::Expired,
+            // The reader holds on to the frame until it is fed again.
+            SendReceiveResult::Received(()) => {
+                SendReceiveResult::Received(unwrap!(self.reader.last_frame()))
+            }
+        })
+    }
 
-                            // Impossible to lead to a frame.
-                            let _ = self.reader.feed(b);
+    fn poll_send<const F: usize, U>(
+        &mut self,
+        frame: &mut CsmaFrameInProgress<F>,
+        deadline: Option<C::Instant>,
+        on_receive: impl FnOnce(FrameRef<'_>) -> U,
+    ) -> nb::Result<SendReceiveResult<U>, T::Error> {
+        self.transceiver.handle_interrupts();
 
-                            // Wait for the error to clear and the bus to be reset again.
-                            self.state = WaitForBusIdle;
-                            return nb::Result::Err(nb::Error::WouldBlock);
-                        }
-                    }
-                }
-                _ => {
-                    trace!("Received(R) {}", b);
-                    self.state = WaitForBusIdle;
+        if self.handle_state_timeout(frame) {
+            return nb::Result::Err(nb::Error::WouldBlock);
+        }
 
-                    // The byte that we received is part of a valid frame.
-                    if let ReadResult::FrameOK(incoming_frame) = self.reader.feed(b) {
-                        // The frame that was finished should be the same as the one we are trying to send.
-                        // If so, this indicates that the transceiver has succesfully sent our frame.
+        // Handle incoming bytes during our sending process.
+        let read = self.transceiver.read();
+        if read.is_ok() {
+            self.stats.bytes_received += 1;
+        }
+        self.observe_bus(read.is_ok());
 
-                        // The frame is not sent by us, and thus should be reported back to our caller.
-                        return Ok(SendReceiveResult::Received(unwrap!(
-                            incoming_frame.try_into()
-                        )));
-                    }
+        match read {
+            Ok(b) => {
+                if let Some(result) = self.handle_byte(frame, b, on_receive) {
+                    return result;
                 }
-            },
+            }
             Err(nb::Error::WouldBlock) => {
-                // Do nothing, proceed to handle_send.
+                if self.handle_echo_timeout(frame) {
+                    return nb::Result::Err(nb::Error::WouldBlock);
+                }
+
+                // Otherwise, proceed to handle_send.
             }
             Err(nb::Error::Other(ReadError::FrameError)) => {
-                trace!("Frame error");
-                self.stats.frame_errors += 1;
-
-                // Reset the current sending frame so that it is resent.
-                frame.reset();
-
-                // Forget the current incoming frame.
-                self.reader.clear();
-
-                // Wait for the error to clear and the bus to be reset again.
-                self.state = WaitForBusIdle;
+                self.handle_frame_error(frame);
                 return nb::Result::Err(nb::Error::WouldBlock);
             }
             Err(nb::Error::Other(ReadError::UnderlyingError(e))) => {
@@ -363,28 +1231,93 @@ impl<T: Transceiver, C: Clock, R: RngCore, CONF: Config<C>> CsmaStrategy<T, C, R
             }
         }
 
+        if deadline.is_some_and(|deadline| self.clock.now() >= deadline)
+            && !self.state.kind().is_transmitting()
+        {
+            trace!("Frame expired");
+            self.stats.frames_expired += 1;
+            frame.reset();
+            return Ok(SendReceiveResult::Expired);
+        }
+
         nb::Result::Err(self.handle_send(frame))
     }
 
-    pub fn receive(&mut self) -> nb::Result<FrameRef<'_>, T::Error> {
-        self.transceiver.handle_interrupts();
-
-        match self.transceiver.read() {
-            Ok(b) => match self.reader.feed(b) {
-                ReadResult::FrameOK(fr) => Ok(fr),
-                _ => nb::Result::Err(nb::Error::WouldBlock),
-            },
+    /// Give up on the current state if we have been in it for too long, such that `frame` is sent again.
+    ///
+    /// Yields whether the state timed out.
+    fn handle_state_timeout<const F: usize>(&mut self, frame: &mut CsmaFrameInProgress<F>) -> bool {
+        self.state_timed_out() && self.run(frame, Input::StateTimeout, |_| ()).is_some()
+    }
+
+    /// Give up on sending `frame` if it is not looped back in time, such that it is sent again.
+    ///
+    /// Yields whether the echo timed out.
+    fn handle_echo_timeout<const F: usize>(&mut self, frame: &mut CsmaFrameInProgress<F>) -> bool {
+        self.echo_timed_out(frame) && self.run(frame, Input::EchoTimeout, |_| ()).is_some()
+    }
+
+    /// Handle a byte read from the bus while we are trying to send `frame`.
+    ///
+    /// Yields the result of polling if we are done for now, or `None` if sending should proceed.
+    fn handle_byte<const F: usize, U>(
+        &mut self,
+        frame: &mut CsmaFrameInProgress<F>,
+        b: u8,
+        on_receive: impl FnOnce(FrameRef<'_>) -> U,
+    ) -> Option<nb::Result<SendReceiveResult<U>, T::Error>> {
+        trace!("Received {}", b);
+        self.run(frame, Input::Received(b), on_receive)
+    }
+
+    /// Handle a broken frame on the bus while we are trying to send `frame`, such that it is sent again.
+    fn handle_frame_error<const F: usize>(&mut self, frame: &mut CsmaFrameInProgress<F>) {
+        self.run(frame, Input::FrameError, |_| ());
+    }
+
+    pub fn receive(&mut self) -> nb::Result<FrameRef<'_>, T::Error> {
+        self.receive_verbose().map_err(|e| match e {
+            nb::Error::Other(ReceiveError::UnderlyingError(e)) => nb::Error::Other(e),
+            nb::Error::Other(ReceiveError::Dropped(_)) | nb::Error::WouldBlock => {
+                nb::Error::WouldBlock
+            }
+        })
+    }
+
+    /// Like `receive`, but yields `ReceiveError::Dropped` with the reason whenever an incoming frame is dropped.
+    pub fn receive_verbose(&mut self) -> nb::Result<FrameRef<'_>, ReceiveError<T::Error>> {
+        self.transceiver.handle_interrupts();
+
+        let read = self.transceiver.read();
+        self.observe_bus(read.is_ok());
+        match read {
+            Ok(b) => {
+                self.stats.bytes_received += 1;
+                match self.feed_reader(b) {
+                    Ok(Some(fr)) => Ok(fr),
+                    Ok(None) => nb::Result::Err(nb::Error::WouldBlock),
+                    Err(reason) => nb::Result::Err(nb::Error::Other(ReceiveError::Dropped(reason))),
+                }
+            }
             Err(nb::Error::Other(ReadError::FrameError)) => {
                 self.stats.frame_errors += 1;
+                Self::note_error(
+                    &self.config,
+                    &mut self.transceiver,
+                    &mut self.stats,
+                    &mut self.consecutive_errors,
+                );
 
                 // Forget the current incoming frame.
                 self.reader.clear();
 
                 // Wait for the error to clear and the bus to be reset again.
-                nb::Result::Err(nb::Error::WouldBlock)
+                nb::Result::Err(nb::Error::Other(ReceiveError::Dropped(
+                    DropReason::FrameError,
+                )))
             }
             Err(nb::Error::Other(ReadError::UnderlyingError(e))) => {
-                nb::Result::Err(nb::Error::Other(e))
+                nb::Result::Err(nb::Error::Other(ReceiveError::UnderlyingError(e)))
             }
             Err(nb::Error::WouldBlock) => nb::Result::Err(nb::Error::WouldBlock),
         }
@@ -395,10 +1328,983 @@ impl<T: Transceiver, C: Clock, R: RngCore, CONF: Config<C>> CsmaStrategy<T, C, R
     }
 }
 
-impl<T: Transceiver, C: Clock + Debug, R: RngCore, CONF: Config<C>> core::fmt::Debug
-    for CsmaStrategy<T, C, R, CONF>
+impl<
+        T: Transceiver,
+        C: Clock + Debug,
+        R: RngCore,
+        const N: usize,
+        const D: usize,
+        K: Hooks<C>,
+        const H: usize,
+        const BUS: u8,
+    > core::fmt::Debug for CsmaStrategy<T, C, R, N, D, K, H, BUS>
 {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         self.state.fmt(f)
     }
 }
+
+/// The earliest of two instants, if any.
+fn earliest<I: PartialOrd>(a: Option<I>, b: Option<I>) -> Option<I> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(if b < a { b } else { a }),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::cell::Cell;
+
+    use kiri_protocol::{Address, FrameBuilder, Writer};
+
+    use super::*;
+
+    struct TestClock(Cell<u64>);
+
+    impl Clock for &TestClock {
+        type Instant = u64;
+        type Duration = u64;
+
+        fn now(&self) -> u64 {
+            self.0.get()
+        }
+    }
+
+    struct TestConfig;
+
+    impl Config<&TestClock> for TestConfig {
+        const BUS_MIN_IDLE_DURATION: u64 = 1;
+        const BUS_MAX_IDLE_DURATION: u64 = 5;
+        const ECHO_BYTE_TIMEOUT: u64 = 10;
+        const ECHO_FRAME_TIMEOUT: u64 = 1000;
+    }
+
+    /// Bus on which nothing is ever received, and that is idle or on which somebody else is talking all the time.
+    struct QuietBus {
+        idle: bool,
+    }
+
+    impl Transceiver for QuietBus {
+        type Error = ();
+
+        fn handle_interrupts(&self) {}
+
+        fn bus_is_idle(&self) -> bool {
+            self.idle
+        }
+
+        fn write(&mut self, _byte: u8) -> nb::Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn read(&mut self) -> nb::Result<u8, ReadError<Self::Error>> {
+            Err(nb::Error::WouldBlock)
+        }
+    }
+
+    /// Bus on which other senders put bytes, and that can be muted until idle.
+    struct Incoming {
+        bus: heapless::Deque<u8, 256>,
+        mutable: bool,
+    }
+
+    impl Transceiver for Incoming {
+        type Error = ();
+
+        fn handle_interrupts(&self) {}
+
+        fn bus_is_idle(&self) -> bool {
+            self.bus.is_empty()
+        }
+
+        fn write(&mut self, _byte: u8) -> nb::Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn read(&mut self) -> nb::Result<u8, ReadError<Self::Error>> {
+            self.bus.pop_front().ok_or(nb::Error::WouldBlock)
+        }
+
+        fn mute_until_idle(&mut self) -> bool {
+            if self.mutable {
+                // Drop the rest of the frame, up to and including the delimiter.
+                while let Some(b) = self.bus.pop_front() {
+                    if b == 0 {
+                        break;
+                    }
+                }
+            }
+            self.mutable
+        }
+    }
+
+    #[test]
+    fn listen_for() {
+        for mutable in [false, true] {
+            let clock = TestClock(Cell::new(0));
+            let rng = rand::rngs::mock::StepRng::new(0, 1);
+            let transceiver = Incoming {
+                bus: heapless::Deque::new(),
+                mutable,
+            };
+            let mut strategy = CsmaStrategy::new::<TestConfig>(transceiver, &clock, rng);
+            assert_eq!(
+                strategy.listen_for(&[Address::new(1); MAX_LISTEN_ADDRESSES + 1]),
+                Err(TooManyAddresses)
+            );
+            strategy
+                .listen_for(&[Address::new(1), Address::group(1)])
+                .unwrap();
+
+            let management = Address::management(1).unwrap();
+            let frames = [
+                (Address::new(3), Address::new(2)),
+                (Address::new(3), Address::broadcast()),
+                (Address::new(3), Address::group(1)),
+                (Address::new(3), Address::group(2)),
+                (Address::new(3), management),
+                (Address::group(1), Address::new(1)),
+                (Address::new(3), Address::new(1)),
+            ];
+            for (src, dst) in frames {
+                let frame = Writer::package(src, dst, b"listen").unwrap();
+                for b in frame.as_slice() {
+                    strategy.transceiver.bus.push_back(*b).unwrap();
+                }
+            }
+
+            let mut received = heapless::Vec::<_, 8>::new();
+            let mut dropped = heapless::Vec::<_, 8>::new();
+            while !strategy.transceiver.bus.is_empty() {
+                match strategy.receive_verbose() {
+                    Ok(frame) => received.push(frame.header.address_dst).unwrap(),
+                    Err(nb::Error::Other(ReceiveError::Dropped(reason))) => {
+                        dropped.push(reason).unwrap()
+                    }
+                    Err(_) => (),
+                }
+            }
+            assert_eq!(
+                received,
+                [
+                    Address::broadcast(),
+                    Address::group(1),
+                    management,
+                    Address::new(1)
+                ]
+            );
+            assert_eq!(
+                dropped,
+                [DropReason::Skipped, DropReason::Skipped, DropReason::Source]
+            );
+            assert_eq!(strategy.stats().frames_skipped, 2);
+            assert_eq!(strategy.stats().frames_received, 4);
+        }
+    }
+
+    #[test]
+    fn drop_group_source() {
+        let clock = TestClock(Cell::new(0));
+        let rng = rand::rngs::mock::StepRng::new(0, 1);
+        let transceiver = Incoming {
+            bus: heapless::Deque::new(),
+            mutable: false,
+        };
+        let mut strategy = CsmaStrategy::new::<TestConfig>(transceiver, &clock, rng);
+
+        let sources = [
+            Address::broadcast(),
+            Address::group(1),
+            Address::management(1).unwrap(),
+            Address::new(0xF0000001),
+            Address::new(3),
+        ];
+        for src in sources {
+            let frame = Writer::package(src, Address::new(1), b"source").unwrap();
+            for b in frame.as_slice() {
+                strategy.transceiver.bus.push_back(*b).unwrap();
+            }
+        }
+
+        let mut received = heapless::Vec::<_, 8>::new();
+        let mut dropped = 0;
+        while !strategy.transceiver.bus.is_empty() {
+            match strategy.receive_verbose() {
+                Ok(frame) => received.push(frame.header.address_src).unwrap(),
+                Err(nb::Error::Other(ReceiveError::Dropped(DropReason::Source))) => dropped += 1,
+                Err(_) => (),
+            }
+        }
+        assert_eq!(received, [Address::new(0xF0000001), Address::new(3)]);
+        assert_eq!(dropped, 3);
+    }
+
+    /// Bus on which other senders put bytes, of which `None` arrives as a framing error.
+    ///
+    /// Counts how often the transceiver is asked to recover.
+    struct Noisy {
+        bus: heapless::Deque<Option<u8>, 2048>,
+        recoveries: u32,
+    }
+
+    impl Transceiver for Noisy {
+        type Error = ();
+
+        fn handle_interrupts(&self) {}
+
+        fn bus_is_idle(&self) -> bool {
+            self.bus.is_empty()
+        }
+
+        fn write(&mut self, _byte: u8) -> nb::Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn read(&mut self) -> nb::Result<u8, ReadError<Self::Error>> {
+            match self.bus.pop_front() {
+                Some(Some(b)) => Ok(b),
+                Some(None) => Err(nb::Error::Other(ReadError::FrameError)),
+                None => Err(nb::Error::WouldBlock),
+            }
+        }
+
+        fn recover(&mut self) {
+            self.recoveries += 1;
+        }
+    }
+
+    #[test]
+    fn drop_reasons() {
+        let clock = TestClock(Cell::new(0));
+        let rng = rand::rngs::mock::StepRng::new(0, 1);
+        let transceiver = Noisy {
+            bus: heapless::Deque::new(),
+            recoveries: 0,
+        };
+        let mut strategy = CsmaStrategy::new::<TestConfig>(transceiver, &clock, rng);
+        strategy.listen_for(&[Address::new(1)]).unwrap();
+
+        let (src, dst) = (Address::new(3), Address::new(1));
+        let valid = Writer::package(src, dst, b"valid").unwrap();
+        let mut broken = valid.clone();
+        // Breaks the contents, but not the header.
+        let last = broken.0.len() - 4;
+        broken.0[last] ^= 0x01;
+        // The marker arrives before the last COBS block is complete.
+        let (_, truncated) = valid.as_slice().split_last().unwrap();
+        let truncated = &truncated[..truncated.len() - 1];
+        // Nothing but the magic word, which is the start of the first COBS block.
+        let encoded = valid.as_slice();
+        assert!(encoded[0] >= 3);
+        let magic = [3, encoded[1], encoded[2], 0];
+        let other_bus = FrameBuilder::new(src, dst).bus(2).build().unwrap();
+        let oversized =
+            Writer::package_extended(src, dst, &[1; kiri_protocol::MAX_MESSAGE_LEN + 100]).unwrap();
+        let sequenced = Writer::package_with_sequence(src, dst, 1, b"sequenced").unwrap();
+        let skipped = Writer::package(src, Address::new(2), b"skipped").unwrap();
+        let source = Writer::package(Address::group(1), dst, b"source").unwrap();
+
+        strategy.transceiver.bus.push_back(None).unwrap();
+        let frames: [&[u8]; 10] = [
+            oversized.as_slice(),
+            truncated,
+            &[0],
+            other_bus.as_slice(),
+            &magic,
+            broken.as_slice(),
+            sequenced.as_slice(),
+            sequenced.as_slice(),
+            skipped.as_slice(),
+            source.as_slice(),
+        ];
+        for b in frames.into_iter().flatten() {
+            strategy.transceiver.bus.push_back(Some(*b)).unwrap();
+        }
+
+        let mut received = 0;
+        let mut dropped = heapless::Vec::<_, 16>::new();
+        while !strategy.transceiver.bus.is_empty() {
+            match strategy.receive_verbose() {
+                Ok(_) => received += 1,
+                Err(nb::Error::Other(ReceiveError::Dropped(reason))) => {
+                    dropped.push(reason).unwrap()
+                }
+                Err(_) => (),
+            }
+        }
+        // Headers of this layout unpack from any bits, such that `DropReason::Header` does not occur.
+        assert_eq!(
+            dropped,
+            [
+                DropReason::FrameError,
+                DropReason::Overflow,
+                DropReason::Cobs,
+                DropReason::Magic,
+                DropReason::Size,
+                DropReason::Checksum,
+                DropReason::Duplicate,
+                DropReason::Skipped,
+                DropReason::Source,
+            ]
+        );
+        assert_eq!(received, 1);
+        assert_eq!(strategy.stats().frame_errors, 1);
+        assert_eq!(strategy.stats().crc_failures, 1);
+        assert_eq!(strategy.stats().duplicates_dropped, 1);
+        assert_eq!(strategy.stats().frames_skipped, 1);
+    }
+
+    #[test]
+    fn recover_after_errors() {
+        let clock = TestClock(Cell::new(0));
+        let rng = rand::rngs::mock::StepRng::new(0, 1);
+        let transceiver = Noisy {
+            bus: heapless::Deque::new(),
+            recoveries: 0,
+        };
+        let mut strategy = CsmaStrategy::new::<TestConfig>(transceiver, &clock, rng);
+        assert_eq!(strategy.config().recover_after_errors, 3);
+        let feed = |strategy: &mut CsmaStrategy<Noisy, &TestClock, _>, bytes: &[Option<u8>]| {
+            for b in bytes {
+                strategy.transceiver.bus.push_back(*b).unwrap();
+            }
+            while !strategy.transceiver.bus.is_empty() {
+                let _ = strategy.receive();
+            }
+        };
+
+        // A frame that is received completely in between starts the count over.
+        let frame = Writer::package(Address::new(3), Address::new(1), b"valid").unwrap();
+        let frame: heapless::Vec<_, 64> = frame.as_slice().iter().map(|b| Some(*b)).collect();
+        feed(&mut strategy, &[None, None]);
+        feed(&mut strategy, &frame);
+        feed(&mut strategy, &[None, None]);
+        assert_eq!(strategy.transceiver.recoveries, 0);
+
+        feed(&mut strategy, &[None]);
+        assert_eq!(strategy.transceiver.recoveries, 1);
+        feed(&mut strategy, &[None; 5]);
+        assert_eq!(strategy.transceiver.recoveries, 2);
+        assert_eq!(strategy.stats().recoveries, 2);
+        assert_eq!(strategy.stats().frame_errors, 10);
+    }
+
+    #[test]
+    fn with_bus() {
+        let clock = TestClock(Cell::new(0));
+        let rng = rand::rngs::mock::StepRng::new(0, 1);
+        let transceiver = Incoming {
+            bus: heapless::Deque::new(),
+            mutable: false,
+        };
+        let mut strategy =
+            CsmaStrategy::new::<TestConfig>(transceiver, &clock, rng).with_bus::<2>();
+
+        let (src, dst) = (Address::new(3), Address::new(1));
+        let frames = [
+            Writer::package(src, dst, b"bus 0").unwrap(),
+            FrameBuilder::new(src, dst)
+                .bus(1)
+                .payload(b"bus 1")
+                .build()
+                .unwrap(),
+            FrameBuilder::new(src, dst)
+                .bus(2)
+                .payload(b"bus 2")
+                .build()
+                .unwrap(),
+        ];
+        for frame in &frames {
+            for b in frame.as_slice() {
+                strategy.transceiver.bus.push_back(*b).unwrap();
+            }
+        }
+
+        let mut received = heapless::Vec::<_, 4>::new();
+        let mut dropped = heapless::Vec::<_, 4>::new();
+        while !strategy.transceiver.bus.is_empty() {
+            match strategy.receive_verbose() {
+                Ok(frame) => received
+                    .push(heapless::Vec::<u8, 8>::from_slice(frame.contents).unwrap())
+                    .unwrap(),
+                Err(nb::Error::Other(ReceiveError::Dropped(reason))) => {
+                    dropped.push(reason).unwrap()
+                }
+                Err(_) => (),
+            }
+        }
+        assert_eq!(received, [&b"bus 2"[..]]);
+        assert_eq!(dropped, [DropReason::Magic, DropReason::Magic]);
+    }
+
+    /// Bus that loops back every byte we send, on which nobody else talks.
+    struct Loopback {
+        bus: heapless::Deque<u8, 256>,
+    }
+
+    impl Transceiver for Loopback {
+        type Error = ();
+
+        fn handle_interrupts(&self) {}
+
+        fn bus_is_idle(&self) -> bool {
+            self.bus.is_empty()
+        }
+
+        fn write(&mut self, byte: u8) -> nb::Result<(), Self::Error> {
+            self.bus.push_back(byte).map_err(|_| nb::Error::WouldBlock)
+        }
+
+        fn read(&mut self) -> nb::Result<u8, ReadError<Self::Error>> {
+            self.bus.pop_front().ok_or(nb::Error::WouldBlock)
+        }
+    }
+
+    #[test]
+    fn send_queued() {
+        let clock = TestClock(Cell::new(0));
+        let rng = rand::rngs::mock::StepRng::new(0, 1);
+        let transceiver = Loopback {
+            bus: heapless::Deque::new(),
+        };
+        let mut strategy = CsmaStrategy::new::<TestConfig>(transceiver, &clock, rng);
+
+        let mut queue = TxQueue::<4>::new();
+        let mut push = |contents: &[u8], priority| {
+            let frame = Writer::package(Address::new(1), Address::new(2), contents).unwrap();
+            queue.push(frame, priority).unwrap()
+        };
+        let telemetry = push(b"telemetry", 0);
+        let canceled = push(b"canceled", 0);
+        let rpc = push(b"rpc", 1);
+        assert!(queue.cancel(canceled));
+
+        let mut sent = heapless::Vec::<_, 4>::new();
+        for now in 0..1000 {
+            clock.0.set(now);
+            match strategy.send_or_receive_queued(&mut queue, |_| ()) {
+                Ok(QueueResult::Sent(handle)) => sent.push(handle).unwrap(),
+                Ok(QueueResult::Received(())) => panic!("received our own frame"),
+                Err(_) => (),
+            }
+        }
+        assert_eq!(sent, [rpc, telemetry]);
+        assert!(queue.is_empty());
+        assert_eq!(strategy.stats().frames_sent, 2);
+    }
+
+    #[test]
+    fn send_or_receive_borrowed() {
+        let clock = TestClock(Cell::new(0));
+        let rng = rand::rngs::mock::StepRng::new(0, 1);
+        let mut transceiver = Loopback {
+            bus: heapless::Deque::new(),
+        };
+        let incoming = Writer::package(Address::new(3), Address::new(1), b"incoming").unwrap();
+        for b in incoming.as_slice() {
+            transceiver.bus.push_back(*b).unwrap();
+        }
+        let mut strategy = CsmaStrategy::new::<TestConfig>(transceiver, &clock, rng);
+
+        let frame = Writer::package(Address::new(1), Address::new(2), b"outgoing").unwrap();
+        let mut frame = CsmaFrameInProgress::new(frame);
+        let mut received = 0;
+        for now in 0..1000 {
+            clock.0.set(now);
+            match strategy.send_or_receive(&mut frame) {
+                Ok(SendReceiveResult::Received(incoming)) => {
+                    assert_eq!(incoming.header.address_src, Address::new(3));
+                    assert_eq!(incoming.contents, b"incoming");
+                    received += 1;
+                }
+                Ok(SendReceiveResult::SendComplete) => break,
+                _ => (),
+            }
+        }
+        assert_eq!(received, 1);
+        assert_eq!(strategy.stats().frames_sent, 1);
+    }
+
+    #[test]
+    fn history() {
+        let clock = TestClock(Cell::new(0));
+        let rng = rand::rngs::mock::StepRng::new(0, 1);
+        let mut transceiver = Loopback {
+            bus: heapless::Deque::new(),
+        };
+        let incoming = Writer::package(Address::new(3), Address::new(1), b"incoming").unwrap();
+        for b in incoming.as_slice().iter().chain(incoming.as_slice()) {
+            transceiver.bus.push_back(*b).unwrap();
+        }
+        let mut strategy =
+            CsmaStrategy::new::<TestConfig>(transceiver, &clock, rng).with_history::<2>();
+
+        let frame = Writer::package(Address::new(1), Address::new(2), b"outgoing").unwrap();
+        let mut frame = CsmaFrameInProgress::new(frame);
+        for now in 0..1000 {
+            clock.0.set(now);
+            if let Ok(SendReceiveResult::SendComplete) = strategy.send_or_receive(&mut frame) {
+                break;
+            }
+        }
+
+        // Only the last frames are kept.
+        let history = strategy.history();
+        assert_eq!(history.len(), 2);
+        let records: heapless::Vec<_, 2> = history
+            .iter()
+            .map(|record| (record.src, record.dst, record.len, record.outcome))
+            .collect();
+        assert_eq!(
+            records,
+            [
+                (Address::new(3), Address::new(1), 8, FrameOutcome::Received),
+                (Address::new(1), Address::new(2), 8, FrameOutcome::Sent),
+            ]
+        );
+        assert!(history.iter().all(|record| record.at > 0));
+    }
+
+    #[test]
+    fn history_of_dropped() {
+        let clock = TestClock(Cell::new(0));
+        let rng = rand::rngs::mock::StepRng::new(0, 1);
+        let mut transceiver = Incoming {
+            bus: heapless::Deque::new(),
+            mutable: false,
+        };
+        let incoming = Writer::package(Address::new(3), Address::new(1), b"incoming").unwrap();
+        let mut broken = incoming.clone();
+        // Breaks the contents, but not the header.
+        let last = broken.0.len() - 4;
+        broken.0[last] ^= 0x01;
+        for b in broken.as_slice().iter().chain(incoming.as_slice()) {
+            transceiver.bus.push_back(*b).unwrap();
+        }
+        let mut strategy =
+            CsmaStrategy::new::<TestConfig>(transceiver, &clock, rng).with_history::<4>();
+        while strategy.receive().is_ok() || !strategy.transceiver.bus.is_empty() {}
+
+        let outcomes: heapless::Vec<_, 4> = strategy
+            .history()
+            .iter()
+            .map(|record| record.outcome)
+            .collect();
+        assert_eq!(
+            outcomes,
+            [
+                FrameOutcome::Dropped(DropReason::Checksum),
+                FrameOutcome::Received,
+            ]
+        );
+    }
+
+    struct GapConfig;
+
+    impl Config<&TestClock> for GapConfig {
+        const BUS_MIN_IDLE_DURATION: u64 = 1;
+        const BUS_MAX_IDLE_DURATION: u64 = 5;
+        const ECHO_BYTE_TIMEOUT: u64 = 10;
+        const ECHO_FRAME_TIMEOUT: u64 = 1000;
+        const POST_SEND_GAP: Option<u64> = Some(20);
+    }
+
+    #[test]
+    fn post_send_gap() {
+        let clock = TestClock(Cell::new(0));
+        let rng = rand::rngs::mock::StepRng::new(0, 1);
+        let transceiver = Loopback {
+            bus: heapless::Deque::new(),
+        };
+        let mut strategy = CsmaStrategy::new::<GapConfig>(transceiver, &clock, rng);
+        let frame = Writer::package(Address::new(1), Address::new(2), b"first").unwrap();
+        let mut frame = CsmaFrameInProgress::new(frame);
+
+        while !matches!(
+            strategy.send_or_receive(&mut frame),
+            Ok(SendReceiveResult::SendComplete)
+        ) {
+            clock.0.set(clock.0.get() + 1);
+        }
+        let sent_at = clock.0.get();
+        assert!(matches!(
+            strategy.state,
+            CsmaStrategyState::PostSendGap { until } if until == sent_at + 20
+        ));
+        assert_eq!(strategy.next_poll_at(), Some(sent_at + 20));
+
+        // The next frame does not contend for the bus before the gap passed.
+        let frame = Writer::package(Address::new(1), Address::new(2), b"second").unwrap();
+        let mut frame = CsmaFrameInProgress::new(frame);
+        for now in sent_at..sent_at + 20 {
+            clock.0.set(now);
+            assert!(strategy.send_or_receive(&mut frame).is_err());
+            assert!(!frame.is_started());
+        }
+
+        while !matches!(
+            strategy.send_or_receive(&mut frame),
+            Ok(SendReceiveResult::SendComplete)
+        ) {
+            clock.0.set(clock.0.get() + 1);
+        }
+        assert_eq!(strategy.stats().frames_sent, 2);
+    }
+
+    struct EchoConfig;
+
+    impl Config<&TestClock> for EchoConfig {
+        const BUS_MIN_IDLE_DURATION: u64 = 1;
+        const BUS_MAX_IDLE_DURATION: u64 = 5;
+        const ECHO_BYTE_TIMEOUT: u64 = 10;
+        const ECHO_FRAME_TIMEOUT: u64 = 100;
+    }
+
+    /// Bus that loops back every byte we send `delay` after it was written, one byte at a time.
+    struct SlowLoopback<'a> {
+        clock: &'a TestClock,
+        delay: u64,
+        bus: heapless::Deque<(u64, u8), 256>,
+    }
+
+    impl Transceiver for SlowLoopback<'_> {
+        type Error = ();
+
+        fn handle_interrupts(&self) {}
+
+        fn bus_is_idle(&self) -> bool {
+            self.bus.is_empty()
+        }
+
+        fn write(&mut self, byte: u8) -> nb::Result<(), Self::Error> {
+            if !self.bus.is_empty() {
+                return Err(nb::Error::WouldBlock);
+            }
+            let at = self.clock.0.get() + self.delay;
+            self.bus
+                .push_back((at, byte))
+                .map_err(|_| nb::Error::WouldBlock)
+        }
+
+        fn read(&mut self) -> nb::Result<u8, ReadError<Self::Error>> {
+            match self.bus.front() {
+                Some((at, _)) if *at <= self.clock.0.get() => Ok(self.bus.pop_front().unwrap().1),
+                _ => Err(nb::Error::WouldBlock),
+            }
+        }
+    }
+
+    #[test]
+    fn echo_timeout() {
+        // Bytes that never loop back time out on the first of them.
+        let clock = TestClock(Cell::new(0));
+        let rng = rand::rngs::mock::StepRng::new(0, 1);
+        let transceiver = QuietBus { idle: true };
+        let mut strategy = CsmaStrategy::new::<EchoConfig>(transceiver, &clock, rng);
+        let frame = Writer::package(Address::new(1), Address::new(2), b"swallowed").unwrap();
+        let len = frame.as_slice().len() as u64;
+        let mut frame = CsmaFrameInProgress::new(frame);
+
+        while strategy.stats().echo_timeouts == 0 {
+            assert!(matches!(
+                strategy.send_or_receive(&mut frame),
+                Err(nb::Error::WouldBlock)
+            ));
+            assert!(clock.0.get() < 100);
+            clock.0.set(clock.0.get() + 1);
+        }
+        assert!(!frame.is_started());
+        assert!(strategy.stats().bytes_sent < len);
+        assert_eq!(strategy.stats().retransmissions, 1);
+        assert_eq!(strategy.stats().frames_sent, 0);
+
+        // Bytes that loop back in time, but too slowly for the frame as a whole, time out on the frame.
+        let clock = TestClock(Cell::new(0));
+        let rng = rand::rngs::mock::StepRng::new(0, 1);
+        let transceiver = SlowLoopback {
+            clock: &clock,
+            delay: 8,
+            bus: heapless::Deque::new(),
+        };
+        let mut strategy = CsmaStrategy::new::<EchoConfig>(transceiver, &clock, rng);
+        let frame = Writer::package(Address::new(1), Address::new(2), b"slowly").unwrap();
+        let mut frame = CsmaFrameInProgress::new(frame);
+
+        while strategy.stats().echo_timeouts == 0 {
+            assert!(strategy.send_or_receive(&mut frame).is_err());
+            assert!(clock.0.get() < 1000);
+            clock.0.set(clock.0.get() + 1);
+        }
+        assert!(!frame.is_started());
+        assert!(strategy.stats().bytes_sent > 1);
+        assert!(clock.0.get() > 100);
+        assert_eq!(strategy.stats().retransmissions, 1);
+        assert_eq!(strategy.stats().frames_sent, 0);
+    }
+
+    /// What an observer was told, without the borrowed state.
+    #[derive(Debug, PartialEq)]
+    enum Observed {
+        State(StateKind),
+        FrameSent,
+        FrameReceived,
+        CollisionDetected,
+        EchoTimeout,
+        StateTimeout,
+        FrameSkipped,
+    }
+
+    /// Observer that records every event.
+    #[derive(Default)]
+    struct Recorder {
+        events: heapless::Vec<Observed, 32>,
+    }
+
+    impl Observer<&TestClock> for Recorder {
+        fn on_event(&mut self, event: Event<'_, &TestClock>) {
+            let observed = match event {
+                Event::StateChanged(state) => Observed::State(state.kind()),
+                Event::FrameSent => Observed::FrameSent,
+                Event::FrameReceived => Observed::FrameReceived,
+                Event::CollisionDetected => Observed::CollisionDetected,
+                Event::EchoTimeout => Observed::EchoTimeout,
+                Event::StateTimeout => Observed::StateTimeout,
+                Event::FrameSkipped => Observed::FrameSkipped,
+            };
+            self.events.push(observed).unwrap();
+        }
+    }
+
+    #[test]
+    fn observer() {
+        let clock = TestClock(Cell::new(0));
+        let rng = rand::rngs::mock::StepRng::new(0, 1);
+        let transceiver = Loopback {
+            bus: heapless::Deque::new(),
+        };
+        let mut strategy = CsmaStrategy::new::<TestConfig>(transceiver, &clock, rng)
+            .with_observer(Recorder::default());
+
+        let frame = Writer::package(Address::new(1), Address::new(2), b"observed").unwrap();
+        let mut frame = CsmaFrameInProgress::new(frame);
+        while !matches!(
+            strategy.send_or_receive(&mut frame),
+            Ok(SendReceiveResult::SendComplete)
+        ) {
+            clock.0.set(clock.0.get() + 1);
+        }
+        assert_eq!(
+            strategy.observer().events,
+            [
+                Observed::State(StateKind::BusIdleCooldown),
+                Observed::State(StateKind::StartSend),
+                Observed::State(StateKind::EnablingDriver),
+                Observed::State(StateKind::Sending),
+                Observed::State(StateKind::ConfirmingSendWithoutErrors),
+                Observed::FrameSent,
+                Observed::State(StateKind::WaitForBusIdle),
+            ]
+        );
+
+        // Frames of others are reported once received, or as soon as they turn out not to be addressed to us.
+        strategy.observer().events.clear();
+        strategy.listen_for(&[Address::new(1)]).unwrap();
+        for dst in [Address::new(1), Address::new(2)] {
+            let frame = Writer::package(Address::new(3), dst, b"observed").unwrap();
+            for b in frame.as_slice() {
+                strategy.transceiver.bus.push_back(*b).unwrap();
+            }
+        }
+        while !strategy.transceiver.bus.is_empty() {
+            let _ = strategy.receive();
+        }
+        assert_eq!(
+            strategy.observer().events,
+            [Observed::FrameReceived, Observed::FrameSkipped]
+        );
+    }
+
+    struct DwellConfig<const RESET: bool>;
+
+    impl<const RESET: bool> Config<&TestClock> for DwellConfig<RESET> {
+        const BUS_MIN_IDLE_DURATION: u64 = 1;
+        const BUS_MAX_IDLE_DURATION: u64 = 5;
+        const ECHO_BYTE_TIMEOUT: u64 = 10;
+        const ECHO_FRAME_TIMEOUT: u64 = 1000;
+        const RESET_ON_STATE_TIMEOUT: bool = RESET;
+
+        fn max_dwell_duration(state: &CsmaStrategyState<&TestClock>) -> Option<u64> {
+            match state {
+                CsmaStrategyState::EnablingDriver => Some(20),
+                _ => None,
+            }
+        }
+    }
+
+    /// Idle bus of which the driver never becomes ready, counting how often the transceiver is reset.
+    struct Wedged {
+        resets: u32,
+    }
+
+    impl Transceiver for Wedged {
+        type Error = ();
+
+        fn handle_interrupts(&self) {}
+
+        fn bus_is_idle(&self) -> bool {
+            true
+        }
+
+        fn write(&mut self, _byte: u8) -> nb::Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn read(&mut self) -> nb::Result<u8, ReadError<Self::Error>> {
+            Err(nb::Error::WouldBlock)
+        }
+
+        fn start_transmit(&mut self) -> nb::Result<(), Self::Error> {
+            Err(nb::Error::WouldBlock)
+        }
+
+        fn reset(&mut self) {
+            self.resets += 1;
+        }
+    }
+
+    /// How often a strategy configured by `CONF` times out and resets while waiting for a wedged driver for `ticks`.
+    fn wedged<CONF: for<'a> Config<&'a TestClock>>(clock: &TestClock, ticks: u64) -> (u64, u32) {
+        let rng = rand::rngs::mock::StepRng::new(0, 1);
+        let transceiver = Wedged { resets: 0 };
+        let mut strategy = CsmaStrategy::new::<CONF>(transceiver, clock, rng);
+        let frame = Writer::package(Address::new(1), Address::new(2), b"wedged").unwrap();
+        let mut frame = CsmaFrameInProgress::new(frame);
+        for _ in 0..ticks {
+            assert!(strategy.send_or_receive(&mut frame).is_err());
+            clock.0.set(clock.0.get() + 1);
+        }
+        (strategy.stats().state_timeouts, strategy.transceiver.resets)
+    }
+
+    #[test]
+    fn reset_on_state_timeout() {
+        let (timeouts, resets) = wedged::<DwellConfig<true>>(&TestClock(Cell::new(0)), 100);
+        assert!(timeouts >= 2);
+        assert_eq!(resets as u64, timeouts);
+
+        // Without resetting, the strategy only starts over.
+        let (timeouts, resets) = wedged::<DwellConfig<false>>(&TestClock(Cell::new(0)), 100);
+        assert!(timeouts >= 2);
+        assert_eq!(resets, 0);
+    }
+
+    /// Bus on which the third byte we send is overwritten by another sender.
+    #[derive(Default)]
+    struct Collision {
+        written: heapless::Vec<u8, 256>,
+        bus: heapless::Deque<u8, 256>,
+    }
+
+    impl Transceiver for Collision {
+        type Error = ();
+
+        fn handle_interrupts(&self) {}
+
+        fn bus_is_idle(&self) -> bool {
+            self.bus.is_empty()
+        }
+
+        fn write(&mut self, byte: u8) -> nb::Result<(), Self::Error> {
+            let echo = match self.written.len() {
+                2 => !byte,
+                _ => byte,
+            };
+            self.written.push(byte).unwrap();
+            self.bus.push_back(echo).map_err(|_| nb::Error::WouldBlock)
+        }
+
+        fn read(&mut self) -> nb::Result<u8, ReadError<Self::Error>> {
+            self.bus.pop_front().ok_or(nb::Error::WouldBlock)
+        }
+    }
+
+    struct JamConfig;
+
+    impl Config<&TestClock> for JamConfig {
+        const BUS_MIN_IDLE_DURATION: u64 = 1;
+        const BUS_MAX_IDLE_DURATION: u64 = 5;
+        const ECHO_BYTE_TIMEOUT: u64 = 10;
+        const ECHO_FRAME_TIMEOUT: u64 = 1000;
+        const JAM_LEN: u8 = 2;
+    }
+
+    #[test]
+    fn jam_after_collision() {
+        let clock = TestClock(Cell::new(0));
+        let rng = rand::rngs::mock::StepRng::new(0, 1);
+        let mut strategy = CsmaStrategy::new::<JamConfig>(Collision::default(), &clock, rng);
+        let frame = Writer::package(Address::new(1), Address::new(2), b"collide").unwrap();
+        let mut frame = CsmaFrameInProgress::new(frame);
+
+        while strategy.stats().collisions == 0 {
+            assert!(strategy.send_or_receive(&mut frame).is_err());
+            clock.0.set(clock.0.get() + 1);
+        }
+        assert!(!frame.is_started());
+
+        // No more bytes of our frame are sent, only the jam.
+        while !matches!(strategy.state, CsmaStrategyState::WaitForBusIdle) {
+            assert!(strategy.send_or_receive(&mut frame).is_err());
+        }
+        let written = &strategy.transceiver.written;
+        let (sent, jam) = written.split_at(written.len() - 2);
+        assert!(frame.frame.as_slice().starts_with(sent));
+        assert_eq!(jam, [JAM_BYTE; 2]);
+        assert_eq!(strategy.stats().bytes_sent, written.len() as u64);
+    }
+
+    #[test]
+    fn send_before_deadline() {
+        let clock = TestClock(Cell::new(0));
+        let rng = rand::rngs::mock::StepRng::new(0, 1);
+        let mut strategy = CsmaStrategy::new::<TestConfig>(QuietBus { idle: false }, &clock, rng);
+        let frame = Writer::package(Address::new(1), Address::new(2), b"stale").unwrap();
+        let mut frame = CsmaFrameInProgress::new(frame);
+
+        for now in 0..10 {
+            clock.0.set(now);
+            assert!(matches!(
+                strategy.send_or_receive_before(&mut frame, 10),
+                Err(nb::Error::WouldBlock)
+            ));
+        }
+        clock.0.set(10);
+        assert!(matches!(
+            strategy.send_or_receive_before(&mut frame, 10),
+            Ok(SendReceiveResult::Expired)
+        ));
+        assert_eq!(strategy.stats().frames_expired, 1);
+    }
+
+    #[test]
+    fn next_poll_at() {
+        let clock = TestClock(Cell::new(100));
+        let rng = rand::rngs::mock::StepRng::new(0, 1);
+        let mut strategy = CsmaStrategy::new::<TestConfig>(QuietBus { idle: true }, &clock, rng);
+        let frame = Writer::package(Address::new(1), Address::new(2), b"sleepy").unwrap();
+        let mut frame = CsmaFrameInProgress::new(frame);
+
+        // Nothing to do until the bus becomes idle.
+        assert_eq!(strategy.next_poll_at(), None);
+
+        // Then wait for the backoff to expire.
+        assert!(strategy.send_or_receive(&mut frame).is_err());
+        let ready_at = match strategy.state {
+            CsmaStrategyState::BusIdleCooldown { ready_at, .. } => ready_at,
+            _ => panic!("expected backoff"),
+        };
+        assert_eq!(strategy.next_poll_at(), Some(ready_at));
+    }
+}
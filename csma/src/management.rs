@@ -0,0 +1,832 @@
+//! Management messages that every node answers by itself, i.e. to monitor the health of all nodes on a bus.
+//!
+//! Management frames carry the `options::MANAGEMENT` option with the kind of message as its value, such that
+//! they can not be confused with the payloads of the application. Nodes hand all received frames to a
+//! `Responder`, which answers a `Message::HealthQuery` with the `Stats` of their strategy, and a
+//! `Message::EchoRequest` with the same sequence number and data, i.e. to measure the round-trip time.
+//! A `Message::HistoryQuery` is answered with the last frames the strategy recorded, see `history`.
+//!
+//! To enumerate the nodes on a bus, a host sends a `Message::ScanQuery` for a range of addresses to all nodes.
+//! Every node in the range answers with a `Message::ScanReply` after a delay within the window of the query, such
+//! that the replies are spread out rather than all contending for the bus at once. The delay is derived from the
+//! address and the nonce of the query, so nodes need no random source, and nodes that collided in one round are
+//! spread differently in the next round with another nonce.
+//!
+//! Values are serialized in the wire format of postcard: integers as LEB128 varints, fields in order.
+
+use kiri_protocol::{options::MANAGEMENT, Address, Frame, FrameBuilder, FrameRef, WriteError};
+
+use crate::{
+    history::{FrameHistory, FrameOutcome},
+    Clock, DropReason, Stats,
+};
+
+const KIND_HEALTH_QUERY: u8 = 0x01;
+const KIND_HEALTH_REPORT: u8 = 0x02;
+const KIND_ECHO_REQUEST: u8 = 0x03;
+const KIND_ECHO_REPLY: u8 = 0x04;
+const KIND_HISTORY_QUERY: u8 = 0x05;
+const KIND_HISTORY_REPORT: u8 = 0x06;
+const KIND_SCAN_QUERY: u8 = 0x07;
+const KIND_SCAN_REPLY: u8 = 0x08;
+
+/// How much data an echo request can carry at most.
+pub const MAX_ECHO_DATA_LEN: usize = 64;
+
+/// How many of the most recent frames a history report carries at most.
+pub const MAX_HISTORY_ENTRIES: usize = 8;
+
+/// How long an encoded `Health` can be at most.
+const MAX_HEALTH_LEN: usize = 7 * MAX_VARINT_LEN;
+pub(crate) const MAX_VARINT_LEN: usize = 10;
+/// How long an encoded `HistoryEntry` can be at most.
+const MAX_HISTORY_ENTRY_LEN: usize = 5 * MAX_VARINT_LEN;
+/// How long an encoded `Message::ScanQuery` can be at most.
+const MAX_SCAN_QUERY_LEN: usize = 4 * MAX_VARINT_LEN;
+/// How long an encoded message can be at most.
+const MAX_MESSAGE_LEN: usize = max(
+    max(MAX_HEALTH_LEN, 2 * MAX_VARINT_LEN + MAX_ECHO_DATA_LEN),
+    max(
+        MAX_HISTORY_ENTRIES * MAX_HISTORY_ENTRY_LEN,
+        MAX_SCAN_QUERY_LEN,
+    ),
+);
+
+const fn max(a: usize, b: usize) -> usize {
+    if a > b {
+        a
+    } else {
+        b
+    }
+}
+
+/// Health of a node, as reported in answer to a `Message::HealthQuery`.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Health {
+    pub frame_errors: u64,
+    pub crc_failures: u64,
+    pub collisions: u64,
+    pub echo_timeouts: u64,
+    pub frames_sent: u64,
+    pub frames_received: u64,
+    /// Seconds since the node started.
+    pub uptime: u64,
+}
+
+impl Health {
+    pub fn from_stats<D>(stats: &Stats<D>, uptime: u64) -> Self {
+        Self {
+            frame_errors: stats.frame_errors,
+            crc_failures: stats.crc_failures,
+            collisions: stats.collisions,
+            echo_timeouts: stats.echo_timeouts,
+            frames_sent: stats.frames_sent,
+            frames_received: stats.frames_received,
+            uptime,
+        }
+    }
+
+    fn parse(mut data: &[u8]) -> Option<Self> {
+        let mut field = || read_varint(&mut data);
+        let health = Self {
+            frame_errors: field()?,
+            crc_failures: field()?,
+            collisions: field()?,
+            echo_timeouts: field()?,
+            frames_sent: field()?,
+            frames_received: field()?,
+            uptime: field()?,
+        };
+        data.is_empty().then_some(health)
+    }
+
+    fn encode(&self, buf: &mut [u8]) -> usize {
+        let fields = [
+            self.frame_errors,
+            self.crc_failures,
+            self.collisions,
+            self.echo_timeouts,
+            self.frames_sent,
+            self.frames_received,
+            self.uptime,
+        ];
+        fields
+            .iter()
+            .fold(0, |len, field| len + write_varint(*field, &mut buf[len..]))
+    }
+}
+
+/// A frame that a node recorded, as reported in answer to a `Message::HistoryQuery`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HistoryEntry {
+    pub src: Address,
+    pub dst: Address,
+    /// Length of the contents, including any options.
+    pub len: u64,
+    /// Seconds before the report was sent.
+    pub age: u64,
+    pub outcome: FrameOutcome,
+}
+
+impl HistoryEntry {
+    fn parse(data: &mut &[u8]) -> Option<Self> {
+        let mut field = || read_varint(data);
+        Some(Self {
+            src: Address::new(field()?.try_into().ok()?),
+            dst: Address::new(field()?.try_into().ok()?),
+            len: field()?,
+            age: field()?,
+            outcome: decode_outcome(field()?)?,
+        })
+    }
+
+    fn encode(&self, buf: &mut [u8]) -> usize {
+        let fields = [
+            self.src.to_primitive() as u64,
+            self.dst.to_primitive() as u64,
+            self.len,
+            self.age,
+            encode_outcome(self.outcome),
+        ];
+        fields
+            .iter()
+            .fold(0, |len, field| len + write_varint(*field, &mut buf[len..]))
+    }
+}
+
+/// The entries of a `Message::HistoryReport`, oldest first, as they are encoded in the frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HistoryReport<'a> {
+    encoded: &'a [u8],
+}
+
+impl<'a> HistoryReport<'a> {
+    /// Encode the last `MAX_HISTORY_ENTRIES` entries of `history` into `buf`, of which the ages are decided by `age`.
+    fn encode<I: Copy, const H: usize>(
+        history: &FrameHistory<I, H>,
+        age: impl Fn(I) -> u64,
+        buf: &'a mut [u8],
+    ) -> Self {
+        let skip = history.len().saturating_sub(MAX_HISTORY_ENTRIES);
+        let len = history.iter().skip(skip).fold(0, |len, record| {
+            let entry = HistoryEntry {
+                src: record.src,
+                dst: record.dst,
+                len: record.len as u64,
+                age: age(record.at),
+                outcome: record.outcome,
+            };
+            len + entry.encode(&mut buf[len..])
+        });
+        Self {
+            encoded: &buf[..len],
+        }
+    }
+
+    fn parse(encoded: &'a [u8]) -> Option<Self> {
+        let mut data = encoded;
+        while !data.is_empty() {
+            HistoryEntry::parse(&mut data)?;
+        }
+        Some(Self { encoded })
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = HistoryEntry> + 'a {
+        let mut data = self.encoded;
+        // Can not fail, as `parse` checks all entries.
+        core::iter::from_fn(move || HistoryEntry::parse(&mut data))
+    }
+}
+
+fn encode_outcome(outcome: FrameOutcome) -> u64 {
+    match outcome {
+        FrameOutcome::Sent => 0,
+        FrameOutcome::Collided => 1,
+        FrameOutcome::EchoTimeout => 2,
+        FrameOutcome::Received => 3,
+        FrameOutcome::Dropped(reason) => {
+            0x10 + match reason {
+                DropReason::FrameError => 0,
+                DropReason::Overflow => 1,
+                DropReason::Cobs => 2,
+                DropReason::Magic => 3,
+                DropReason::Header => 4,
+                DropReason::Size => 5,
+                DropReason::Checksum => 6,
+                DropReason::Duplicate => 7,
+                DropReason::Skipped => 8,
+                DropReason::Source => 9,
+            }
+        }
+    }
+}
+
+fn decode_outcome(code: u64) -> Option<FrameOutcome> {
+    Some(match code {
+        0 => FrameOutcome::Sent,
+        1 => FrameOutcome::Collided,
+        2 => FrameOutcome::EchoTimeout,
+        3 => FrameOutcome::Received,
+        0x10 => FrameOutcome::Dropped(DropReason::FrameError),
+        0x11 => FrameOutcome::Dropped(DropReason::Overflow),
+        0x12 => FrameOutcome::Dropped(DropReason::Cobs),
+        0x13 => FrameOutcome::Dropped(DropReason::Magic),
+        0x14 => FrameOutcome::Dropped(DropReason::Header),
+        0x15 => FrameOutcome::Dropped(DropReason::Size),
+        0x16 => FrameOutcome::Dropped(DropReason::Checksum),
+        0x17 => FrameOutcome::Dropped(DropReason::Duplicate),
+        0x18 => FrameOutcome::Dropped(DropReason::Skipped),
+        0x19 => FrameOutcome::Dropped(DropReason::Source),
+        _ => return None,
+    })
+}
+
+/// Management message, as carried in a frame with the `options::MANAGEMENT` option.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Message<'a> {
+    /// Ask the destination, or all nodes if multicast, for their `Health`.
+    HealthQuery,
+    HealthReport(Health),
+    /// Ask the destination to send `data` back, of at most `MAX_ECHO_DATA_LEN` bytes.
+    EchoRequest {
+        sequence: u16,
+        data: &'a [u8],
+    },
+    /// Answer to the `EchoRequest` with the same sequence number.
+    EchoReply {
+        sequence: u16,
+        data: &'a [u8],
+    },
+    /// Ask the destination, or all nodes if multicast, for the last frames they recorded.
+    HistoryQuery,
+    HistoryReport(HistoryReport<'a>),
+    /// Ask the nodes with an address within `start..=end` to answer within `window` milliseconds, see `scan_delay`.
+    ScanQuery {
+        start: Address,
+        end: Address,
+        window: u16,
+        nonce: u16,
+    },
+    /// Answer to the `ScanQuery` with the same nonce.
+    ScanReply {
+        nonce: u16,
+    },
+}
+
+impl<'a> Message<'a> {
+    /// The management message in `frame`, if it is a management frame that is understood.
+    pub fn parse(frame: &FrameRef<'a>) -> Option<Self> {
+        let (options, payload) = frame.options().ok()?;
+        match (options.get(MANAGEMENT)?, payload) {
+            ([KIND_HEALTH_QUERY], []) => Some(Message::HealthQuery),
+            ([KIND_HEALTH_REPORT], data) => Health::parse(data).map(Message::HealthReport),
+            ([KIND_ECHO_REQUEST], data) => {
+                let (sequence, data) = parse_echo(data)?;
+                (data.len() <= MAX_ECHO_DATA_LEN).then_some(Message::EchoRequest { sequence, data })
+            }
+            ([KIND_ECHO_REPLY], data) => {
+                let (sequence, data) = parse_echo(data)?;
+                Some(Message::EchoReply { sequence, data })
+            }
+            ([KIND_HISTORY_QUERY], []) => Some(Message::HistoryQuery),
+            ([KIND_HISTORY_REPORT], data) => HistoryReport::parse(data).map(Message::HistoryReport),
+            ([KIND_SCAN_QUERY], mut data) => {
+                let mut field = || read_varint(&mut data)?.try_into().ok();
+                let query = Message::ScanQuery {
+                    start: Address::new(field()?),
+                    end: Address::new(field()?),
+                    window: field()?.try_into().ok()?,
+                    nonce: field()?.try_into().ok()?,
+                };
+                data.is_empty().then_some(query)
+            }
+            ([KIND_SCAN_REPLY], mut data) => {
+                let nonce = read_varint(&mut data)?.try_into().ok()?;
+                data.is_empty().then_some(Message::ScanReply { nonce })
+            }
+            _ => None,
+        }
+    }
+
+    pub fn package(&self, src: Address, dst: Address) -> Result<Frame, WriteError> {
+        let mut buf = [0u8; MAX_MESSAGE_LEN];
+        let (kind, payload) = match self {
+            Message::HealthQuery => (KIND_HEALTH_QUERY, &buf[..0]),
+            Message::HealthReport(health) => {
+                let len = health.encode(&mut buf);
+                (KIND_HEALTH_REPORT, &buf[..len])
+            }
+            Message::EchoRequest { sequence, data } => {
+                let len = encode_echo(*sequence, data, &mut buf).ok_or(WriteError::TooLong)?;
+                (KIND_ECHO_REQUEST, &buf[..len])
+            }
+            Message::EchoReply { sequence, data } => {
+                let len = encode_echo(*sequence, data, &mut buf).ok_or(WriteError::TooLong)?;
+                (KIND_ECHO_REPLY, &buf[..len])
+            }
+            Message::HistoryQuery => (KIND_HISTORY_QUERY, &buf[..0]),
+            Message::HistoryReport(report) => (KIND_HISTORY_REPORT, report.encoded),
+            Message::ScanQuery {
+                start,
+                end,
+                window,
+                nonce,
+            } => {
+                let fields = [
+                    start.to_primitive() as u64,
+                    end.to_primitive() as u64,
+                    *window as u64,
+                    *nonce as u64,
+                ];
+                let len = fields
+                    .iter()
+                    .fold(0, |len, field| len + write_varint(*field, &mut buf[len..]));
+                (KIND_SCAN_QUERY, &buf[..len])
+            }
+            Message::ScanReply { nonce } => {
+                let len = write_varint(*nonce as u64, &mut buf);
+                (KIND_SCAN_REPLY, &buf[..len])
+            }
+        };
+        let frame = FrameBuilder::new(src, dst)
+            .option(MANAGEMENT, &[kind])
+            .payload(payload)
+            .build();
+        frame
+    }
+}
+
+/// The sequence number and data of an echo, the latter prefixed with its length.
+fn parse_echo(mut data: &[u8]) -> Option<(u16, &[u8])> {
+    let sequence = read_varint(&mut data)?.try_into().ok()?;
+    let len = read_varint(&mut data)?;
+    (len == data.len() as u64).then_some((sequence, data))
+}
+
+fn encode_echo(sequence: u16, data: &[u8], buf: &mut [u8]) -> Option<usize> {
+    if data.len() > MAX_ECHO_DATA_LEN {
+        return None;
+    }
+    let mut len = write_varint(sequence as u64, buf);
+    len += write_varint(data.len() as u64, &mut buf[len..]);
+    buf[len..len + data.len()].copy_from_slice(data);
+    Some(len + data.len())
+}
+
+/// How many milliseconds the node at `address` waits before it answers a scan query with `nonce` and `window`.
+///
+/// Spread evenly over the window for consecutive addresses, and different for every nonce.
+pub fn scan_delay(address: Address, nonce: u16, window: u16) -> u16 {
+    if window == 0 {
+        return 0;
+    }
+    let mut x =
+        address.to_primitive().wrapping_mul(0x9E37_79B9) ^ (nonce as u32).wrapping_mul(0x85EB_CA6B);
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7FEB_352D);
+    x ^= x >> 15;
+    (x % window as u32) as u16
+}
+
+/// Whether `frame` is a management frame, which the application should ignore.
+pub fn is_management(frame: &FrameRef) -> bool {
+    matches!(frame.options(), Ok((options, _)) if options.get(MANAGEMENT).is_some())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum ResponderState<I> {
+    Idle,
+    /// A health query of `to` was received, which is not answered yet.
+    Health {
+        to: Address,
+    },
+    /// An echo request of `to` was received, which is not answered yet.
+    Echo {
+        to: Address,
+        sequence: u16,
+        data: heapless::Vec<u8, MAX_ECHO_DATA_LEN>,
+    },
+    /// A history query of `to` was received, which is not answered yet.
+    History {
+        to: Address,
+    },
+    /// A scan query of `to` was received, which is answered once `delay` milliseconds passed since `since`.
+    Scan {
+        to: Address,
+        nonce: u16,
+        delay: u16,
+        /// When the first answer was asked for, which is when the query was received if polled regularly.
+        since: Option<I>,
+    },
+}
+
+/// Answers the management messages addressed to a node.
+///
+/// Feed it all received frames using `handle`, and send the frame from `answer` whenever there is one.
+pub struct Responder<C: Clock> {
+    address: Address,
+    started_at: C::Instant,
+    /// Converts the time since `started_at` into seconds.
+    as_secs: fn(C::Duration) -> u64,
+    /// Converts durations of the clock into milliseconds, to delay the answers to scan queries.
+    as_millis: Option<fn(C::Duration) -> u64>,
+    state: ResponderState<C::Instant>,
+}
+
+impl<C: Clock> Responder<C> {
+    /// Responder for the node at `address`, which started at `started_at`.
+    ///
+    /// The uptime is reported in seconds, as converted from durations of the clock by `as_secs`.
+    pub fn new(address: Address, started_at: C::Instant, as_secs: fn(C::Duration) -> u64) -> Self {
+        Self {
+            address,
+            started_at,
+            as_secs,
+            as_millis: None,
+            state: ResponderState::Idle,
+        }
+    }
+
+    /// Delay the answers to scan queries as asked, see `scan_delay`, converting durations into milliseconds by `as_millis`.
+    ///
+    /// Without, scan queries are answered right away.
+    pub fn with_scan_delay(mut self, as_millis: fn(C::Duration) -> u64) -> Self {
+        self.as_millis = Some(as_millis);
+        self
+    }
+
+    /// Handle a received frame, yielding whether it was a management frame.
+    ///
+    /// Only the last request is answered, if several arrive before the answer is taken.
+    pub fn handle(&mut self, frame: &FrameRef) -> bool {
+        let dst = frame.header.address_dst;
+        let to = frame.header.address_src;
+        if dst == self.address || dst.is_multicast() {
+            match Message::parse(frame) {
+                Some(Message::HealthQuery) => self.state = ResponderState::Health { to },
+                Some(Message::HistoryQuery) => self.state = ResponderState::History { to },
+                Some(Message::ScanQuery {
+                    start,
+                    end,
+                    window,
+                    nonce,
+                }) if (start.to_primitive()..=end.to_primitive())
+                    .contains(&self.address.to_primitive()) =>
+                {
+                    self.state = ResponderState::Scan {
+                        to,
+                        nonce,
+                        delay: scan_delay(self.address, nonce, window),
+                        since: None,
+                    }
+                }
+                Some(Message::EchoRequest { sequence, data }) => {
+                    self.state = ResponderState::Echo {
+                        to,
+                        sequence,
+                        // Can not fail, as `parse` checks the length.
+                        data: heapless::Vec::from_slice(data).unwrap_or_default(),
+                    }
+                }
+                _ => (),
+            }
+        }
+        is_management(frame)
+    }
+
+    /// Whether a request awaits its answer.
+    pub fn is_pending(&self) -> bool {
+        !matches!(self.state, ResponderState::Idle)
+    }
+
+    /// Take the answer to the last request, if any, given the `stats` of the strategy at `now`.
+    ///
+    /// History queries are answered without any entries, see `answer_with_history`.
+    pub fn answer<D>(
+        &mut self,
+        stats: &Stats<D>,
+        now: C::Instant,
+    ) -> Option<Result<Frame, WriteError>> {
+        self.answer_with_history(stats, &FrameHistory::<C::Instant, 0>::new(), now)
+    }
+
+    /// Like `answer`, but answers history queries with the last entries of `history`, see `CsmaStrategy::history`.
+    pub fn answer_with_history<D, const H: usize>(
+        &mut self,
+        stats: &Stats<D>,
+        history: &FrameHistory<C::Instant, H>,
+        now: C::Instant,
+    ) -> Option<Result<Frame, WriteError>> {
+        match core::mem::replace(&mut self.state, ResponderState::Idle) {
+            ResponderState::Idle => None,
+            ResponderState::Health { to } => {
+                let health = Health::from_stats(stats, (self.as_secs)(now - self.started_at));
+                Some(Message::HealthReport(health).package(self.address, to))
+            }
+            ResponderState::Echo { to, sequence, data } => {
+                let reply = Message::EchoReply {
+                    sequence,
+                    data: &data,
+                };
+                Some(reply.package(self.address, to))
+            }
+            ResponderState::History { to } => {
+                let mut buf = [0u8; MAX_HISTORY_ENTRIES * MAX_HISTORY_ENTRY_LEN];
+                let age = |at| (self.as_secs)(now - at);
+                let report = HistoryReport::encode(history, age, &mut buf);
+                Some(Message::HistoryReport(report).package(self.address, to))
+            }
+            ResponderState::Scan {
+                to,
+                nonce,
+                delay,
+                since,
+            } => {
+                let since = since.unwrap_or(now);
+                match self.as_millis {
+                    Some(as_millis) if as_millis(now - since) < delay as u64 => {
+                        self.state = ResponderState::Scan {
+                            to,
+                            nonce,
+                            delay,
+                            since: Some(since),
+                        };
+                        None
+                    }
+                    _ => Some(Message::ScanReply { nonce }.package(self.address, to)),
+                }
+            }
+        }
+    }
+}
+
+pub(crate) fn write_varint(mut value: u64, buf: &mut [u8]) -> usize {
+    let mut len = 0;
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            buf[len] = byte;
+            return len + 1;
+        }
+        buf[len] = byte | 0x80;
+        len += 1;
+    }
+}
+
+pub(crate) fn read_varint(data: &mut &[u8]) -> Option<u64> {
+    let mut value = 0u64;
+    for (i, byte) in data.iter().enumerate().take(MAX_VARINT_LEN) {
+        value |= ((byte & 0x7F) as u64).checked_shl(7 * i as u32)?;
+        if byte & 0x80 == 0 {
+            *data = &data[i + 1..];
+            return Some(value);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use kiri_protocol::{Reader, Writer};
+
+    use super::*;
+
+    struct TestClock;
+
+    impl Clock for TestClock {
+        type Instant = u64;
+        type Duration = u64;
+
+        fn now(&self) -> u64 {
+            0
+        }
+    }
+
+    fn receive<U>(reader: &mut Reader, frame: &Frame, f: impl FnMut(FrameRef) -> U) -> Option<U> {
+        let mut f = f;
+        frame
+            .as_slice()
+            .iter()
+            .find_map(|b| reader.feed(*b).ok().flatten().map(&mut f))
+    }
+
+    #[test]
+    fn responder_answers_health_query() {
+        let host = Address::new(0x100);
+        let node = Address::new(0x2);
+        let mut reader = Reader::new();
+        let mut responder = Responder::<TestClock>::new(node, 1_000, |ms| ms / 1_000);
+        let stats = Stats::<u64> {
+            frames_sent: 300,
+            collisions: 2,
+            ..Default::default()
+        };
+
+        let application = Writer::package(host, node, &[KIND_HEALTH_QUERY]).unwrap();
+        let query = Message::HealthQuery
+            .package(host, Address::multicast())
+            .unwrap();
+        for (frame, management) in [(&application, false), (&query, true)] {
+            let received = frame
+                .as_slice()
+                .iter()
+                .find_map(|b| reader.feed(*b).ok().flatten().map(|f| responder.handle(&f)));
+            assert_eq!(received, Some(management));
+        }
+
+        assert!(responder.is_pending());
+        let answer = responder.answer(&stats, 61_500).unwrap().unwrap();
+        assert!(responder.answer(&stats, 61_500).is_none());
+        assert_eq!(
+            receive(&mut reader, &answer, |f| match Message::parse(&f) {
+                Some(Message::HealthReport(health)) => Some(health),
+                _ => None,
+            })
+            .flatten(),
+            Some(Health {
+                frames_sent: 300,
+                collisions: 2,
+                uptime: 60,
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn responder_answers_echo_request() {
+        let host = Address::new(0x100);
+        let node = Address::new(0x2);
+        let mut reader = Reader::new();
+        let mut responder = Responder::<TestClock>::new(node, 0, |ms| ms / 1_000);
+        let stats = Stats::<u64>::default();
+
+        let request = Message::EchoRequest {
+            sequence: 300,
+            data: b"ping",
+        };
+        let other = request.package(host, Address::new(0x3)).unwrap();
+        assert_eq!(
+            receive(&mut reader, &other, |f| responder.handle(&f)),
+            Some(true)
+        );
+        assert!(!responder.is_pending());
+
+        let request = request.package(host, node).unwrap();
+        receive(&mut reader, &request, |f| responder.handle(&f));
+        let reply = responder.answer(&stats, 0).unwrap().unwrap();
+        let reply = receive(&mut reader, &reply, |f| {
+            assert_eq!(f.header.address_dst, host);
+            match Message::parse(&f) {
+                Some(Message::EchoReply { sequence, data }) => Some((sequence, data == b"ping")),
+                _ => None,
+            }
+        });
+        assert_eq!(reply.flatten(), Some((300, true)));
+
+        let too_long = Message::EchoRequest {
+            sequence: 0,
+            data: &[0; MAX_ECHO_DATA_LEN + 1],
+        };
+        assert!(too_long.package(host, node).is_err());
+    }
+
+    #[test]
+    fn responder_answers_history_query() {
+        use crate::history::FrameRecord;
+
+        let host = Address::new(0x100);
+        let node = Address::new(0x2);
+        let mut reader = Reader::new();
+        let mut responder = Responder::<TestClock>::new(node, 0, |ms| ms / 1_000);
+        let stats = Stats::<u64>::default();
+
+        let mut history = FrameHistory::<u64, 16>::new();
+        for i in 0..10 {
+            history.record(FrameRecord {
+                src: node,
+                dst: host,
+                len: i,
+                at: i as u64 * 1_000,
+                outcome: FrameOutcome::Sent,
+            });
+        }
+        history.record(FrameRecord {
+            src: host,
+            dst: node,
+            len: 300,
+            at: 10_000,
+            outcome: FrameOutcome::Dropped(DropReason::Checksum),
+        });
+
+        let query = Message::HistoryQuery.package(host, node).unwrap();
+        receive(&mut reader, &query, |f| responder.handle(&f));
+        let report = responder
+            .answer_with_history(&stats, &history, 12_000)
+            .unwrap()
+            .unwrap();
+        let entries = receive(&mut reader, &report, |f| match Message::parse(&f) {
+            Some(Message::HistoryReport(report)) => {
+                report.entries().collect::<heapless::Vec<_, 16>>()
+            }
+            _ => heapless::Vec::new(),
+        })
+        .unwrap();
+
+        // Only the most recent entries fit.
+        assert_eq!(entries.len(), MAX_HISTORY_ENTRIES);
+        assert_eq!((entries[0].len, entries[0].age), (3, 9));
+        assert_eq!(
+            entries.last(),
+            Some(&HistoryEntry {
+                src: host,
+                dst: node,
+                len: 300,
+                age: 2,
+                outcome: FrameOutcome::Dropped(DropReason::Checksum),
+            })
+        );
+
+        // Without history, there is nothing to report.
+        receive(&mut reader, &query, |f| responder.handle(&f));
+        let report = responder.answer(&stats, 12_000).unwrap().unwrap();
+        let empty = receive(&mut reader, &report, |f| match Message::parse(&f) {
+            Some(Message::HistoryReport(report)) => report.entries().count() == 0,
+            _ => false,
+        });
+        assert_eq!(empty, Some(true));
+    }
+
+    #[test]
+    fn responder_answers_scan_query() {
+        let host = Address::new(0x100);
+        let node = Address::new(0x2);
+        let mut reader = Reader::new();
+        let stats = Stats::<u64>::default();
+
+        let query = Message::ScanQuery {
+            start: Address::new(0),
+            end: Address::new(0x3FF),
+            window: 1_000,
+            nonce: 7,
+        };
+        let delay = scan_delay(node, 7, 1_000) as u64;
+        assert!(delay > 0);
+
+        // Answered once the delay passed since the answer was first asked for.
+        let mut responder =
+            Responder::<TestClock>::new(node, 0, |ms| ms / 1_000).with_scan_delay(|ms| ms);
+        let frame = query.package(host, Address::multicast()).unwrap();
+        receive(&mut reader, &frame, |f| responder.handle(&f));
+        assert!(responder.answer(&stats, 5_000).is_none());
+        assert!(responder.answer(&stats, 5_000 + delay - 1).is_none());
+        assert!(responder.is_pending());
+        let reply = responder.answer(&stats, 5_000 + delay).unwrap().unwrap();
+        let reply = receive(&mut reader, &reply, |f| {
+            assert_eq!(f.header.address_dst, host);
+            match Message::parse(&f) {
+                Some(Message::ScanReply { nonce }) => Some(nonce),
+                _ => None,
+            }
+        });
+        assert_eq!(reply.flatten(), Some(7));
+
+        // Without a delay, it is answered right away.
+        let mut responder = Responder::<TestClock>::new(node, 0, |ms| ms / 1_000);
+        receive(&mut reader, &frame, |f| responder.handle(&f));
+        assert!(responder.answer(&stats, 0).is_some());
+
+        // Nodes outside of the range do not answer.
+        let mut responder = Responder::<TestClock>::new(Address::new(0x400), 0, |ms| ms / 1_000);
+        receive(&mut reader, &frame, |f| responder.handle(&f));
+        assert!(!responder.is_pending());
+    }
+
+    #[test]
+    fn scan_delay_spreads_replies() {
+        let delays =
+            |nonce| (0..1024).map(move |address| scan_delay(Address::new(address), nonce, 2_000));
+        assert!(delays(1).all(|delay| delay < 2_000));
+
+        // Most of the slots are used, by other nodes for another nonce.
+        let mut slots = [0u8; 2_000];
+        for delay in delays(1) {
+            slots[delay as usize] += 1;
+        }
+        assert!(slots.iter().filter(|count| **count > 0).count() > 700);
+        assert!(delays(1).zip(delays(2)).filter(|(a, b)| a == b).count() < 10);
+        assert_eq!(scan_delay(Address::new(1), 1, 0), 0);
+    }
+
+    #[test]
+    fn varint_roundtrip() {
+        let mut buf = [0u8; MAX_VARINT_LEN];
+        for (value, len) in [(0, 1), (127, 1), (128, 2), (300, 2), (u64::MAX, 10)] {
+            assert_eq!(write_varint(value, &mut buf), len);
+            let mut data = &buf[..len];
+            assert_eq!(read_varint(&mut data), Some(value));
+            assert!(data.is_empty());
+        }
+        assert_eq!(read_varint(&mut &[0x80, 0x80][..]), None);
+    }
+}
@@ -0,0 +1,271 @@
+//! Keeping `Stats` across reboots in non-volatile storage, see `StatsPersister`.
+//!
+//! Stats are encoded like postcard encodes them, such that host tools can decode a dump of the storage using
+//! `postcard::from_bytes` on a struct with the same fields, without firmware depending on serde.
+
+use core::ops::Add;
+
+use crate::{
+    management::{read_varint, write_varint, MAX_VARINT_LEN},
+    Clock, Stats,
+};
+
+/// How many fields `Stats` consists of.
+const STATS_FIELDS: usize = 15;
+
+/// How many bytes `Stats::encode` needs at most.
+pub const MAX_ENCODED_STATS_LEN: usize = STATS_FIELDS * MAX_VARINT_LEN;
+
+/// Non-volatile storage to keep statistics in, i.e. a page of flash or EEPROM.
+pub trait StatsSink<D> {
+    type Error;
+
+    /// Store `stats`, replacing what was stored before. Use `Stats::encode` to turn them into bytes.
+    fn persist(&mut self, stats: &Stats<D>) -> Result<(), Self::Error>;
+}
+
+impl<D: Copy + Into<u64>> Stats<D> {
+    /// Encode all counters in the order they are declared in, as postcard varints.
+    pub fn encode<'a>(&self, buf: &'a mut [u8; MAX_ENCODED_STATS_LEN]) -> &'a [u8] {
+        let len = self
+            .fields()
+            .iter()
+            .fold(0, |len, field| len + write_varint(*field, &mut buf[len..]));
+        &buf[..len]
+    }
+
+    fn fields(&self) -> [u64; STATS_FIELDS] {
+        [
+            self.frame_errors,
+            self.echo_timeouts,
+            self.frames_sent,
+            self.frames_received,
+            self.bytes_sent,
+            self.bytes_received,
+            self.collisions,
+            self.retransmissions,
+            self.backoff_time.into(),
+            self.crc_failures,
+            self.state_timeouts,
+            self.recoveries,
+            self.duplicates_dropped,
+            self.frames_expired,
+            self.frames_skipped,
+        ]
+    }
+}
+
+impl<D: TryFrom<u64>> Stats<D> {
+    /// Decode stats that were encoded by `Stats::encode`, if they are intact.
+    ///
+    /// Fields are only ever added at the end. Those missing from stats stored by an older firmware are zero.
+    pub fn decode(mut bytes: &[u8]) -> Option<Self> {
+        // Fields are initialised in the order they are written in.
+        let mut next = || match bytes.is_empty() {
+            true => Some(0),
+            false => read_varint(&mut bytes),
+        };
+        Some(Self {
+            frame_errors: next()?,
+            echo_timeouts: next()?,
+            frames_sent: next()?,
+            frames_received: next()?,
+            bytes_sent: next()?,
+            bytes_received: next()?,
+            collisions: next()?,
+            retransmissions: next()?,
+            backoff_time: D::try_from(next()?).ok()?,
+            crc_failures: next()?,
+            state_timeouts: next()?,
+            recoveries: next()?,
+            duplicates_dropped: next()?,
+            frames_expired: next()?,
+            frames_skipped: next()?,
+        })
+    }
+}
+
+impl<D: Copy + Add<Output = D>> Stats<D> {
+    /// Add the counters of `other` to these, i.e. those since boot to those persisted before.
+    pub fn merge(&mut self, other: &Self) {
+        self.frame_errors += other.frame_errors;
+        self.echo_timeouts += other.echo_timeouts;
+        self.frames_sent += other.frames_sent;
+        self.frames_received += other.frames_received;
+        self.bytes_sent += other.bytes_sent;
+        self.bytes_received += other.bytes_received;
+        self.collisions += other.collisions;
+        self.retransmissions += other.retransmissions;
+        self.backoff_time = self.backoff_time + other.backoff_time;
+        self.crc_failures += other.crc_failures;
+        self.state_timeouts += other.state_timeouts;
+        self.recoveries += other.recoveries;
+        self.duplicates_dropped += other.duplicates_dropped;
+        self.frames_expired += other.frames_expired;
+        self.frames_skipped += other.frames_skipped;
+    }
+}
+
+impl<D> Stats<D> {
+    /// All errors that point at a problem with the link, as opposed to contention.
+    pub fn errors(&self) -> u64 {
+        self.frame_errors
+            + self.echo_timeouts
+            + self.crc_failures
+            + self.state_timeouts
+            + self.recoveries
+    }
+}
+
+/// Hands the stats of a strategy to a `StatsSink` every `interval`, or as soon as `threshold` errors occurred.
+///
+/// Persisted stats include those that were persisted before the last reboot, which are handed to `new`. Storage
+/// wears with every write, hence pick an interval of hours rather than seconds.
+pub struct StatsPersister<C: Clock> {
+    baseline: Stats<C::Duration>,
+    interval: C::Duration,
+    threshold: u64,
+    /// When the stats were last persisted, and how many errors they held.
+    last: Option<(C::Instant, u64)>,
+}
+
+impl<C: Clock> StatsPersister<C> {
+    /// Persist on top of `baseline`, i.e. what `Stats::decode` yields from the storage at boot.
+    pub fn new(baseline: Stats<C::Duration>, interval: C::Duration, threshold: u64) -> Self {
+        Self {
+            baseline,
+            interval,
+            threshold,
+            last: None,
+        }
+    }
+
+    /// Persist `stats` since boot if it is time to, yielding whether they were.
+    ///
+    /// Call this periodically, i.e. whenever the strategy is polled. The first call only starts the interval.
+    pub fn poll<S: StatsSink<C::Duration>>(
+        &mut self,
+        now: C::Instant,
+        stats: &Stats<C::Duration>,
+        sink: &mut S,
+    ) -> Result<bool, S::Error> {
+        let errors = self.baseline.errors() + stats.errors();
+        let (at, persisted_errors) = match self.last {
+            Some(last) => last,
+            None => {
+                self.last = Some((now, errors));
+                return Ok(false);
+            }
+        };
+
+        if now < at + self.interval && errors < persisted_errors + self.threshold {
+            return Ok(false);
+        }
+
+        self.persist(now, stats, sink)?;
+        Ok(true)
+    }
+
+    /// Persist `stats` since boot right away, i.e. before a planned reboot.
+    pub fn persist<S: StatsSink<C::Duration>>(
+        &mut self,
+        now: C::Instant,
+        stats: &Stats<C::Duration>,
+        sink: &mut S,
+    ) -> Result<(), S::Error> {
+        let mut total = self.baseline.clone();
+        total.merge(stats);
+        sink.persist(&total)?;
+        self.last = Some((now, total.errors()));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestClock;
+
+    impl Clock for TestClock {
+        type Instant = u64;
+        type Duration = u64;
+
+        fn now(&self) -> u64 {
+            0
+        }
+    }
+
+    #[derive(Default)]
+    struct Flash {
+        stored: heapless::Vec<u8, MAX_ENCODED_STATS_LEN>,
+        writes: u32,
+    }
+
+    impl StatsSink<u64> for Flash {
+        type Error = ();
+
+        fn persist(&mut self, stats: &Stats<u64>) -> Result<(), Self::Error> {
+            let mut buf = [0u8; MAX_ENCODED_STATS_LEN];
+            self.stored = heapless::Vec::from_slice(stats.encode(&mut buf))?;
+            self.writes += 1;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn encode_decode() {
+        let stats = Stats::<u64> {
+            frame_errors: 1,
+            bytes_sent: 300,
+            backoff_time: u64::MAX,
+            frames_skipped: 127,
+            ..Default::default()
+        };
+
+        let mut buf = [0u8; MAX_ENCODED_STATS_LEN];
+        let encoded = stats.encode(&mut buf);
+        #[rustfmt::skip]
+        assert_eq!(encoded, [
+            1, 0, 0, 0, 0xAC, 0x02, 0, 0, 0,
+            0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x01,
+            0, 0, 0, 0, 0, 127,
+        ]);
+        assert_eq!(Stats::<u64>::decode(encoded), Some(stats.clone()));
+
+        // Stats of an older firmware lack the fields that were added since.
+        let old = Stats::<u64>::decode(&encoded[..4]).unwrap();
+        assert_eq!(old.frame_errors, 1);
+        assert_eq!(old.bytes_sent, 0);
+
+        assert_eq!(Stats::<u64>::decode(&[0x80]), None);
+        assert_eq!(Stats::<u8>::decode(encoded), None);
+    }
+
+    #[test]
+    fn persist_periodically() {
+        let baseline = Stats {
+            recoveries: 2,
+            frames_sent: 10,
+            ..Default::default()
+        };
+        let mut persister = StatsPersister::<TestClock>::new(baseline, 1000, 5);
+        let mut flash = Flash::default();
+        let mut stats = Stats::default();
+
+        assert_eq!(persister.poll(0, &stats, &mut flash), Ok(false));
+        stats.frames_sent = 1;
+        assert_eq!(persister.poll(999, &stats, &mut flash), Ok(false));
+        assert_eq!(persister.poll(1000, &stats, &mut flash), Ok(true));
+        let stored = Stats::<u64>::decode(&flash.stored).unwrap();
+        assert_eq!((stored.frames_sent, stored.recoveries), (11, 2));
+
+        // Bursts of errors are persisted right away, in case they end in a watchdog reset.
+        stats.crc_failures = 4;
+        assert_eq!(persister.poll(1001, &stats, &mut flash), Ok(false));
+        stats.crc_failures = 5;
+        assert_eq!(persister.poll(1002, &stats, &mut flash), Ok(true));
+        assert_eq!(persister.poll(1003, &stats, &mut flash), Ok(false));
+        assert_eq!(flash.writes, 2);
+    }
+}
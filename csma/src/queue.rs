@@ -0,0 +1,189 @@
+//! Queue of outgoing frames that several parts of an application share, see `TxQueue`.
+
+use kiri_protocol::{Frame, MAX_FRAME_LEN};
+
+use crate::CsmaFrameInProgress;
+
+/// Refers to a frame in a `TxQueue`, i.e. to cancel it.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct TxHandle(u32);
+
+/// Outcome of `CsmaStrategy::send_or_receive_queued`, where `U` is what a received frame has been turned into.
+pub enum QueueResult<U> {
+    /// The frame of the handle was sent, and has been removed from the queue.
+    Sent(TxHandle),
+    Received(U),
+}
+
+struct Entry<const F: usize> {
+    handle: TxHandle,
+    priority: u8,
+    frame: CsmaFrameInProgress<F>,
+}
+
+/// Up to `N` frames of at most `F` bytes that are waiting to be sent, see `CsmaStrategy::send_or_receive_queued`.
+///
+/// Frames with a higher priority are sent first, and frames of the same priority in the order they were pushed.
+/// A frame of which the first bytes are on the bus is finished first, even if a more important frame was pushed.
+pub struct TxQueue<const N: usize, const F: usize = MAX_FRAME_LEN> {
+    /// In the order they were pushed.
+    entries: heapless::Vec<Entry<F>, N>,
+    next_handle: u32,
+}
+
+impl<const N: usize, const F: usize> TxQueue<N, F> {
+    pub fn new() -> Self {
+        Self {
+            entries: heapless::Vec::new(),
+            next_handle: 0,
+        }
+    }
+
+    /// Queue `frame` with `priority`, where higher is more important.
+    ///
+    /// Yields the frame back if the queue is full.
+    pub fn push(&mut self, frame: Frame<F>, priority: u8) -> Result<TxHandle, Frame<F>> {
+        if self.entries.is_full() {
+            return Err(frame);
+        }
+
+        let handle = TxHandle(self.next_handle);
+        self.next_handle = self.next_handle.wrapping_add(1);
+        // Can not fail, as the queue is not full.
+        let _ = self.entries.push(Entry {
+            handle,
+            priority,
+            frame: CsmaFrameInProgress::new(frame),
+        });
+        Ok(handle)
+    }
+
+    /// Remove the frame of `handle`, yielding whether it was still waiting to be sent.
+    ///
+    /// A frame of which the first bytes are on the bus can not be canceled anymore.
+    pub fn cancel(&mut self, handle: TxHandle) -> bool {
+        match self.position(handle) {
+            Some(i) if !self.entries[i].frame.is_started() => {
+                self.entries.remove(i);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether the frame of `handle` still has to be sent.
+    pub fn contains(&self, handle: TxHandle) -> bool {
+        self.position(handle).is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.entries.is_full()
+    }
+
+    /// Forget all frames, including one that is being sent.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    fn position(&self, handle: TxHandle) -> Option<usize> {
+        self.entries.iter().position(|entry| entry.handle == handle)
+    }
+
+    /// The frame to send next, along with its handle.
+    pub(crate) fn next_mut(&mut self) -> Option<(TxHandle, &mut CsmaFrameInProgress<F>)> {
+        let started = self
+            .entries
+            .iter()
+            .position(|entry| entry.frame.is_started());
+        let i = started.or_else(|| {
+            // The first of the most important frames, as `max_by_key` would yield the last.
+            self.entries
+                .iter()
+                .enumerate()
+                .rev()
+                .max_by_key(|(_, entry)| entry.priority)
+                .map(|(i, _)| i)
+        })?;
+        let entry = &mut self.entries[i];
+        Some((entry.handle, &mut entry.frame))
+    }
+
+    /// Remove the frame of `handle` once it has been sent.
+    pub(crate) fn remove(&mut self, handle: TxHandle) {
+        if let Some(i) = self.position(handle) {
+            self.entries.remove(i);
+        }
+    }
+}
+
+impl<const N: usize, const F: usize> Default for TxQueue<N, F> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use kiri_protocol::{Address, Writer};
+
+    use super::*;
+
+    fn frame(contents: &[u8]) -> Frame {
+        Writer::package(Address::new(1), Address::new(2), contents).unwrap()
+    }
+
+    /// Remove the frames in the order they would be sent, yielding their handles.
+    fn drain<const N: usize>(queue: &mut TxQueue<N>) -> heapless::Vec<TxHandle, N> {
+        let mut order = heapless::Vec::new();
+        while let Some((handle, _)) = queue.next_mut() {
+            order.push(handle).unwrap();
+            queue.remove(handle);
+        }
+        order
+    }
+
+    #[test]
+    fn priority_then_fifo() {
+        let mut queue = TxQueue::<4>::new();
+        let a = queue.push(frame(b"a"), 0).unwrap();
+        let b = queue.push(frame(b"b"), 2).unwrap();
+        let c = queue.push(frame(b"c"), 0).unwrap();
+        let d = queue.push(frame(b"d"), 2).unwrap();
+        assert!(queue.is_full());
+        assert!(queue.push(frame(b"e"), 3).is_err());
+
+        assert_eq!(drain(&mut queue), [b, d, a, c]);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn cancel() {
+        let mut queue = TxQueue::<4>::new();
+        let a = queue.push(frame(b"a"), 0).unwrap();
+        let b = queue.push(frame(b"b"), 1).unwrap();
+        let c = queue.push(frame(b"c"), 0).unwrap();
+
+        assert!(queue.cancel(c));
+        assert!(!queue.cancel(c));
+        assert!(!queue.contains(c));
+
+        // Once started, a frame is finished before more important ones.
+        let (handle, started) = queue.next_mut().unwrap();
+        assert_eq!(handle, b);
+        started.notify_send();
+        assert!(!queue.cancel(b));
+        let d = queue.push(frame(b"d"), 2).unwrap();
+        assert_eq!(queue.next_mut().unwrap().0, b);
+
+        assert!(queue.cancel(a));
+        assert_eq!(drain(&mut queue), [b, d]);
+    }
+}
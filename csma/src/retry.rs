@@ -0,0 +1,155 @@
+//! How often to repeat requests that are not answered, per destination or priority, see `RetryPolicies`.
+
+use kiri_protocol::Address;
+
+/// How often and after how long to repeat a request that is not answered, where `D` is a duration.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy<D> {
+    /// How many times to repeat the request at most, or `None` to keep repeating it until it is answered.
+    pub retries: Option<u32>,
+    /// How long to wait for an answer before repeating the request.
+    pub timeout: D,
+}
+
+impl<D> RetryPolicy<D> {
+    pub fn new(retries: u32, timeout: D) -> Self {
+        Self {
+            retries: Some(retries),
+            timeout,
+        }
+    }
+
+    /// Keep repeating the request until it is answered, i.e. for commands that must arrive eventually.
+    pub fn forever(timeout: D) -> Self {
+        Self {
+            retries: None,
+            timeout,
+        }
+    }
+
+    /// Never repeat the request, i.e. for periodic data that is superseded by the next one anyway.
+    pub fn once(timeout: D) -> Self {
+        Self::new(0, timeout)
+    }
+
+    /// Whether a request that has been repeated `retries` times already may be repeated once more.
+    pub fn may_retry(&self, retries: u32) -> bool {
+        match self.retries {
+            Some(max) => retries < max,
+            None => true,
+        }
+    }
+}
+
+/// Table of `RetryPolicy`s for up to `P` destinations and `P` priorities, on top of a default policy.
+///
+/// The policy of a destination takes precedence over the policy of a priority.
+#[derive(Debug, Clone)]
+pub struct RetryPolicies<D, const P: usize> {
+    default: RetryPolicy<D>,
+    destinations: heapless::LinearMap<Address, RetryPolicy<D>, P>,
+    priorities: heapless::LinearMap<u8, RetryPolicy<D>, P>,
+}
+
+impl<D, const P: usize> RetryPolicies<D, P> {
+    /// Use `default` for requests to which no other policy applies.
+    pub fn new(default: RetryPolicy<D>) -> Self {
+        Self {
+            default,
+            destinations: heapless::LinearMap::new(),
+            priorities: heapless::LinearMap::new(),
+        }
+    }
+
+    pub fn default_policy(&self) -> &RetryPolicy<D> {
+        &self.default
+    }
+
+    pub fn set_default_policy(&mut self, policy: RetryPolicy<D>) {
+        self.default = policy;
+    }
+
+    /// Use `policy` for requests to `dst`, yielding it back if the table is full.
+    pub fn set_destination(
+        &mut self,
+        dst: Address,
+        policy: RetryPolicy<D>,
+    ) -> Result<(), RetryPolicy<D>> {
+        self.destinations
+            .insert(dst, policy)
+            .map(|_| ())
+            .map_err(|(_, policy)| policy)
+    }
+
+    /// Use `policy` for requests of `priority`, see `options::PRIORITY`, yielding it back if the table is full.
+    pub fn set_priority(
+        &mut self,
+        priority: u8,
+        policy: RetryPolicy<D>,
+    ) -> Result<(), RetryPolicy<D>> {
+        self.priorities
+            .insert(priority, policy)
+            .map(|_| ())
+            .map_err(|(_, policy)| policy)
+    }
+
+    /// Use the policy of the priority or the default policy again for requests to `dst`.
+    pub fn remove_destination(&mut self, dst: Address) -> Option<RetryPolicy<D>> {
+        self.destinations.remove(&dst)
+    }
+
+    /// Use the default policy again for requests of `priority`.
+    pub fn remove_priority(&mut self, priority: u8) -> Option<RetryPolicy<D>> {
+        self.priorities.remove(&priority)
+    }
+
+    /// The policy that applies to a request to `dst`, with `priority` if it has one.
+    pub fn policy(&self, dst: Address, priority: Option<u8>) -> &RetryPolicy<D> {
+        self.destinations
+            .get(&dst)
+            .or_else(|| priority.and_then(|priority| self.priorities.get(&priority)))
+            .unwrap_or(&self.default)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn policy_lookup() {
+        let actuator = Address::new(1);
+        let sensor = Address::new(2);
+        let other = Address::new(3);
+
+        let mut policies = RetryPolicies::<u32, 2>::new(RetryPolicy::new(3, 100));
+        policies
+            .set_destination(actuator, RetryPolicy::forever(50))
+            .unwrap();
+        policies
+            .set_destination(sensor, RetryPolicy::once(10))
+            .unwrap();
+        assert_eq!(
+            policies.set_destination(other, RetryPolicy::once(10)),
+            Err(RetryPolicy::once(10))
+        );
+        policies.set_priority(7, RetryPolicy::new(5, 20)).unwrap();
+
+        assert_eq!(
+            policies.policy(actuator, Some(7)),
+            &RetryPolicy::forever(50)
+        );
+        assert_eq!(policies.policy(sensor, None), &RetryPolicy::once(10));
+        assert_eq!(policies.policy(other, Some(7)), &RetryPolicy::new(5, 20));
+        assert_eq!(policies.policy(other, Some(6)), &RetryPolicy::new(3, 100));
+        assert_eq!(policies.policy(other, None), policies.default_policy());
+
+        assert!(policies.remove_destination(sensor).is_some());
+        assert_eq!(policies.policy(sensor, None), &RetryPolicy::new(3, 100));
+
+        assert!(policies.policy(actuator, None).may_retry(u32::MAX));
+        assert!(!RetryPolicy::once(10).may_retry(0));
+        assert!(RetryPolicy::new(2, 10).may_retry(1));
+        assert!(!RetryPolicy::new(2, 10).may_retry(2));
+    }
+}
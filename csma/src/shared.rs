@@ -0,0 +1,105 @@
+//! Share a `CsmaStrategy` between interrupt handlers and the main loop, see `SharedCsma`.
+
+use core::{
+    cell::{Cell, UnsafeCell},
+    marker::PhantomData,
+};
+
+use kiri_protocol::MAX_FRAME_LEN;
+
+use crate::{backoff::UniformBackoff, CsmaStrategy};
+
+/// Runs code without being interrupted by other users of the same data, i.e. with interrupts disabled.
+///
+/// Implement this for your platform, i.e. on top of `cortex_m::interrupt::free`.
+///
+/// # Safety
+/// No two calls of `with` may run `f` at the same time, be it on other threads, in interrupt handlers or on other
+/// cores. `Shared` relies on this to hand out mutable access to its data from `&self`.
+pub unsafe trait CriticalSection {
+    fn with<R>(f: impl FnOnce() -> R) -> R;
+}
+
+/// Data that is only accessed within a `CriticalSection`, such that it can be used from interrupt handlers.
+pub struct Shared<CS: CriticalSection, T> {
+    inner: UnsafeCell<T>,
+    /// Whether the data is in use, to catch it being accessed from within `with`.
+    locked: Cell<bool>,
+    _cs: PhantomData<CS>,
+}
+
+// Safety: the data is only ever accessed within a critical section, and never reentrantly.
+unsafe impl<CS: CriticalSection, T: Send> Sync for Shared<CS, T> {}
+
+impl<CS: CriticalSection, T> Shared<CS, T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            inner: UnsafeCell::new(value),
+            locked: Cell::new(false),
+            _cs: PhantomData,
+        }
+    }
+
+    /// Access the data within a critical section.
+    ///
+    /// Panics when used from within `f`.
+    pub fn with<R>(&self, f: impl FnOnce(&mut T) -> R) -> R {
+        CS::with(|| {
+            assert!(!self.locked.replace(true), "Shared data used reentrantly");
+            // Safety: we are in a critical section, and nobody else holds the data.
+            let result = f(unsafe { &mut *self.inner.get() });
+            self.locked.set(false);
+            result
+        })
+    }
+
+    /// Access the data without a critical section, as nobody else can hold it.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.inner.get_mut()
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner.into_inner()
+    }
+}
+
+/// A `CsmaStrategy` that can be used from both interrupt handlers and the main loop.
+pub type SharedCsma<
+    CS,
+    T,
+    C,
+    R,
+    const N: usize = MAX_FRAME_LEN,
+    const D: usize = 8,
+    K = ((), UniformBackoff),
+    const H: usize = 0,
+    const BUS: u8 = 0,
+> = Shared<CS, CsmaStrategy<T, C, R, N, D, K, H, BUS>>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct NoInterrupts;
+
+    // Safety: the tests use their data on a single thread, without interrupts.
+    unsafe impl CriticalSection for NoInterrupts {
+        fn with<R>(f: impl FnOnce() -> R) -> R {
+            f()
+        }
+    }
+
+    #[test]
+    fn shared_with() {
+        let shared = Shared::<NoInterrupts, _>::new(1u32);
+        assert_eq!(shared.with(|value| core::mem::replace(value, 2)), 1);
+        assert_eq!(shared.into_inner(), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "reentrantly")]
+    fn shared_reentrant() {
+        let shared = Shared::<NoInterrupts, _>::new(1u32);
+        shared.with(|_| shared.with(|_| ()));
+    }
+}
@@ -0,0 +1,376 @@
+//! Split a `CsmaStrategy` into a sender and a receiver, i.e. to receive in an interrupt handler and send from the main loop.
+//!
+//! Both halves share a `CsmaCore`, which they only touch within a `CriticalSection`.
+
+use kiri_protocol::{Frame, FrameOwned, FrameRef};
+use rand::RngCore;
+
+use crate::{
+    shared::{CriticalSection, Shared},
+    Clock, CsmaFrameInProgress, CsmaStrategy, Hooks, ReadError, ReceiveError, SendReceiveResult,
+    Stats, Transceiver,
+};
+
+struct Inner<
+    T: Transceiver,
+    C: Clock,
+    R: RngCore,
+    const N: usize,
+    const D: usize,
+    K: Hooks<C>,
+    const H: usize,
+    const BUS: u8,
+> {
+    strategy: CsmaStrategy<T, C, R, N, D, K, H, BUS>,
+    /// Frame handed to the sender, until it is confirmed to be sent.
+    outgoing: Option<CsmaFrameInProgress<N>>,
+    /// Whether the receiver saw the outgoing frame loop back completely.
+    sent: bool,
+}
+
+/// State shared by a `CsmaSender` and a `CsmaReceiver`, see `CsmaCore::split`.
+pub struct CsmaCore<
+    CS: CriticalSection,
+    T: Transceiver,
+    C: Clock,
+    R: RngCore,
+    const N: usize,
+    const D: usize,
+    K: Hooks<C>,
+    const H: usize = 0,
+    const BUS: u8 = 0,
+> {
+    inner: Shared<CS, Inner<T, C, R, N, D, K, H, BUS>>,
+}
+
+impl<
+        CS: CriticalSection,
+        T: Transceiver,
+        C: Clock,
+        R: RngCore,
+        const N: usize,
+        const D: usize,
+        K: Hooks<C>,
+        const H: usize,
+        const BUS: u8,
+    > CsmaCore<CS, T, C, R, N, D, K, H, BUS>
+{
+    pub fn new(strategy: CsmaStrategy<T, C, R, N, D, K, H, BUS>) -> Self {
+        Self {
+            inner: Shared::new(Inner {
+                strategy,
+                outgoing: None,
+                sent: false,
+            }),
+        }
+    }
+
+    /// Split into a sender and a receiver, which can be used from different contexts.
+    #[allow(clippy::type_complexity)]
+    pub fn split(
+        &mut self,
+    ) -> (
+        CsmaSender<'_, CS, T, C, R, N, D, K, H, BUS>,
+        CsmaReceiver<'_, CS, T, C, R, N, D, K, H, BUS>,
+    ) {
+        (CsmaSender { core: self }, CsmaReceiver { core: self })
+    }
+
+    /// Take back the strategy, dropping any frame that was still being sent.
+    pub fn into_strategy(self) -> CsmaStrategy<T, C, R, N, D, K, H, BUS> {
+        self.inner.into_inner().strategy
+    }
+
+    fn lock<U>(&self, f: impl FnOnce(&mut Inner<T, C, R, N, D, K, H, BUS>) -> U) -> U {
+        self.inner.with(f)
+    }
+}
+
+/// Sending half of a split `CsmaStrategy`, which drives the bus access.
+pub struct CsmaSender<
+    'a,
+    CS: CriticalSection,
+    T: Transceiver,
+    C: Clock,
+    R: RngCore,
+    const N: usize,
+    const D: usize,
+    K: Hooks<C>,
+    const H: usize = 0,
+    const BUS: u8 = 0,
+> {
+    core: &'a CsmaCore<CS, T, C, R, N, D, K, H, BUS>,
+}
+
+impl<
+        CS: CriticalSection,
+        T: Transceiver,
+        C: Clock,
+        R: RngCore,
+        const N: usize,
+        const D: usize,
+        K: Hooks<C>,
+        const H: usize,
+        const BUS: u8,
+    > CsmaSender<'_, CS, T, C, R, N, D, K, H, BUS>
+{
+    /// Start sending `frame`, yielding it back if the previous frame has not been sent yet.
+    pub fn send(&mut self, frame: Frame<N>) -> Result<(), Frame<N>> {
+        self.core.lock(|inner| match inner.outgoing {
+            Some(_) => Err(frame),
+            None => {
+                inner.outgoing = Some(CsmaFrameInProgress::new(frame));
+                Ok(())
+            }
+        })
+    }
+
+    /// Whether a frame is still being sent.
+    pub fn is_busy(&self) -> bool {
+        self.core.lock(|inner| inner.outgoing.is_some())
+    }
+
+    /// When the sender needs to be polled next, at the latest, see `CsmaStrategy::next_poll_at`.
+    pub fn next_poll_at(&self) -> Option<C::Instant> {
+        self.core.lock(|inner| {
+            inner
+                .outgoing
+                .as_ref()
+                .and_then(|_| inner.strategy.next_poll_at())
+        })
+    }
+
+    /// Keep sending the current frame, if any.
+    ///
+    /// Keep polling this function until `Ok`, which means that the frame has been sent, or that there was none.
+    /// The receiver must be polled as well, as it confirms that the frame made it onto the bus.
+    pub fn poll(&mut self) -> nb::Result<(), T::Error> {
+        self.core.lock(|inner| {
+            let Inner {
+                strategy,
+                outgoing,
+                sent,
+            } = inner;
+            let frame = match outgoing {
+                None => return Ok(()),
+                Some(_) if *sent => {
+                    *outgoing = None;
+                    *sent = false;
+                    return Ok(());
+                }
+                Some(frame) => frame,
+            };
+
+            strategy.transceiver.handle_interrupts();
+            if strategy.handle_state_timeout(frame) || strategy.handle_echo_timeout(frame) {
+                return nb::Result::Err(nb::Error::WouldBlock);
+            }
+            nb::Result::Err(strategy.handle_send(frame))
+        })
+    }
+
+    pub fn stats(&self) -> Stats<C::Duration> {
+        self.core.lock(|inner| inner.strategy.stats().clone())
+    }
+}
+
+/// Receiving half of a split `CsmaStrategy`, which handles all incoming bytes.
+pub struct CsmaReceiver<
+    'a,
+    CS: CriticalSection,
+    T: Transceiver,
+    C: Clock,
+    R: RngCore,
+    const N: usize,
+    const D: usize,
+    K: Hooks<C>,
+    const H: usize = 0,
+    const BUS: u8 = 0,
+> {
+    core: &'a CsmaCore<CS, T, C, R, N, D, K, H, BUS>,
+}
+
+impl<
+        CS: CriticalSection,
+        T: Transceiver,
+        C: Clock,
+        R: RngCore,
+        const N: usize,
+        const D: usize,
+        K: Hooks<C>,
+        const H: usize,
+        const BUS: u8,
+    > CsmaReceiver<'_, CS, T, C, R, N, D, K, H, BUS>
+{
+    /// Handle an incoming byte, handing any received frame to `on_receive` without copying it.
+    ///
+    /// Do not use the sender from within `on_receive`, as the core is still in use.
+    pub fn receive_with<U>(
+        &mut self,
+        on_receive: impl FnOnce(FrameRef<'_>) -> U,
+    ) -> nb::Result<U, ReceiveError<T::Error>> {
+        self.core.lock(|inner| {
+            let Inner {
+                strategy,
+                outgoing,
+                sent,
+            } = inner;
+            let frame = match outgoing {
+                Some(frame) if !*sent => frame,
+                _ => return strategy.receive_verbose().map(on_receive),
+            };
+
+            strategy.transceiver.handle_interrupts();
+            let read = strategy.transceiver.read();
+            if read.is_ok() {
+                strategy.stats.bytes_received += 1;
+            }
+
+            match read {
+                Ok(b) => match strategy.handle_byte(frame, b, on_receive) {
+                    Some(Ok(SendReceiveResult::SendComplete)) => {
+                        *sent = true;
+                        nb::Result::Err(nb::Error::WouldBlock)
+                    }
+                    Some(Ok(SendReceiveResult::Received(received))) => Ok(received),
+                    Some(Err(e)) => nb::Result::Err(e.map(ReceiveError::UnderlyingError)),
+                    Some(Ok(SendReceiveResult::Expired)) | None => {
+                        nb::Result::Err(nb::Error::WouldBlock)
+                    }
+                },
+                Err(nb::Error::Other(ReadError::FrameError)) => {
+                    strategy.handle_frame_error(frame);
+                    nb::Result::Err(nb::Error::Other(ReceiveError::Dropped(
+                        crate::DropReason::FrameError,
+                    )))
+                }
+                Err(nb::Error::Other(ReadError::UnderlyingError(e))) => {
+                    nb::Result::Err(nb::Error::Other(ReceiveError::UnderlyingError(e)))
+                }
+                Err(nb::Error::WouldBlock) => nb::Result::Err(nb::Error::WouldBlock),
+            }
+        })
+    }
+
+    /// Handle an incoming byte, yielding any received frame.
+    pub fn receive(&mut self) -> nb::Result<FrameOwned, T::Error> {
+        self.receive_with(|incoming_frame| unwrap!(incoming_frame.try_into()))
+            .map_err(|e| match e {
+                nb::Error::Other(ReceiveError::UnderlyingError(e)) => nb::Error::Other(e),
+                nb::Error::Other(ReceiveError::Dropped(_)) | nb::Error::WouldBlock => {
+                    nb::Error::WouldBlock
+                }
+            })
+    }
+
+    pub fn stats(&self) -> Stats<C::Duration> {
+        self.core.lock(|inner| inner.strategy.stats().clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::cell::Cell;
+
+    use kiri_protocol::{Address, Writer, MAX_FRAME_LEN};
+
+    use super::*;
+    use crate::Config;
+
+    struct NoInterrupts;
+
+    // Safety: the tests use their data on a single thread, without interrupts.
+    unsafe impl CriticalSection for NoInterrupts {
+        fn with<R>(f: impl FnOnce() -> R) -> R {
+            f()
+        }
+    }
+
+    struct TestClock(Cell<u64>);
+
+    impl Clock for &TestClock {
+        type Instant = u64;
+        type Duration = u64;
+
+        fn now(&self) -> u64 {
+            self.0.get()
+        }
+    }
+
+    struct TestConfig;
+
+    impl Config<&TestClock> for TestConfig {
+        const BUS_MIN_IDLE_DURATION: u64 = 1;
+        const BUS_MAX_IDLE_DURATION: u64 = 5;
+        const ECHO_BYTE_TIMEOUT: u64 = 10;
+        const ECHO_FRAME_TIMEOUT: u64 = 1000;
+    }
+
+    /// Bus that loops back every byte written, and on which other senders can put bytes.
+    #[derive(Default)]
+    struct Loopback {
+        bus: heapless::Deque<u8, 256>,
+    }
+
+    impl Transceiver for Loopback {
+        type Error = ();
+
+        fn handle_interrupts(&self) {}
+
+        fn bus_is_idle(&self) -> bool {
+            self.bus.is_empty()
+        }
+
+        fn write(&mut self, byte: u8) -> nb::Result<(), Self::Error> {
+            self.bus.push_back(byte).map_err(|_| nb::Error::WouldBlock)
+        }
+
+        fn read(&mut self) -> nb::Result<u8, ReadError<Self::Error>> {
+            self.bus.pop_front().ok_or(nb::Error::WouldBlock)
+        }
+    }
+
+    #[test]
+    fn split_send_receive() {
+        let clock = TestClock(Cell::new(0));
+        let rng = rand::rngs::mock::StepRng::new(0, 7919);
+        let strategy = CsmaStrategy::new::<TestConfig>(Loopback::default(), &clock, rng);
+        let mut core = CsmaCore::<NoInterrupts, _, _, _, MAX_FRAME_LEN, 8, _>::new(strategy);
+        let (mut sender, mut receiver) = core.split();
+
+        let frame = Writer::package(Address::new(1), Address::new(2), b"split").unwrap();
+        let len = frame.as_slice().len() as u64;
+        assert!(sender.send(frame.clone()).is_ok());
+        assert!(sender.send(frame).is_err());
+        assert!(sender.is_busy());
+
+        for _ in 0..1000 {
+            if sender.poll().is_ok() {
+                break;
+            }
+            assert!(matches!(receiver.receive(), Err(nb::Error::WouldBlock)));
+            clock.0.set(clock.0.get() + 1);
+        }
+        assert!(!sender.is_busy());
+        assert_eq!(sender.stats().frames_sent, 1);
+        assert_eq!(sender.stats().bytes_sent, len);
+        assert_eq!(receiver.stats().bytes_received, len);
+
+        // A frame of another sender is yielded by the receiver.
+        let incoming = Writer::package(Address::new(3), Address::new(1), b"hello").unwrap();
+        let bus = &mut core.inner.get_mut().strategy.transceiver.bus;
+        for b in incoming.as_slice() {
+            bus.push_back(*b).unwrap();
+        }
+        let (_, mut receiver) = core.split();
+        let received = loop {
+            match receiver.receive() {
+                Ok(received) => break received,
+                Err(nb::Error::WouldBlock) => (),
+                Err(nb::Error::Other(())) => panic!(),
+            }
+        };
+        assert_eq!(received.contents, b"hello");
+        assert_eq!(core.into_strategy().stats().frames_received, 1);
+    }
+}
@@ -0,0 +1,90 @@
+//! Clocks counting the ticks of a hardware timer, see `TickClock`.
+//!
+//! Time types like those of `fugit` do not implement `SampleUniform`, and can hence not be used as `Clock::Duration`.
+//! Instead, hand their tick count to a `TickClock`, i.e. `TickClock::<_, 1_000_000>::new(|| Mono::now().ticks())`.
+//! `kiri-rtic` does so for the timers of `rtic-monotonics`.
+
+use crate::Clock;
+
+/// Monotonic tick count of a timer.
+pub trait TickSource {
+    fn ticks(&self) -> u64;
+}
+
+impl<F: Fn() -> u64> TickSource for F {
+    fn ticks(&self) -> u64 {
+        self()
+    }
+}
+
+/// Clock counting the ticks of `S`, which runs at `HZ` ticks per second.
+///
+/// Instants and durations are plain tick counts. Use `millis` and `micros` to express durations in the `Config`.
+#[derive(Debug, Clone, Copy)]
+pub struct TickClock<S: TickSource, const HZ: u32> {
+    source: S,
+}
+
+/// Clock counting milliseconds.
+pub type MillisClock<S> = TickClock<S, 1_000>;
+
+impl<S: TickSource, const HZ: u32> TickClock<S, HZ> {
+    pub const fn new(source: S) -> Self {
+        Self { source }
+    }
+
+    /// Ticks in `ms` milliseconds.
+    pub const fn millis(ms: u64) -> u64 {
+        ms * HZ as u64 / 1_000
+    }
+
+    /// Ticks in `us` microseconds.
+    pub const fn micros(us: u64) -> u64 {
+        us * HZ as u64 / 1_000_000
+    }
+}
+
+impl<S: TickSource, const HZ: u32> Clock for TickClock<S, HZ> {
+    type Instant = u64;
+    type Duration = u64;
+
+    fn now(&self) -> u64 {
+        self.source.ticks()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::cell::Cell;
+
+    use super::*;
+    use crate::{Config, CsmaConfig};
+
+    type TestClock<'a> = TickClock<&'a dyn Fn() -> u64, 32_768>;
+
+    struct TestConfig;
+
+    impl Config<TestClock<'_>> for TestConfig {
+        const BUS_MIN_IDLE_DURATION: u64 = TestClock::micros(500);
+        const BUS_MAX_IDLE_DURATION: u64 = TestClock::millis(2);
+        const ECHO_BYTE_TIMEOUT: u64 = TestClock::millis(10);
+        const ECHO_FRAME_TIMEOUT: u64 = TestClock::millis(100);
+    }
+
+    #[test]
+    fn tick_clock() {
+        assert_eq!(TestClock::millis(1000), 32_768);
+        assert_eq!(TestClock::micros(500), 16);
+        assert_eq!(MillisClock::<fn() -> u64>::millis(5), 5);
+        let config = CsmaConfig::<TestClock>::from_config::<TestConfig>();
+        assert_eq!(config.bus_min_idle, 16);
+        assert_eq!(config.bus_max_idle, 65);
+
+        let ticks = Cell::new(7);
+        let source = || ticks.get();
+        let clock = TestClock::new(&source);
+        assert_eq!(clock.now(), 7);
+        ticks.set(8);
+        assert_eq!(clock.now(), 8);
+    }
+}
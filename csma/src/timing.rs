@@ -0,0 +1,173 @@
+use kiri_protocol::{FrameError, FrameRef, Reader};
+
+use crate::Clock;
+
+/// Amount of buckets in the inter-byte gap histogram.
+///
+/// Bucket `0` counts gaps of at most one tick, bucket `i` counts gaps in `[2^i, 2^(i+1))`.
+/// The last bucket also counts all gaps that are even larger.
+pub const GAP_HISTOGRAM_BUCKETS: usize = 16;
+
+/// Inter-byte timing of a single frame, in clock ticks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameTiming {
+    /// Amount of gaps measured, i.e. one less than the amount of bytes.
+    pub gaps: u32,
+    pub min: u64,
+    pub max: u64,
+    pub mean: u64,
+    pub histogram: [u32; GAP_HISTOGRAM_BUCKETS],
+    /// Whether the frame was decoded correctly.
+    pub ok: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct TimingStats {
+    /// Timing of the last frame that was either completed or errored.
+    pub last_frame: Option<FrameTiming>,
+    /// Largest gap seen in any frame since creation.
+    pub max_gap: u64,
+}
+
+#[derive(Default)]
+struct GapAccumulator {
+    gaps: u32,
+    min: u64,
+    max: u64,
+    sum: u64,
+    histogram: [u32; GAP_HISTOGRAM_BUCKETS],
+}
+
+impl GapAccumulator {
+    fn push(&mut self, gap: u64) {
+        if self.gaps == 0 {
+            self.min = gap;
+        }
+        self.gaps += 1;
+        self.min = self.min.min(gap);
+        self.max = self.max.max(gap);
+        self.sum = self.sum.saturating_add(gap);
+
+        let bucket = (u64::BITS - gap.max(1).leading_zeros() - 1) as usize;
+        self.histogram[bucket.min(GAP_HISTOGRAM_BUCKETS - 1)] += 1;
+    }
+
+    fn finish(&mut self, ok: bool) -> FrameTiming {
+        let acc = core::mem::take(self);
+        FrameTiming {
+            gaps: acc.gaps,
+            min: acc.min,
+            max: acc.max,
+            mean: acc.sum.checked_div(acc.gaps as u64).unwrap_or(0),
+            histogram: acc.histogram,
+            ok,
+        }
+    }
+}
+
+/// Diagnostic wrapper around a `Reader` that measures the time between consecutive bytes of a frame.
+///
+/// Useful to find senders that pause mid-frame. The timing of a frame is available through `stats()`
+/// as soon as the frame is completed or turns out to be broken.
+pub struct TimedReader<C: Clock> {
+    reader: Reader,
+    clock: C,
+    last_byte_at: Option<C::Instant>,
+    current: GapAccumulator,
+    stats: TimingStats,
+}
+
+impl<C: Clock> TimedReader<C>
+where
+    C::Duration: Into<u64>,
+{
+    pub fn new(clock: C) -> Self {
+        Self {
+            reader: Reader::new(),
+            clock,
+            last_byte_at: None,
+            current: GapAccumulator::default(),
+            stats: TimingStats::default(),
+        }
+    }
+
+    pub fn stats(&self) -> &TimingStats {
+        &self.stats
+    }
+
+    /// Clear the reader, discarding the timing of the current frame.
+    pub fn clear(&mut self) {
+        self.reader.clear();
+        self.last_byte_at = None;
+        self.current = GapAccumulator::default();
+    }
+
+    /// Feed a new byte to the underlying reader, recording the gap since the previous byte.
+    pub fn feed(&mut self, byte: u8) -> Result<Option<FrameRef<'_>>, FrameError> {
+        let now = self.clock.now();
+        if let Some(last_byte_at) = self.last_byte_at {
+            self.current.push((now - last_byte_at).into());
+        }
+        self.last_byte_at = Some(now);
+
+        let result = self.reader.feed(byte);
+        let done = match &result {
+            Ok(None) => None,
+            Ok(Some(_)) => Some(true),
+            Err(_) => Some(false),
+        };
+
+        if let Some(ok) = done {
+            let timing = self.current.finish(ok);
+            self.stats.max_gap = self.stats.max_gap.max(timing.max);
+            self.stats.last_frame = Some(timing);
+            self.last_byte_at = None;
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::cell::Cell;
+
+    use kiri_protocol::{Address, Writer};
+
+    use super::*;
+
+    struct TestClock(Cell<u64>);
+
+    impl Clock for &TestClock {
+        type Instant = u64;
+        type Duration = u64;
+
+        fn now(&self) -> u64 {
+            self.0.get()
+        }
+    }
+
+    #[test]
+    fn gaps_of_frame() {
+        let clock = TestClock(Cell::new(0));
+        let frame = Writer::package(Address::new(1), Address::new(2), b"hello").unwrap();
+        let mut reader = TimedReader::new(&clock);
+
+        let (last, init) = frame.as_slice().split_last().unwrap();
+        for b in init {
+            assert_eq!(reader.feed(*b), Ok(None));
+            clock.0.set(clock.0.get() + 2);
+        }
+        clock.0.set(clock.0.get() + 98);
+        assert!(matches!(reader.feed(*last), Ok(Some(_))));
+
+        let timing = reader.stats().last_frame.as_ref().unwrap();
+        assert!(timing.ok);
+        assert_eq!(timing.gaps as usize, frame.as_slice().len() - 1);
+        assert_eq!(timing.min, 2);
+        assert_eq!(timing.max, 100);
+        assert_eq!(timing.histogram[1], timing.gaps - 1);
+        assert_eq!(timing.histogram[6], 1);
+        assert_eq!(reader.stats().max_gap, 100);
+    }
+}
@@ -0,0 +1,930 @@
+//! The state machine of `CsmaStrategy`, as a table of guarded transitions and the pure `step` function that takes
+//! them.
+//!
+//! The strategy feeds all it observes to `step`, and only carries out the `Action`s it yields, such that the
+//! decisions can be tested without a transceiver. It checks every change of its state against `TRANSITIONS` in debug
+//! builds, such that the table can not drift from the implementation. Render it to Graphviz with `Dot` or to PlantUML
+//! with `PlantUml` for documentation, or feed `TRANSITIONS` into a model checker.
+
+use core::fmt::{self, Display};
+
+use crate::{Clock, CsmaConfig, CsmaStrategyState};
+
+/// The states of `CsmaStrategyState`, without the data they carry.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum StateKind {
+    WaitForBusIdle,
+    BusIdleCooldown,
+    StartSend,
+    EnablingDriver,
+    Sending,
+    ConfirmingSendWithoutErrors,
+    PostSendGap,
+    Jamming,
+}
+
+impl StateKind {
+    pub const ALL: [StateKind; 8] = [
+        StateKind::WaitForBusIdle,
+        StateKind::BusIdleCooldown,
+        StateKind::StartSend,
+        StateKind::EnablingDriver,
+        StateKind::Sending,
+        StateKind::ConfirmingSendWithoutErrors,
+        StateKind::PostSendGap,
+        StateKind::Jamming,
+    ];
+
+    /// The state a new strategy starts in.
+    pub const INITIAL: StateKind = StateKind::WaitForBusIdle;
+
+    /// Whether the driver of the transceiver might be enabled.
+    pub fn is_transmitting(&self) -> bool {
+        matches!(
+            self,
+            StateKind::EnablingDriver
+                | StateKind::Sending
+                | StateKind::ConfirmingSendWithoutErrors
+                | StateKind::Jamming
+        )
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            StateKind::WaitForBusIdle => "WaitForBusIdle",
+            StateKind::BusIdleCooldown => "BusIdleCooldown",
+            StateKind::StartSend => "StartSend",
+            StateKind::EnablingDriver => "EnablingDriver",
+            StateKind::Sending => "Sending",
+            StateKind::ConfirmingSendWithoutErrors => "ConfirmingSendWithoutErrors",
+            StateKind::PostSendGap => "PostSendGap",
+            StateKind::Jamming => "Jamming",
+        }
+    }
+}
+
+/// The condition under which a transition is taken.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Guard {
+    /// The transceiver reports the bus to be idle.
+    BusIdle,
+    /// The transceiver reports the bus to be busy.
+    BusBusy,
+    /// The randomized backoff passed while the bus stayed idle.
+    BackoffElapsed,
+    /// The transceiver enabled its driver.
+    DriverEnabled,
+    /// The last byte of our frame was written to the transceiver.
+    FrameWritten,
+    /// Our frame was looped back completely, and `Config::POST_SEND_GAP` is set.
+    EchoedWithGap,
+    /// Our frame was looped back completely, and `Config::POST_SEND_GAP` is not set.
+    Echoed,
+    /// The byte looped back differs from the one we sent, and `Config::JAM_LEN` is set.
+    CollisionWithJam,
+    /// The byte looped back differs from the one we sent, and `Config::JAM_LEN` is not set.
+    Collision,
+    /// A `JAM_BYTE` was written, and more are left to send.
+    JamByteWritten,
+    /// The last `JAM_BYTE` was written.
+    JamWritten,
+    /// The gap after our frame passed.
+    GapElapsed,
+    /// Our frame was not looped back in time.
+    EchoTimeout,
+    /// The strategy stayed in the state for longer than `Config::max_dwell_duration`.
+    StateTimeout,
+    /// A byte of a frame of another node was received.
+    ByteReceived,
+    /// The transceiver reported a broken byte.
+    FrameError,
+}
+
+impl Guard {
+    pub fn description(&self) -> &'static str {
+        match self {
+            Guard::BusIdle => "bus idle",
+            Guard::BusBusy => "bus busy",
+            Guard::BackoffElapsed => "backoff elapsed",
+            Guard::DriverEnabled => "driver enabled",
+            Guard::FrameWritten => "frame written",
+            Guard::EchoedWithGap => "echoed [post-send gap]",
+            Guard::Echoed => "echoed [no post-send gap]",
+            Guard::CollisionWithJam => "collision [jam]",
+            Guard::Collision => "collision [no jam]",
+            Guard::JamByteWritten => "jam byte written",
+            Guard::JamWritten => "jam written",
+            Guard::GapElapsed => "gap elapsed",
+            Guard::EchoTimeout => "echo timeout",
+            Guard::StateTimeout => "state timeout",
+            Guard::ByteReceived => "byte received",
+            Guard::FrameError => "frame error",
+        }
+    }
+}
+
+/// A change from state `from` to state `to`, taken when `guard` holds.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Transition {
+    pub from: StateKind,
+    pub guard: Guard,
+    pub to: StateKind,
+}
+
+const fn t(from: StateKind, guard: Guard, to: StateKind) -> Transition {
+    Transition { from, guard, to }
+}
+
+use Guard::*;
+use StateKind::*;
+
+/// All transitions the strategy may take.
+pub const TRANSITIONS: &[Transition] = &[
+    // Contending for the bus.
+    t(WaitForBusIdle, BusIdle, BusIdleCooldown),
+    t(BusIdleCooldown, BusBusy, WaitForBusIdle),
+    t(BusIdleCooldown, BackoffElapsed, StartSend),
+    t(StartSend, BusBusy, WaitForBusIdle),
+    t(StartSend, BusIdle, EnablingDriver),
+    t(EnablingDriver, BusBusy, WaitForBusIdle),
+    t(EnablingDriver, DriverEnabled, Sending),
+    // Sending and confirming our frame.
+    t(Sending, FrameWritten, ConfirmingSendWithoutErrors),
+    t(Sending, EchoedWithGap, PostSendGap),
+    t(Sending, Echoed, WaitForBusIdle),
+    t(ConfirmingSendWithoutErrors, EchoedWithGap, PostSendGap),
+    t(ConfirmingSendWithoutErrors, Echoed, WaitForBusIdle),
+    t(PostSendGap, GapElapsed, WaitForBusIdle),
+    // Collisions.
+    t(Sending, CollisionWithJam, Jamming),
+    t(Sending, Collision, WaitForBusIdle),
+    t(ConfirmingSendWithoutErrors, CollisionWithJam, Jamming),
+    t(ConfirmingSendWithoutErrors, Collision, WaitForBusIdle),
+    t(Jamming, JamByteWritten, Jamming),
+    t(Jamming, JamWritten, WaitForBusIdle),
+    t(Sending, EchoTimeout, WaitForBusIdle),
+    t(ConfirmingSendWithoutErrors, EchoTimeout, WaitForBusIdle),
+    // Another node took the bus.
+    t(WaitForBusIdle, ByteReceived, WaitForBusIdle),
+    t(BusIdleCooldown, ByteReceived, WaitForBusIdle),
+    t(StartSend, ByteReceived, WaitForBusIdle),
+    t(EnablingDriver, ByteReceived, WaitForBusIdle),
+    t(PostSendGap, ByteReceived, WaitForBusIdle),
+    // Errors, which are handled in all states but while jamming, as the jam causes them.
+    t(WaitForBusIdle, FrameError, WaitForBusIdle),
+    t(BusIdleCooldown, FrameError, WaitForBusIdle),
+    t(StartSend, FrameError, WaitForBusIdle),
+    t(EnablingDriver, FrameError, WaitForBusIdle),
+    t(Sending, FrameError, WaitForBusIdle),
+    t(ConfirmingSendWithoutErrors, FrameError, WaitForBusIdle),
+    t(PostSendGap, FrameError, WaitForBusIdle),
+    // Getting stuck.
+    t(WaitForBusIdle, StateTimeout, WaitForBusIdle),
+    t(BusIdleCooldown, StateTimeout, WaitForBusIdle),
+    t(StartSend, StateTimeout, WaitForBusIdle),
+    t(EnablingDriver, StateTimeout, WaitForBusIdle),
+    t(Sending, StateTimeout, WaitForBusIdle),
+    t(ConfirmingSendWithoutErrors, StateTimeout, WaitForBusIdle),
+    t(PostSendGap, StateTimeout, WaitForBusIdle),
+    t(Jamming, StateTimeout, WaitForBusIdle),
+];
+
+/// Whether `TRANSITIONS` allows going from `from` to `to` when `guard` holds.
+pub fn allows(from: StateKind, guard: Guard, to: StateKind) -> bool {
+    TRANSITIONS.contains(&t(from, guard, to))
+}
+
+/// What the strategy observed, to take a `step` on.
+#[derive(Debug)]
+pub enum Input<C: Clock> {
+    /// Nothing was received. `written` tells whether all of our frame was written to the transceiver.
+    Poll {
+        now: C::Instant,
+        bus_idle: bool,
+        written: bool,
+    },
+    /// A backoff was drawn, following `Action::DrawBackoff`.
+    BackoffDrawn {
+        now: C::Instant,
+        backoff: C::Duration,
+    },
+    /// The transceiver enabled its driver, following `Action::EnableDriver`.
+    DriverEnabled,
+    /// A byte of our frame was written, following `Action::WriteFrame`.
+    FrameByteWritten { written: bool },
+    /// A `JAM_BYTE` was written, following `Action::WriteJam`.
+    JamByteWritten,
+    /// A byte was received.
+    Received(u8),
+    /// The received byte is the next one of our frame, following `Action::CheckEcho`. `complete` if it is the last.
+    Echoed { now: C::Instant, complete: bool },
+    /// The received byte differs from the next one of our frame, following `Action::CheckEcho`.
+    EchoMismatch(u8),
+    /// Our frame was not looped back within `Config::ECHO_BYTE_TIMEOUT` or `Config::ECHO_FRAME_TIMEOUT`.
+    EchoTimeout,
+    /// The strategy stayed in its state for longer than `Config::max_dwell_duration`.
+    StateTimeout,
+    /// The transceiver reported a broken byte.
+    FrameError,
+}
+
+/// What the strategy is to do, in order, as decided by `step`.
+#[derive(Debug)]
+pub enum Action<C: Clock> {
+    /// Move to the state, because the guard holds.
+    Enter(Guard, CsmaStrategyState<C>),
+    /// Draw a backoff from the `BackoffSource`, and step on `Input::BackoffDrawn`.
+    DrawBackoff,
+    /// Count time spent backing off, see `Stats::backoff_time`.
+    CountBackoff(C::Duration),
+    /// Enable the driver of the transceiver, and step on `Input::DriverEnabled` once it is.
+    EnableDriver,
+    /// Disable the driver of the transceiver.
+    DisableDriver,
+    /// Write the next byte of our frame, and step on `Input::FrameByteWritten` once it is.
+    WriteFrame,
+    /// Write a `JAM_BYTE`, and step on `Input::JamByteWritten` once it is.
+    WriteJam,
+    /// Check the received byte against the next byte of our frame, and step on the outcome.
+    CheckEcho(u8),
+    /// Note when our frame started to be sent, for `Config::ECHO_FRAME_TIMEOUT`.
+    StartEchoTimer,
+    /// Note that a byte of our frame was looped back, for `Config::ECHO_BYTE_TIMEOUT`.
+    EchoProgress,
+    /// Forget when our frame started to be sent and was last looped back.
+    StopEchoTimers,
+    /// Forget the frame being received.
+    ClearReader,
+    /// Feed the received byte to the reader, as the start of whatever follows.
+    Discard(u8),
+    /// Feed the received byte to the reader, and hand out the frame it completes. Always the last action.
+    Receive(u8),
+    /// Count our frame as sent, and yield `SendReceiveResult::SendComplete`.
+    Sent,
+    /// Count a frame error.
+    CountFrameError,
+    /// Register a frame error, asking the transceiver to recover if they keep occurring.
+    NoteError,
+    /// Count our frame as collided.
+    Collided,
+    /// Count our frame as not looped back in time.
+    EchoTimedOut,
+    /// Count that the strategy got stuck in its state.
+    StateTimedOut,
+    /// Reset our frame such that it is sent again.
+    RestartFrame,
+    /// Reset the transceiver, see `Config::RESET_ON_STATE_TIMEOUT`.
+    ResetTransceiver,
+    /// Stop polling for now, yielding `WouldBlock`.
+    Yield,
+}
+
+/// The most actions a single `step` yields.
+pub const MAX_ACTIONS: usize = 10;
+
+/// The actions yielded by `step`.
+pub struct Step<C: Clock>(heapless::Vec<Action<C>, MAX_ACTIONS>);
+
+impl<C: Clock> Step<C> {
+    fn push(&mut self, action: Action<C>) {
+        let pushed = self.0.push(action).is_ok();
+        debug_assert!(pushed, "Step exceeds MAX_ACTIONS");
+    }
+
+    fn enter(&mut self, guard: Guard, state: CsmaStrategyState<C>) {
+        self.push(Action::Enter(guard, state));
+    }
+
+    /// Stop transmitting, if we were, and move to `state`.
+    fn stop(&mut self, from: StateKind, guard: Guard, state: CsmaStrategyState<C>) {
+        if from.is_transmitting() {
+            self.push(Action::DisableDriver);
+        }
+        self.enter(guard, state);
+        self.push(Action::StopEchoTimers);
+    }
+
+    /// Stop transmitting, if we were, and wait for the bus to be idle again.
+    fn abort(&mut self, from: StateKind, guard: Guard) {
+        self.stop(from, guard, CsmaStrategyState::WaitForBusIdle);
+    }
+
+    pub fn actions(&self) -> &[Action<C>] {
+        &self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl<C: Clock> IntoIterator for Step<C> {
+    type Item = Action<C>;
+    type IntoIter = <heapless::Vec<Action<C>, MAX_ACTIONS> as IntoIterator>::IntoIter;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
+}
+
+/// Decide what to do in `state` on `input`, without doing any of it.
+///
+/// Yields no actions if nothing is to be done, in which case polling proceeds.
+pub fn step<C: Clock>(
+    state: &CsmaStrategyState<C>,
+    input: Input<C>,
+    config: &CsmaConfig<C>,
+) -> Step<C> {
+    use CsmaStrategyState as S;
+
+    let from = state.kind();
+    let mut step = Step(heapless::Vec::new());
+    match (state, input) {
+        // Contending for the bus.
+        (S::WaitForBusIdle, Input::Poll { bus_idle: true, .. }) => step.push(Action::DrawBackoff),
+        (S::WaitForBusIdle, Input::BackoffDrawn { now, backoff }) => step.enter(
+            Guard::BusIdle,
+            S::BusIdleCooldown {
+                started_at: now,
+                ready_at: now + backoff,
+            },
+        ),
+        (
+            S::BusIdleCooldown { started_at, .. },
+            Input::Poll {
+                now,
+                bus_idle: false,
+                ..
+            },
+        ) => {
+            step.push(Action::CountBackoff(now - *started_at));
+            step.enter(Guard::BusBusy, S::WaitForBusIdle);
+        }
+        (
+            S::BusIdleCooldown {
+                started_at,
+                ready_at,
+            },
+            Input::Poll { now, .. },
+        ) if now >= *ready_at => {
+            step.push(Action::CountBackoff(*ready_at - *started_at));
+            step.enter(Guard::BackoffElapsed, S::StartSend);
+        }
+        (
+            S::StartSend,
+            Input::Poll {
+                bus_idle: false, ..
+            },
+        ) => step.enter(Guard::BusBusy, S::WaitForBusIdle),
+        (S::StartSend, Input::Poll { .. }) => {
+            step.push(Action::ClearReader);
+            step.enter(Guard::BusIdle, S::EnablingDriver);
+            step.push(Action::EnableDriver);
+        }
+        (
+            S::EnablingDriver,
+            Input::Poll {
+                bus_idle: false, ..
+            },
+        ) => step.abort(from, Guard::BusBusy),
+        (S::EnablingDriver, Input::Poll { .. }) => step.push(Action::EnableDriver),
+        (S::EnablingDriver, Input::DriverEnabled) => {
+            step.enter(Guard::DriverEnabled, S::Sending);
+            step.push(Action::StartEchoTimer);
+        }
+
+        // Sending and confirming our frame.
+        (S::Sending, Input::Poll { written: true, .. })
+        | (S::Sending, Input::FrameByteWritten { written: true }) => {
+            step.enter(Guard::FrameWritten, S::ConfirmingSendWithoutErrors)
+        }
+        (S::Sending, Input::Poll { .. }) => step.push(Action::WriteFrame),
+        (S::Sending | S::ConfirmingSendWithoutErrors, Input::Received(b)) => {
+            step.push(Action::CheckEcho(b))
+        }
+        (
+            S::Sending | S::ConfirmingSendWithoutErrors,
+            Input::Echoed {
+                now,
+                complete: true,
+            },
+        ) => {
+            step.push(Action::Sent);
+            match config.post_send_gap {
+                Some(gap) => step.stop(
+                    from,
+                    Guard::EchoedWithGap,
+                    S::PostSendGap { until: now + gap },
+                ),
+                None => step.stop(from, Guard::Echoed, S::WaitForBusIdle),
+            }
+        }
+        (S::Sending | S::ConfirmingSendWithoutErrors, Input::Echoed { .. }) => {
+            step.push(Action::EchoProgress)
+        }
+        (S::PostSendGap { until }, Input::Poll { now, .. }) if now >= *until => {
+            step.enter(Guard::GapElapsed, S::WaitForBusIdle)
+        }
+
+        // Collisions.
+        (S::Sending | S::ConfirmingSendWithoutErrors, Input::EchoMismatch(b)) => {
+            step.push(Action::CountFrameError);
+            step.push(Action::Collided);
+            step.push(Action::RestartFrame);
+            step.push(Action::ClearReader);
+            // Impossible to lead to a frame.
+            step.push(Action::Discard(b));
+            match config.jam_len {
+                0 => step.abort(from, Guard::Collision),
+                left => step.enter(Guard::CollisionWithJam, S::Jamming { left }),
+            }
+            step.push(Action::Yield);
+        }
+        (S::Jamming { .. }, Input::Poll { .. }) => step.push(Action::WriteJam),
+        (S::Jamming { left }, Input::JamByteWritten) => match left - 1 {
+            0 => step.abort(from, Guard::JamWritten),
+            left => step.enter(Guard::JamByteWritten, S::Jamming { left }),
+        },
+        // The jam ends whatever frame is on the bus, including our own.
+        (S::Jamming { .. }, Input::Received(_)) => step.push(Action::ClearReader),
+        (S::Sending | S::ConfirmingSendWithoutErrors, Input::EchoTimeout) => {
+            step.push(Action::EchoTimedOut);
+            step.push(Action::RestartFrame);
+            step.push(Action::ClearReader);
+            step.abort(from, Guard::EchoTimeout);
+            step.push(Action::Yield);
+        }
+
+        // Another node took the bus.
+        (_, Input::Received(b)) => {
+            step.abort(from, Guard::ByteReceived);
+            step.push(Action::Receive(b));
+        }
+
+        // Errors, of which those while jamming are caused by the jam itself, which is to be finished.
+        (S::Jamming { .. }, Input::FrameError) => {
+            step.push(Action::CountFrameError);
+            step.push(Action::Yield);
+        }
+        (_, Input::FrameError) => {
+            step.push(Action::CountFrameError);
+            step.push(Action::NoteError);
+            if from.is_transmitting() {
+                step.push(Action::Collided);
+            }
+            step.push(Action::RestartFrame);
+            step.push(Action::ClearReader);
+            step.abort(from, Guard::FrameError);
+            step.push(Action::Yield);
+        }
+
+        // Getting stuck.
+        (_, Input::StateTimeout) => {
+            step.push(Action::StateTimedOut);
+            step.push(Action::RestartFrame);
+            step.push(Action::ClearReader);
+            step.abort(from, Guard::StateTimeout);
+            if config.reset_on_state_timeout {
+                step.push(Action::ResetTransceiver);
+            }
+            step.push(Action::Yield);
+        }
+
+        _ => (),
+    }
+    step
+}
+
+/// The state machine as a Graphviz digraph.
+#[derive(Debug, Clone, Copy)]
+pub struct Dot;
+
+impl Display for Dot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "digraph CsmaStrategy {{")?;
+        writeln!(f, "    node [shape=box, style=rounded];")?;
+        writeln!(f, "    initial [shape=point];")?;
+        writeln!(f, "    initial -> {};", StateKind::INITIAL.name())?;
+        for Transition { from, guard, to } in TRANSITIONS {
+            writeln!(
+                f,
+                "    {} -> {} [label=\"{}\"];",
+                from.name(),
+                to.name(),
+                guard.description()
+            )?;
+        }
+        writeln!(f, "}}")
+    }
+}
+
+/// The state machine as a PlantUML state diagram.
+#[derive(Debug, Clone, Copy)]
+pub struct PlantUml;
+
+impl Display for PlantUml {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "@startuml")?;
+        writeln!(f, "[*] --> {}", StateKind::INITIAL.name())?;
+        for Transition { from, guard, to } in TRANSITIONS {
+            writeln!(
+                f,
+                "{} --> {} : {}",
+                from.name(),
+                to.name(),
+                guard.description()
+            )?;
+        }
+        writeln!(f, "@enduml")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::fmt::Write;
+
+    use super::*;
+
+    fn render(diagram: impl Display) -> heapless::String<8192> {
+        let mut out = heapless::String::new();
+        write!(out, "{}", diagram).unwrap();
+        out
+    }
+
+    #[derive(Debug)]
+    struct Ticks;
+
+    impl Clock for Ticks {
+        type Instant = u64;
+        type Duration = u64;
+
+        fn now(&self) -> u64 {
+            0
+        }
+    }
+
+    type State = CsmaStrategyState<Ticks>;
+
+    fn config(jam_len: u8, post_send_gap: Option<u64>, reset: bool) -> CsmaConfig<Ticks> {
+        CsmaConfig {
+            bus_min_idle: 1,
+            bus_max_idle: 2,
+            echo_byte_timeout: 1,
+            echo_frame_timeout: 1,
+            recover_after_errors: 3,
+            reset_on_state_timeout: reset,
+            max_dwell_duration: |_| None,
+            utilization_interval: 1,
+            post_send_gap,
+            jam_len,
+        }
+    }
+
+    fn states() -> [State; 9] {
+        [
+            State::WaitForBusIdle,
+            State::BusIdleCooldown {
+                started_at: 10,
+                ready_at: 20,
+            },
+            State::StartSend,
+            State::EnablingDriver,
+            State::Sending,
+            State::ConfirmingSendWithoutErrors,
+            State::PostSendGap { until: 20 },
+            State::Jamming { left: 1 },
+            State::Jamming { left: 2 },
+        ]
+    }
+
+    fn inputs() -> [Input<Ticks>; 19] {
+        let poll = |now, bus_idle, written| Input::Poll {
+            now,
+            bus_idle,
+            written,
+        };
+        [
+            poll(15, false, false),
+            poll(15, false, true),
+            poll(15, true, false),
+            poll(15, true, true),
+            poll(25, false, false),
+            poll(25, false, true),
+            poll(25, true, false),
+            poll(25, true, true),
+            Input::BackoffDrawn {
+                now: 15,
+                backoff: 5,
+            },
+            Input::DriverEnabled,
+            Input::FrameByteWritten { written: false },
+            Input::FrameByteWritten { written: true },
+            Input::JamByteWritten,
+            Input::Received(1),
+            Input::Echoed {
+                now: 15,
+                complete: false,
+            },
+            Input::Echoed {
+                now: 15,
+                complete: true,
+            },
+            Input::EchoMismatch(1),
+            Input::EchoTimeout,
+            Input::FrameError,
+        ]
+    }
+
+    fn configs() -> [CsmaConfig<Ticks>; 4] {
+        [
+            config(0, None, false),
+            config(2, None, true),
+            config(0, Some(5), false),
+            config(2, Some(5), true),
+        ]
+    }
+
+    /// Take a step on every input in every state, for every configuration.
+    fn all_steps(mut f: impl FnMut(&State, &Step<Ticks>)) {
+        for config in &configs() {
+            for state in &states() {
+                for input in inputs() {
+                    f(state, &step(state, input, config));
+                }
+                f(state, &step(state, Input::StateTimeout, config));
+            }
+        }
+    }
+
+    #[test]
+    fn steps_follow_transitions() {
+        let mut taken = [false; TRANSITIONS.len()];
+        all_steps(|state, step| {
+            let mut from = state.kind();
+            for action in step.actions() {
+                if let Action::Enter(guard, to) = action {
+                    let transition = t(from, *guard, to.kind());
+                    let index = TRANSITIONS.iter().position(|t| *t == transition);
+                    assert!(index.is_some(), "{:?} is not listed", transition);
+                    taken[index.unwrap()] = true;
+                    from = to.kind();
+                }
+            }
+        });
+        for (transition, taken) in TRANSITIONS.iter().zip(taken) {
+            assert!(taken, "{:?} is never taken", transition);
+        }
+    }
+
+    #[test]
+    fn steps_are_well_formed() {
+        all_steps(|state, step| {
+            let actions = step.actions();
+            let entered = actions
+                .iter()
+                .filter(|a| matches!(a, Action::Enter(..)))
+                .count();
+            assert!(entered <= 1, "{:?} enters more than once", actions);
+
+            // The driver is only touched while it might be enabled.
+            let transmitting = state.kind().is_transmitting()
+                || matches!(state, State::StartSend)
+                    && actions.iter().any(|a| matches!(a, Action::EnableDriver));
+            if !transmitting {
+                assert!(
+                    !actions.iter().any(|a| matches!(
+                        a,
+                        Action::DisableDriver | Action::WriteFrame | Action::WriteJam
+                    )),
+                    "{:?} in {:?}",
+                    actions,
+                    state
+                );
+            }
+
+            // Nothing follows handing out a received frame, as it is borrowed from the reader.
+            if let Some(position) = actions.iter().position(|a| matches!(a, Action::Receive(_))) {
+                assert_eq!(position, actions.len() - 1);
+            }
+        });
+    }
+
+    #[test]
+    fn contend_and_send() {
+        let config = config(0, None, false);
+        let poll = |now, bus_idle, written| Input::Poll {
+            now,
+            bus_idle,
+            written,
+        };
+
+        let s = step(&State::WaitForBusIdle, poll(0, false, false), &config);
+        assert!(s.is_empty());
+        let s = step(&State::WaitForBusIdle, poll(0, true, false), &config);
+        assert!(matches!(s.actions(), [Action::DrawBackoff]));
+        let s = step(
+            &State::WaitForBusIdle,
+            Input::BackoffDrawn { now: 3, backoff: 4 },
+            &config,
+        );
+        assert!(matches!(
+            s.actions(),
+            [Action::Enter(
+                Guard::BusIdle,
+                State::BusIdleCooldown {
+                    started_at: 3,
+                    ready_at: 7
+                }
+            )]
+        ));
+
+        let cooldown = State::BusIdleCooldown {
+            started_at: 3,
+            ready_at: 7,
+        };
+        assert!(step(&cooldown, poll(5, true, false), &config).is_empty());
+        let s = step(&cooldown, poll(5, false, false), &config);
+        assert!(matches!(
+            s.actions(),
+            [
+                Action::CountBackoff(2),
+                Action::Enter(Guard::BusBusy, State::WaitForBusIdle)
+            ]
+        ));
+        let s = step(&cooldown, poll(8, true, false), &config);
+        assert!(matches!(
+            s.actions(),
+            [
+                Action::CountBackoff(4),
+                Action::Enter(Guard::BackoffElapsed, State::StartSend)
+            ]
+        ));
+
+        let s = step(&State::StartSend, poll(8, true, false), &config);
+        assert!(matches!(
+            s.actions(),
+            [
+                Action::ClearReader,
+                Action::Enter(Guard::BusIdle, State::EnablingDriver),
+                Action::EnableDriver
+            ]
+        ));
+        let s = step(&State::EnablingDriver, Input::DriverEnabled, &config);
+        assert!(matches!(
+            s.actions(),
+            [
+                Action::Enter(Guard::DriverEnabled, State::Sending),
+                Action::StartEchoTimer
+            ]
+        ));
+
+        let s = step(&State::Sending, poll(8, true, false), &config);
+        assert!(matches!(s.actions(), [Action::WriteFrame]));
+        let s = step(
+            &State::Sending,
+            Input::FrameByteWritten { written: true },
+            &config,
+        );
+        assert!(matches!(
+            s.actions(),
+            [Action::Enter(
+                Guard::FrameWritten,
+                State::ConfirmingSendWithoutErrors
+            )]
+        ));
+
+        let confirming = State::ConfirmingSendWithoutErrors;
+        let s = step(&confirming, Input::Received(0x42), &config);
+        assert!(matches!(s.actions(), [Action::CheckEcho(0x42)]));
+        let s = step(
+            &confirming,
+            Input::Echoed {
+                now: 9,
+                complete: true,
+            },
+            &config,
+        );
+        assert!(matches!(
+            s.actions(),
+            [
+                Action::Sent,
+                Action::DisableDriver,
+                Action::Enter(Guard::Echoed, State::WaitForBusIdle),
+                Action::StopEchoTimers
+            ]
+        ));
+    }
+
+    #[test]
+    fn collide_and_jam() {
+        let config = config(2, Some(5), false);
+        let s = step(&State::Sending, Input::EchoMismatch(0x42), &config);
+        assert!(matches!(
+            s.actions(),
+            [
+                Action::CountFrameError,
+                Action::Collided,
+                Action::RestartFrame,
+                Action::ClearReader,
+                Action::Discard(0x42),
+                Action::Enter(Guard::CollisionWithJam, State::Jamming { left: 2 }),
+                Action::Yield
+            ]
+        ));
+
+        let jamming = State::Jamming { left: 2 };
+        let s = step(&jamming, Input::JamByteWritten, &config);
+        assert!(matches!(
+            s.actions(),
+            [Action::Enter(
+                Guard::JamByteWritten,
+                State::Jamming { left: 1 }
+            )]
+        ));
+        let s = step(&jamming, Input::FrameError, &config);
+        assert!(matches!(
+            s.actions(),
+            [Action::CountFrameError, Action::Yield]
+        ));
+        let s = step(&State::Jamming { left: 1 }, Input::JamByteWritten, &config);
+        assert!(matches!(
+            s.actions(),
+            [
+                Action::DisableDriver,
+                Action::Enter(Guard::JamWritten, State::WaitForBusIdle),
+                Action::StopEchoTimers
+            ]
+        ));
+    }
+
+    #[test]
+    fn received_while_contending() {
+        let config = config(0, None, false);
+        let s = step(
+            &State::PostSendGap { until: 20 },
+            Input::Received(7),
+            &config,
+        );
+        assert!(matches!(
+            s.actions(),
+            [
+                Action::Enter(Guard::ByteReceived, State::WaitForBusIdle),
+                Action::StopEchoTimers,
+                Action::Receive(7)
+            ]
+        ));
+        assert!(step(
+            &State::PostSendGap { until: 20 },
+            Input::EchoTimeout,
+            &config
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn all_states_reachable() {
+        let mut reached = [false; StateKind::ALL.len()];
+        let index = |state: StateKind| StateKind::ALL.iter().position(|s| *s == state).unwrap();
+        reached[index(StateKind::INITIAL)] = true;
+        loop {
+            let mut changed = false;
+            for transition in TRANSITIONS {
+                if reached[index(transition.from)] && !reached[index(transition.to)] {
+                    reached[index(transition.to)] = true;
+                    changed = true;
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+        assert_eq!(reached, [true; StateKind::ALL.len()]);
+    }
+
+    #[test]
+    fn guards_are_deterministic() {
+        for (i, a) in TRANSITIONS.iter().enumerate() {
+            for b in &TRANSITIONS[i + 1..] {
+                assert!(
+                    !(a.from == b.from && a.guard == b.guard),
+                    "{:?} and {:?} share a guard",
+                    a,
+                    b
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn diagrams() {
+        let dot = render(Dot);
+        assert!(dot.starts_with("digraph CsmaStrategy {\n"));
+        assert!(
+            dot.contains("    Sending -> ConfirmingSendWithoutErrors [label=\"frame written\"];\n")
+        );
+
+        let uml = render(PlantUml);
+        assert!(uml.contains("[*] --> WaitForBusIdle\n"));
+        assert!(uml.contains("Jamming --> Jamming : jam byte written\n"));
+    }
+}
@@ -0,0 +1,215 @@
+extern crate std;
+
+use core::cell::{Cell, RefCell};
+use std::{
+    collections::VecDeque,
+    io,
+    net::{ToSocketAddrs, UdpSocket},
+    time::{Duration, Instant},
+};
+
+use crate::{Clock, ReadError, Transceiver};
+
+/// Clock backed by `std::time::Instant`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    type Instant = Instant;
+    type Duration = Duration;
+
+    fn now(&self) -> Self::Instant {
+        Instant::now()
+    }
+}
+
+/// Transceiver that tunnels the byte stream of a bus over UDP to a single peer.
+///
+/// Every written byte is sent as a datagram, and looped back locally just like an RS485 transceiver would.
+/// The bus is considered idle if no byte was sent or received for the idle duration.
+///
+/// Bytes of both sides that are sent at the same time end up interleaved, which the strategy detects as a collision.
+#[derive(Debug)]
+pub struct UdpTransceiver {
+    socket: UdpSocket,
+    idle_after: Duration,
+    rx: RefCell<VecDeque<u8>>,
+    last_activity: Cell<Instant>,
+}
+
+impl UdpTransceiver {
+    /// Bind to `local` and exchange bytes with `peer` only.
+    pub fn new(
+        local: impl ToSocketAddrs,
+        peer: impl ToSocketAddrs,
+        idle_after: Duration,
+    ) -> io::Result<Self> {
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(peer)?;
+        socket.set_nonblocking(true)?;
+        Ok(Self {
+            socket,
+            idle_after,
+            rx: RefCell::new(VecDeque::new()),
+            last_activity: Cell::new(Instant::now()),
+        })
+    }
+
+    pub fn socket(&self) -> &UdpSocket {
+        &self.socket
+    }
+
+    /// Move all datagrams that arrived into the receive queue.
+    fn poll_socket(&self) -> io::Result<()> {
+        let mut buf = [0u8; 1500];
+        loop {
+            match self.socket.recv(&mut buf) {
+                Ok(len) => {
+                    self.rx.borrow_mut().extend(&buf[..len]);
+                    self.last_activity.set(Instant::now());
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                // The peer might not be listening yet.
+                Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => return Ok(()),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+impl Transceiver for UdpTransceiver {
+    type Error = io::Error;
+
+    fn handle_interrupts(&self) {
+        if let Err(e) = self.poll_socket() {
+            warn!("UDP receive failed: {}", e);
+        }
+    }
+
+    fn bus_is_idle(&self) -> bool {
+        self.rx.borrow().is_empty() && self.last_activity.get().elapsed() >= self.idle_after
+    }
+
+    fn write(&mut self, byte: u8) -> nb::Result<(), Self::Error> {
+        match self.socket.send(&[byte]) {
+            Ok(_) => (),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Err(nb::Error::WouldBlock),
+            // Nobody listening on the other side is the same as an empty bus.
+            Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => (),
+            Err(e) => return Err(nb::Error::Other(e)),
+        }
+
+        self.rx.borrow_mut().push_back(byte);
+        self.last_activity.set(Instant::now());
+        Ok(())
+    }
+
+    fn read(&mut self) -> nb::Result<u8, ReadError<Self::Error>> {
+        self.poll_socket()
+            .map_err(|e| nb::Error::Other(ReadError::UnderlyingError(e)))?;
+        self.rx
+            .borrow_mut()
+            .pop_front()
+            .ok_or(nb::Error::WouldBlock)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Config, CsmaFrameInProgress, CsmaStrategy, Event, Observer, SendReceiveResult};
+    use kiri_protocol::{Address, Writer};
+
+    struct TestConfig;
+
+    impl Config<SystemClock> for TestConfig {
+        const BUS_MIN_IDLE_DURATION: Duration = Duration::from_micros(100);
+        const BUS_MAX_IDLE_DURATION: Duration = Duration::from_millis(1);
+        const ECHO_BYTE_TIMEOUT: Duration = Duration::from_millis(100);
+        const ECHO_FRAME_TIMEOUT: Duration = Duration::from_secs(1);
+    }
+
+    #[derive(Default)]
+    struct CountingObserver {
+        state_changes: usize,
+        frames_sent: usize,
+    }
+
+    impl Observer<SystemClock> for CountingObserver {
+        fn on_event(&mut self, event: Event<'_, SystemClock>) {
+            match event {
+                Event::StateChanged(_) => self.state_changes += 1,
+                Event::FrameSent => self.frames_sent += 1,
+                _ => (),
+            }
+        }
+    }
+
+    #[test]
+    fn udp_send_receive() {
+        let a = UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let b = UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let idle = Duration::from_millis(1);
+
+        let rng = rand::rngs::mock::StepRng::new(0, 7919);
+        let mut sender = CsmaStrategy::new::<TestConfig>(
+            UdpTransceiver::new(a, b, idle).unwrap(),
+            SystemClock,
+            rng.clone(),
+        )
+        .with_observer(CountingObserver::default());
+        let mut receiver = CsmaStrategy::new::<TestConfig>(
+            UdpTransceiver::new(b, a, idle).unwrap(),
+            SystemClock,
+            rng,
+        );
+
+        let frame = Writer::package(Address::new(1), Address::new(2), b"over the wire").unwrap();
+        let mut frame = CsmaFrameInProgress::new(frame);
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let (mut sent, mut received) = (false, false);
+        while !(sent && received) {
+            assert!(Instant::now() < deadline, "timed out");
+
+            if !sent {
+                match sender.send_or_receive(&mut frame) {
+                    Ok(SendReceiveResult::SendComplete) => sent = true,
+                    Ok(SendReceiveResult::Received(_) | SendReceiveResult::Expired) => {
+                        panic!("unexpected result")
+                    }
+                    Err(nb::Error::WouldBlock) => (),
+                    Err(nb::Error::Other(e)) => panic!("{}", e),
+                }
+            }
+
+            match receiver.receive() {
+                Ok(incoming) => {
+                    assert_eq!(incoming.contents, b"over the wire");
+                    received = true;
+                }
+                Err(nb::Error::WouldBlock) => (),
+                Err(nb::Error::Other(e)) => panic!("{}", e),
+            }
+        }
+
+        let len = frame.frame.0.len() as u64;
+        assert_eq!(sender.stats().frames_sent, 1);
+        assert_eq!(sender.stats().bytes_sent, len);
+        assert_eq!(sender.stats().bytes_received, len);
+        assert_eq!(receiver.stats().frames_received, 1);
+        assert_eq!(receiver.stats().bytes_received, len);
+
+        assert_eq!(sender.observer().frames_sent, 1);
+        assert!(sender.observer().state_changes > 0);
+
+        receiver.reset_stats();
+        assert_eq!(receiver.stats().frames_received, 0);
+    }
+}
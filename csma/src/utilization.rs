@@ -0,0 +1,132 @@
+//! Online estimate of how busy the bus is, see `UtilizationEstimator`.
+
+use crate::Clock;
+
+/// How many samples the sliding window consists of.
+pub const UTILIZATION_WINDOW: u32 = u64::BITS;
+
+/// Estimates the fraction of time the bus was not idle, over the last `UTILIZATION_WINDOW` samples.
+///
+/// Samples are taken every `interval`, hence the window spans `UTILIZATION_WINDOW` intervals. A sample is busy if
+/// the bus was not idle whenever it was looked at during its interval. Intervals in which the estimator was not
+/// looked at all are counted like the interval before, up to the window.
+#[derive(Debug, Clone)]
+pub struct UtilizationEstimator<C: Clock> {
+    interval: C::Duration,
+    /// When the current interval ends, if one started.
+    next_sample_at: Option<C::Instant>,
+    /// Whether the bus was seen busy during the current interval.
+    busy: bool,
+    /// The last samples, of which the most recent one is in the lowest bit. Set bits are busy samples.
+    samples: u64,
+    /// How many samples were taken, up to the window.
+    taken: u32,
+}
+
+impl<C: Clock> UtilizationEstimator<C> {
+    pub fn new(interval: C::Duration) -> Self {
+        Self {
+            interval,
+            next_sample_at: None,
+            busy: false,
+            samples: 0,
+            taken: 0,
+        }
+    }
+
+    /// Look at the bus at `now`, which is `busy` if it is not idle or a byte was received.
+    pub fn observe(&mut self, now: C::Instant, busy: bool) {
+        self.busy |= busy;
+        let mut at = match self.next_sample_at {
+            Some(at) if now < at => return,
+            Some(at) => at,
+            None => {
+                self.next_sample_at = Some(now + self.interval);
+                return;
+            }
+        };
+
+        let mut taken = 0;
+        while at <= now && taken < UTILIZATION_WINDOW {
+            self.samples = (self.samples << 1) | self.busy as u64;
+            self.taken = (self.taken + 1).min(UTILIZATION_WINDOW);
+            at = at + self.interval;
+            taken += 1;
+        }
+        // Skip intervals that have fallen out of the window entirely.
+        if at <= now {
+            at = now + self.interval;
+        }
+        self.next_sample_at = Some(at);
+        self.busy = busy;
+    }
+
+    /// The fraction of busy samples in the window, from `0.0` for a quiet bus to `1.0` for one that is never idle.
+    ///
+    /// Yields `0.0` until the first interval has passed.
+    pub fn utilization(&self) -> f32 {
+        if self.taken == 0 {
+            return 0.0;
+        }
+        let mask = u64::MAX >> (UTILIZATION_WINDOW - self.taken);
+        (self.samples & mask).count_ones() as f32 / self.taken as f32
+    }
+
+    /// Forget all samples, i.e. after changing the interval.
+    pub fn reset(&mut self) {
+        *self = Self::new(self.interval);
+    }
+
+    pub fn interval(&self) -> C::Duration {
+        self.interval
+    }
+
+    pub fn set_interval(&mut self, interval: C::Duration) {
+        self.interval = interval;
+        self.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestClock;
+
+    impl Clock for TestClock {
+        type Instant = u64;
+        type Duration = u64;
+
+        fn now(&self) -> u64 {
+            0
+        }
+    }
+
+    #[test]
+    fn utilization_window() {
+        let mut estimator = UtilizationEstimator::<TestClock>::new(10);
+        assert_eq!(estimator.utilization(), 0.0);
+
+        // Busy during part of every interval.
+        for now in 0..40 * 10 {
+            estimator.observe(now, now % 10 < 3);
+        }
+        assert_eq!(estimator.utilization(), 1.0);
+
+        // Busy during 16 of the last 64 intervals.
+        for now in 400..890 {
+            estimator.observe(now, false);
+        }
+        assert_eq!(estimator.utilization(), 16.0 / 64.0);
+
+        // A long gap in observations counts like the interval before it, up to the window.
+        estimator.observe(10_000, false);
+        assert_eq!(estimator.utilization(), 0.0);
+        estimator.observe(10_001, true);
+        estimator.observe(20_000, true);
+        assert_eq!(estimator.utilization(), 1.0);
+
+        estimator.reset();
+        assert_eq!(estimator.utilization(), 0.0);
+    }
+}
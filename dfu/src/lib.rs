@@ -0,0 +1,323 @@
+#![no_std]
+
+//! Firmware update over the bus.
+//!
+//! A `Sender` transfers an image to a `Receiver` in chunks, using stop-and-wait: every request is answered
+//! with a `Status`, and requests are repeated until they are answered. The status carries the offset the
+//! receiver expects next, such that interrupted transfers resume where they left off.
+//!
+//! The transfer consists of:
+//! * `Request::Start` with the length and CRC-32 of the image, after which the receiver prepares its storage.
+//! * `Request::Data` for every chunk of the image, in order.
+//! * `Request::Commit`, after which the receiver verifies the image and makes it active.
+
+use crc::{Crc, CRC_32_ISO_HDLC};
+use kiri_protocol::{Address, Frame, WriteError, Writer};
+
+mod receiver;
+mod sender;
+
+pub use receiver::{Receiver, Storage};
+pub use sender::{Progress, Sender};
+
+/// Checksum over the complete image.
+pub const IMAGE_CHECKSUM: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+/// Chunk length that fits a frame of any size, with room to spare for the request header.
+pub const DEFAULT_CHUNK_LEN: usize = 256;
+
+/// How much bytes the header of a data request takes up.
+pub const DATA_HEADER_LEN: usize = 5;
+
+const TAG_START: u8 = 0x01;
+const TAG_DATA: u8 = 0x02;
+const TAG_COMMIT: u8 = 0x03;
+const TAG_ABORT: u8 = 0x04;
+const TAG_QUERY: u8 = 0x05;
+const TAG_STATUS: u8 = 0x80;
+
+/// How much bytes an encoded status takes up.
+pub const STATUS_LEN: usize = 6;
+
+/// Description of the image that is transferred.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Metadata {
+    pub len: u32,
+    /// `IMAGE_CHECKSUM` of the image.
+    pub crc: u32,
+}
+
+impl Metadata {
+    pub fn of(image: &[u8]) -> Self {
+        Self {
+            len: image.len() as u32,
+            crc: IMAGE_CHECKSUM.checksum(image),
+        }
+    }
+}
+
+/// Request of the sender.
+#[derive(Debug, PartialEq)]
+pub enum Request<'a> {
+    /// Start a transfer, or resume it if the receiver is already receiving the same image.
+    Start(Metadata),
+    /// Part of the image at `offset`.
+    Data { offset: u32, data: &'a [u8] },
+    /// Verify the complete image, and make it active.
+    Commit,
+    /// Forget the current transfer.
+    Abort,
+    /// Only ask for the status of the receiver.
+    Query,
+}
+
+impl<'a> Request<'a> {
+    /// Interpret the contents of a frame as request.
+    pub fn parse(contents: &'a [u8]) -> Option<Self> {
+        let (tag, rest) = contents.split_first()?;
+        match (*tag, rest.len()) {
+            (TAG_START, 8) => Some(Request::Start(Metadata {
+                len: read_u32(&rest[0..4]),
+                crc: read_u32(&rest[4..8]),
+            })),
+            (TAG_DATA, len) if len >= 4 => Some(Request::Data {
+                offset: read_u32(&rest[0..4]),
+                data: &rest[4..],
+            }),
+            (TAG_COMMIT, 0) => Some(Request::Commit),
+            (TAG_ABORT, 0) => Some(Request::Abort),
+            (TAG_QUERY, 0) => Some(Request::Query),
+            _ => None,
+        }
+    }
+
+    pub fn package(&self, src: Address, dst: Address) -> Result<Frame, WriteError> {
+        match self {
+            Request::Start(metadata) => {
+                let mut buf = [TAG_START, 0, 0, 0, 0, 0, 0, 0, 0];
+                buf[1..5].copy_from_slice(&metadata.len.to_be_bytes());
+                buf[5..9].copy_from_slice(&metadata.crc.to_be_bytes());
+                Writer::package(src, dst, &buf)
+            }
+            Request::Data { offset, data } => {
+                let mut header = [TAG_DATA; DATA_HEADER_LEN];
+                header[1..5].copy_from_slice(&offset.to_be_bytes());
+                Writer::package_vectored(src, dst, &[&header, data])
+            }
+            Request::Commit => Writer::package(src, dst, &[TAG_COMMIT]),
+            Request::Abort => Writer::package(src, dst, &[TAG_ABORT]),
+            Request::Query => Writer::package(src, dst, &[TAG_QUERY]),
+        }
+    }
+}
+
+/// State of the receiver.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum State {
+    Idle = 0,
+    Receiving = 1,
+    /// The image was verified and made active.
+    Committed = 2,
+    /// The image did not match its checksum after it was received completely.
+    ChecksumMismatch = 3,
+    /// The storage of the receiver failed.
+    StorageError = 4,
+    /// The image does not fit the storage of the receiver.
+    TooLarge = 5,
+}
+
+impl State {
+    fn from_u8(state: u8) -> Option<Self> {
+        use State::*;
+        [
+            Idle,
+            Receiving,
+            Committed,
+            ChecksumMismatch,
+            StorageError,
+            TooLarge,
+        ]
+        .into_iter()
+        .find(|s| *s as u8 == state)
+    }
+
+    pub fn is_error(&self) -> bool {
+        matches!(
+            self,
+            State::ChecksumMismatch | State::StorageError | State::TooLarge
+        )
+    }
+}
+
+/// Answer of the receiver to every request.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Status {
+    pub state: State,
+    /// Offset of the next chunk the receiver expects.
+    pub next_offset: u32,
+}
+
+impl Status {
+    /// Interpret the contents of a frame as status.
+    pub fn parse(contents: &[u8]) -> Option<Self> {
+        match contents {
+            [TAG_STATUS, state, offset @ ..] if offset.len() == 4 => Some(Self {
+                state: State::from_u8(*state)?,
+                next_offset: read_u32(offset),
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn encode(&self) -> [u8; STATUS_LEN] {
+        let mut buf = [TAG_STATUS, self.state as u8, 0, 0, 0, 0];
+        buf[2..6].copy_from_slice(&self.next_offset.to_be_bytes());
+        buf
+    }
+
+    pub fn package(&self, src: Address, dst: Address) -> Result<Frame, WriteError> {
+        Writer::package(src, dst, &self.encode())
+    }
+}
+
+fn read_u32(buf: &[u8]) -> u32 {
+    u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Storage in RAM, which fails writing at `fail_at` once to simulate a reset.
+    struct RamStorage {
+        buf: [u8; 2048],
+        committed: Option<Metadata>,
+        fail_at: Option<u32>,
+    }
+
+    impl Storage for RamStorage {
+        type Error = ();
+
+        fn capacity(&self) -> u32 {
+            self.buf.len() as u32
+        }
+
+        fn prepare(&mut self, len: u32) -> Result<(), ()> {
+            self.buf[..len as usize].fill(0xFF);
+            Ok(())
+        }
+
+        fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), ()> {
+            if self.fail_at.is_some_and(|at| at <= offset) {
+                self.fail_at = None;
+                return Err(());
+            }
+            let offset = offset as usize;
+            self.buf[offset..offset + data.len()].copy_from_slice(data);
+            Ok(())
+        }
+
+        fn read(&mut self, offset: u32, buf: &mut [u8]) -> Result<(), ()> {
+            let offset = offset as usize;
+            buf.copy_from_slice(&self.buf[offset..offset + buf.len()]);
+            Ok(())
+        }
+
+        fn commit(&mut self, metadata: &Metadata) -> Result<(), ()> {
+            self.committed = Some(*metadata);
+            Ok(())
+        }
+    }
+
+    /// Transfer the requests and statuses through actual frames, and count the round trips.
+    fn run(sender: &mut Sender, receiver: &mut Receiver<RamStorage>) -> (Progress, usize) {
+        let (a, b) = (Address::new(1), Address::new(2));
+        let mut reader = kiri_protocol::Reader::new();
+        for round_trips in 1..100 {
+            let frame = sender.request().package(a, b).unwrap();
+            let contents = reader.feed_all(frame.as_slice()).unwrap();
+            let status = receiver.handle(&Request::parse(&contents).unwrap());
+
+            let frame = status.package(b, a).unwrap();
+            let contents = reader.feed_all(frame.as_slice()).unwrap();
+            let status = Status::parse(&contents).unwrap();
+            match sender.handle_status(&status) {
+                Progress::InProgress { .. } => (),
+                progress => return (progress, round_trips),
+            }
+        }
+        panic!("Transfer did not finish");
+    }
+
+    trait FeedAll {
+        fn feed_all(&mut self, buf: &[u8]) -> Option<heapless::Vec<u8, 512>>;
+    }
+
+    impl FeedAll for kiri_protocol::Reader {
+        fn feed_all(&mut self, buf: &[u8]) -> Option<heapless::Vec<u8, 512>> {
+            for b in buf {
+                if let Ok(Some(frame)) = self.feed(*b) {
+                    return heapless::Vec::from_slice(frame.contents).ok();
+                }
+            }
+            None
+        }
+    }
+
+    #[test]
+    fn transfer_and_resume() {
+        let mut image = [0u8; 1000];
+        for (i, b) in image.iter_mut().enumerate() {
+            *b = (i * 7) as u8;
+        }
+
+        let storage = RamStorage {
+            buf: [0; 2048],
+            committed: None,
+            fail_at: Some(512),
+        };
+        let mut receiver = Receiver::new(storage);
+        let mut sender = Sender::new(&image, DEFAULT_CHUNK_LEN);
+
+        // Start, 2 chunks and the third chunk fails.
+        let (progress, _) = run(&mut sender, &mut receiver);
+        assert_eq!(progress, Progress::Failed(State::StorageError));
+
+        // Resuming after a reset of the receiver only sends the remaining chunks.
+        let next_offset = 2 * DEFAULT_CHUNK_LEN as u32;
+        let mut receiver =
+            Receiver::resume(receiver.into_storage(), Metadata::of(&image), next_offset);
+        let mut sender = Sender::new(&image, DEFAULT_CHUNK_LEN);
+        let (progress, round_trips) = run(&mut sender, &mut receiver);
+        assert_eq!(progress, Progress::Done);
+        assert_eq!(round_trips, 1 + 2 + 1);
+
+        let storage = receiver.into_storage();
+        assert_eq!(storage.committed, Some(Metadata::of(&image)));
+        assert_eq!(&storage.buf[..image.len()], &image);
+    }
+
+    #[test]
+    fn checksum_mismatch() {
+        let image = [0x42u8; 100];
+        let storage = RamStorage {
+            buf: [0; 2048],
+            committed: None,
+            fail_at: None,
+        };
+        let mut receiver = Receiver::new(storage);
+        receiver.handle(&Request::Start(Metadata {
+            len: 100,
+            crc: 0xDEADBEEF,
+        }));
+        receiver.handle(&Request::Data {
+            offset: 0,
+            data: &image,
+        });
+        assert_eq!(
+            receiver.handle(&Request::Commit).state,
+            State::ChecksumMismatch
+        );
+        assert_eq!(receiver.into_storage().committed, None);
+    }
+}
@@ -0,0 +1,163 @@
+use crate::{Metadata, Request, State, Status, IMAGE_CHECKSUM};
+
+/// Where the receiver puts the image, i.e. the inactive slot of the flash of a dual bank bootloader.
+pub trait Storage {
+    type Error;
+
+    /// How large an image can be at most.
+    fn capacity(&self) -> u32;
+
+    /// Prepare for an image of `len` bytes, i.e. erase the flash.
+    fn prepare(&mut self, len: u32) -> Result<(), Self::Error>;
+
+    fn write(&mut self, offset: u32, data: &[u8]) -> Result<(), Self::Error>;
+
+    fn read(&mut self, offset: u32, buf: &mut [u8]) -> Result<(), Self::Error>;
+
+    /// Make the verified image active, i.e. mark it to be booted after the next reset.
+    fn commit(&mut self, metadata: &Metadata) -> Result<(), Self::Error>;
+}
+
+/// How much bytes to read at once to verify the image.
+const VERIFY_CHUNK_LEN: usize = 64;
+
+/// Receiving side of a firmware update, answering every `Request` with a `Status`.
+pub struct Receiver<S: Storage> {
+    storage: S,
+    transfer: Option<Metadata>,
+    next_offset: u32,
+    state: State,
+}
+
+impl<S: Storage> Receiver<S> {
+    pub fn new(storage: S) -> Self {
+        Self {
+            storage,
+            transfer: None,
+            next_offset: 0,
+            state: State::Idle,
+        }
+    }
+
+    /// Continue a transfer that was interrupted by a reset, of which the first `next_offset` bytes are stored.
+    ///
+    /// The sender resumes the transfer from there, if it starts the transfer of the same image.
+    pub fn resume(storage: S, metadata: Metadata, next_offset: u32) -> Self {
+        Self {
+            storage,
+            transfer: Some(metadata),
+            next_offset: next_offset.min(metadata.len),
+            state: State::Receiving,
+        }
+    }
+
+    pub fn status(&self) -> Status {
+        Status {
+            state: self.state,
+            next_offset: self.next_offset,
+        }
+    }
+
+    /// The image that is being received, and how much of it is stored.
+    ///
+    /// Persist this regularly to `resume` after a reset.
+    pub fn progress(&self) -> Option<(Metadata, u32)> {
+        self.transfer.map(|metadata| (metadata, self.next_offset))
+    }
+
+    pub fn storage(&mut self) -> &mut S {
+        &mut self.storage
+    }
+
+    pub fn into_storage(self) -> S {
+        self.storage
+    }
+
+    /// Handle a request, yielding the status to answer it with.
+    pub fn handle(&mut self, request: &Request) -> Status {
+        match request {
+            Request::Start(metadata) => self.start(metadata),
+            Request::Data { offset, data } => self.data(*offset, data),
+            Request::Commit => self.commit(),
+            Request::Abort => self.reset(State::Idle),
+            Request::Query => (),
+        }
+        self.status()
+    }
+
+    fn reset(&mut self, state: State) {
+        self.transfer = None;
+        self.next_offset = 0;
+        self.state = state;
+    }
+
+    fn start(&mut self, metadata: &Metadata) {
+        let resumable = matches!(self.state, State::Receiving | State::Committed);
+        if resumable && self.transfer.as_ref() == Some(metadata) {
+            return;
+        }
+
+        if metadata.len > self.storage.capacity() {
+            return self.reset(State::TooLarge);
+        }
+
+        if self.storage.prepare(metadata.len).is_err() {
+            return self.reset(State::StorageError);
+        }
+
+        self.transfer = Some(*metadata);
+        self.next_offset = 0;
+        self.state = State::Receiving;
+    }
+
+    fn data(&mut self, offset: u32, data: &[u8]) {
+        let metadata = match (&self.state, &self.transfer) {
+            (State::Receiving, Some(metadata)) => metadata,
+            _ => return,
+        };
+
+        // Only accept the next chunk, the status tells the sender which one that is.
+        let end = offset as u64 + data.len() as u64;
+        if offset != self.next_offset || end > metadata.len as u64 {
+            return;
+        }
+
+        match self.storage.write(offset, data) {
+            Ok(()) => self.next_offset = end as u32,
+            Err(_) => self.state = State::StorageError,
+        }
+    }
+
+    fn commit(&mut self) {
+        let metadata = match (&self.state, &self.transfer) {
+            (State::Receiving, Some(metadata)) if self.next_offset == metadata.len => *metadata,
+            _ => return,
+        };
+
+        match self.verify(&metadata) {
+            Ok(true) => (),
+            Ok(false) => return self.reset(State::ChecksumMismatch),
+            Err(_) => return self.reset(State::StorageError),
+        }
+
+        match self.storage.commit(&metadata) {
+            Ok(()) => self.state = State::Committed,
+            Err(_) => self.reset(State::StorageError),
+        }
+    }
+
+    /// Whether the stored image corresponds to its checksum.
+    fn verify(&mut self, metadata: &Metadata) -> Result<bool, S::Error> {
+        let mut digest = IMAGE_CHECKSUM.digest();
+        let mut buf = [0u8; VERIFY_CHUNK_LEN];
+        let mut offset = 0;
+        while offset < metadata.len {
+            let len = (metadata.len - offset).min(VERIFY_CHUNK_LEN as u32);
+            let buf = &mut buf[..len as usize];
+            self.storage.read(offset, buf)?;
+            digest.update(buf);
+            offset += len;
+        }
+        Ok(digest.finalize() == metadata.crc)
+    }
+}
@@ -0,0 +1,99 @@
+use crate::{Metadata, Request, State, Status};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Phase {
+    Start,
+    Data,
+    Commit,
+    Done,
+    Failed(State),
+}
+
+/// Result of `Sender::handle_status`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Progress {
+    /// Keep sending `Sender::request`.
+    InProgress { sent: u32, total: u32 },
+    /// The image has been committed by the receiver.
+    Done,
+    /// The receiver failed, and the transfer has stopped.
+    Failed(State),
+}
+
+/// Sending side of a firmware update.
+///
+/// Send `request` to the receiver, and pass its answer to `handle_status`. Send the request again if no answer
+/// arrives in time.
+pub struct Sender<'a> {
+    image: &'a [u8],
+    metadata: Metadata,
+    chunk_len: usize,
+    next_offset: u32,
+    phase: Phase,
+}
+
+impl<'a> Sender<'a> {
+    /// Transfer `image` in chunks of at most `chunk_len` bytes, see `DEFAULT_CHUNK_LEN`.
+    pub fn new(image: &'a [u8], chunk_len: usize) -> Self {
+        Self {
+            image,
+            metadata: Metadata::of(image),
+            chunk_len: chunk_len.max(1),
+            next_offset: 0,
+            phase: Phase::Start,
+        }
+    }
+
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    /// The request to send (again) to the receiver.
+    pub fn request(&self) -> Request<'a> {
+        match self.phase {
+            Phase::Start => Request::Start(self.metadata),
+            Phase::Data => {
+                let offset = self.next_offset as usize;
+                let end = (offset + self.chunk_len).min(self.image.len());
+                Request::Data {
+                    offset: self.next_offset,
+                    data: &self.image[offset..end],
+                }
+            }
+            Phase::Commit => Request::Commit,
+            Phase::Done | Phase::Failed(_) => Request::Query,
+        }
+    }
+
+    /// Handle the answer of the receiver to the last request.
+    pub fn handle_status(&mut self, status: &Status) -> Progress {
+        self.phase = match (self.phase, status.state) {
+            (Phase::Done | Phase::Failed(_), _) => self.phase,
+            (_, state) if state.is_error() => Phase::Failed(state),
+            (Phase::Commit, State::Committed) => Phase::Done,
+            (_, State::Receiving) => {
+                // Continue wherever the receiver is, which resumes an interrupted transfer.
+                self.next_offset = status.next_offset.min(self.metadata.len);
+                if self.next_offset == self.metadata.len {
+                    Phase::Commit
+                } else {
+                    Phase::Data
+                }
+            }
+            // The receiver lost track of our transfer, or still has an older image committed.
+            _ => Phase::Start,
+        };
+        self.progress()
+    }
+
+    pub fn progress(&self) -> Progress {
+        match self.phase {
+            Phase::Done => Progress::Done,
+            Phase::Failed(state) => Progress::Failed(state),
+            _ => Progress::InProgress {
+                sent: self.next_offset,
+                total: self.metadata.len,
+            },
+        }
+    }
+}
@@ -0,0 +1,178 @@
+#![no_std]
+
+//! Demultiplexing of received frames to handlers by port.
+//!
+//! The payload of a frame for a port starts with the 8-bit port, followed by the message. Nodes bind a handler
+//! to every port they serve, i.e. one for requests and one for telemetry, and hand all received frames to
+//! `Dispatcher::dispatch` instead of matching them in one large handler.
+//!
+//! Ports are carried in the payload, after any options, such that frames for a port can have options as well.
+
+use kiri_protocol::{Address, Frame, FrameRef, WriteError, Writer};
+
+pub type Port = u8;
+
+/// How much bytes of the payload the port uses up.
+pub const PORT_LEN: usize = 1;
+
+/// A message that was sent to a port.
+#[derive(Debug, PartialEq)]
+pub struct Delivery<'a> {
+    pub src: Address,
+    pub dst: Address,
+    pub port: Port,
+    pub message: &'a [u8],
+}
+
+impl<'a> Delivery<'a> {
+    /// Interpret a frame as a message to a port, if its payload carries one.
+    pub fn parse(frame: &FrameRef<'a>) -> Option<Self> {
+        let (port, message) = frame.payload().ok()?.split_first()?;
+        Some(Self {
+            src: frame.header.address_src,
+            dst: frame.header.address_dst,
+            port: *port,
+            message,
+        })
+    }
+}
+
+/// Package a message to `port` of `dst` into a frame.
+pub fn package(
+    src: Address,
+    dst: Address,
+    port: Port,
+    message: &[u8],
+) -> Result<Frame, WriteError> {
+    Writer::package_vectored(src, dst, &[&[port], message])
+}
+
+/// Result of `Dispatcher::dispatch`.
+#[derive(Debug, PartialEq)]
+pub enum Dispatch {
+    /// The message was handed to the handler of its port.
+    Handled,
+    /// The frame carries a port, but no handler is bound to it.
+    Unbound,
+    /// The frame does not carry a port.
+    NoPort,
+}
+
+/// Handlers for at most `N` ports.
+pub struct Dispatcher<'a, const N: usize> {
+    handlers: heapless::LinearMap<Port, &'a mut dyn FnMut(&Delivery), N>,
+}
+
+impl<'a, const N: usize> Dispatcher<'a, N> {
+    pub fn new() -> Self {
+        Self {
+            handlers: heapless::LinearMap::new(),
+        }
+    }
+
+    /// Call `handler` for every message to `port`, replacing any previous handler.
+    ///
+    /// Yields the handler back if there are already `N` ports bound.
+    pub fn bind(
+        &mut self,
+        port: Port,
+        handler: &'a mut dyn FnMut(&Delivery),
+    ) -> Result<(), &'a mut dyn FnMut(&Delivery)> {
+        self.handlers
+            .insert(port, handler)
+            .map(|_| ())
+            .map_err(|(_, handler)| handler)
+    }
+
+    pub fn unbind(&mut self, port: Port) {
+        self.handlers.remove(&port);
+    }
+
+    pub fn is_bound(&self, port: Port) -> bool {
+        self.handlers.contains_key(&port)
+    }
+
+    /// Hand a received frame to the handler of its port, if it carries one.
+    pub fn dispatch(&mut self, frame: &FrameRef) -> Dispatch {
+        let delivery = match Delivery::parse(frame) {
+            Some(delivery) => delivery,
+            None => return Dispatch::NoPort,
+        };
+
+        match self.handlers.get_mut(&delivery.port) {
+            Some(handler) => {
+                handler(&delivery);
+                Dispatch::Handled
+            }
+            None => Dispatch::Unbound,
+        }
+    }
+}
+
+impl<const N: usize> Default for Dispatcher<'_, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use kiri_protocol::{FrameBuilder, Reader};
+
+    use super::*;
+
+    const RPC: Port = 1;
+    const TELEMETRY: Port = 2;
+
+    fn dispatch<const N: usize>(dispatcher: &mut Dispatcher<N>, frame: &Frame) -> Dispatch {
+        let mut reader = Reader::new();
+        for b in frame.as_slice() {
+            if let Ok(Some(frame)) = reader.feed(*b) {
+                return dispatcher.dispatch(&frame);
+            }
+        }
+        panic!("Frame not received");
+    }
+
+    #[test]
+    fn dispatch_ports() {
+        let (a, b) = (Address::new(1), Address::new(2));
+        let mut requests = 0;
+        let mut rpc = |delivery: &Delivery| {
+            assert_eq!((delivery.src, delivery.dst), (a, b));
+            assert_eq!(delivery.message, b"reboot");
+            requests += 1;
+        };
+        let mut samples = 0;
+        let mut telemetry = |delivery: &Delivery| {
+            assert_eq!(delivery.port, TELEMETRY);
+            samples += 1;
+        };
+
+        let mut dispatcher = Dispatcher::<2>::new();
+        dispatcher.bind(RPC, &mut rpc).ok().unwrap();
+        dispatcher.bind(TELEMETRY, &mut telemetry).ok().unwrap();
+        assert!(dispatcher.is_bound(RPC));
+
+        let frame = package(a, b, RPC, b"reboot").unwrap();
+        assert_eq!(dispatch(&mut dispatcher, &frame), Dispatch::Handled);
+
+        // Ports follow the options, if any.
+        let parts: &[&[u8]] = &[&[TELEMETRY], b"21.5"];
+        let frame = FrameBuilder::new(a, b)
+            .priority(3)
+            .payload_vectored(parts)
+            .build()
+            .unwrap();
+        assert_eq!(dispatch(&mut dispatcher, &frame), Dispatch::Handled);
+
+        dispatcher.unbind(TELEMETRY);
+        assert_eq!(dispatch(&mut dispatcher, &frame), Dispatch::Unbound);
+
+        let frame = Writer::package(a, b, b"").unwrap();
+        assert_eq!(dispatch(&mut dispatcher, &frame), Dispatch::NoPort);
+
+        drop(dispatcher);
+        assert_eq!((requests, samples), (1, 1));
+    }
+}
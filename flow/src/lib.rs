@@ -0,0 +1,193 @@
+#![no_std]
+
+//! Credit based flow control between peers.
+//!
+//! A sender may only send a peer as many frames as the peer has granted credits for, such that a fast sender
+//! does not overwhelm a slow peer. Both sides start out with `initial_credits`, and receivers grant more credits
+//! as they process frames.
+//!
+//! Grants carry the total amount of frames the peer may have sent, instead of an increment.
+//! Hence a lost grant is compensated by the next one, and grants can safely be repeated.
+//! Only unicast frames are flow controlled.
+
+use kiri_protocol::{Address, Frame, FrameRef, WriteError, Writer};
+
+const TAG_DATA: u8 = 0x00;
+const TAG_GRANT: u8 = 0x01;
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Peer {
+    /// Frames sent to the peer.
+    sent: u16,
+    /// Total amount of frames the peer allows us to send.
+    limit: u16,
+    /// Frames received from the peer.
+    received: u16,
+}
+
+#[derive(Debug)]
+pub enum FlowError {
+    /// The peer has not granted credits for another frame yet.
+    NoCredits,
+    /// Already keeping track of the maximum amount of peers.
+    TooManyPeers,
+    /// Flow control does not apply to multicast frames.
+    Multicast,
+    Write(WriteError),
+}
+
+/// Result of `FlowControl::receive`.
+#[derive(Debug, PartialEq)]
+pub enum Received<'a> {
+    /// Payload of a flow controlled frame of the peer.
+    Data { src: Address, payload: &'a [u8] },
+    /// The peer granted us credits.
+    Grant { src: Address },
+    /// The frame is not part of flow control, or not meant for us.
+    Other,
+}
+
+/// Flow control with at most `P` peers, in both directions.
+pub struct FlowControl<const P: usize> {
+    address: Address,
+    initial_credits: u16,
+    peers: heapless::LinearMap<Address, Peer, P>,
+}
+
+impl<const P: usize> FlowControl<P> {
+    /// Flow control for the node at `address`, of which all peers agree on `initial_credits`.
+    pub fn new(address: Address, initial_credits: u16) -> Self {
+        Self {
+            address,
+            initial_credits,
+            peers: heapless::LinearMap::new(),
+        }
+    }
+
+    fn peer(&mut self, address: Address) -> Result<&mut Peer, FlowError> {
+        if !self.peers.contains_key(&address) {
+            let peer = Peer {
+                limit: self.initial_credits,
+                ..Default::default()
+            };
+            self.peers
+                .insert(address, peer)
+                .map_err(|_| FlowError::TooManyPeers)?;
+        }
+        Ok(self.peers.get_mut(&address).unwrap())
+    }
+
+    /// How many more frames we may send to `dst`.
+    pub fn credits(&self, dst: Address) -> u16 {
+        match self.peers.get(&dst) {
+            Some(peer) => peer.limit.wrapping_sub(peer.sent),
+            None => self.initial_credits,
+        }
+    }
+
+    /// Package `payload` for `dst`, using up one of its credits.
+    pub fn package(&mut self, dst: Address, payload: &[u8]) -> Result<Frame, FlowError> {
+        if dst.is_group() {
+            return Err(FlowError::Multicast);
+        }
+        if self.credits(dst) == 0 {
+            return Err(FlowError::NoCredits);
+        }
+
+        let frame = Writer::package_vectored(self.address, dst, &[&[TAG_DATA], payload])
+            .map_err(FlowError::Write)?;
+        let peer = self.peer(dst)?;
+        peer.sent = peer.sent.wrapping_add(1);
+        Ok(frame)
+    }
+
+    /// Handle a received frame, updating the credits if it is a grant.
+    pub fn receive<'a>(&mut self, frame: &FrameRef<'a>) -> Received<'a> {
+        let src = frame.header.address_src;
+        if frame.header.address_dst != self.address {
+            return Received::Other;
+        }
+
+        match frame.contents.split_first() {
+            Some((&TAG_DATA, payload)) => match self.peer(src) {
+                Ok(peer) => {
+                    peer.received = peer.received.wrapping_add(1);
+                    Received::Data { src, payload }
+                }
+                Err(_) => Received::Other,
+            },
+            Some((&TAG_GRANT, &[a, b])) => match self.peer(src) {
+                Ok(peer) => {
+                    let limit = u16::from_be_bytes([a, b]);
+                    // Ignore grants that were overtaken by a later one.
+                    if limit.wrapping_sub(peer.sent) <= u16::MAX / 2 {
+                        peer.limit = limit;
+                    }
+                    Received::Grant { src }
+                }
+                Err(_) => Received::Other,
+            },
+            _ => Received::Other,
+        }
+    }
+
+    /// A grant for `peer`, allowing it to send another `free` frames on top of those we received from it.
+    ///
+    /// Send a grant whenever frames of the peer have been processed, and repeat it every now and then.
+    pub fn grant(&mut self, peer: Address, free: u16) -> Result<Frame, FlowError> {
+        let limit = self.peer(peer)?.received.wrapping_add(free);
+        Writer::package_vectored(self.address, peer, &[&[TAG_GRANT], &limit.to_be_bytes()])
+            .map_err(FlowError::Write)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use kiri_protocol::Reader;
+
+    use super::*;
+
+    fn deliver<const P: usize>(to: &mut FlowControl<P>, frame: &Frame) -> bool {
+        let mut reader = Reader::new();
+        for b in frame.as_slice() {
+            if let Ok(Some(frame)) = reader.feed(*b) {
+                return matches!(to.receive(&frame), Received::Data { payload: b"x", .. });
+            }
+        }
+        false
+    }
+
+    #[test]
+    fn credits() {
+        let (a, b) = (Address::new(1), Address::new(2));
+        let mut fast = FlowControl::<2>::new(a, 2);
+        let mut slow = FlowControl::<2>::new(b, 2);
+
+        for _ in 0..2 {
+            assert!(deliver(&mut slow, &fast.package(b, b"x").unwrap()));
+        }
+        assert!(matches!(fast.package(b, b"x"), Err(FlowError::NoCredits)));
+
+        // Granting twice does not give any more credits, and old grants are ignored.
+        let old = slow.grant(a, 0).unwrap();
+        let grant = slow.grant(a, 1).unwrap();
+        deliver(&mut fast, &grant);
+        deliver(&mut fast, &grant);
+        assert_eq!(fast.credits(b), 1);
+        assert!(deliver(&mut slow, &fast.package(b, b"x").unwrap()));
+        deliver(&mut fast, &old);
+        assert_eq!(fast.credits(b), 0);
+    }
+
+    #[test]
+    fn groups_are_not_flow_controlled() {
+        let mut flow = FlowControl::<2>::new(Address::new(1), 2);
+        for dst in [
+            Address::broadcast(),
+            Address::group(1),
+            Address::management(1).unwrap(),
+        ] {
+            assert!(matches!(flow.package(dst, b"x"), Err(FlowError::Multicast)));
+        }
+    }
+}
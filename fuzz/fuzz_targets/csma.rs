@@ -0,0 +1,107 @@
+#![no_main]
+
+use core::cell::Cell;
+
+use kiri_csma::{
+    Clock, Config, CsmaFrameInProgress, CsmaStrategy, ReadError, SendReceiveResult, Transceiver,
+};
+use kiri_protocol::{Address, Writer};
+use libfuzzer_sys::fuzz_target;
+use rand::rngs::mock::StepRng;
+
+/// Transceiver that replays a scripted trace, one operation per input byte.
+struct ScriptedTransceiver<'a> {
+    script: &'a [u8],
+    pos: Cell<usize>,
+}
+
+impl ScriptedTransceiver<'_> {
+    fn next(&self) -> Option<u8> {
+        let b = self.script.get(self.pos.get()).copied();
+        self.pos.set(self.pos.get() + 1);
+        b
+    }
+}
+
+impl Transceiver for ScriptedTransceiver<'_> {
+    type Error = ();
+
+    fn handle_interrupts(&self) {}
+
+    fn bus_is_idle(&self) -> bool {
+        self.next().is_some_and(|b| b & 1 == 1)
+    }
+
+    fn write(&mut self, _byte: u8) -> nb::Result<(), Self::Error> {
+        match self.next() {
+            Some(b) if b & 3 == 0 => Err(nb::Error::WouldBlock),
+            _ => Ok(()),
+        }
+    }
+
+    fn read(&mut self) -> nb::Result<u8, ReadError<Self::Error>> {
+        match self.next() {
+            None => Err(nb::Error::WouldBlock),
+            Some(0xFE) => Err(nb::Error::Other(ReadError::FrameError)),
+            Some(0xFF) => Err(nb::Error::WouldBlock),
+            Some(_) => self.next().ok_or(nb::Error::WouldBlock),
+        }
+    }
+}
+
+struct StepClock(Cell<u64>);
+
+impl Clock for &StepClock {
+    type Instant = u64;
+    type Duration = u64;
+
+    fn now(&self) -> u64 {
+        let now = self.0.get();
+        self.0.set(now + 1);
+        now
+    }
+}
+
+struct FuzzConfig;
+
+impl<'a> Config<&'a StepClock> for FuzzConfig {
+    const BUS_MIN_IDLE_DURATION: u64 = 1;
+    const BUS_MAX_IDLE_DURATION: u64 = 8;
+    const ECHO_BYTE_TIMEOUT: u64 = 16;
+    const ECHO_FRAME_TIMEOUT: u64 = 1024;
+}
+
+fuzz_target!(|data: &[u8]| {
+    let (payload_len, script) = match data.split_first() {
+        Some((len, script)) => (*len as usize, script),
+        None => return,
+    };
+
+    let payload = [0xA5u8; 256];
+    let frame = Writer::package(Address::new(1), Address::new(2), &payload[..payload_len])
+        .expect("Payload always fits");
+    let mut frame = CsmaFrameInProgress::new(frame);
+
+    let clock = StepClock(Cell::new(0));
+    let transceiver = ScriptedTransceiver {
+        script,
+        pos: Cell::new(0),
+    };
+    let mut strategy =
+        CsmaStrategy::new::<FuzzConfig>(transceiver, &clock, StepRng::new(0, 1));
+
+    // Every poll consumes at least one operation, so this always terminates.
+    for _ in 0..=script.len() {
+        match strategy.send_or_receive(&mut frame) {
+            Ok(SendReceiveResult::SendComplete) => frame.reset(),
+            Ok(SendReceiveResult::Received(received)) => {
+                assert_eq!(
+                    received.contents.len(),
+                    *received.header.len as usize
+                );
+            }
+            Err(nb::Error::WouldBlock) => (),
+            Ok(SendReceiveResult::Expired) | Err(nb::Error::Other(())) => unreachable!(),
+        }
+    }
+});
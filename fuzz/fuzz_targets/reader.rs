@@ -0,0 +1,23 @@
+#![no_main]
+
+use kiri_protocol::{Reader, MAX_NAKED_LEN};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let mut reader = Reader::new();
+
+    for b in data {
+        let result = reader.feed(*b);
+        let is_error = result.is_err();
+
+        if let Ok(Some(frame)) = result {
+            assert_eq!(frame.contents.len(), *frame.header.len as usize);
+        }
+
+        assert!(reader.buffered_len() <= MAX_NAKED_LEN);
+
+        if is_error {
+            reader.clear();
+        }
+    }
+});
@@ -0,0 +1,335 @@
+//! A `futures::Stream` and `futures::Sink` of frames on a bus, for gateways built on an async runtime.
+//!
+//! A `FrameTransport` runs the strategy on a thread of its own, as the strategy has to be polled continuously rather
+//! than when the port becomes readable. Received frames are queued for the stream and frames to send are queued for
+//! the thread, waking the task waiting on either. Use `StreamExt::split` to receive and send from separate tasks,
+//! or `StreamExt::forward` to pipe frames from elsewhere onto the bus.
+
+use std::{
+    collections::VecDeque,
+    io,
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard},
+    task::{Context, Poll, Waker},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use futures_core::Stream;
+use futures_sink::Sink;
+use kiri_csma::{CsmaFrameInProgress, CsmaStrategy, SendReceiveResult, SystemClock, Transceiver};
+use kiri_protocol::{Frame, FrameOwned, FrameRef, Writer};
+use rand::RngCore;
+
+/// How many frames the sink takes before it is no longer ready, until the thread has sent some of them.
+pub const SEND_QUEUE_LEN: usize = 8;
+
+/// How long the thread sleeps while there is nothing to send, before polling the port again.
+///
+/// The port buffers what is received meanwhile, hence this only adds latency to receiving.
+const IDLE_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Shared between the transport and its thread.
+#[derive(Default)]
+struct State {
+    received: VecDeque<FrameOwned>,
+    to_send: VecDeque<Frame>,
+    /// Whether the thread is sending a frame it took from `to_send`.
+    sending: bool,
+    /// The transport was closed or dropped, hence the thread stops once it has sent what is queued.
+    closed: bool,
+    /// The thread stopped, either because the transport was closed or because the port failed.
+    stopped: bool,
+    /// Why the thread stopped, if the port failed, until it is yielded by the sink.
+    error: Option<io::Error>,
+    /// The task waiting for a frame to be received.
+    receiver: Option<Waker>,
+    /// The task waiting for frames to be sent.
+    sender: Option<Waker>,
+}
+
+impl State {
+    fn stopped_error(&mut self) -> io::Error {
+        self.error
+            .take()
+            .unwrap_or_else(|| io::Error::new(io::ErrorKind::BrokenPipe, "transport stopped"))
+    }
+}
+
+fn wake(waker: &mut Option<Waker>) {
+    if let Some(waker) = waker.take() {
+        waker.wake();
+    }
+}
+
+/// Frames on the bus as a `Stream` of received frames and a `Sink` of frames to send.
+///
+/// The stream ends and the sink fails once the port fails. Closing the sink flushes the frames queued and stops the
+/// thread, ending the stream as well. Dropping the transport stops the thread once it has sent what is queued.
+pub struct FrameTransport {
+    state: Arc<Mutex<State>>,
+    thread: JoinHandle<()>,
+}
+
+impl FrameTransport {
+    /// Run `strategy` on a thread of its own.
+    pub fn spawn<T, R>(strategy: CsmaStrategy<T, SystemClock, R>) -> Self
+    where
+        T: Transceiver<Error = io::Error> + Send + 'static,
+        R: RngCore + Send + 'static,
+    {
+        let state = Arc::new(Mutex::new(State::default()));
+        let thread = {
+            let state = state.clone();
+            thread::spawn(move || run(strategy, &state))
+        };
+        Self { state, thread }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        lock(&self.state)
+    }
+}
+
+impl Drop for FrameTransport {
+    fn drop(&mut self) {
+        self.lock().closed = true;
+        self.thread.thread().unpark();
+    }
+}
+
+impl Stream for FrameTransport {
+    type Item = FrameOwned;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<FrameOwned>> {
+        let mut state = self.lock();
+        match state.received.pop_front() {
+            Some(frame) => Poll::Ready(Some(frame)),
+            None if state.stopped => Poll::Ready(None),
+            None => {
+                state.receiver = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl Sink<FrameOwned> for FrameTransport {
+    type Error = io::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let mut state = self.lock();
+        if state.stopped || state.closed {
+            Poll::Ready(Err(state.stopped_error()))
+        } else if state.to_send.len() < SEND_QUEUE_LEN {
+            Poll::Ready(Ok(()))
+        } else {
+            state.sender = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+
+    fn start_send(self: Pin<&mut Self>, frame: FrameOwned) -> io::Result<()> {
+        let frame = Writer::repackage(&FrameRef::from(&frame))
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, format!("{:?}", e)))?;
+        self.lock().to_send.push_back(frame);
+        self.thread.thread().unpark();
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let mut state = self.lock();
+        if state.to_send.is_empty() && !state.sending {
+            Poll::Ready(Ok(()))
+        } else if state.stopped {
+            Poll::Ready(Err(state.stopped_error()))
+        } else {
+            state.sender = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let mut state = self.lock();
+        state.closed = true;
+        if state.stopped {
+            // Sent everything, unless the port failed meanwhile.
+            Poll::Ready(state.error.take().map_or(Ok(()), Err))
+        } else {
+            state.sender = Some(cx.waker().clone());
+            drop(state);
+            self.thread.thread().unpark();
+            Poll::Pending
+        }
+    }
+}
+
+fn lock(state: &Mutex<State>) -> MutexGuard<'_, State> {
+    // The thread does not panic while holding the lock, nor do the tasks.
+    state.lock().unwrap()
+}
+
+fn to_owned(frame: FrameRef<'_>) -> Option<FrameOwned> {
+    frame.try_into().ok()
+}
+
+/// Poll `strategy` until the transport is closed or the port fails, exchanging frames through `state`.
+fn run<T, R>(mut strategy: CsmaStrategy<T, SystemClock, R>, state: &Mutex<State>)
+where
+    T: Transceiver<Error = io::Error>,
+    R: RngCore,
+{
+    let mut sending = None;
+    let result = loop {
+        if sending.is_none() {
+            let mut state = lock(state);
+            match state.to_send.pop_front() {
+                Some(frame) => {
+                    sending = Some(CsmaFrameInProgress::new(frame));
+                    state.sending = true;
+                    wake(&mut state.sender);
+                }
+                None if state.closed => break Ok(()),
+                None => (),
+            }
+        }
+
+        let received = match &mut sending {
+            Some(frame) => match strategy.send_or_receive_with(frame, to_owned) {
+                Ok(SendReceiveResult::SendComplete | SendReceiveResult::Expired) => {
+                    sending = None;
+                    let mut state = lock(state);
+                    state.sending = false;
+                    wake(&mut state.sender);
+                    None
+                }
+                Ok(SendReceiveResult::Received(frame)) => frame,
+                Err(nb::Error::WouldBlock) => None,
+                Err(nb::Error::Other(e)) => break Err(e),
+            },
+            None => match strategy.receive() {
+                Ok(frame) => to_owned(frame),
+                Err(nb::Error::WouldBlock) => {
+                    thread::park_timeout(IDLE_POLL_INTERVAL);
+                    None
+                }
+                Err(nb::Error::Other(e)) => break Err(e),
+            },
+        };
+
+        if let Some(frame) = received {
+            let mut state = lock(state);
+            state.received.push_back(frame);
+            wake(&mut state.receiver);
+        }
+    };
+
+    let mut state = lock(state);
+    state.stopped = true;
+    state.error = result.err();
+    wake(&mut state.receiver);
+    wake(&mut state.sender);
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{executor::block_on, SinkExt, StreamExt};
+    use kiri_csma::ReadError;
+    use kiri_host::transceiver::HostConfig;
+    use kiri_protocol::Address;
+    use rand::rngs::mock::StepRng;
+
+    use super::*;
+
+    /// Bytes on an in-memory bus, on which everything written is looped back.
+    #[derive(Debug, Default)]
+    struct Wire {
+        incoming: VecDeque<u8>,
+        written: Vec<u8>,
+        /// Whether reads fail like those of a serial port of which the adapter was unplugged.
+        hung_up: bool,
+    }
+
+    /// Transceiver on a `Wire` that the test holds on to as well.
+    #[derive(Debug, Clone, Default)]
+    struct MemoryTransceiver(Arc<Mutex<Wire>>);
+
+    impl Transceiver for MemoryTransceiver {
+        type Error = io::Error;
+
+        fn handle_interrupts(&self) {}
+
+        fn bus_is_idle(&self) -> bool {
+            self.0.lock().unwrap().incoming.is_empty()
+        }
+
+        fn write(&mut self, byte: u8) -> nb::Result<(), Self::Error> {
+            let mut wire = self.0.lock().unwrap();
+            wire.written.push(byte);
+            wire.incoming.push_back(byte);
+            Ok(())
+        }
+
+        fn read(&mut self) -> nb::Result<u8, ReadError<Self::Error>> {
+            let mut wire = self.0.lock().unwrap();
+            if wire.hung_up {
+                let e = io::Error::new(io::ErrorKind::UnexpectedEof, "serial port hung up");
+                return Err(nb::Error::Other(ReadError::UnderlyingError(e)));
+            }
+            wire.incoming.pop_front().ok_or(nb::Error::WouldBlock)
+        }
+    }
+
+    fn transport(transceiver: &MemoryTransceiver) -> FrameTransport {
+        let strategy = CsmaStrategy::new::<HostConfig>(
+            transceiver.clone(),
+            SystemClock,
+            StepRng::new(0, 7919),
+        );
+        FrameTransport::spawn(strategy)
+    }
+
+    #[test]
+    fn receive_and_send() {
+        let transceiver = MemoryTransceiver::default();
+        let mut transport = transport(&transceiver);
+
+        let frame =
+            Writer::package_with_sequence(Address::new(3), Address::new(1), 1, b"reading").unwrap();
+        transceiver
+            .0
+            .lock()
+            .unwrap()
+            .incoming
+            .extend(frame.as_slice());
+
+        let received = block_on(transport.next()).unwrap();
+        assert_eq!(received.header.address_src, Address::new(3));
+        assert_eq!(received.contents.as_slice(), b"reading");
+
+        // Sending it back puts the very same frame on the bus.
+        block_on(transport.send(received)).unwrap();
+        assert_eq!(transceiver.0.lock().unwrap().written, frame.as_slice());
+    }
+
+    #[test]
+    fn close() {
+        let transceiver = MemoryTransceiver::default();
+        let mut transport = transport(&transceiver);
+
+        block_on(transport.close()).unwrap();
+        assert!(block_on(transport.next()).is_none());
+    }
+
+    #[test]
+    fn hang_up() {
+        let transceiver = MemoryTransceiver::default();
+        let mut transport = transport(&transceiver);
+
+        transceiver.0.lock().unwrap().hung_up = true;
+        assert!(block_on(transport.next()).is_none());
+
+        let e = block_on(transport.close()).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -0,0 +1,57 @@
+use std::{fmt::Display, process::exit, str::FromStr};
+
+use kiri_protocol::Address;
+
+/// Minimal command line parser shared by the host binaries.
+pub struct Args {
+    args: std::iter::Peekable<std::env::Args>,
+    usage: &'static str,
+}
+
+impl Args {
+    pub fn new(usage: &'static str) -> Self {
+        let mut args = std::env::args().peekable();
+        args.next(); // Skip program name.
+        Self { args, usage }
+    }
+
+    /// Continue parsing the arguments of a subcommand, which has a usage of its own.
+    pub fn subcommand(self, usage: &'static str) -> Self {
+        Self { usage, ..self }
+    }
+
+    /// Print the usage and exit the process.
+    pub fn fail(&self, msg: impl Display) -> ! {
+        eprintln!("error: {}\n\n{}", msg, self.usage);
+        exit(2)
+    }
+
+    pub fn next_arg(&mut self) -> Option<String> {
+        self.args.next()
+    }
+
+    /// The value belonging to the flag `flag`.
+    pub fn value(&mut self, flag: &str) -> String {
+        match self.args.next() {
+            Some(value) => value,
+            None => self.fail(format!("missing value for {}", flag)),
+        }
+    }
+
+    pub fn parse<T: FromStr>(&mut self, flag: &str) -> T {
+        let value = self.value(flag);
+        match value.parse() {
+            Ok(value) => value,
+            Err(_) => self.fail(format!("invalid value {:?} for {}", value, flag)),
+        }
+    }
+
+    /// Parse an address given as 8 hexadecimal digits.
+    pub fn address(&mut self, flag: &str) -> Address {
+        let value = self.value(flag);
+        match Address::from_hex_str(&value) {
+            Ok(address) => address,
+            Err(e) => self.fail(format!("{} for {}: {:?}", e, flag, value)),
+        }
+    }
+}
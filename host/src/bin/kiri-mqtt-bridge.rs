@@ -0,0 +1,219 @@
+use std::{
+    io::{self, Write},
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use kiri_host::{
+    args::Args,
+    homeassistant::Discovery,
+    mqtt::{MqttClient, Packet},
+    serial::SerialPort,
+};
+use kiri_protocol::{iter::ReadFramer, Address, Writer};
+
+const USAGE: &str = "usage: kiri-mqtt-bridge <port> [--baud <rate>] [--broker <host:port>] [--client-id <id>] [--prefix <topic>]
+                        [--ha-class <start>-<end>=<component>]... [--ha-address <addr>] [--ha-prefix <topic>]
+
+Publishes every frame on the bus to `<prefix>/<src>/<dst>`, and puts the payload of every message
+published to `<prefix>/send/<src>/<dst>` on the bus. The prefix defaults to `kiri`.
+
+With `--ha-class`, announces the nodes in the range of addresses to Home Assistant as `sensor`,
+`binary_sensor` or `switch` once they send a frame, and publishes their frames to `<prefix>/<addr>/state`.
+Switches are sent commands from `--ha-address`. The discovery prefix defaults to `homeassistant`.";
+
+const KEEP_ALIVE_SECS: u16 = 30;
+
+fn main() {
+    pretty_env_logger::init();
+
+    let mut args = Args::new(USAGE);
+    let mut port = None;
+    let mut baud = 115200;
+    let mut broker = "localhost:1883".to_string();
+    let mut client_id = "kiri-mqtt-bridge".to_string();
+    let mut prefix = "kiri".to_string();
+    let mut ha_classes = Vec::new();
+    let mut ha_address = None;
+    let mut ha_prefix = "homeassistant".to_string();
+
+    while let Some(arg) = args.next_arg() {
+        match arg.as_str() {
+            "--baud" => baud = args.parse("--baud"),
+            "--broker" => broker = args.value("--broker"),
+            "--client-id" => client_id = args.value("--client-id"),
+            "--prefix" => prefix = args.value("--prefix"),
+            "--ha-class" => ha_classes.push(args.parse("--ha-class")),
+            "--ha-address" => ha_address = Some(args.address("--ha-address")),
+            "--ha-prefix" => ha_prefix = args.value("--ha-prefix"),
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            _ if port.is_none() => port = Some(arg),
+            _ => args.fail(format!("unexpected argument {:?}", arg)),
+        }
+    }
+
+    let port = port.unwrap_or_else(|| args.fail("missing port"));
+    let discovery = match ha_classes.is_empty() {
+        true => None,
+        false => Some(
+            Discovery::new(&ha_prefix, &prefix, ha_classes, ha_address)
+                .unwrap_or_else(|e| args.fail(e)),
+        ),
+    };
+    let serial = SerialPort::open(&port, baud, 0).unwrap_or_else(|e| args.fail(e));
+
+    if let Err(e) = bridge(serial, &broker, &client_id, &prefix, discovery) {
+        log::error!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+fn bridge(
+    serial: SerialPort,
+    broker: &str,
+    client_id: &str,
+    prefix: &str,
+    discovery: Option<Discovery>,
+) -> io::Result<()> {
+    let mut mqtt = MqttClient::connect(broker, client_id, KEEP_ALIVE_SECS)?;
+    mqtt.subscribe(&format!("{}/send/+/+", prefix))?;
+    if let Some(discovery) = &discovery {
+        mqtt.subscribe(&discovery.status_topic())?;
+    }
+    log::info!("Connected to {}", broker);
+
+    let publisher = Arc::new(Mutex::new(mqtt.try_clone()?));
+    let discovery = discovery.map(|discovery| Arc::new(Mutex::new(discovery)));
+
+    {
+        let publisher = publisher.clone();
+        let discovery = discovery.clone();
+        let serial = serial.try_clone()?;
+        let prefix = prefix.to_string();
+        thread::spawn(move || {
+            if let Err(e) = serial_to_mqtt(serial, &publisher, &prefix, discovery.as_deref()) {
+                log::error!("Serial: {}", e);
+                std::process::exit(1);
+            }
+        });
+    }
+
+    {
+        let publisher = publisher.clone();
+        thread::spawn(move || loop {
+            thread::sleep(Duration::from_secs(KEEP_ALIVE_SECS as u64 / 2));
+            if let Err(e) = publisher.lock().unwrap().ping() {
+                log::error!("MQTT: {}", e);
+                std::process::exit(1);
+            }
+        });
+    }
+
+    mqtt_to_serial(mqtt, serial, prefix, &publisher, discovery.as_deref())
+}
+
+fn serial_to_mqtt(
+    serial: SerialPort,
+    publisher: &Mutex<MqttClient>,
+    prefix: &str,
+    discovery: Option<&Mutex<Discovery>>,
+) -> io::Result<()> {
+    let mut framer = ReadFramer::new(serial);
+    for result in framer.by_ref() {
+        match result {
+            Ok(frame) => {
+                let topic = format!(
+                    "{}/{}/{}",
+                    prefix, frame.header.address_src, frame.header.address_dst
+                );
+                log::debug!("Publishing {} bytes to {}", frame.contents.len(), topic);
+                publisher.lock().unwrap().publish(&topic, &frame.contents)?;
+
+                if let Some(discovery) = discovery {
+                    let mut discovery = discovery.lock().unwrap();
+                    let src = frame.header.address_src;
+                    if let Some(announcement) = discovery.found(src) {
+                        log::info!("Announcing {} on {}", src, announcement.topic);
+                        publisher.lock().unwrap().publish_retained(
+                            &announcement.topic,
+                            announcement.payload.as_bytes(),
+                        )?;
+                    }
+                    if let Some(topic) = discovery.state_topic(src) {
+                        publisher.lock().unwrap().publish(&topic, &frame.contents)?;
+                    }
+                }
+            }
+            Err(e) => log::debug!("Dropped frame: {:?}", e),
+        }
+    }
+
+    match framer.take_error() {
+        Some(e) => Err(e),
+        None => Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "serial port closed",
+        )),
+    }
+}
+
+/// Parse `<prefix>/send/<src>/<dst>` into its addresses.
+fn parse_send_topic(topic: &str, prefix: &str) -> Option<(Address, Address)> {
+    let rest = topic.strip_prefix(prefix)?.strip_prefix("/send/")?;
+    let (src, dst) = rest.split_once('/')?;
+    Some((
+        Address::from_hex_str(src).ok()?,
+        Address::from_hex_str(dst).ok()?,
+    ))
+}
+
+fn mqtt_to_serial(
+    mut mqtt: MqttClient,
+    mut serial: SerialPort,
+    prefix: &str,
+    publisher: &Mutex<MqttClient>,
+    discovery: Option<&Mutex<Discovery>>,
+) -> io::Result<()> {
+    loop {
+        let (topic, payload) = match mqtt.read()? {
+            Packet::Publish { topic, payload } => (topic, payload),
+            Packet::Other(_) => continue,
+        };
+
+        if let Some(discovery) = discovery {
+            let discovery = discovery.lock().unwrap();
+            if topic == discovery.status_topic() {
+                // Home Assistant forgets the entities when it restarts, unless the broker retained them.
+                if payload == b"online" {
+                    for announcement in discovery.announcements() {
+                        publisher.lock().unwrap().publish_retained(
+                            &announcement.topic,
+                            announcement.payload.as_bytes(),
+                        )?;
+                    }
+                }
+                continue;
+            }
+        }
+
+        let (src, dst) = match parse_send_topic(&topic, prefix) {
+            Some(addresses) => addresses,
+            None => {
+                log::warn!("Ignoring message on {}", topic);
+                continue;
+            }
+        };
+
+        match Writer::package(src, dst, &payload) {
+            Ok(frame) => {
+                serial.write_all(frame.as_slice())?;
+                log::debug!("Sent {} bytes {} -> {}", payload.len(), src, dst);
+            }
+            Err(e) => log::warn!("Can not send message on {}: {:?}", topic, e),
+        }
+    }
+}
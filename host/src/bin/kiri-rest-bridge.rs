@@ -0,0 +1,173 @@
+use std::{
+    io::{self, BufReader},
+    net::{TcpListener, TcpStream},
+    sync::{mpsc::RecvTimeoutError, Arc},
+    thread,
+    time::Duration,
+};
+
+use kiri_host::{
+    args::Args,
+    bus,
+    http::{self, write_response, Request, WebSocket},
+    metrics::{self, BusMetrics},
+    router::{FrameRouter, ReconnectPolicy, Route, RouterHandle},
+};
+use kiri_protocol::{Address, FrameRef, Writer};
+use serde_json::{json, Value};
+
+const USAGE: &str = "usage: kiri-rest-bridge <port> [--baud <rate>] [--listen <host:port>]
+                        [--metrics <host:port>]
+
+Serves `POST /send`, which puts a frame on the bus. Its JSON body holds the `src` and `dst` addresses as
+8 hexadecimal digits, and the `payload` as hexadecimal string. Streams every frame on the bus as JSON
+messages on the WebSocket `/frames`. Listens on `localhost:8080` by default.
+
+The port is opened again whenever it fails, buffering the frames to send meanwhile.
+
+With `--metrics`, serves counters of the frames received, of the strategy and the state of the port on
+`/metrics` for Prometheus.";
+
+/// Interval to refresh the counters of the strategy at, when no frames arrive.
+const METRICS_INTERVAL: Duration = Duration::from_secs(1);
+
+fn main() {
+    pretty_env_logger::init();
+
+    let mut args = Args::new(USAGE);
+    let mut port = None;
+    let mut baud = 115200;
+    let mut listen = "localhost:8080".to_string();
+    let mut metrics_listen = None;
+
+    while let Some(arg) = args.next_arg() {
+        match arg.as_str() {
+            "--baud" => baud = args.parse("--baud"),
+            "--listen" => listen = args.value("--listen"),
+            "--metrics" => metrics_listen = Some(args.value("--metrics")),
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            _ if port.is_none() => port = Some(arg),
+            _ => args.fail(format!("unexpected argument {:?}", arg)),
+        }
+    }
+
+    let port = port.unwrap_or_else(|| args.fail("missing port"));
+    let listener = TcpListener::bind(&listen).unwrap_or_else(|e| args.fail(e));
+    log::info!("Listening on {}", listen);
+
+    let router =
+        FrameRouter::reconnecting(move || bus::open(&port, baud), ReconnectPolicy::default());
+
+    if let Some(metrics_listen) = metrics_listen {
+        let metrics = Arc::new(BusMetrics::new());
+        metrics::serve(&metrics_listen, metrics.clone()).unwrap_or_else(|e| args.fail(e));
+        log::info!("Serving metrics on {}", metrics_listen);
+        let handle = router.handle();
+        thread::spawn(move || collect(&handle, &metrics));
+    }
+
+    let handle = router.handle();
+    http::serve(listener, http::MAX_CONNECTIONS, move |stream| {
+        serve(stream, &handle)
+    });
+}
+
+fn serve(stream: &TcpStream, router: &RouterHandle) -> io::Result<()> {
+    let request = match Request::read(BufReader::new(stream)) {
+        Ok(request) => request,
+        Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+            return respond(
+                stream,
+                400,
+                "Bad Request",
+                json!({ "error": e.to_string() }),
+            );
+        }
+        Err(e) => return Err(e),
+    };
+
+    match (request.method.as_str(), request.path.as_str()) {
+        ("POST", "/send") => match parse_send(&request.body) {
+            Ok((src, dst, payload)) => match Writer::package(src, dst, &payload) {
+                Ok(frame) => {
+                    router.send(frame)?;
+                    log::debug!("Queued {} bytes {} -> {}", payload.len(), src, dst);
+                    respond(stream, 202, "Accepted", json!({}))
+                }
+                Err(e) => respond(
+                    stream,
+                    400,
+                    "Bad Request",
+                    json!({ "error": e.to_string() }),
+                ),
+            },
+            Err(e) => respond(stream, 400, "Bad Request", json!({ "error": e })),
+        },
+        ("GET", "/frames") if request.is_websocket() => {
+            let frames = router.subscribe(Route::All);
+            let mut socket = WebSocket::accept(stream, &request)?;
+            for frame in frames {
+                socket.send_text(&frame_to_json(&FrameRef::from(&frame)).to_string())?;
+            }
+            Ok(())
+        }
+        (_, "/send" | "/frames") => respond(
+            stream,
+            405,
+            "Method Not Allowed",
+            json!({ "error": "method not allowed" }),
+        ),
+        _ => respond(stream, 404, "Not Found", json!({ "error": "not found" })),
+    }
+}
+
+/// Keep `metrics` up to date with the frames, counters and link state of `router`.
+fn collect(router: &RouterHandle, metrics: &BusMetrics) {
+    let frames = router.subscribe(Route::All);
+    loop {
+        match frames.recv_timeout(METRICS_INTERVAL) {
+            Ok(frame) => metrics.record_frame(&FrameRef::from(&frame)),
+            Err(RecvTimeoutError::Timeout) => (),
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+        metrics.set_link_up(router.is_up());
+        metrics.set_stats(router.stats());
+    }
+}
+
+fn respond(stream: &TcpStream, status: u16, reason: &str, body: Value) -> io::Result<()> {
+    write_response(
+        stream,
+        status,
+        reason,
+        "application/json",
+        body.to_string().as_bytes(),
+    )
+}
+
+/// Parse the body of `POST /send` into the addresses and the payload.
+fn parse_send(body: &[u8]) -> Result<(Address, Address, Vec<u8>), String> {
+    let body: Value = serde_json::from_slice(body).map_err(|e| e.to_string())?;
+    let field = |name: &str| {
+        body.get(name)
+            .and_then(Value::as_str)
+            .ok_or_else(|| format!("missing {}", name))
+    };
+    let address =
+        |name: &str| Address::from_hex_str(field(name)?).map_err(|e| format!("{} for {}", e, name));
+    let payload = hex::decode(field("payload")?).map_err(|e| format!("{} for payload", e))?;
+    Ok((address("src")?, address("dst")?, payload))
+}
+
+fn frame_to_json(frame: &FrameRef) -> Value {
+    json!({
+        "src": frame.header.address_src.to_string(),
+        "dst": frame.header.address_dst.to_string(),
+        "sequence": frame.sequence(),
+        "hop_limit": frame.hop_limit(),
+        "payload": hex::encode(frame.payload().unwrap_or(frame.contents)),
+    })
+}
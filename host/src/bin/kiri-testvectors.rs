@@ -0,0 +1,84 @@
+use std::{
+    fs,
+    io::{self, Write},
+};
+
+use kiri_host::args::Args;
+use kiri_protocol::testvectors::{vectors, TestVector};
+
+const USAGE: &str = "usage: kiri-testvectors generate
+       kiri-testvectors check <file>
+
+`generate` prints all canonical test vectors, one per line as `<name> <hex encoded frame>`.
+`check` validates a file in the same format, as produced by another implementation.";
+
+fn main() {
+    let mut args = Args::new(USAGE);
+
+    match args.next_arg().as_deref() {
+        Some("generate") => {
+            if let Err(e) = generate(io::stdout().lock()) {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+        Some("check") => {
+            let path = args.value("check");
+            let contents = fs::read_to_string(&path).unwrap_or_else(|e| args.fail(e));
+            if !check(&contents) {
+                std::process::exit(1);
+            }
+        }
+        Some("-h") | Some("--help") => println!("{}", USAGE),
+        Some(arg) => args.fail(format!("unknown command {:?}", arg)),
+        None => args.fail("missing command"),
+    }
+}
+
+fn generate(mut output: impl Write) -> io::Result<()> {
+    for vector in vectors() {
+        let frame = vector.encode().expect("Test vectors must be encodable");
+        writeln!(output, "{} {}", vector, hex::encode(frame.as_slice()))?;
+    }
+    Ok(())
+}
+
+/// Check all vectors in `contents`, returning whether all of them are canonical.
+fn check(contents: &str) -> bool {
+    let mut passed = 0;
+    let mut failed = 0;
+
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let result = line
+            .split_once(' ')
+            .ok_or_else(|| "expected `<name> <hex>`".to_string())
+            .and_then(|(name, encoded)| {
+                let vector = TestVector::from_name(name)
+                    .ok_or_else(|| format!("unknown vector {:?}", name))?;
+                let encoded = hex::decode(encoded.trim()).map_err(|e| e.to_string())?;
+                vector
+                    .check_encoded(&encoded)
+                    .map_err(|e| format!("{}: {:?}", vector, e))
+            });
+
+        match result {
+            Ok(()) => passed += 1,
+            Err(e) => {
+                failed += 1;
+                println!("line {}: {}", i + 1, e);
+            }
+        }
+    }
+
+    let missing = vectors().count().saturating_sub(passed + failed);
+    println!(
+        "{} passed, {} failed, {} not covered",
+        passed, failed, missing
+    );
+    failed == 0
+}
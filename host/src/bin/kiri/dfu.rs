@@ -0,0 +1,134 @@
+use std::{io, time::Duration};
+
+use kiri_csma::retry::RetryPolicy;
+use kiri_dfu::{Progress, Sender, Status, DEFAULT_CHUNK_LEN};
+use kiri_host::{
+    args::Args,
+    bus::{self, HostStrategy},
+};
+use kiri_protocol::{Address, MAX_MESSAGE_LEN};
+
+use crate::{parse_address, Options};
+
+pub const USAGE: &str =
+    "usage: kiri dfu <addr> <image> [--chunk <len>] [--timeout <ms>] [--retries <count|forever>]
+
+Transfers a firmware image to the node at `<addr>`, which commits it once it is received completely.
+Requests that are not answered within the timeout are repeated, up to `--retries` times in a row,
+or until they are answered with `--retries forever`. An interrupted transfer resumes where the
+node left off when running this again.";
+
+pub fn run(mut args: Args, options: &Options) {
+    let mut dst = None;
+    let mut file = None;
+    let mut chunk_len = DEFAULT_CHUNK_LEN;
+    let mut timeout_ms: u64 = 500;
+    let mut retries = Some(10);
+
+    while let Some(arg) = args.next_arg() {
+        match arg.as_str() {
+            "--chunk" => chunk_len = args.parse("--chunk"),
+            "--timeout" => timeout_ms = args.parse("--timeout"),
+            "--retries" => {
+                retries = match args.value("--retries").as_str() {
+                    "forever" => None,
+                    count => Some(count.parse().unwrap_or_else(|_| {
+                        args.fail(format!("invalid value {:?} for --retries", count))
+                    })),
+                }
+            }
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            _ if dst.is_none() => dst = Some(parse_address(&args, &arg)),
+            _ if file.is_none() => file = Some(arg),
+            _ => args.fail(format!("unexpected argument {:?}", arg)),
+        }
+    }
+
+    let dst = dst.unwrap_or_else(|| args.fail("missing address"));
+    let file = file.unwrap_or_else(|| args.fail("missing image"));
+    let src = options.address(&args);
+    if chunk_len == 0 || chunk_len + kiri_dfu::DATA_HEADER_LEN > MAX_MESSAGE_LEN {
+        args.fail("chunk length does not fit a frame");
+    }
+    let image = std::fs::read(&file).unwrap_or_else(|e| args.fail(format!("{}: {}", file, e)));
+
+    let mut strategy = options.open(&args);
+
+    let mut sender = Sender::new(&image, chunk_len);
+    let policy = RetryPolicy {
+        retries,
+        timeout: Duration::from_millis(timeout_ms),
+    };
+    match transfer(&mut strategy, &mut sender, src, dst, &policy) {
+        Ok(()) => log::info!("Image of {} bytes committed", image.len()),
+        Err(e) => {
+            log::error!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn transfer(
+    strategy: &mut HostStrategy,
+    sender: &mut Sender,
+    src: Address,
+    dst: Address,
+    policy: &RetryPolicy<Duration>,
+) -> io::Result<()> {
+    let metadata = sender.metadata();
+    log::info!(
+        "Transferring {} bytes with CRC {:08x}",
+        metadata.len,
+        metadata.crc
+    );
+
+    let mut attempts = 0;
+    loop {
+        let frame = sender
+            .request()
+            .package(src, dst)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, format!("{:?}", e)))?;
+
+        let status = match request(strategy, frame, src, dst, policy.timeout)? {
+            Some(status) => status,
+            None if policy.may_retry(attempts) => {
+                attempts += 1;
+                match policy.retries {
+                    Some(retries) => log::warn!("No answer, retrying ({}/{})", attempts, retries),
+                    None => log::warn!("No answer, retrying ({})", attempts),
+                }
+                continue;
+            }
+            None => return Err(io::Error::new(io::ErrorKind::TimedOut, "no answer")),
+        };
+        attempts = 0;
+
+        match sender.handle_status(&status) {
+            Progress::InProgress { sent, total } => log::info!("{}/{} bytes", sent, total),
+            Progress::Done => return Ok(()),
+            Progress::Failed(state) => {
+                return Err(io::Error::other(format!("node failed: {:?}", state)))
+            }
+        }
+    }
+}
+
+/// Send a request, and wait for the status that answers it.
+fn request(
+    strategy: &mut HostStrategy,
+    frame: kiri_protocol::Frame,
+    src: Address,
+    dst: Address,
+    timeout: Duration,
+) -> io::Result<Option<Status>> {
+    bus::request(strategy, frame, timeout, |frame| {
+        if frame.header.address_src == dst && frame.header.address_dst == src {
+            Status::parse(frame.contents)
+        } else {
+            None
+        }
+    })
+}
@@ -0,0 +1,34 @@
+use std::fs;
+
+use kiri_host::args::Args;
+use kiri_protocol::dissector::LuaDissector;
+
+pub const USAGE: &str = "usage: kiri gen-dissector [<path>]
+
+Writes the Wireshark dissector for bus captures to `<path>`, or to stdout. It is generated from the
+definitions of the protocol, and is kept in `contrib/wireshark/kiri.lua`.";
+
+pub fn run(mut args: Args) {
+    let mut path = None;
+
+    while let Some(arg) = args.next_arg() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            _ if path.is_none() => path = Some(arg),
+            _ => args.fail(format!("unexpected argument {:?}", arg)),
+        }
+    }
+
+    let dissector = LuaDissector.to_string();
+    match path {
+        Some(path) => {
+            if let Err(e) = fs::write(&path, dissector) {
+                args.fail(format!("{}: {}", e, path));
+            }
+        }
+        None => print!("{}", dissector),
+    }
+}
@@ -0,0 +1,40 @@
+use std::fs;
+
+use kiri_csma::transitions::{Dot, PlantUml};
+use kiri_host::args::Args;
+
+pub const USAGE: &str = "usage: kiri gen-state-machine [--plantuml] [<path>]
+
+Writes the state machine of the CSMA strategy as a Graphviz digraph to `<path>`, or to stdout. Use
+`--plantuml` for a PlantUML state diagram instead. It is rendered from the table of transitions
+that the strategy checks its state changes against.";
+
+pub fn run(mut args: Args) {
+    let mut path = None;
+    let mut plantuml = false;
+
+    while let Some(arg) = args.next_arg() {
+        match arg.as_str() {
+            "--plantuml" => plantuml = true,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            _ if path.is_none() => path = Some(arg),
+            _ => args.fail(format!("unexpected argument {:?}", arg)),
+        }
+    }
+
+    let diagram = match plantuml {
+        true => PlantUml.to_string(),
+        false => Dot.to_string(),
+    };
+    match path {
+        Some(path) => {
+            if let Err(e) = fs::write(&path, diagram) {
+                args.fail(format!("{}: {}", e, path));
+            }
+        }
+        None => print!("{}", diagram),
+    }
+}
@@ -0,0 +1,111 @@
+use std::env;
+
+use kiri_host::{
+    args::Args,
+    bus::{self, HostStrategy},
+};
+use kiri_protocol::Address;
+
+mod dfu;
+mod gen_dissector;
+mod gen_state_machine;
+mod ping;
+mod scan;
+mod send;
+mod sniff;
+mod stats;
+
+const USAGE: &str =
+    "usage: kiri [--port <port>] [--baud <rate>] [--address <addr>] <command> [<args>]
+
+Commands:
+  sniff   decode and print all frames on the bus
+  send    package a payload into a frame and write it to the bus
+  ping    send echo requests to a node, and report the round-trip time and loss
+  scan    list the addresses of the nodes on the bus
+  stats   ask a node for its health, and print its counters and uptime
+  dfu     transfer a firmware image to a node
+
+  gen-dissector      write the Wireshark dissector for bus captures
+  gen-state-machine  write the state machine of the CSMA strategy as a diagram
+
+The port defaults to `$KIRI_PORT`. The address of this host on the bus, which commands that expect
+an answer send from, defaults to `$KIRI_ADDRESS`. Addresses are 8 hexadecimal digits.
+Run `kiri <command> --help` for the arguments of a command.";
+
+/// Options shared by all commands, for how to reach the bus.
+pub struct Options {
+    port: Option<String>,
+    pub baud: u32,
+    address: Option<Address>,
+}
+
+impl Options {
+    pub fn port(&self, args: &Args) -> &str {
+        match &self.port {
+            Some(port) => port,
+            None => args.fail("missing --port"),
+        }
+    }
+
+    /// The address of this host, for commands that expect an answer.
+    pub fn address(&self, args: &Args) -> Address {
+        self.address
+            .unwrap_or_else(|| args.fail("missing --address"))
+    }
+
+    /// Open the port to take part on the bus as a regular node.
+    pub fn open(&self, args: &Args) -> HostStrategy {
+        bus::open(self.port(args), self.baud).unwrap_or_else(|e| args.fail(e))
+    }
+}
+
+/// Parse the address `value` of a positional argument, given as 8 hexadecimal digits.
+pub fn parse_address(args: &Args, value: &str) -> Address {
+    Address::from_hex_str(value).unwrap_or_else(|e| args.fail(format!("{}: {:?}", e, value)))
+}
+
+fn main() {
+    pretty_env_logger::init();
+
+    let mut args = Args::new(USAGE);
+    let mut options = Options {
+        port: env::var("KIRI_PORT").ok(),
+        baud: 115200,
+        address: None,
+    };
+    if let Ok(address) = env::var("KIRI_ADDRESS") {
+        match Address::from_hex_str(&address) {
+            Ok(address) => options.address = Some(address),
+            Err(e) => args.fail(format!("{} for KIRI_ADDRESS: {:?}", e, address)),
+        }
+    }
+
+    let command = loop {
+        match args.next_arg() {
+            Some(arg) => match arg.as_str() {
+                "--port" => options.port = Some(args.value("--port")),
+                "--baud" => options.baud = args.parse("--baud"),
+                "--address" => options.address = Some(args.address("--address")),
+                "-h" | "--help" => {
+                    println!("{}", USAGE);
+                    return;
+                }
+                _ => break arg,
+            },
+            None => args.fail("missing command"),
+        }
+    };
+
+    match command.as_str() {
+        "sniff" => sniff::run(args.subcommand(sniff::USAGE), &options),
+        "send" => send::run(args.subcommand(send::USAGE), &options),
+        "ping" => ping::run(args.subcommand(ping::USAGE), &options),
+        "scan" => scan::run(args.subcommand(scan::USAGE), &options),
+        "stats" => stats::run(args.subcommand(stats::USAGE), &options),
+        "dfu" => dfu::run(args.subcommand(dfu::USAGE), &options),
+        "gen-dissector" => gen_dissector::run(args.subcommand(gen_dissector::USAGE)),
+        "gen-state-machine" => gen_state_machine::run(args.subcommand(gen_state_machine::USAGE)),
+        _ => args.fail(format!("unknown command {:?}", command)),
+    }
+}
@@ -0,0 +1,130 @@
+use std::{
+    io,
+    time::{Duration, Instant},
+};
+
+use kiri_csma::management::{Message, MAX_ECHO_DATA_LEN};
+use kiri_host::{
+    args::Args,
+    bus::{self, HostStrategy},
+};
+use kiri_protocol::{Address, FrameRef};
+
+use crate::{parse_address, Options};
+
+pub const USAGE: &str =
+    "usage: kiri ping <addr> [--count <count>] [--interval <ms>] [--timeout <ms>] [--size <bytes>]
+
+Sends echo requests to the node at `<addr>`, and reports the round-trip time of every reply.
+Finishes with the loss and the minimum, average and maximum round-trip time.";
+
+pub fn run(mut args: Args, options: &Options) {
+    let mut dst = None;
+    let mut count: u16 = 4;
+    let mut interval_ms: u64 = 1000;
+    let mut timeout_ms: u64 = 1000;
+    let mut size: usize = 0;
+
+    while let Some(arg) = args.next_arg() {
+        match arg.as_str() {
+            "--count" => count = args.parse("--count"),
+            "--interval" => interval_ms = args.parse("--interval"),
+            "--timeout" => timeout_ms = args.parse("--timeout"),
+            "--size" => size = args.parse("--size"),
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            _ if dst.is_none() => dst = Some(parse_address(&args, &arg)),
+            _ => args.fail(format!("unexpected argument {:?}", arg)),
+        }
+    }
+
+    let dst = dst.unwrap_or_else(|| args.fail("missing address"));
+    let src = options.address(&args);
+    if size > MAX_ECHO_DATA_LEN {
+        args.fail(format!("size exceeds {} bytes", MAX_ECHO_DATA_LEN));
+    }
+
+    let mut strategy = options.open(&args);
+
+    let data: Vec<u8> = (0..size).map(|i| i as u8).collect();
+    let timeout = Duration::from_millis(timeout_ms);
+    let mut rtts = Vec::new();
+    for sequence in 0..count {
+        let started_at = Instant::now();
+        match ping(&mut strategy, src, dst, sequence, &data, timeout) {
+            Ok(Some(rtt)) => {
+                println!(
+                    "reply from {}: seq={} time={:.2} ms",
+                    dst,
+                    sequence,
+                    rtt.as_secs_f64() * 1e3
+                );
+                rtts.push(rtt);
+            }
+            Ok(None) => println!("no reply from {}: seq={}", dst, sequence),
+            Err(e) => {
+                log::error!("{}", e);
+                std::process::exit(1);
+            }
+        }
+
+        if sequence + 1 < count {
+            let next = started_at + Duration::from_millis(interval_ms);
+            std::thread::sleep(next.saturating_duration_since(Instant::now()));
+        }
+    }
+
+    let lost = count as usize - rtts.len();
+    println!(
+        "{} sent, {} received, {:.1}% loss",
+        count,
+        rtts.len(),
+        100.0 * lost as f64 / count.max(1) as f64
+    );
+    if let (Some(min), Some(max)) = (rtts.iter().min(), rtts.iter().max()) {
+        let avg = rtts.iter().sum::<Duration>() / rtts.len() as u32;
+        println!(
+            "rtt min/avg/max = {:.2}/{:.2}/{:.2} ms",
+            min.as_secs_f64() * 1e3,
+            avg.as_secs_f64() * 1e3,
+            max.as_secs_f64() * 1e3
+        );
+    }
+    if rtts.is_empty() {
+        std::process::exit(1);
+    }
+}
+
+/// Send an echo request, yielding the round-trip time if the reply arrived within `timeout`.
+///
+/// The round-trip time is measured from when the request was sent completely, and hence excludes the time spent
+/// waiting for the bus to become idle.
+fn ping(
+    strategy: &mut HostStrategy,
+    src: Address,
+    dst: Address,
+    sequence: u16,
+    data: &[u8],
+    timeout: Duration,
+) -> io::Result<Option<Duration>> {
+    let is_reply = |frame: FrameRef| {
+        (frame.header.address_src == dst
+            && frame.header.address_dst == src
+            && matches!(
+                Message::parse(&frame),
+                Some(Message::EchoReply { sequence: s, data: d }) if s == sequence && d == data
+            ))
+        .then_some(())
+    };
+
+    let request = Message::EchoRequest { sequence, data }
+        .package(src, dst)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?;
+    bus::send(strategy, request, |_| None::<()>)?;
+
+    let sent_at = Instant::now();
+    let reply = bus::receive_until(strategy, sent_at + timeout, is_reply)?;
+    Ok(reply.map(|()| sent_at.elapsed()))
+}
@@ -0,0 +1,117 @@
+use std::{
+    collections::BTreeSet,
+    io,
+    time::{Duration, Instant},
+};
+
+use kiri_csma::management::Message;
+use kiri_host::{
+    args::Args,
+    bus::{self, HostStrategy},
+};
+use kiri_protocol::{Address, FrameRef};
+use rand::Rng;
+
+use crate::Options;
+
+pub const USAGE: &str =
+    "usage: kiri scan [--start <addr>] [--end <addr>] [--window <ms>] [--rounds <count>]
+
+Lists the addresses of the nodes on the bus within `--start` and `--end`, one per line. Defaults to
+the first 1024 addresses.
+
+All nodes in the range are asked at once, and answer at a moment within the window that depends on
+their address. The scan is repeated for up to `--rounds` rounds, until a round finds no new nodes,
+to find the nodes of which the answers collided.";
+
+/// How long to wait for answers after the window, for those that had to wait for the bus.
+const MARGIN: Duration = Duration::from_millis(250);
+
+pub fn run(mut args: Args, options: &Options) {
+    let mut start = Address::new(0);
+    let mut end = Address::new(0x3FF);
+    let mut window: u16 = 2000;
+    let mut rounds: u32 = 3;
+
+    while let Some(arg) = args.next_arg() {
+        match arg.as_str() {
+            "--start" => start = args.address("--start"),
+            "--end" => end = args.address("--end"),
+            "--window" => window = args.parse("--window"),
+            "--rounds" => rounds = args.parse("--rounds"),
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            _ => args.fail(format!("unexpected argument {:?}", arg)),
+        }
+    }
+    if start.to_primitive() > end.to_primitive() {
+        args.fail("start exceeds end");
+    }
+
+    let src = options.address(&args);
+    let mut strategy = options.open(&args);
+
+    let mut found = BTreeSet::new();
+    for round in 1..=rounds.max(1) {
+        match scan(&mut strategy, src, start, end, window) {
+            Ok(answered) => {
+                let new = answered.difference(&found).count();
+                log::info!(
+                    "Round {}: {} nodes answered, {} of them new",
+                    round,
+                    answered.len(),
+                    new
+                );
+                found.extend(answered);
+                if new == 0 {
+                    break;
+                }
+            }
+            Err(e) => {
+                log::error!("{}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    for address in &found {
+        println!("{}", Address::new(*address));
+    }
+    log::info!("{} nodes found", found.len());
+}
+
+/// Send a scan query for `start..=end` to all nodes, and collect who answered within the window.
+fn scan(
+    strategy: &mut HostStrategy,
+    src: Address,
+    start: Address,
+    end: Address,
+    window: u16,
+) -> io::Result<BTreeSet<u32>> {
+    let nonce = rand::thread_rng().gen();
+    let mut answered = BTreeSet::new();
+    let mut answer = |frame: FrameRef| {
+        if frame.header.address_dst == src
+            && Message::parse(&frame) == Some(Message::ScanReply { nonce })
+        {
+            answered.insert(frame.header.address_src.to_primitive());
+        }
+        None::<()>
+    };
+
+    let query = Message::ScanQuery {
+        start,
+        end,
+        window,
+        nonce,
+    }
+    .package(src, Address::multicast())
+    .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?;
+    bus::send(strategy, query, &mut answer)?;
+
+    let deadline = Instant::now() + Duration::from_millis(window as u64) + MARGIN;
+    bus::receive_until(strategy, deadline, answer)?;
+    Ok(answered)
+}
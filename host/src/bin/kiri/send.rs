@@ -0,0 +1,148 @@
+use std::{
+    io::{self, Read, Write},
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use kiri_host::{
+    args::Args,
+    bus::{self, HostStrategy},
+    serial::SerialPort,
+};
+use kiri_protocol::{Address, Frame, Writer};
+
+use crate::Options;
+
+pub const USAGE: &str =
+    "usage: kiri send --src <addr> --dst <addr> (--hex <bytes> | --file <path> | --stdin)
+                 [--repeat <count>] [--rate <frames per second>] [--csma | --stdout]
+
+Packages the payload into a frame and writes it to the bus, or to stdout with `--stdout`.
+A repeat count of 0 repeats indefinitely.
+With `--csma` the frames are sent using collision detection, instead of greedily.";
+
+enum Output {
+    Greedy(Box<dyn Write>),
+    Csma(Box<HostStrategy>),
+}
+
+impl Output {
+    fn send(&mut self, frame: Frame) -> io::Result<()> {
+        match self {
+            Output::Greedy(output) => {
+                output.write_all(frame.as_slice())?;
+                output.flush()
+            }
+            Output::Csma(strategy) => bus::send(strategy, frame, |_| None::<()>).map(|_| ()),
+        }
+    }
+}
+
+enum Payload {
+    Hex(String),
+    File(String),
+    Stdin,
+}
+
+pub fn run(mut args: Args, options: &Options) {
+    let mut src = None;
+    let mut dst = None;
+    let mut payload = None;
+    let mut repeat: u64 = 1;
+    let mut rate: Option<f64> = None;
+    let mut csma = false;
+    let mut stdout = false;
+
+    while let Some(arg) = args.next_arg() {
+        match arg.as_str() {
+            "--src" => src = Some(args.address("--src")),
+            "--dst" => dst = Some(args.address("--dst")),
+            "--hex" => payload = Some(Payload::Hex(args.value("--hex"))),
+            "--file" => payload = Some(Payload::File(args.value("--file"))),
+            "--stdin" => payload = Some(Payload::Stdin),
+            "--repeat" => repeat = args.parse("--repeat"),
+            "--rate" => rate = Some(args.parse("--rate")),
+            "--csma" => csma = true,
+            "--stdout" => stdout = true,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            _ => args.fail(format!("unexpected argument {:?}", arg)),
+        }
+    }
+
+    let src = src.unwrap_or_else(|| args.fail("missing --src"));
+    let dst = dst.unwrap_or_else(|| args.fail("missing --dst"));
+    let payload = match payload {
+        Some(payload) => read_payload(payload).unwrap_or_else(|e| args.fail(e)),
+        None => args.fail("missing payload"),
+    };
+    let interval = match rate {
+        Some(rate) if rate > 0. => Some(Duration::from_secs_f64(1. / rate)),
+        Some(_) => args.fail("rate must be positive"),
+        None => None,
+    };
+
+    let output = match (stdout, csma) {
+        (true, true) => args.fail("--stdout does not take part on the bus, unlike --csma"),
+        (true, false) => Output::Greedy(Box::new(io::stdout())),
+        (false, true) => Output::Csma(Box::new(options.open(&args))),
+        (false, false) => Output::Greedy(Box::new(
+            SerialPort::open(options.port(&args), options.baud, 0).unwrap_or_else(|e| args.fail(e)),
+        )),
+    };
+
+    if let Err(e) = send(output, src, dst, &payload, repeat, interval) {
+        log::error!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+fn read_payload(payload: Payload) -> Result<Vec<u8>, String> {
+    match payload {
+        Payload::Hex(str) => {
+            let str: String = str.split_whitespace().collect();
+            hex::decode(str).map_err(|e| format!("invalid hex payload: {}", e))
+        }
+        Payload::File(path) => std::fs::read(&path).map_err(|e| format!("{}: {}", path, e)),
+        Payload::Stdin => {
+            let mut buf = Vec::new();
+            io::stdin()
+                .read_to_end(&mut buf)
+                .map_err(|e| e.to_string())?;
+            Ok(buf)
+        }
+    }
+}
+
+fn send(
+    mut output: Output,
+    src: Address,
+    dst: Address,
+    payload: &[u8],
+    repeat: u64,
+    interval: Option<Duration>,
+) -> io::Result<()> {
+    let frame = Writer::package(src, dst, payload)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, format!("{:?}", e)))?;
+
+    let start = Instant::now();
+    let mut sent = 0u64;
+    while repeat == 0 || sent < repeat {
+        if let Some(interval) = interval {
+            let deadline = start + interval.mul_f64(sent as f64);
+            if let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+                sleep(remaining);
+            }
+        }
+
+        let len = frame.as_slice().len();
+        output.send(frame.clone())?;
+        sent += 1;
+        log::debug!("Sent frame {} ({} bytes)", sent, len);
+    }
+
+    log::info!("Sent {} frames in {:?}", sent, start.elapsed());
+    Ok(())
+}
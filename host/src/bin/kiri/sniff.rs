@@ -0,0 +1,110 @@
+use std::{
+    fs::File,
+    io::{self, Read},
+    sync::Arc,
+    time::Instant,
+};
+
+use kiri_host::{
+    args::Args,
+    format::{describe_error, FrameDisplay},
+    metrics::{self, BusMetrics},
+    serial::SerialPort,
+};
+use kiri_protocol::{Address, Reader};
+
+use crate::Options;
+
+pub const USAGE: &str =
+    "usage: kiri sniff [<capture>|-] [--src <addr>] [--dst <addr>] [--no-errors] [--metrics <host:port>]
+
+Decodes all frames on the bus and prints them. Reads a capture from a file instead of the port when
+given, or from stdin with `-`.
+
+With `--metrics`, serves counters of all frames and errors on `/metrics` for Prometheus.";
+
+struct Filter {
+    src: Option<Address>,
+    dst: Option<Address>,
+    errors: bool,
+}
+
+pub fn run(mut args: Args, options: &Options) {
+    let mut capture = None;
+    let mut filter = Filter {
+        src: None,
+        dst: None,
+        errors: true,
+    };
+    let mut listen = None;
+
+    while let Some(arg) = args.next_arg() {
+        match arg.as_str() {
+            "--src" => filter.src = Some(args.address("--src")),
+            "--dst" => filter.dst = Some(args.address("--dst")),
+            "--no-errors" => filter.errors = false,
+            "--metrics" => listen = Some(args.value("--metrics")),
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            _ if capture.is_none() => capture = Some(arg),
+            _ => args.fail(format!("unexpected argument {:?}", arg)),
+        }
+    }
+
+    let input: Box<dyn Read> = match capture.as_deref() {
+        Some("-") => Box::new(io::stdin()),
+        Some(path) => Box::new(File::open(path).unwrap_or_else(|e| args.fail(e))),
+        None => Box::new(
+            SerialPort::open(options.port(&args), options.baud, 0).unwrap_or_else(|e| args.fail(e)),
+        ),
+    };
+
+    let metrics = Arc::new(BusMetrics::new());
+    if let Some(listen) = listen {
+        metrics::serve(&listen, metrics.clone()).unwrap_or_else(|e| args.fail(e));
+        log::info!("Serving metrics on {}", listen);
+    }
+
+    if let Err(e) = sniff(input, &filter, &metrics) {
+        log::error!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+fn sniff(mut input: impl Read, filter: &Filter, metrics: &BusMetrics) -> io::Result<()> {
+    let start = Instant::now();
+    let mut reader = Reader::new();
+    let mut buf = [0u8; 256];
+
+    loop {
+        let len = input.read(&mut buf)?;
+        if len == 0 {
+            return Ok(());
+        }
+
+        for b in &buf[..len] {
+            let elapsed = start.elapsed().as_secs_f64();
+            match reader.feed(*b) {
+                Err(error) => {
+                    metrics.record_error(&error);
+                    if filter.errors {
+                        println!("[{:10.6}] ! {}", elapsed, describe_error(&error));
+                    }
+                }
+                Ok(Some(frame)) => {
+                    metrics.record_frame(&frame);
+                    let header = &frame.header;
+                    if filter.src.is_some_and(|src| src != header.address_src)
+                        || filter.dst.is_some_and(|dst| dst != header.address_dst)
+                    {
+                        continue;
+                    }
+                    print!("[{:10.6}] {}", elapsed, FrameDisplay(&frame));
+                }
+                Ok(None) => (),
+            }
+        }
+    }
+}
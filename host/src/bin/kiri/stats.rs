@@ -0,0 +1,97 @@
+use std::{
+    collections::BTreeMap,
+    io,
+    time::{Duration, Instant},
+};
+
+use kiri_csma::management::{Health, Message};
+use kiri_host::{
+    args::Args,
+    bus::{self, HostStrategy},
+};
+use kiri_protocol::{Address, FrameRef};
+
+use crate::{parse_address, Options};
+
+pub const USAGE: &str = "usage: kiri stats <addr> [--timeout <ms>]
+
+Asks the node at `<addr>` for its health, and prints its counters and uptime. Use `ffffffff` as
+address to ask all nodes at once, and print the answers that arrive within the timeout.";
+
+pub fn run(mut args: Args, options: &Options) {
+    let mut dst = None;
+    let mut timeout_ms: u64 = 1000;
+
+    while let Some(arg) = args.next_arg() {
+        match arg.as_str() {
+            "--timeout" => timeout_ms = args.parse("--timeout"),
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            _ if dst.is_none() => dst = Some(parse_address(&args, &arg)),
+            _ => args.fail(format!("unexpected argument {:?}", arg)),
+        }
+    }
+
+    let dst = dst.unwrap_or_else(|| args.fail("missing address"));
+    let src = options.address(&args);
+    let mut strategy = options.open(&args);
+
+    match poll(&mut strategy, src, dst, Duration::from_millis(timeout_ms)) {
+        Ok(reports) if reports.is_empty() => {
+            log::error!("No answers");
+            std::process::exit(1);
+        }
+        Ok(reports) => print(&reports),
+        Err(e) => {
+            log::error!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Send a health query to `dst`, and collect the reports addressed to `src` until `timeout` passed.
+fn poll(
+    strategy: &mut HostStrategy,
+    src: Address,
+    dst: Address,
+    timeout: Duration,
+) -> io::Result<BTreeMap<u32, Health>> {
+    let mut reports = BTreeMap::new();
+    let mut report = |frame: FrameRef| {
+        if frame.header.address_dst == src {
+            if let Some(Message::HealthReport(health)) = Message::parse(&frame) {
+                reports.insert(frame.header.address_src.to_primitive(), health);
+            }
+        }
+        None::<()>
+    };
+
+    let query = Message::HealthQuery
+        .package(src, dst)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?;
+    bus::send(strategy, query, &mut report)?;
+    bus::receive_until(strategy, Instant::now() + timeout, report)?;
+    Ok(reports)
+}
+
+fn print(reports: &BTreeMap<u32, Health>) {
+    println!(
+        "{:<8} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10} {:>10}",
+        "node", "uptime", "sent", "received", "errors", "crc", "collisions", "timeouts"
+    );
+    for (address, health) in reports {
+        println!(
+            "{} {:>9}s {:>10} {:>10} {:>10} {:>10} {:>10} {:>10}",
+            Address::new(*address),
+            health.uptime,
+            health.frames_sent,
+            health.frames_received,
+            health.frame_errors,
+            health.crc_failures,
+            health.collisions,
+            health.echo_timeouts
+        );
+    }
+}
@@ -0,0 +1,88 @@
+//! Taking part on the bus as a regular node, for tools that send requests and wait for the answers.
+
+use std::{
+    io,
+    time::{Duration, Instant},
+};
+
+use kiri_csma::{CsmaFrameInProgress, CsmaStrategy, SendReceiveResult, SystemClock};
+use kiri_protocol::{Frame, FrameRef};
+
+use crate::{
+    serial::SerialPort,
+    transceiver::{HostConfig, SerialPortTransceiver},
+};
+
+pub type HostStrategy = CsmaStrategy<SerialPortTransceiver, SystemClock, rand::rngs::ThreadRng>;
+
+/// Latency of USB serial adapters to account for when deciding whether the bus is idle.
+const ADAPTER_LATENCY: Duration = Duration::from_millis(2);
+
+/// How long to sleep when no byte is available.
+const POLL_INTERVAL: Duration = Duration::from_micros(100);
+
+/// Open `port` to send and receive frames using collision detection.
+pub fn open(port: &str, baud: u32) -> io::Result<HostStrategy> {
+    let serial = SerialPort::open(port, baud, 0)?;
+    let idle = SerialPortTransceiver::idle_duration(baud, ADAPTER_LATENCY);
+    let transceiver = SerialPortTransceiver::new(serial, idle)?;
+    Ok(HostStrategy::new::<HostConfig>(
+        transceiver,
+        SystemClock,
+        rand::thread_rng(),
+    ))
+}
+
+/// Send `frame`, handing the frames received meanwhile to `on_receive`.
+///
+/// Yields early with the first answer that `on_receive` yields, as an answer might arrive before the frame is
+/// confirmed to be sent.
+pub fn send<U>(
+    strategy: &mut HostStrategy,
+    frame: Frame,
+    mut on_receive: impl FnMut(FrameRef) -> Option<U>,
+) -> io::Result<Option<U>> {
+    let mut frame = CsmaFrameInProgress::new(frame);
+    loop {
+        match strategy.send_or_receive_with(&mut frame, &mut on_receive) {
+            Ok(SendReceiveResult::SendComplete) => return Ok(None),
+            Ok(SendReceiveResult::Expired) => return Err(io::ErrorKind::TimedOut.into()),
+            Ok(SendReceiveResult::Received(Some(answer))) => return Ok(Some(answer)),
+            Ok(SendReceiveResult::Received(None)) | Err(nb::Error::WouldBlock) => (),
+            Err(nb::Error::Other(e)) => return Err(e),
+        }
+    }
+}
+
+/// Hand the frames received until `deadline` to `on_receive`, until it yields an answer.
+pub fn receive_until<U>(
+    strategy: &mut HostStrategy,
+    deadline: Instant,
+    mut on_receive: impl FnMut(FrameRef) -> Option<U>,
+) -> io::Result<Option<U>> {
+    while Instant::now() < deadline {
+        match strategy.receive() {
+            Ok(frame) => {
+                if let Some(answer) = on_receive(frame) {
+                    return Ok(Some(answer));
+                }
+            }
+            Err(nb::Error::WouldBlock) => std::thread::sleep(POLL_INTERVAL),
+            Err(nb::Error::Other(e)) => return Err(e),
+        }
+    }
+    Ok(None)
+}
+
+/// Send `frame` and wait for an answer for up to `timeout` once it was sent, see `send` and `receive_until`.
+pub fn request<U>(
+    strategy: &mut HostStrategy,
+    frame: Frame,
+    timeout: Duration,
+    mut on_receive: impl FnMut(FrameRef) -> Option<U>,
+) -> io::Result<Option<U>> {
+    match send(strategy, frame, &mut on_receive)? {
+        Some(answer) => Ok(Some(answer)),
+        None => receive_until(strategy, Instant::now() + timeout, on_receive),
+    }
+}
@@ -0,0 +1,63 @@
+use std::fmt::{self, Display};
+
+use kiri_protocol::{FrameError, FrameRef};
+
+/// Classic hexdump of 16 bytes per line, with offset and printable ASCII.
+pub struct HexDump<'a>(pub &'a [u8]);
+
+impl Display for HexDump<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, chunk) in self.0.chunks(16).enumerate() {
+            write!(f, "  {:04x}  ", i * 16)?;
+            for j in 0..16 {
+                match chunk.get(j) {
+                    Some(b) => write!(f, "{:02x} ", b)?,
+                    None => f.write_str("   ")?,
+                }
+                if j == 7 {
+                    f.write_str(" ")?;
+                }
+            }
+            f.write_str(" |")?;
+            for b in chunk {
+                let c = if b.is_ascii_graphic() || *b == b' ' {
+                    *b as char
+                } else {
+                    '.'
+                };
+                write!(f, "{}", c)?;
+            }
+            writeln!(f, "|")?;
+        }
+        Ok(())
+    }
+}
+
+/// One line summary of a decoded frame, followed by a hexdump of the contents.
+pub struct FrameDisplay<'a, 'b>(pub &'b FrameRef<'a>);
+
+impl Display for FrameDisplay<'_, '_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let header = &self.0.header;
+        writeln!(
+            f,
+            "{} -> {} len={} crc=ok",
+            header.address_src,
+            header.address_dst,
+            self.0.contents.len()
+        )?;
+        HexDump(self.0.contents).fmt(f)
+    }
+}
+
+/// Short description of why a frame could not be decoded, in the style of `FrameDisplay`.
+pub fn describe_error(error: &FrameError) -> &'static str {
+    match error {
+        FrameError::Overflow => "buffer overflow",
+        FrameError::Cobs => "invalid COBS encoding",
+        FrameError::Magic => "invalid magic word",
+        FrameError::Header => "invalid header",
+        FrameError::Size => "length mismatch",
+        FrameError::Checksum => "crc=bad",
+    }
+}
@@ -0,0 +1,307 @@
+//! Home Assistant MQTT discovery, announcing the nodes on a bus as entities.
+//!
+//! A node is found once it sends a frame from a unicast or dynamic address. If its address is in one of the
+//! configured ranges, it is announced as an entity of the component of that range, see `Component`. Nodes send
+//! their state as text, i.e. `21.5` or `ON`, which the bridge publishes to the state topic of the entity.
+//! Commands of switches are put on the bus from the address of the bridge.
+
+use std::{collections::BTreeSet, fmt, ops::RangeInclusive, str::FromStr};
+
+use kiri_protocol::{Address, AddressClass};
+use serde_json::json;
+
+/// The kind of entity that a node is announced as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Component {
+    Sensor,
+    BinarySensor,
+    /// A switch, which is sent `ON` and `OFF` as commands.
+    Switch,
+}
+
+impl Component {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Component::Sensor => "sensor",
+            Component::BinarySensor => "binary_sensor",
+            Component::Switch => "switch",
+        }
+    }
+}
+
+impl fmt::Display for Component {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Component {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sensor" => Ok(Component::Sensor),
+            "binary_sensor" => Ok(Component::BinarySensor),
+            "switch" => Ok(Component::Switch),
+            _ => Err(format!("unknown component {:?}", s)),
+        }
+    }
+}
+
+/// The nodes in a range of addresses, which are all announced as the same component.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceClass {
+    pub addresses: RangeInclusive<u32>,
+    pub component: Component,
+}
+
+impl FromStr for DeviceClass {
+    type Err = String;
+
+    /// Parse `<start>-<end>=<component>`, i.e. `00001000-00001fff=sensor`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (range, component) = s
+            .split_once('=')
+            .ok_or_else(|| "expected <start>-<end>=<component>".to_string())?;
+        let (start, end) = range
+            .split_once('-')
+            .ok_or_else(|| "expected <start>-<end>".to_string())?;
+        let address = |address: &str| {
+            Address::from_hex_str(address)
+                .map(|address| address.to_primitive())
+                .map_err(|e| e.to_string())
+        };
+        Ok(Self {
+            addresses: address(start)?..=address(end)?,
+            component: component.parse()?,
+        })
+    }
+}
+
+/// A retained message to publish to announce an entity.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Announcement {
+    pub topic: String,
+    pub payload: String,
+}
+
+/// Tracks the nodes found on the bus, and yields the announcements for them.
+#[derive(Debug)]
+pub struct Discovery {
+    /// Prefix of the discovery topics that Home Assistant subscribes to, `homeassistant` by default.
+    discovery_prefix: String,
+    /// Prefix of the topics of the bridge.
+    prefix: String,
+    classes: Vec<DeviceClass>,
+    /// Address to send commands from, required for switches.
+    address: Option<Address>,
+    found: BTreeSet<u32>,
+}
+
+impl Discovery {
+    /// Announce nodes by the first of `classes` that they are in. Fails if a switch is configured without `address`.
+    pub fn new(
+        discovery_prefix: &str,
+        prefix: &str,
+        classes: Vec<DeviceClass>,
+        address: Option<Address>,
+    ) -> Result<Self, String> {
+        if address.is_none()
+            && classes
+                .iter()
+                .any(|class| class.component == Component::Switch)
+        {
+            return Err("switches need an address to send commands from".to_string());
+        }
+        Ok(Self {
+            discovery_prefix: discovery_prefix.to_string(),
+            prefix: prefix.to_string(),
+            classes,
+            address,
+            found: BTreeSet::new(),
+        })
+    }
+
+    /// The topic the state of `address` is published to, if it was announced.
+    pub fn state_topic(&self, address: Address) -> Option<String> {
+        self.found
+            .contains(&address.to_primitive())
+            .then(|| format!("{}/{}/state", self.prefix, address))
+    }
+
+    /// The topic to which Home Assistant publishes `online` when it starts, after which all should be announced again.
+    pub fn status_topic(&self) -> String {
+        format!("{}/status", self.discovery_prefix)
+    }
+
+    /// Note that a frame from `address` was seen, yielding its announcement if it is found for the first time.
+    pub fn found(&mut self, address: Address) -> Option<Announcement> {
+        if !matches!(
+            address.class(),
+            AddressClass::Unicast | AddressClass::Dynamic
+        ) || self.found.contains(&address.to_primitive())
+        {
+            return None;
+        }
+        let announcement = self.announcement(address)?;
+        self.found.insert(address.to_primitive());
+        Some(announcement)
+    }
+
+    /// The announcements of all nodes found so far.
+    pub fn announcements(&self) -> Vec<Announcement> {
+        self.found
+            .iter()
+            .filter_map(|address| self.announcement(Address::new(*address)))
+            .collect()
+    }
+
+    fn announcement(&self, address: Address) -> Option<Announcement> {
+        let class = self
+            .classes
+            .iter()
+            .find(|class| class.addresses.contains(&address.to_primitive()))?;
+        let id = format!("kiri_{}", address);
+        let mut config = json!({
+            "name": null,
+            "unique_id": id,
+            "state_topic": format!("{}/{}/state", self.prefix, address),
+            "device": {
+                "identifiers": [id],
+                "name": format!("kiri {}", address),
+            },
+        });
+        if let (Component::Switch, Some(from)) = (class.component, self.address) {
+            config["command_topic"] = format!("{}/send/{}/{}", self.prefix, from, address).into();
+        }
+        Some(Announcement {
+            topic: format!(
+                "{}/{}/{}/config",
+                self.discovery_prefix, class.component, id
+            ),
+            payload: config.to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::*;
+
+    fn class(s: &str) -> DeviceClass {
+        s.parse().unwrap()
+    }
+
+    fn discovery() -> Discovery {
+        Discovery::new(
+            "homeassistant",
+            "kiri",
+            vec![
+                class("00001000-00001fff=sensor"),
+                class("00002000-00002fff=switch"),
+                class("f0000000-ffffffff=binary_sensor"),
+            ],
+            Some(Address::new(0x01)),
+        )
+        .unwrap()
+    }
+
+    fn payload(announcement: &Announcement) -> Value {
+        serde_json::from_str(&announcement.payload).unwrap()
+    }
+
+    #[test]
+    fn parse_device_class() {
+        assert_eq!(
+            class("00001000-00001fff=binary_sensor"),
+            DeviceClass {
+                addresses: 0x1000..=0x1FFF,
+                component: Component::BinarySensor,
+            }
+        );
+        assert!("00001000-00001fff".parse::<DeviceClass>().is_err());
+        assert!("00001000=sensor".parse::<DeviceClass>().is_err());
+        assert!("00001000-00001fff=light".parse::<DeviceClass>().is_err());
+        assert!("1000-1fff=sensor".parse::<DeviceClass>().is_err());
+    }
+
+    #[test]
+    fn switches_need_address() {
+        let classes = vec![class("00002000-00002fff=switch")];
+        assert!(Discovery::new("homeassistant", "kiri", classes.clone(), None).is_err());
+        assert!(Discovery::new("homeassistant", "kiri", classes, Some(Address::new(1))).is_ok());
+        let sensors = vec![class("00001000-00001fff=sensor")];
+        assert!(Discovery::new("homeassistant", "kiri", sensors, None).is_ok());
+    }
+
+    #[test]
+    fn announce_sensor() {
+        let mut discovery = discovery();
+        let address = Address::new(0x1234);
+        assert_eq!(discovery.state_topic(address), None);
+
+        let announcement = discovery.found(address).unwrap();
+        assert_eq!(
+            announcement.topic,
+            "homeassistant/sensor/kiri_00001234/config"
+        );
+        assert_eq!(
+            payload(&announcement),
+            json!({
+                "name": null,
+                "unique_id": "kiri_00001234",
+                "state_topic": "kiri/00001234/state",
+                "device": {
+                    "identifiers": ["kiri_00001234"],
+                    "name": "kiri 00001234",
+                },
+            })
+        );
+        assert_eq!(
+            discovery.state_topic(address).as_deref(),
+            Some("kiri/00001234/state")
+        );
+
+        // Only once, until all are announced again.
+        assert_eq!(discovery.found(address), None);
+        assert_eq!(discovery.announcements(), [announcement]);
+    }
+
+    #[test]
+    fn announce_switch() {
+        let mut discovery = discovery();
+        let announcement = discovery.found(Address::new(0x2001)).unwrap();
+        assert_eq!(
+            announcement.topic,
+            "homeassistant/switch/kiri_00002001/config"
+        );
+        assert_eq!(
+            payload(&announcement),
+            json!({
+                "name": null,
+                "unique_id": "kiri_00002001",
+                "state_topic": "kiri/00002001/state",
+                "command_topic": "kiri/send/00000001/00002001",
+                "device": {
+                    "identifiers": ["kiri_00002001"],
+                    "name": "kiri 00002001",
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn announce_unicast_and_dynamic_only() {
+        let mut discovery = discovery();
+        // Not in any class.
+        assert_eq!(discovery.found(Address::new(0x0001)), None);
+
+        assert!(discovery.found(Address::new(0xF000_0001)).is_some());
+        assert_eq!(discovery.found(Address::new(0xFF00_0001)), None);
+        assert_eq!(discovery.found(Address::group(1)), None);
+        assert_eq!(discovery.found(Address::broadcast()), None);
+        assert_eq!(discovery.announcements().len(), 1);
+    }
+}
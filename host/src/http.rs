@@ -0,0 +1,483 @@
+//! Minimal HTTP/1.1 server side, supporting just enough for bridging: requests with a body, and WebSocket streams.
+//!
+//! Every request is answered on a connection of its own, i.e. `Connection: close`. WebSockets only send text
+//! messages, anything the client sends is ignored. A client that went away is noticed once sending fails.
+//!
+//! `serve` handles each connection on a thread of its own, up to a limit, and drops clients that stall.
+
+use std::{
+    io::{self, BufRead, Write},
+    net::{TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+/// Requests with a larger body are refused.
+pub const MAX_BODY_LEN: usize = 64 * 1024;
+
+/// Connections served at once by default, beyond which clients are answered with `503 Service Unavailable`.
+pub const MAX_CONNECTIONS: usize = 64;
+
+/// How long a read or write may block before the connection is given up, i.e. for a client that never sends its
+/// request or stops reading a WebSocket.
+pub const IO_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Requests with more headers are refused.
+const MAX_HEADERS: usize = 64;
+
+/// Appended to the key of the client to accept a WebSocket, see RFC 6455 section 1.3.
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+const OPCODE_TEXT: u8 = 0x1;
+
+#[derive(Debug, PartialEq)]
+pub struct Request {
+    pub method: String,
+    /// Path including the query, if any.
+    pub path: String,
+    headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+fn read_line(r: &mut impl BufRead) -> io::Result<String> {
+    let mut line = String::new();
+    if r.read_line(&mut line)? == 0 {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+impl Request {
+    /// Read a request, along with the body announced by its `Content-Length`.
+    pub fn read(mut r: impl BufRead) -> io::Result<Self> {
+        let request_line = read_line(&mut r)?;
+        let mut parts = request_line.split(' ');
+        let (method, path) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(method), Some(path), Some(version)) if version.starts_with("HTTP/1.") => {
+                (method.to_string(), path.to_string())
+            }
+            _ => return Err(invalid("malformed request line")),
+        };
+
+        let mut headers = Vec::new();
+        loop {
+            let line = read_line(&mut r)?;
+            if line.is_empty() {
+                break;
+            }
+            if headers.len() == MAX_HEADERS {
+                return Err(invalid("too many headers"));
+            }
+            match line.split_once(':') {
+                Some((name, value)) => {
+                    headers.push((name.trim().to_string(), value.trim().to_string()))
+                }
+                None => return Err(invalid("malformed header")),
+            }
+        }
+
+        let mut request = Self {
+            method,
+            path,
+            headers,
+            body: Vec::new(),
+        };
+        let len = match request.header("Content-Length") {
+            Some(len) => len
+                .parse()
+                .map_err(|_| invalid("malformed Content-Length"))?,
+            None => 0,
+        };
+        if len > MAX_BODY_LEN {
+            return Err(invalid("body too large"));
+        }
+        request.body = vec![0; len];
+        r.read_exact(&mut request.body)?;
+        Ok(request)
+    }
+
+    /// The value of the header `name`, which is case insensitive.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Whether the client asks to upgrade to a WebSocket.
+    pub fn is_websocket(&self) -> bool {
+        self.header("Upgrade")
+            .is_some_and(|upgrade| upgrade.eq_ignore_ascii_case("websocket"))
+    }
+}
+
+/// Answer with `status` and `body`, and close the connection.
+pub fn write_response(
+    mut w: impl Write,
+    status: u16,
+    reason: &str,
+    content_type: &str,
+    body: &[u8],
+) -> io::Result<()> {
+    write!(
+        w,
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        reason,
+        content_type,
+        body.len()
+    )?;
+    w.write_all(body)?;
+    w.flush()
+}
+
+/// Decrements the number of connections being served once the connection is done with.
+struct ConnectionGuard(Arc<AtomicUsize>);
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Serve every connection to `listener` with `handle` on a thread of its own, with at most `max_connections` at once.
+///
+/// Reads and writes on the connections time out after `IO_TIMEOUT`. Errors of `handle` only close the connection.
+pub fn serve<F>(listener: TcpListener, max_connections: usize, handle: F)
+where
+    F: Fn(&TcpStream) -> io::Result<()> + Send + Sync + 'static,
+{
+    let handle = Arc::new(handle);
+    let active = Arc::new(AtomicUsize::new(0));
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                log::warn!("Accepting connection failed: {}", e);
+                continue;
+            }
+        };
+        if let Err(e) = stream
+            .set_read_timeout(Some(IO_TIMEOUT))
+            .and_then(|()| stream.set_write_timeout(Some(IO_TIMEOUT)))
+        {
+            log::warn!("Setting timeouts failed: {}", e);
+            continue;
+        }
+
+        if active.fetch_add(1, Ordering::Relaxed) >= max_connections {
+            active.fetch_sub(1, Ordering::Relaxed);
+            log::debug!("Refusing connection, serving {} already", max_connections);
+            let _ = write_response(
+                &stream,
+                503,
+                "Service Unavailable",
+                "text/plain",
+                b"too many connections\n",
+            );
+            continue;
+        }
+        let guard = ConnectionGuard(active.clone());
+        let handle = handle.clone();
+        thread::spawn(move || {
+            if let Err(e) = handle(&stream) {
+                log::debug!("Connection closed: {}", e);
+            }
+            // Before the stream is closed, such that a client may connect again right away.
+            drop(guard);
+        });
+    }
+}
+
+/// Sending side of a WebSocket.
+#[derive(Debug)]
+pub struct WebSocket<W: Write> {
+    stream: W,
+}
+
+impl<W: Write> WebSocket<W> {
+    /// Accept the upgrade that `request` asks for, see `Request::is_websocket`.
+    pub fn accept(mut stream: W, request: &Request) -> io::Result<Self> {
+        let key = request
+            .header("Sec-WebSocket-Key")
+            .ok_or_else(|| invalid("missing Sec-WebSocket-Key"))?;
+        write!(
+            stream,
+            "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+            accept_key(key)
+        )?;
+        stream.flush()?;
+        Ok(Self { stream })
+    }
+
+    /// Send `text` as a single message.
+    pub fn send_text(&mut self, text: &str) -> io::Result<()> {
+        // Final fragment, and the length in 7, 16 or 64 bits. Servers do not mask.
+        let mut header = vec![0x80 | OPCODE_TEXT];
+        match text.len() {
+            len @ 0..=125 => header.push(len as u8),
+            len @ 126..=0xFFFF => {
+                header.push(126);
+                header.extend_from_slice(&(len as u16).to_be_bytes());
+            }
+            len => {
+                header.push(127);
+                header.extend_from_slice(&(len as u64).to_be_bytes());
+            }
+        }
+        self.stream.write_all(&header)?;
+        self.stream.write_all(text.as_bytes())?;
+        self.stream.flush()
+    }
+}
+
+/// The `Sec-WebSocket-Accept` for the `Sec-WebSocket-Key` of a client.
+fn accept_key(key: &str) -> String {
+    base64(&sha1(format!("{}{}", key, WEBSOCKET_GUID).as_bytes()))
+}
+
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&(data.len() as u64 * 8).to_be_bytes());
+
+    for block in message.chunks(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (h, x) in h.iter_mut().zip([a, b, c, d, e]) {
+            *h = h.wrapping_add(x);
+        }
+    }
+
+    let mut digest = [0u8; 20];
+    for (bytes, h) in digest.chunks_mut(4).zip(h) {
+        bytes.copy_from_slice(&h.to_be_bytes());
+    }
+    digest
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::new();
+    for chunk in data.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, b)| n | (*b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            match i <= chunk.len() {
+                true => out.push(ALPHABET[(n >> (18 - 6 * i)) as usize & 0x3F] as char),
+                false => out.push('='),
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::Read,
+        sync::{mpsc, Mutex},
+    };
+
+    use super::*;
+
+    #[test]
+    fn accept_key_rfc6455() {
+        // The example of RFC 6455 section 1.3.
+        assert_eq!(
+            accept_key("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+
+    #[test]
+    fn base64_padding() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+    }
+
+    #[test]
+    fn read_request() {
+        let request = Request::read(
+            &b"POST /send?x=1 HTTP/1.1\r\nHost: localhost\r\ncontent-length: 4\r\n\r\nbodyrest"[..],
+        )
+        .unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/send?x=1");
+        assert_eq!(request.header("Content-Length"), Some("4"));
+        assert_eq!(request.header("host"), Some("localhost"));
+        assert_eq!(request.header("Upgrade"), None);
+        assert_eq!(request.body, b"body");
+        assert!(!request.is_websocket());
+    }
+
+    #[test]
+    fn read_websocket_request() {
+        let request = Request::read(
+            &b"GET /frames HTTP/1.1\r\nUpgrade: WebSocket\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n"[..],
+        )
+        .unwrap();
+        assert!(request.is_websocket());
+        assert!(request.body.is_empty());
+
+        let mut out = Vec::new();
+        WebSocket::accept(&mut out, &request).unwrap();
+        assert_eq!(
+            out,
+            b"HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n\r\n"
+        );
+    }
+
+    #[test]
+    fn read_malformed_request() {
+        let invalid = |request: &[u8]| Request::read(request).unwrap_err().kind();
+        assert_eq!(invalid(b"GET /\r\n\r\n"), io::ErrorKind::InvalidData);
+        assert_eq!(invalid(b"GET / SPDY/3\r\n\r\n"), io::ErrorKind::InvalidData);
+        assert_eq!(
+            invalid(b"GET / HTTP/1.1\r\nno colon\r\n\r\n"),
+            io::ErrorKind::InvalidData
+        );
+        assert_eq!(
+            invalid(b"POST / HTTP/1.1\r\nContent-Length: four\r\n\r\n"),
+            io::ErrorKind::InvalidData
+        );
+        assert_eq!(
+            invalid(
+                format!(
+                    "POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+                    MAX_BODY_LEN + 1
+                )
+                .as_bytes()
+            ),
+            io::ErrorKind::InvalidData
+        );
+        let headers = "X: y\r\n".repeat(MAX_HEADERS + 1);
+        assert_eq!(
+            invalid(format!("GET / HTTP/1.1\r\n{}\r\n", headers).as_bytes()),
+            io::ErrorKind::InvalidData
+        );
+        // Closed before the end of the headers or the body.
+        assert_eq!(
+            invalid(b"GET / HTTP/1.1\r\nHost: x\r\n"),
+            io::ErrorKind::UnexpectedEof
+        );
+        assert_eq!(
+            invalid(b"POST / HTTP/1.1\r\nContent-Length: 4\r\n\r\nbo"),
+            io::ErrorKind::UnexpectedEof
+        );
+    }
+
+    #[test]
+    fn write_response_head() {
+        let mut out = Vec::new();
+        write_response(&mut out, 404, "Not Found", "text/plain", b"gone").unwrap();
+        assert_eq!(
+            out,
+            b"HTTP/1.1 404 Not Found\r\nContent-Type: text/plain\r\nContent-Length: 4\r\nConnection: close\r\n\r\ngone"
+        );
+    }
+
+    #[test]
+    fn websocket_frame_lengths() {
+        let frame = |len: usize| {
+            let mut socket = WebSocket { stream: Vec::new() };
+            socket.send_text(&"a".repeat(len)).unwrap();
+            socket.stream
+        };
+
+        assert_eq!(frame(0), [0x81, 0]);
+        assert_eq!(&frame(125)[..2], [0x81, 125]);
+        assert_eq!(frame(125).len(), 2 + 125);
+        assert_eq!(&frame(126)[..4], [0x81, 126, 0, 126]);
+        assert_eq!(frame(126).len(), 4 + 126);
+        assert_eq!(&frame(0xFFFF)[..4], [0x81, 126, 0xFF, 0xFF]);
+        assert_eq!(&frame(0x10000)[..10], [0x81, 127, 0, 0, 0, 0, 0, 1, 0, 0]);
+        assert_eq!(frame(0x10000).len(), 10 + 0x10000);
+    }
+
+    #[test]
+    fn serve_refuses_beyond_limit() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        // Every connection waits until the test lets it go.
+        let (release, released) = mpsc::channel::<()>();
+        let released = Mutex::new(released);
+        thread::spawn(move || {
+            serve(listener, 1, move |stream| {
+                Request::read(io::BufReader::new(stream))?;
+                let _ = released.lock().unwrap().recv();
+                write_response(stream, 200, "OK", "text/plain", b"ok")
+            })
+        });
+
+        let connect = |request: bool| {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            if request {
+                stream.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+            }
+            stream
+        };
+        let response = |mut stream: TcpStream| {
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+
+        // Connections are counted as they are accepted, hence the second one is refused. It does not send its
+        // request, which the refusal leaves unread.
+        let first = connect(true);
+        let second = connect(false);
+        assert!(response(second).starts_with("HTTP/1.1 503 "));
+
+        release.send(()).unwrap();
+        assert!(response(first).starts_with("HTTP/1.1 200 "));
+
+        // Served again once the first connection is done.
+        let third = connect(true);
+        release.send(()).unwrap();
+        assert!(response(third).starts_with("HTTP/1.1 200 "));
+    }
+}
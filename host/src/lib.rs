@@ -0,0 +1,12 @@
+//! Host side tooling for kiri buses, for use on machines with a serial port attached to the bus.
+
+pub mod args;
+pub mod bus;
+pub mod format;
+pub mod homeassistant;
+pub mod http;
+pub mod metrics;
+pub mod mqtt;
+pub mod router;
+pub mod serial;
+pub mod transceiver;
@@ -0,0 +1,359 @@
+//! Prometheus metrics of the traffic on a bus, served in the text exposition format on `/metrics`.
+//!
+//! Frame rates follow from the counters using `rate()`, i.e. `rate(kiri_frames_total[1m])`.
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    io::{self, BufReader},
+    net::{TcpListener, ToSocketAddrs},
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use kiri_csma::Stats;
+use kiri_protocol::{Address, FrameError, FrameRef};
+
+use crate::http::{self, write_response, Request};
+
+/// Content type of the text exposition format.
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// Scrapes served at once, which are few and quick.
+const METRICS_MAX_CONNECTIONS: usize = 4;
+
+/// Label of a frame that could not be decoded, by why it could not.
+pub fn error_label(error: &FrameError) -> &'static str {
+    match error {
+        FrameError::Overflow => "overflow",
+        FrameError::Cobs => "cobs",
+        FrameError::Magic => "magic",
+        FrameError::Header => "header",
+        FrameError::Size => "size",
+        FrameError::Checksum => "crc",
+    }
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    /// Frames and bytes of contents, by source and destination.
+    traffic: BTreeMap<(u32, u32), (u64, u64)>,
+    /// Frames that could not be decoded, by `error_label`.
+    errors: BTreeMap<&'static str, u64>,
+    /// Counters of the strategy, for tools that take part on the bus.
+    stats: Option<Stats<Duration>>,
+    link_up: Option<bool>,
+}
+
+/// Counters of what was seen on the bus, to be rendered for Prometheus.
+#[derive(Debug, Default)]
+pub struct BusMetrics {
+    counters: Mutex<Counters>,
+}
+
+impl BusMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record_frame(&self, frame: &FrameRef) {
+        let mut counters = self.counters.lock().unwrap();
+        let key = (
+            frame.header.address_src.to_primitive(),
+            frame.header.address_dst.to_primitive(),
+        );
+        let (frames, bytes) = counters.traffic.entry(key).or_default();
+        *frames += 1;
+        *bytes += frame.contents.len() as u64;
+    }
+
+    pub fn record_error(&self, error: &FrameError) {
+        *self
+            .counters
+            .lock()
+            .unwrap()
+            .errors
+            .entry(error_label(error))
+            .or_default() += 1;
+    }
+
+    pub fn set_stats(&self, stats: Stats<Duration>) {
+        self.counters.lock().unwrap().stats = Some(stats);
+    }
+
+    pub fn set_link_up(&self, up: bool) {
+        self.counters.lock().unwrap().link_up = Some(up);
+    }
+
+    /// Render all metrics in the text exposition format.
+    pub fn render(&self) -> String {
+        let counters = self.counters.lock().unwrap();
+        // Writing to a `String` does not fail.
+        let mut out = String::new();
+
+        let _ = writeln!(
+            out,
+            "# HELP kiri_frames_total Frames seen on the bus.\n# TYPE kiri_frames_total counter"
+        );
+        for ((src, dst), (frames, _)) in &counters.traffic {
+            let _ = writeln!(
+                out,
+                "kiri_frames_total{{src=\"{}\",dst=\"{}\"}} {}",
+                Address::new(*src),
+                Address::new(*dst),
+                frames
+            );
+        }
+        let _ = writeln!(
+            out,
+            "# HELP kiri_frame_bytes_total Bytes of contents of the frames seen on the bus.\n# TYPE kiri_frame_bytes_total counter"
+        );
+        for ((src, dst), (_, bytes)) in &counters.traffic {
+            let _ = writeln!(
+                out,
+                "kiri_frame_bytes_total{{src=\"{}\",dst=\"{}\"}} {}",
+                Address::new(*src),
+                Address::new(*dst),
+                bytes
+            );
+        }
+        let _ = writeln!(
+            out,
+            "# HELP kiri_frame_errors_total Frames that could not be decoded, by reason.\n# TYPE kiri_frame_errors_total counter"
+        );
+        for (reason, count) in &counters.errors {
+            let _ = writeln!(
+                out,
+                "kiri_frame_errors_total{{reason=\"{}\"}} {}",
+                reason, count
+            );
+        }
+
+        if let Some(stats) = &counters.stats {
+            let strategy = [
+                (
+                    "frames_sent",
+                    "Frames of our own confirmed to be sent.",
+                    stats.frames_sent,
+                ),
+                (
+                    "frames_received",
+                    "Frames from other nodes received.",
+                    stats.frames_received,
+                ),
+                ("bytes_sent", "Bytes written to the bus.", stats.bytes_sent),
+                (
+                    "bytes_received",
+                    "Bytes read from the bus.",
+                    stats.bytes_received,
+                ),
+                (
+                    "frame_errors",
+                    "Bytes received with a framing or parity error.",
+                    stats.frame_errors,
+                ),
+                (
+                    "crc_failures",
+                    "Received frames dropped because of a checksum mismatch.",
+                    stats.crc_failures,
+                ),
+                (
+                    "collisions",
+                    "Frames of our own overwritten by another sender.",
+                    stats.collisions,
+                ),
+                (
+                    "retransmissions",
+                    "Frames sent again after an attempt.",
+                    stats.retransmissions,
+                ),
+                (
+                    "echo_timeouts",
+                    "Frames aborted as they did not loop back in time.",
+                    stats.echo_timeouts,
+                ),
+                (
+                    "state_timeouts",
+                    "Times the strategy stayed in a state for too long.",
+                    stats.state_timeouts,
+                ),
+                (
+                    "duplicates_dropped",
+                    "Received frames dropped as duplicates.",
+                    stats.duplicates_dropped,
+                ),
+                (
+                    "frames_expired",
+                    "Frames of our own not sent before their deadline.",
+                    stats.frames_expired,
+                ),
+                (
+                    "recoveries",
+                    "Times the transceiver was asked to recover.",
+                    stats.recoveries,
+                ),
+            ];
+            for (name, help, value) in strategy {
+                let _ = writeln!(
+                    out,
+                    "# HELP kiri_strategy_{name}_total {help}\n# TYPE kiri_strategy_{name}_total counter\nkiri_strategy_{name}_total {value}",
+                );
+            }
+        }
+
+        if let Some(up) = counters.link_up {
+            let _ = writeln!(
+                out,
+                "# HELP kiri_link_up Whether the serial port is open.\n# TYPE kiri_link_up gauge\nkiri_link_up {}",
+                up as u8
+            );
+        }
+        out
+    }
+}
+
+/// Serve `metrics` on `/metrics` of `listen` from a thread of its own.
+pub fn serve(listen: impl ToSocketAddrs, metrics: Arc<BusMetrics>) -> io::Result<()> {
+    let listener = TcpListener::bind(listen)?;
+    thread::spawn(move || {
+        http::serve(listener, METRICS_MAX_CONNECTIONS, move |stream| {
+            let request = Request::read(BufReader::new(stream))?;
+            match request.path.as_str() {
+                "/metrics" => {
+                    write_response(stream, 200, "OK", CONTENT_TYPE, metrics.render().as_bytes())
+                }
+                _ => write_response(stream, 404, "Not Found", "text/plain", b"not found\n"),
+            }
+        })
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use kiri_protocol::{Reader, Writer, MAX_FRAME_LEN};
+
+    use super::*;
+
+    fn record(metrics: &BusMetrics, src: u32, dst: u32, contents: &[u8]) {
+        let frame = Writer::package(Address::new(src), Address::new(dst), contents).unwrap();
+        let mut reader = Reader::<MAX_FRAME_LEN>::default();
+        for b in frame.as_slice() {
+            if let Ok(Some(frame)) = reader.feed(*b) {
+                metrics.record_frame(&frame);
+            }
+        }
+    }
+
+    #[test]
+    fn render_empty() {
+        assert_eq!(
+            BusMetrics::new().render(),
+            "# HELP kiri_frames_total Frames seen on the bus.\n\
+             # TYPE kiri_frames_total counter\n\
+             # HELP kiri_frame_bytes_total Bytes of contents of the frames seen on the bus.\n\
+             # TYPE kiri_frame_bytes_total counter\n\
+             # HELP kiri_frame_errors_total Frames that could not be decoded, by reason.\n\
+             # TYPE kiri_frame_errors_total counter\n"
+        );
+    }
+
+    #[test]
+    fn render() {
+        let metrics = BusMetrics::new();
+        record(&metrics, 0x10, 0x01, b"21.5");
+        record(&metrics, 0x10, 0x01, b"21.75");
+        record(&metrics, 0x02, 0xFFFF_FFFF, b"");
+        metrics.record_error(&FrameError::Checksum);
+        metrics.set_link_up(true);
+        metrics.set_stats(Stats {
+            frame_errors: 1,
+            echo_timeouts: 2,
+            frames_sent: 3,
+            frames_received: 4,
+            bytes_sent: 5,
+            bytes_received: 6,
+            collisions: 7,
+            retransmissions: 8,
+            backoff_time: Duration::from_millis(9),
+            crc_failures: 10,
+            state_timeouts: 11,
+            recoveries: 12,
+            duplicates_dropped: 13,
+            frames_expired: 14,
+            frames_skipped: 15,
+        });
+
+        let mut expected = String::from(
+            "# HELP kiri_frames_total Frames seen on the bus.\n\
+             # TYPE kiri_frames_total counter\n\
+             kiri_frames_total{src=\"00000002\",dst=\"ffffffff\"} 1\n\
+             kiri_frames_total{src=\"00000010\",dst=\"00000001\"} 2\n\
+             # HELP kiri_frame_bytes_total Bytes of contents of the frames seen on the bus.\n\
+             # TYPE kiri_frame_bytes_total counter\n\
+             kiri_frame_bytes_total{src=\"00000002\",dst=\"ffffffff\"} 0\n\
+             kiri_frame_bytes_total{src=\"00000010\",dst=\"00000001\"} 9\n\
+             # HELP kiri_frame_errors_total Frames that could not be decoded, by reason.\n\
+             # TYPE kiri_frame_errors_total counter\n\
+             kiri_frame_errors_total{reason=\"crc\"} 1\n",
+        );
+        for (name, help, value) in [
+            ("frames_sent", "Frames of our own confirmed to be sent.", 3),
+            ("frames_received", "Frames from other nodes received.", 4),
+            ("bytes_sent", "Bytes written to the bus.", 5),
+            ("bytes_received", "Bytes read from the bus.", 6),
+            (
+                "frame_errors",
+                "Bytes received with a framing or parity error.",
+                1,
+            ),
+            (
+                "crc_failures",
+                "Received frames dropped because of a checksum mismatch.",
+                10,
+            ),
+            (
+                "collisions",
+                "Frames of our own overwritten by another sender.",
+                7,
+            ),
+            ("retransmissions", "Frames sent again after an attempt.", 8),
+            (
+                "echo_timeouts",
+                "Frames aborted as they did not loop back in time.",
+                2,
+            ),
+            (
+                "state_timeouts",
+                "Times the strategy stayed in a state for too long.",
+                11,
+            ),
+            (
+                "duplicates_dropped",
+                "Received frames dropped as duplicates.",
+                13,
+            ),
+            (
+                "frames_expired",
+                "Frames of our own not sent before their deadline.",
+                14,
+            ),
+            (
+                "recoveries",
+                "Times the transceiver was asked to recover.",
+                12,
+            ),
+        ] {
+            expected += &format!(
+                "# HELP kiri_strategy_{name}_total {help}\n\
+                 # TYPE kiri_strategy_{name}_total counter\n\
+                 kiri_strategy_{name}_total {value}\n"
+            );
+        }
+        expected += "# HELP kiri_link_up Whether the serial port is open.\n\
+                     # TYPE kiri_link_up gauge\n\
+                     kiri_link_up 1\n";
+        assert_eq!(metrics.render(), expected);
+    }
+}
@@ -0,0 +1,289 @@
+//! Minimal MQTT 3.1.1 client, supporting just enough for bridging: QoS 0 publish and subscribe, and retained messages.
+
+use std::{
+    io::{self, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    sync::{
+        atomic::{AtomicU16, Ordering},
+        Arc,
+    },
+};
+
+const CONNECT: u8 = 0x10;
+const CONNACK: u8 = 0x20;
+const PUBLISH: u8 = 0x30;
+const SUBSCRIBE: u8 = 0x82;
+const PINGREQ: u8 = 0xC0;
+
+/// Flag of a PUBLISH for the broker to keep the message for future subscribers.
+const RETAIN: u8 = 0x01;
+
+/// An incoming packet.
+#[derive(Debug, PartialEq)]
+pub enum Packet {
+    Publish {
+        topic: String,
+        payload: Vec<u8>,
+    },
+    /// Any other packet, identified by its packet type.
+    Other(u8),
+}
+
+#[derive(Debug)]
+pub struct MqttClient {
+    stream: TcpStream,
+    /// Shared by the handles to the connection, as packet identifiers must be unique per connection.
+    next_packet_id: Arc<AtomicU16>,
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+fn push_str(buf: &mut Vec<u8>, str: &str) -> io::Result<()> {
+    let len: u16 = str
+        .len()
+        .try_into()
+        .map_err(|_| invalid("string too long"))?;
+    buf.extend_from_slice(&len.to_be_bytes());
+    buf.extend_from_slice(str.as_bytes());
+    Ok(())
+}
+
+fn write_packet(mut w: impl Write, kind: u8, body: &[u8]) -> io::Result<()> {
+    let mut packet = vec![kind];
+    let mut len = body.len();
+    loop {
+        let mut b = (len % 128) as u8;
+        len /= 128;
+        if len > 0 {
+            b |= 0x80;
+        }
+        packet.push(b);
+        if len == 0 {
+            break;
+        }
+    }
+    packet.extend_from_slice(body);
+    w.write_all(&packet)
+}
+
+fn read_packet(mut r: impl Read) -> io::Result<(u8, Vec<u8>)> {
+    let mut kind = [0u8];
+    r.read_exact(&mut kind)?;
+
+    let mut len = 0usize;
+    for i in 0..4 {
+        let mut b = [0u8];
+        r.read_exact(&mut b)?;
+        len |= ((b[0] & 0x7F) as usize) << (7 * i);
+        if b[0] & 0x80 == 0 {
+            let mut body = vec![0u8; len];
+            r.read_exact(&mut body)?;
+            return Ok((kind[0], body));
+        }
+    }
+    Err(invalid("malformed remaining length"))
+}
+
+impl Packet {
+    fn read(r: impl Read) -> io::Result<Self> {
+        let (kind, body) = read_packet(r)?;
+        if kind & 0xF0 != PUBLISH {
+            return Ok(Packet::Other(kind & 0xF0));
+        }
+
+        let qos = (kind >> 1) & 0x03;
+        let topic_len = match body.get(0..2) {
+            Some(len) => u16::from_be_bytes([len[0], len[1]]) as usize,
+            None => return Err(invalid("truncated PUBLISH")),
+        };
+        // Packets with QoS > 0 carry a packet identifier after the topic.
+        let payload_start = 2 + topic_len + if qos > 0 { 2 } else { 0 };
+        if body.len() < payload_start {
+            return Err(invalid("truncated PUBLISH"));
+        }
+
+        let topic = String::from_utf8(body[2..2 + topic_len].to_vec())
+            .map_err(|_| invalid("topic is not UTF-8"))?;
+        Ok(Packet::Publish {
+            topic,
+            payload: body[payload_start..].to_vec(),
+        })
+    }
+}
+
+impl MqttClient {
+    /// Connect to a broker with a clean session.
+    pub fn connect(
+        addr: impl ToSocketAddrs,
+        client_id: &str,
+        keep_alive_secs: u16,
+    ) -> io::Result<Self> {
+        let mut stream = TcpStream::connect(addr)?;
+
+        let mut body = Vec::new();
+        push_str(&mut body, "MQTT")?;
+        body.push(4); // Protocol level 3.1.1.
+        body.push(0x02); // Clean session.
+        body.extend_from_slice(&keep_alive_secs.to_be_bytes());
+        push_str(&mut body, client_id)?;
+        write_packet(&mut stream, CONNECT, &body)?;
+
+        match read_packet(&mut stream)? {
+            (CONNACK, body) if body.len() == 2 && body[1] == 0 => Ok(Self {
+                stream,
+                next_packet_id: Arc::new(AtomicU16::new(1)),
+            }),
+            (CONNACK, body) if body.len() == 2 => Err(io::Error::new(
+                io::ErrorKind::ConnectionRefused,
+                format!("broker refused connection with code {}", body[1]),
+            )),
+            _ => Err(invalid("expected CONNACK")),
+        }
+    }
+
+    /// A second handle to the same connection, such that reading and writing can happen on different threads.
+    ///
+    /// The handles share the packet identifiers, hence either may subscribe.
+    pub fn try_clone(&self) -> io::Result<Self> {
+        Ok(Self {
+            stream: self.stream.try_clone()?,
+            next_packet_id: self.next_packet_id.clone(),
+        })
+    }
+
+    pub fn publish(&mut self, topic: &str, payload: &[u8]) -> io::Result<()> {
+        self.publish_with(PUBLISH, topic, payload)
+    }
+
+    /// Publish a message that the broker keeps, and hands to every client that subscribes later on.
+    pub fn publish_retained(&mut self, topic: &str, payload: &[u8]) -> io::Result<()> {
+        self.publish_with(PUBLISH | RETAIN, topic, payload)
+    }
+
+    fn publish_with(&mut self, kind: u8, topic: &str, payload: &[u8]) -> io::Result<()> {
+        let mut body = Vec::new();
+        push_str(&mut body, topic)?;
+        body.extend_from_slice(payload);
+        write_packet(&mut self.stream, kind, &body)
+    }
+
+    /// Subscribe to a topic filter with QoS 0. The acknowledgement is yielded by `read` as any other packet.
+    pub fn subscribe(&mut self, filter: &str) -> io::Result<()> {
+        let mut body = Vec::new();
+        body.extend_from_slice(&self.packet_id().to_be_bytes());
+        push_str(&mut body, filter)?;
+        body.push(0); // QoS 0.
+        write_packet(&mut self.stream, SUBSCRIBE, &body)
+    }
+
+    /// The next packet identifier, skipping 0 which is not allowed.
+    fn packet_id(&self) -> u16 {
+        loop {
+            match self.next_packet_id.fetch_add(1, Ordering::Relaxed) {
+                0 => continue,
+                id => return id,
+            }
+        }
+    }
+
+    pub fn ping(&mut self) -> io::Result<()> {
+        write_packet(&mut self.stream, PINGREQ, &[])
+    }
+
+    /// Block until the next packet arrives.
+    pub fn read(&mut self) -> io::Result<Packet> {
+        Packet::read(&mut self.stream)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::TcpListener, thread};
+
+    use super::*;
+
+    fn publish(kind: u8, body: &[u8]) -> io::Result<Packet> {
+        let mut packet = Vec::new();
+        write_packet(&mut packet, kind, body).unwrap();
+        Packet::read(packet.as_slice())
+    }
+
+    #[test]
+    fn remaining_length() {
+        for (len, encoded) in [
+            (0, &[0x00][..]),
+            (127, &[0x7F]),
+            (128, &[0x80, 0x01]),
+            (16383, &[0xFF, 0x7F]),
+            (16384, &[0x80, 0x80, 0x01]),
+        ] {
+            let body = vec![0xA5; len];
+            let mut packet = Vec::new();
+            write_packet(&mut packet, PINGREQ, &body).unwrap();
+            assert_eq!(&packet[1..1 + encoded.len()], encoded);
+            assert_eq!(packet.len(), 1 + encoded.len() + len);
+            assert_eq!(read_packet(packet.as_slice()).unwrap(), (PINGREQ, body));
+        }
+    }
+
+    #[test]
+    fn remaining_length_too_long() {
+        let packet = [PINGREQ, 0xFF, 0xFF, 0xFF, 0xFF, 0x01];
+        let e = read_packet(&packet[..]).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn read_publish() {
+        assert_eq!(
+            publish(PUBLISH | RETAIN, b"\x00\x03a/bhello").unwrap(),
+            Packet::Publish {
+                topic: "a/b".to_string(),
+                payload: b"hello".to_vec(),
+            }
+        );
+        // QoS 1, of which the packet identifier 0x1234 is not part of the payload.
+        assert_eq!(
+            publish(PUBLISH | 0x02, b"\x00\x03a/b\x12\x34hello").unwrap(),
+            Packet::Publish {
+                topic: "a/b".to_string(),
+                payload: b"hello".to_vec(),
+            }
+        );
+        assert_eq!(publish(CONNACK, &[0, 0]).unwrap(), Packet::Other(CONNACK));
+    }
+
+    #[test]
+    fn read_truncated_publish() {
+        let truncated = |body: &[u8]| publish(PUBLISH | 0x02, body).unwrap_err().kind();
+        assert_eq!(truncated(b"\x00"), io::ErrorKind::InvalidData);
+        assert_eq!(truncated(b"\x00\x05a/b"), io::ErrorKind::InvalidData);
+        // The topic is complete, but the packet identifier is not.
+        assert_eq!(truncated(b"\x00\x03a/b\x12"), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn clones_share_packet_ids() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let broker = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            assert_eq!(read_packet(&mut stream).unwrap().0, CONNECT);
+            write_packet(&mut stream, CONNACK, &[0, 0]).unwrap();
+            [(); 3].map(|()| {
+                let (kind, body) = read_packet(&mut stream).unwrap();
+                assert_eq!(kind, SUBSCRIBE);
+                u16::from_be_bytes([body[0], body[1]])
+            })
+        });
+
+        let mut client = MqttClient::connect(addr, "test", 60).unwrap();
+        let mut clone = client.try_clone().unwrap();
+        client.subscribe("a").unwrap();
+        clone.subscribe("b").unwrap();
+        client.subscribe("c").unwrap();
+        assert_eq!(broker.join().unwrap(), [1, 2, 3]);
+    }
+}
@@ -0,0 +1,558 @@
+//! Serving many independent tasks of a gateway process over a single serial port.
+//!
+//! A `FrameRouter` runs the strategy on a thread of its own. Tasks subscribe to the frames from or to an address
+//! through a `RouterHandle`, each getting a channel of their own, and send their frames through it as well. Handles
+//! are cloned for as many tasks as needed.
+//!
+//! Started with `FrameRouter::reconnecting`, the router opens the port again whenever it fails, i.e. when a USB
+//! adapter re-enumerates. Frames sent meanwhile are buffered, and supervisors follow the state of the link through
+//! `RouterHandle::link_events`.
+//!
+//! Channels are those of `std::sync::mpsc` rather than of tokio. The strategy has to be polled continuously rather
+//! than when the port becomes readable, which takes a thread either way, and the blocking tools built on this crate
+//! do not need to pull in an async runtime for it. Gateways built on one use the `Stream` and `Sink` of
+//! `kiri-host-futures` instead.
+
+use std::{
+    collections::VecDeque,
+    io,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{channel, Receiver, RecvTimeoutError, Sender, TryRecvError},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use kiri_csma::{
+    CsmaFrameInProgress, CsmaStrategy, SendReceiveResult, Stats, SystemClock, Transceiver,
+};
+use kiri_protocol::{Address, Frame, FrameOwned, FrameRef};
+use rand::RngCore;
+
+/// How long to wait for frames to send while the bus is quiet, before polling the port again.
+///
+/// The port buffers what is received meanwhile, hence this only adds latency to receiving.
+const IDLE_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// Which frames a subscriber receives.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Route {
+    /// Frames sent by an address.
+    Source(Address),
+    /// Frames sent to an address, which includes frames to a group or broadcast only for those addresses.
+    Destination(Address),
+    /// Every frame, i.e. to monitor the bus.
+    All,
+}
+
+impl Route {
+    fn matches(&self, frame: &FrameRef) -> bool {
+        match self {
+            Route::Source(address) => frame.header.address_src == *address,
+            Route::Destination(address) => frame.header.address_dst == *address,
+            Route::All => true,
+        }
+    }
+}
+
+/// A change in the state of the link to the bus.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LinkEvent {
+    /// The port was opened.
+    Up,
+    /// The port failed, i.e. because it disappeared.
+    Down(io::ErrorKind),
+    /// Opening the port failed `failures` times in a row, and is tried again after `retry_after`.
+    Retrying {
+        failures: u32,
+        error: io::ErrorKind,
+        retry_after: Duration,
+    },
+    /// The oldest `dropped` frames were dropped, as more were sent during an outage than are buffered.
+    Overflow { dropped: usize },
+}
+
+/// How a reconnecting router opens the port again.
+#[derive(Debug, Clone)]
+pub struct ReconnectPolicy {
+    /// Time to wait after the first failure, which doubles with every failure after.
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    /// Frames to buffer during an outage at most, after which the oldest ones are dropped.
+    pub max_buffered: usize,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(30),
+            max_buffered: 1024,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// Time to wait after `failures` failures in a row.
+    pub fn delay(&self, failures: u32) -> Duration {
+        self.initial_delay
+            .saturating_mul(1 << failures.saturating_sub(1).min(31))
+            .min(self.max_delay)
+    }
+}
+
+/// State shared by the router thread and the handles.
+#[derive(Debug, Default)]
+struct Shared {
+    /// Subscribers by the route they subscribed to.
+    routes: Mutex<Vec<(Route, Sender<FrameOwned>)>>,
+    supervisors: Mutex<Vec<Sender<LinkEvent>>>,
+    /// Counters of all strategies so far, see `RouterHandle::stats`.
+    stats: Mutex<Stats<Duration>>,
+    /// Whether a strategy is being served, see `RouterHandle::is_up`.
+    up: AtomicBool,
+}
+
+impl Shared {
+    /// Hand `frame` to every subscriber it matches, forgetting those that are gone.
+    fn route(&self, frame: FrameRef) {
+        self.routes.lock().unwrap().retain(|(route, subscriber)| {
+            if !route.matches(&frame) {
+                return true;
+            }
+            let copy = FrameRef {
+                header: frame.header.clone(),
+                contents: frame.contents,
+            };
+            match copy.try_into() {
+                Ok(owned) => subscriber.send(owned).is_ok(),
+                // Frames are never longer than owned frames hold.
+                Err(()) => true,
+            }
+        });
+    }
+
+    fn notify(&self, event: LinkEvent) {
+        match event {
+            LinkEvent::Up => self.up.store(true, Ordering::Relaxed),
+            LinkEvent::Down(_) => self.up.store(false, Ordering::Relaxed),
+            LinkEvent::Retrying { .. } | LinkEvent::Overflow { .. } => (),
+        }
+        self.supervisors
+            .lock()
+            .unwrap()
+            .retain(|supervisor| supervisor.send(event.clone()).is_ok());
+    }
+}
+
+/// Hands frames to send to the router, and subscribes to those it receives.
+#[derive(Debug, Clone)]
+pub struct RouterHandle {
+    shared: Arc<Shared>,
+    outgoing: Sender<Frame>,
+}
+
+impl RouterHandle {
+    /// Queue `frame` to be sent after those queued before it. Fails if the router stopped.
+    pub fn send(&self, frame: Frame) -> io::Result<()> {
+        self.outgoing
+            .send(frame)
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "router stopped"))
+    }
+
+    /// Receive the frames matching `route` on a channel of their own, until the receiver is dropped.
+    ///
+    /// A frame matching multiple routes is received on all of them. Frames matching none are dropped.
+    pub fn subscribe(&self, route: Route) -> Receiver<FrameOwned> {
+        let (sender, receiver) = channel();
+        self.shared.routes.lock().unwrap().push((route, sender));
+        receiver
+    }
+
+    /// Counters of the strategy, including those of the strategies before it when reconnecting.
+    ///
+    /// Updated whenever the bus is quiet, and when a strategy fails.
+    pub fn stats(&self) -> Stats<Duration> {
+        self.shared.stats.lock().unwrap().clone()
+    }
+
+    /// Whether the port is open, i.e. since the last `LinkEvent::Up` without a `LinkEvent::Down` after it.
+    pub fn is_up(&self) -> bool {
+        self.shared.up.load(Ordering::Relaxed)
+    }
+
+    /// Receive every change in the state of the link from now on, until the receiver is dropped.
+    pub fn link_events(&self) -> Receiver<LinkEvent> {
+        let (sender, receiver) = channel();
+        self.shared.supervisors.lock().unwrap().push(sender);
+        receiver
+    }
+}
+
+/// Runs a strategy on a thread of its own, routing the frames it receives to the subscribed tasks.
+///
+/// The thread stops once the router and all handles are dropped, or once the transceiver fails unless reconnecting.
+#[derive(Debug)]
+pub struct FrameRouter {
+    handle: RouterHandle,
+    thread: JoinHandle<io::Result<()>>,
+}
+
+impl FrameRouter {
+    pub fn spawn<T, R>(strategy: CsmaStrategy<T, SystemClock, R>) -> Self
+    where
+        T: Transceiver<Error = io::Error> + Send + 'static,
+        R: RngCore + Send + 'static,
+    {
+        Self::start(move |shared, frames| {
+            shared.notify(LinkEvent::Up);
+            let result = Link::new(shared, frames).serve(strategy);
+            if let Err(e) = &result {
+                shared.notify(LinkEvent::Down(e.kind()));
+            }
+            result
+        })
+    }
+
+    /// Serve the strategies yielded by `connect`, calling it again whenever the previous one failed.
+    ///
+    /// Frames that are still buffered once the handles are dropped during an outage are dropped as well.
+    pub fn reconnecting<T, R, F>(mut connect: F, policy: ReconnectPolicy) -> Self
+    where
+        T: Transceiver<Error = io::Error> + 'static,
+        R: RngCore + 'static,
+        F: FnMut() -> io::Result<CsmaStrategy<T, SystemClock, R>> + Send + 'static,
+    {
+        Self::start(move |shared, frames| {
+            let mut link = Link::new(shared, frames);
+            let mut failures = 0;
+            loop {
+                let strategy = match connect() {
+                    Ok(strategy) => strategy,
+                    Err(e) => {
+                        failures += 1;
+                        let retry_after = policy.delay(failures);
+                        log::warn!(
+                            "Connecting failed {} times, retrying after {:?}: {}",
+                            failures,
+                            retry_after,
+                            e
+                        );
+                        shared.notify(LinkEvent::Retrying {
+                            failures,
+                            error: e.kind(),
+                            retry_after,
+                        });
+                        if !link.buffer_until(Instant::now() + retry_after, policy.max_buffered) {
+                            return Ok(());
+                        }
+                        continue;
+                    }
+                };
+
+                failures = 0;
+                log::info!("Link up");
+                shared.notify(LinkEvent::Up);
+                match link.serve(strategy) {
+                    Ok(()) => return Ok(()),
+                    Err(e) => {
+                        log::warn!("Link down: {}", e);
+                        shared.notify(LinkEvent::Down(e.kind()));
+                    }
+                }
+            }
+        })
+    }
+
+    fn start(
+        run: impl FnOnce(&Shared, &Receiver<Frame>) -> io::Result<()> + Send + 'static,
+    ) -> Self {
+        let shared = Arc::new(Shared::default());
+        let (outgoing, frames) = channel();
+        let thread = {
+            let shared = shared.clone();
+            thread::spawn(move || run(&shared, &frames))
+        };
+        Self {
+            handle: RouterHandle { shared, outgoing },
+            thread,
+        }
+    }
+
+    /// A handle for a task, which can be cloned for more of them.
+    pub fn handle(&self) -> RouterHandle {
+        self.handle.clone()
+    }
+
+    /// Wait for the thread to stop once all other handles are dropped, yielding the error it stopped on if any.
+    pub fn join(self) -> io::Result<()> {
+        drop(self.handle);
+        self.thread
+            .join()
+            .unwrap_or_else(|_| Err(io::Error::other("router panicked")))
+    }
+}
+
+/// The frames to send, which outlive the strategies that send them.
+struct Link<'a> {
+    shared: &'a Shared,
+    frames: &'a Receiver<Frame>,
+    /// Frame being sent, which is sent again from the start by the next strategy if the current one fails.
+    current: Option<CsmaFrameInProgress>,
+    /// Frames taken from the channel that are not sent yet, oldest first.
+    pending: VecDeque<Frame>,
+    /// Whether all handles were dropped, such that no more frames will be sent.
+    stopping: bool,
+    /// Counters of the strategies that failed.
+    baseline: Stats<Duration>,
+}
+
+impl<'a> Link<'a> {
+    fn new(shared: &'a Shared, frames: &'a Receiver<Frame>) -> Self {
+        Self {
+            shared,
+            frames,
+            current: None,
+            pending: VecDeque::new(),
+            stopping: false,
+            baseline: Stats::default(),
+        }
+    }
+
+    /// The next frame to send, if any.
+    fn next_frame(&mut self) -> Option<Frame> {
+        if let Some(frame) = self.pending.pop_front() {
+            return Some(frame);
+        }
+        if self.stopping {
+            return None;
+        }
+        match self.frames.try_recv() {
+            Ok(frame) => Some(frame),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => {
+                self.stopping = true;
+                None
+            }
+        }
+    }
+
+    /// Send and receive using `strategy` until it fails, or until all frames are sent once the handles are dropped.
+    fn serve<T, R>(&mut self, mut strategy: CsmaStrategy<T, SystemClock, R>) -> io::Result<()>
+    where
+        T: Transceiver<Error = io::Error>,
+        R: RngCore,
+    {
+        if let Some(frame) = self.current.as_mut() {
+            frame.reset();
+        }
+        loop {
+            if self.current.is_none() {
+                self.current = self.next_frame().map(CsmaFrameInProgress::new);
+            }
+
+            let shared = self.shared;
+            let result = match self.current.as_mut() {
+                Some(frame) => {
+                    strategy.send_or_receive_with(frame, |received| shared.route(received))
+                }
+                None => strategy.receive().map(|received| {
+                    shared.route(received);
+                    SendReceiveResult::Received(())
+                }),
+            };
+            match result {
+                Ok(SendReceiveResult::Received(())) => (),
+                // Can not expire without a deadline.
+                Ok(SendReceiveResult::SendComplete | SendReceiveResult::Expired) => {
+                    self.current = None
+                }
+                Err(nb::Error::Other(e)) => {
+                    self.baseline.merge(strategy.stats());
+                    self.publish(&Stats::default());
+                    return Err(e);
+                }
+                // Sending takes polling as often as possible, receiving does not.
+                Err(nb::Error::WouldBlock) if self.current.is_some() => (),
+                Err(nb::Error::WouldBlock) => {
+                    self.publish(strategy.stats());
+                    if self.stopping {
+                        return Ok(());
+                    }
+                    match self.frames.recv_timeout(IDLE_POLL_INTERVAL) {
+                        Ok(frame) => self.pending.push_back(frame),
+                        Err(RecvTimeoutError::Timeout) => (),
+                        Err(RecvTimeoutError::Disconnected) => self.stopping = true,
+                    }
+                }
+            }
+        }
+    }
+
+    /// Publish the counters of the strategies that failed along with `current`, see `RouterHandle::stats`.
+    fn publish(&self, current: &Stats<Duration>) {
+        let mut total = self.baseline.clone();
+        total.merge(current);
+        *self.shared.stats.lock().unwrap() = total;
+    }
+
+    /// Buffer the frames sent until `deadline`, keeping at most `max_buffered` of them.
+    ///
+    /// Yields whether to carry on, which is not the case once the handles are dropped.
+    fn buffer_until(&mut self, deadline: Instant, max_buffered: usize) -> bool {
+        while !self.stopping {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            match self.frames.recv_timeout(timeout) {
+                Ok(frame) => self.pending.push_back(frame),
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => self.stopping = true,
+            }
+        }
+
+        let dropped = self.pending.len().saturating_sub(max_buffered);
+        if dropped > 0 {
+            self.pending.drain(..dropped);
+            log::warn!("Dropped {} frames sent during the outage", dropped);
+            self.shared.notify(LinkEvent::Overflow { dropped });
+        }
+        !self.stopping
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use kiri_protocol::{Reader, Writer, MAX_FRAME_LEN};
+    use rand::rngs::mock::StepRng;
+
+    use super::*;
+    use crate::transceiver::HostConfig;
+
+    /// How long to wait for the router thread, which is far longer than it should ever take.
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Bytes on an in-memory bus, on which everything written is looped back.
+    #[derive(Debug, Default)]
+    struct Wire {
+        incoming: VecDeque<u8>,
+        written: Vec<u8>,
+        /// Whether reads fail like those of a serial port of which the adapter was unplugged.
+        hung_up: bool,
+    }
+
+    /// Transceiver on a `Wire` that the test holds on to as well.
+    #[derive(Debug, Clone, Default)]
+    struct MemoryTransceiver(Arc<Mutex<Wire>>);
+
+    impl Transceiver for MemoryTransceiver {
+        type Error = io::Error;
+
+        fn handle_interrupts(&self) {}
+
+        fn bus_is_idle(&self) -> bool {
+            self.0.lock().unwrap().incoming.is_empty()
+        }
+
+        fn write(&mut self, byte: u8) -> nb::Result<(), Self::Error> {
+            let mut wire = self.0.lock().unwrap();
+            wire.written.push(byte);
+            wire.incoming.push_back(byte);
+            Ok(())
+        }
+
+        fn read(&mut self) -> nb::Result<u8, kiri_csma::ReadError<Self::Error>> {
+            let mut wire = self.0.lock().unwrap();
+            if wire.hung_up {
+                let e = io::Error::new(io::ErrorKind::UnexpectedEof, "serial port hung up");
+                return Err(nb::Error::Other(kiri_csma::ReadError::UnderlyingError(e)));
+            }
+            wire.incoming.pop_front().ok_or(nb::Error::WouldBlock)
+        }
+    }
+
+    fn strategy(
+        transceiver: MemoryTransceiver,
+    ) -> CsmaStrategy<MemoryTransceiver, SystemClock, StepRng> {
+        CsmaStrategy::new::<HostConfig>(transceiver, SystemClock, StepRng::new(0, 7919))
+    }
+
+    /// The contents of the frames written to `transceiver`.
+    fn written(transceiver: &MemoryTransceiver) -> Vec<Vec<u8>> {
+        let mut reader = Reader::<MAX_FRAME_LEN>::default();
+        let wire = transceiver.0.lock().unwrap();
+        wire.written
+            .iter()
+            .filter_map(|b| match reader.feed(*b) {
+                Ok(Some(frame)) => Some(frame.contents.to_vec()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn forward() {
+        let transceiver = MemoryTransceiver::default();
+        let router = FrameRouter::spawn(strategy(transceiver.clone()));
+        let handle = router.handle();
+        let sensor = handle.subscribe(Route::Source(Address::new(3)));
+        let other = handle.subscribe(Route::Source(Address::new(4)));
+
+        let frame = Writer::package(Address::new(3), Address::new(1), b"reading").unwrap();
+        transceiver
+            .0
+            .lock()
+            .unwrap()
+            .incoming
+            .extend(frame.as_slice());
+        let received = sensor.recv_timeout(TIMEOUT).unwrap();
+        assert_eq!(received.header.address_dst, Address::new(1));
+        assert_eq!(&received.contents[..], b"reading");
+        assert!(other.try_recv().is_err());
+
+        // Frames that are queued are sent before the router stops.
+        let frame = Writer::package(Address::new(1), Address::new(3), b"command").unwrap();
+        handle.send(frame).unwrap();
+        drop(handle);
+        router.join().unwrap();
+        assert_eq!(written(&transceiver), [b"command"]);
+    }
+
+    #[test]
+    fn reconnect_after_eof() {
+        let transceivers = [MemoryTransceiver::default(), MemoryTransceiver::default()];
+        let mut connect = transceivers.clone().into_iter();
+        let policy = ReconnectPolicy {
+            initial_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(10),
+            max_buffered: 16,
+        };
+        let router = FrameRouter::reconnecting(
+            move || match connect.next() {
+                Some(transceiver) => Ok(strategy(transceiver)),
+                None => Err(io::ErrorKind::NotFound.into()),
+            },
+            policy,
+        );
+        let handle = router.handle();
+        let events = handle.link_events();
+
+        transceivers[0].0.lock().unwrap().hung_up = true;
+        let event = || events.recv_timeout(TIMEOUT).unwrap();
+        // The first port may have come up before the events were subscribed to.
+        let down = match event() {
+            LinkEvent::Up => event(),
+            down => down,
+        };
+        assert_eq!(down, LinkEvent::Down(io::ErrorKind::UnexpectedEof));
+        assert_eq!(event(), LinkEvent::Up);
+
+        // Frames sent after reconnecting go out on the new port.
+        let frame = Writer::package(Address::new(1), Address::new(3), b"command").unwrap();
+        handle.send(frame).unwrap();
+        drop(handle);
+        router.join().unwrap();
+        assert!(written(&transceivers[0]).is_empty());
+        assert_eq!(written(&transceivers[1]), [b"command"]);
+    }
+}
@@ -0,0 +1,148 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Read, Write},
+    os::unix::{fs::OpenOptionsExt, io::AsRawFd},
+    path::Path,
+};
+
+/// A serial port (TTY) configured for raw 8N1 communication.
+#[derive(Debug)]
+pub struct SerialPort {
+    file: File,
+}
+
+fn baud_to_speed(baud: u32) -> io::Result<libc::speed_t> {
+    Ok(match baud {
+        1200 => libc::B1200,
+        2400 => libc::B2400,
+        4800 => libc::B4800,
+        9600 => libc::B9600,
+        19200 => libc::B19200,
+        38400 => libc::B38400,
+        57600 => libc::B57600,
+        115200 => libc::B115200,
+        230400 => libc::B230400,
+        460800 => libc::B460800,
+        500000 => libc::B500000,
+        921600 => libc::B921600,
+        1000000 => libc::B1000000,
+        2000000 => libc::B2000000,
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unsupported baud rate {}", baud),
+            ))
+        }
+    })
+}
+
+fn check(res: libc::c_int) -> io::Result<()> {
+    if res < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+impl SerialPort {
+    /// Open a TTY in raw mode with the given baud rate.
+    ///
+    /// Reads block until at least one byte is available, or until `read_timeout_ds` deciseconds
+    /// have passed when it is non-zero.
+    pub fn open(path: impl AsRef<Path>, baud: u32, read_timeout_ds: u8) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_NOCTTY)
+            .open(path)?;
+        let speed = baud_to_speed(baud)?;
+
+        let fd = file.as_raw_fd();
+        unsafe {
+            let mut termios: libc::termios = core::mem::zeroed();
+            check(libc::tcgetattr(fd, &mut termios))?;
+            libc::cfmakeraw(&mut termios);
+            termios.c_cflag |= libc::CLOCAL | libc::CREAD;
+            termios.c_cflag &= !(libc::CSTOPB | libc::PARENB | libc::CRTSCTS);
+            if read_timeout_ds == 0 {
+                termios.c_cc[libc::VMIN] = 1;
+                termios.c_cc[libc::VTIME] = 0;
+            } else {
+                termios.c_cc[libc::VMIN] = 0;
+                termios.c_cc[libc::VTIME] = read_timeout_ds;
+            }
+            check(libc::cfsetispeed(&mut termios, speed))?;
+            check(libc::cfsetospeed(&mut termios, speed))?;
+            check(libc::tcsetattr(fd, libc::TCSANOW, &termios))?;
+            check(libc::tcflush(fd, libc::TCIOFLUSH))?;
+        }
+
+        Ok(Self { file })
+    }
+
+    /// A second handle to the same port, such that reading and writing can happen on different threads.
+    pub fn try_clone(&self) -> io::Result<Self> {
+        Ok(Self {
+            file: self.file.try_clone()?,
+        })
+    }
+
+    /// Make reads and writes return `WouldBlock` instead of waiting.
+    pub fn set_nonblocking(&mut self, nonblocking: bool) -> io::Result<()> {
+        let fd = self.file.as_raw_fd();
+        unsafe {
+            let flags = libc::fcntl(fd, libc::F_GETFL);
+            check(flags)?;
+            let flags = if nonblocking {
+                flags | libc::O_NONBLOCK
+            } else {
+                flags & !libc::O_NONBLOCK
+            };
+            check(libc::fcntl(fd, libc::F_SETFL, flags))
+        }
+    }
+
+    /// Mark bytes received with a framing or parity error with the prefix `0xFF 0x00`.
+    ///
+    /// Received `0xFF` bytes are escaped as `0xFF 0xFF` in this mode.
+    pub fn set_error_marking(&mut self, enabled: bool) -> io::Result<()> {
+        let fd = self.file.as_raw_fd();
+        unsafe {
+            let mut termios: libc::termios = core::mem::zeroed();
+            check(libc::tcgetattr(fd, &mut termios))?;
+            if enabled {
+                termios.c_iflag |= libc::INPCK | libc::PARMRK;
+                termios.c_iflag &= !(libc::IGNPAR | libc::IGNBRK | libc::ISTRIP);
+            } else {
+                termios.c_iflag &= !(libc::INPCK | libc::PARMRK);
+            }
+            check(libc::tcsetattr(fd, libc::TCSANOW, &termios))
+        }
+    }
+
+    /// Block until all written bytes have been transmitted.
+    pub fn drain(&self) -> io::Result<()> {
+        check(unsafe { libc::tcdrain(self.file.as_raw_fd()) })
+    }
+
+    /// Discard all bytes that have been received but not read, or written but not transmitted.
+    pub fn discard(&self) -> io::Result<()> {
+        check(unsafe { libc::tcflush(self.file.as_raw_fd(), libc::TCIOFLUSH) })
+    }
+}
+
+impl Read for SerialPort {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.file.read(buf)
+    }
+}
+
+impl Write for SerialPort {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
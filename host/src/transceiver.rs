@@ -0,0 +1,171 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::VecDeque,
+    io::{self, Read, Write},
+    time::{Duration, Instant},
+};
+
+use kiri_csma::{Config, CsmaStrategyState, ReadError, SystemClock, Transceiver};
+
+use crate::serial::SerialPort;
+
+/// Bits per character on the line for 8N1: start bit, 8 data bits and a stop bit.
+const BITS_PER_CHAR: u32 = 10;
+
+/// How long it takes to transmit a single character at `baud`.
+pub fn char_time(baud: u32) -> Duration {
+    Duration::from_nanos(1_000_000_000 * BITS_PER_CHAR as u64 / baud as u64)
+}
+
+/// Backoff window for hosts, which is wide enough to absorb the scheduling jitter of a regular OS.
+#[derive(Debug)]
+pub struct HostConfig;
+
+impl Config<SystemClock> for HostConfig {
+    const BUS_MIN_IDLE_DURATION: Duration = Duration::from_millis(1);
+    const BUS_MAX_IDLE_DURATION: Duration = Duration::from_millis(20);
+    const ECHO_BYTE_TIMEOUT: Duration = Duration::from_millis(50);
+    const ECHO_FRAME_TIMEOUT: Duration = Duration::from_secs(1);
+    const RESET_ON_STATE_TIMEOUT: bool = true;
+
+    fn max_dwell_duration(state: &CsmaStrategyState<SystemClock>) -> Option<Duration> {
+        use CsmaStrategyState::*;
+        match state {
+            StartSend | EnablingDriver => Some(Duration::from_millis(100)),
+            Sending | ConfirmingSendWithoutErrors | Jamming { .. } => Some(Duration::from_secs(2)),
+            WaitForBusIdle | BusIdleCooldown { .. } | PostSendGap { .. } => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum MarkState {
+    Normal,
+    /// Received `0xFF`, which is either an escaped `0xFF` or the start of an error mark.
+    Escape,
+    /// Received `0xFF 0x00`, the next byte is the character that was received with an error.
+    Error,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Event {
+    Byte(u8),
+    FrameError,
+}
+
+/// Transceiver for a TTY, for hosts to participate on the bus as a regular node.
+///
+/// The bus is deemed idle if nothing has been sent or received for a while, as a TTY does not expose
+/// the state of the line. Choose the idle duration with some margin, as USB serial adapters add latency.
+/// Framing and parity errors are detected using the `PARMRK` input mode.
+///
+/// The RS485 transceiver behind the port must loop back the transmitted bytes.
+#[derive(Debug)]
+pub struct SerialPortTransceiver {
+    port: RefCell<SerialPort>,
+    idle_after: Duration,
+    last_activity: Cell<Instant>,
+    events: RefCell<VecDeque<Event>>,
+    mark_state: Cell<MarkState>,
+}
+
+impl SerialPortTransceiver {
+    pub fn new(mut port: SerialPort, idle_after: Duration) -> io::Result<Self> {
+        port.set_nonblocking(true)?;
+        port.set_error_marking(true)?;
+        Ok(Self {
+            port: RefCell::new(port),
+            idle_after,
+            last_activity: Cell::new(Instant::now()),
+            events: RefCell::new(VecDeque::new()),
+            mark_state: Cell::new(MarkState::Normal),
+        })
+    }
+
+    /// Idle duration of a few character times for `baud`, plus `latency` for the host and serial adapter.
+    pub fn idle_duration(baud: u32, latency: Duration) -> Duration {
+        char_time(baud) * 3 + latency
+    }
+
+    fn poll_port(&self) -> io::Result<()> {
+        let mut buf = [0u8; 256];
+        loop {
+            let len = match self.port.borrow_mut().read(&mut buf) {
+                // Reads without data would block instead, hence the TTY is gone, i.e. the USB adapter was unplugged.
+                Ok(0) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "serial port hung up",
+                    ))
+                }
+                Ok(len) => len,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            self.last_activity.set(Instant::now());
+
+            let mut events = self.events.borrow_mut();
+            for b in &buf[..len] {
+                let (state, event) = match (self.mark_state.get(), *b) {
+                    (MarkState::Normal, 0xFF) => (MarkState::Escape, None),
+                    (MarkState::Normal, b) => (MarkState::Normal, Some(Event::Byte(b))),
+                    (MarkState::Escape, 0x00) => (MarkState::Error, None),
+                    (MarkState::Escape, b) => (MarkState::Normal, Some(Event::Byte(b))),
+                    (MarkState::Error, _) => (MarkState::Normal, Some(Event::FrameError)),
+                };
+                self.mark_state.set(state);
+                events.extend(event);
+            }
+        }
+    }
+}
+
+impl Transceiver for SerialPortTransceiver {
+    type Error = io::Error;
+
+    fn handle_interrupts(&self) {
+        if let Err(e) = self.poll_port() {
+            log::warn!("Serial receive failed: {}", e);
+        }
+    }
+
+    fn bus_is_idle(&self) -> bool {
+        self.events.borrow().is_empty() && self.last_activity.get().elapsed() >= self.idle_after
+    }
+
+    fn write(&mut self, byte: u8) -> nb::Result<(), Self::Error> {
+        match self.port.borrow_mut().write(&[byte]) {
+            Ok(1) => {
+                self.last_activity.set(Instant::now());
+                Ok(())
+            }
+            Ok(_) => Err(nb::Error::WouldBlock),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => Err(nb::Error::WouldBlock),
+            Err(e) => Err(nb::Error::Other(e)),
+        }
+    }
+
+    fn read(&mut self) -> nb::Result<u8, ReadError<Self::Error>> {
+        self.poll_port()
+            .map_err(|e| nb::Error::Other(ReadError::UnderlyingError(e)))?;
+        match self.events.borrow_mut().pop_front() {
+            Some(Event::Byte(b)) => Ok(b),
+            Some(Event::FrameError) => Err(nb::Error::Other(ReadError::FrameError)),
+            None => Err(nb::Error::WouldBlock),
+        }
+    }
+
+    fn recover(&mut self) {
+        // Resynchronise error marking, in case an escape sequence was lost.
+        self.mark_state.set(MarkState::Normal);
+    }
+
+    fn reset(&mut self) {
+        if let Err(e) = self.port.borrow().discard() {
+            log::warn!("Serial reset failed: {}", e);
+        }
+        self.events.borrow_mut().clear();
+        self.mark_state.set(MarkState::Normal);
+    }
+}
@@ -0,0 +1,3 @@
+//! Model checking of the split `CsmaStrategy` using loom, see the tests.
+//!
+//! Run with `LOOM_MAX_PREEMPTIONS=2 cargo test --release`, as exploring every interleaving takes long otherwise.
@@ -0,0 +1,168 @@
+//! Interleavings of an interrupt handler feeding a `CsmaReceiver`, while the main loop pumps a `CsmaSender`.
+//!
+//! Loom explores every order in which both contexts enter the critical section, checking that no byte is
+//! consumed twice or lost, and that the reader yields incoming frames intact.
+
+use kiri_csma::{
+    backoff::UniformBackoff, shared::CriticalSection, split::CsmaCore, Clock, Config, CsmaStrategy,
+    ReadError, Transceiver,
+};
+use kiri_protocol::{Address, Writer, MAX_FRAME_LEN};
+use loom::{
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+};
+use rand::rngs::mock::StepRng;
+
+loom::lazy_static! {
+    /// Stands in for disabling interrupts.
+    static ref INTERRUPTS: Mutex<()> = Mutex::new(());
+}
+
+struct LoomCriticalSection;
+
+// Safety: `INTERRUPTS` is held for as long as `f` runs.
+unsafe impl CriticalSection for LoomCriticalSection {
+    fn with<R>(f: impl FnOnce() -> R) -> R {
+        let _guard = INTERRUPTS.lock().unwrap();
+        f()
+    }
+}
+
+#[derive(Clone, Default)]
+struct LoomClock(Arc<AtomicU64>);
+
+impl Clock for LoomClock {
+    type Instant = u64;
+    type Duration = u64;
+
+    fn now(&self) -> u64 {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+struct TestConfig;
+
+impl Config<LoomClock> for TestConfig {
+    const BUS_MIN_IDLE_DURATION: u64 = 1;
+    const BUS_MAX_IDLE_DURATION: u64 = 5;
+    // Waiting for the interrupt handler to be scheduled must not count as a lost echo.
+    const ECHO_BYTE_TIMEOUT: u64 = u64::MAX / 4;
+    const ECHO_FRAME_TIMEOUT: u64 = u64::MAX / 4;
+}
+
+/// Bus that loops back every byte written, and on which other senders can put bytes.
+#[derive(Default)]
+struct Loopback {
+    bus: heapless::Deque<u8, 256>,
+}
+
+impl Transceiver for Loopback {
+    type Error = ();
+
+    fn handle_interrupts(&self) {}
+
+    fn bus_is_idle(&self) -> bool {
+        self.bus.is_empty()
+    }
+
+    fn write(&mut self, byte: u8) -> nb::Result<(), Self::Error> {
+        self.bus.push_back(byte).map_err(|_| nb::Error::WouldBlock)
+    }
+
+    fn read(&mut self) -> nb::Result<u8, ReadError<Self::Error>> {
+        self.bus.pop_front().ok_or(nb::Error::WouldBlock)
+    }
+}
+
+type Core = CsmaCore<
+    LoomCriticalSection,
+    Loopback,
+    LoomClock,
+    StepRng,
+    MAX_FRAME_LEN,
+    8,
+    ((), UniformBackoff),
+>;
+
+/// A core that outlives the threads its halves are handed to.
+fn core(transceiver: Loopback, clock: &LoomClock) -> &'static mut Core {
+    let rng = StepRng::new(0, 7919);
+    let strategy = CsmaStrategy::new::<TestConfig>(transceiver, clock.clone(), rng);
+    Box::leak(Box::new(CsmaCore::new(strategy)))
+}
+
+/// Send a frame from the main loop while an interrupt handler receives, yielding the stats and what was received.
+fn send_while_receiving(
+    transceiver: Loopback,
+    contents: &'static [u8],
+) -> (kiri_csma::Stats<u64>, Vec<Vec<u8>>) {
+    let clock = LoomClock::default();
+    let (mut sender, mut receiver) = core(transceiver, &clock).split();
+    let frame = Writer::package(Address::new(1), Address::new(2), contents).unwrap();
+    assert!(sender.send(frame).is_ok());
+
+    let done = Arc::new(AtomicBool::new(false));
+    let isr = {
+        let done = done.clone();
+        thread::spawn(move || {
+            let mut received = Vec::new();
+            while !done.load(Ordering::SeqCst) {
+                match receiver.receive() {
+                    Ok(frame) => received.push(frame.contents.to_vec()),
+                    Err(nb::Error::WouldBlock) => thread::yield_now(),
+                    Err(nb::Error::Other(())) => panic!("transceiver failed"),
+                }
+            }
+            (receiver.stats(), received)
+        })
+    };
+
+    while sender.poll().is_err() {
+        clock.0.fetch_add(1, Ordering::SeqCst);
+        thread::yield_now();
+    }
+    done.store(true, Ordering::SeqCst);
+    isr.join().unwrap()
+}
+
+#[test]
+fn receiver_confirms_sender() {
+    loom::model(|| {
+        let len = Writer::package(Address::new(1), Address::new(2), b"loom")
+            .unwrap()
+            .as_slice()
+            .len() as u64;
+
+        let (stats, received) = send_while_receiving(Loopback::default(), b"loom");
+        assert!(received.is_empty());
+        assert_eq!(stats.frames_sent, 1);
+        assert_eq!(stats.bytes_sent, len);
+        // Every byte that looped back was consumed exactly once.
+        assert_eq!(stats.bytes_received, len);
+        assert_eq!(stats.collisions, 0);
+    });
+}
+
+#[test]
+fn receiver_yields_incoming_once() {
+    loom::model(|| {
+        let incoming = Writer::package(Address::new(3), Address::new(1), b"isr").unwrap();
+        let mut transceiver = Loopback::default();
+        for b in incoming.as_slice() {
+            transceiver.bus.push_back(*b).unwrap();
+        }
+
+        let (stats, received) = send_while_receiving(transceiver, b"loom");
+        assert_eq!(received, [b"isr"]);
+        assert_eq!(stats.frames_received, 1);
+        assert_eq!(stats.frames_sent, 1);
+        assert_eq!(
+            stats.bytes_received,
+            stats.bytes_sent + incoming.as_slice().len() as u64
+        );
+    });
+}
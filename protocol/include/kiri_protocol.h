@@ -0,0 +1,77 @@
+#ifndef KIRI_PROTOCOL_H
+#define KIRI_PROTOCOL_H
+
+/* Generated by cbindgen from src/ffi.rs, do not edit. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// The contents do not fit in a frame, or the frame does not fit in the buffer.
+#define KIRI_WRITE_ERROR_TOO_LONG -1
+
+// The header could not be encoded.
+#define KIRI_WRITE_ERROR_HEADER -2
+
+// Outcome of feeding a byte to a reader, see `ReadResult`.
+typedef enum KiriReadResult {
+  KIRI_READ_RESULT_NOT_YET = 0,
+  KIRI_READ_RESULT_FRAME_OK = 1,
+  KIRI_READ_RESULT_OVERFLOW = -1,
+  KIRI_READ_RESULT_FRAME_ERROR_COBS = -2,
+  KIRI_READ_RESULT_FRAME_ERROR_MAGIC = -3,
+  KIRI_READ_RESULT_FRAME_ERROR_HEADER = -4,
+  KIRI_READ_RESULT_FRAME_ERROR_SIZE = -5,
+  KIRI_READ_RESULT_FRAME_ERROR_CHECKSUM = -6,
+} KiriReadResult;
+
+// Reader of frames, of which the contents are hidden from C.
+typedef struct KiriReader KiriReader;
+
+// A received frame, of which the contents are valid until the reader is fed again.
+typedef struct KiriFrame {
+  uint32_t src;
+  uint32_t dst;
+  uint8_t hop_limit;
+  uint8_t sequence;
+  const uint8_t *contents;
+  size_t contents_len;
+} KiriFrame;
+
+// Bytes to reserve for a reader, which depends on the target and is hence not known to the header.
+size_t kiri_reader_size(void);
+
+// Alignment of the memory of a reader.
+size_t kiri_reader_align(void);
+
+// Create a reader in `mem`, which must be valid for `len` bytes and aligned to `kiri_reader_align()`.
+//
+// Yields null if the memory is too small or misaligned.
+//
+// # Safety
+//
+// `mem` must be valid for writes of `len` bytes, and not be used otherwise for as long as the reader is in use.
+KiriReader *kiri_reader_new(uint8_t *mem, size_t len);
+
+// Feed a byte to `reader`, filling `frame` if this results in a correct frame.
+//
+// # Safety
+//
+// `reader` must come from `kiri_reader_new`, and `frame` must be valid for writes.
+KiriReadResult kiri_reader_feed(KiriReader *reader, uint8_t byte, KiriFrame *frame);
+
+// Package `contents` into a frame in `out`, yielding its length, or a negative `KIRI_WRITE_ERROR_*`.
+//
+// # Safety
+//
+// `contents` must be valid for reads of `contents_len` bytes, and `out` for writes of `out_len` bytes.
+ptrdiff_t kiri_writer_package(uint32_t src,
+                              uint32_t dst,
+                              const uint8_t *contents,
+                              size_t contents_len,
+                              uint8_t *out,
+                              size_t out_len);
+
+#endif /* KIRI_PROTOCOL_H */
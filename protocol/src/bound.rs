@@ -0,0 +1,244 @@
+//! Guarding the source addresses of frames against mistakes, see `BoundWriter` and `SourceBindings`.
+
+use core::fmt::Debug;
+
+use crate::{Address, Frame, FrameBuilder, FrameRef, WriteError, Writer, MAX_EXTENDED_FRAME_LEN};
+
+/// Writer bound to the address of the node itself, which only packages frames from that address.
+///
+/// Use this instead of the `Writer` shorthands where the source is otherwise passed around, such that frames can not
+/// be sent on behalf of another node by mistake. Frames are meant for bus `0`, unless set otherwise by `on_bus`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BoundWriter {
+    address: Address,
+    bus: u8,
+}
+
+impl BoundWriter {
+    pub const fn new(address: Address) -> Self {
+        Self { address, bus: 0 }
+    }
+
+    /// Package frames for bus `bus` instead, see `FrameBuilder::bus`.
+    pub const fn on_bus(self, bus: u8) -> Self {
+        Self { bus, ..self }
+    }
+
+    /// The address all frames are packaged from.
+    pub fn address(&self) -> Address {
+        self.address
+    }
+
+    /// The bus all frames are packaged for.
+    pub fn bus(&self) -> u8 {
+        self.bus
+    }
+
+    /// See `Writer::package`.
+    pub fn package(&self, dst: Address, contents: &[u8]) -> Result<Frame, WriteError> {
+        self.package_sized(dst, contents)
+    }
+
+    /// See `Writer::package_sized`.
+    pub fn package_sized<const N: usize>(
+        &self,
+        dst: Address,
+        contents: &[u8],
+    ) -> Result<Frame<N>, WriteError> {
+        self.builder(dst).payload(contents).build_sized()
+    }
+
+    /// See `Writer::package_vectored`.
+    pub fn package_vectored(&self, dst: Address, parts: &[&[u8]]) -> Result<Frame, WriteError> {
+        self.builder(dst).payload_vectored(parts).build()
+    }
+
+    /// See `Writer::package_extended`.
+    pub fn package_extended(
+        &self,
+        dst: Address,
+        contents: &[u8],
+    ) -> Result<Frame<MAX_EXTENDED_FRAME_LEN>, WriteError> {
+        self.package_sized(dst, contents)
+    }
+
+    /// A builder of a frame from our address, to combine the features of a frame.
+    pub fn builder<'a>(&self, dst: Address) -> FrameBuilder<'a> {
+        FrameBuilder::new(self.address, dst).bus(self.bus)
+    }
+
+    /// Package a frame of another node again to be forwarded by a router onto our bus, see `Writer::forward`.
+    ///
+    /// Frames that claim to be from our own address are not forwarded, as they are either looping or misaddressed.
+    pub fn package_forward(&self, frame: &FrameRef) -> Result<Option<Frame>, WriteError> {
+        match frame.header.address_src == self.address {
+            true => Ok(None),
+            false => Writer::forward(frame, self.bus),
+        }
+    }
+}
+
+/// A frame claims to be from an address that was learned under another identity, see `SourceBindings::check`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct SourceMismatch<K> {
+    pub src: Address,
+    /// The identity the address was learned under.
+    pub learned: K,
+}
+
+impl<K: Debug> core::fmt::Display for SourceMismatch<K> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "address {} was learned under {:?}",
+            self.src, self.learned
+        )
+    }
+}
+
+impl<K: Debug> core::error::Error for SourceMismatch<K> {}
+
+/// The identities of up to `S` neighbours, by their source address, as learned from the first frame of each.
+///
+/// The identity `K` is whatever tells neighbours apart besides the address they claim, i.e. the port of a router a
+/// frame arrived on. Frames that claim the address of a neighbour with another identity are caught by `check`,
+/// which points at nodes that are misconfigured with the same address, or frames that are misaddressed.
+#[derive(Debug)]
+pub struct SourceBindings<K, const S: usize> {
+    bindings: heapless::Vec<(Address, K), S>,
+}
+
+impl<K: PartialEq + Copy, const S: usize> SourceBindings<K, S> {
+    pub fn new() -> Self {
+        Self {
+            bindings: heapless::Vec::new(),
+        }
+    }
+
+    /// Check that `src` is claimed by the same identity as before, learning it if it was not seen before.
+    ///
+    /// Addresses beyond the first `S` are not learned, and hence always pass.
+    pub fn check(&mut self, src: Address, identity: K) -> Result<(), SourceMismatch<K>> {
+        match self.learned(src) {
+            Some(learned) if learned == identity => Ok(()),
+            Some(learned) => Err(SourceMismatch { src, learned }),
+            None => {
+                // A full table is not an error, as the check is merely a safeguard.
+                let _ = self.bindings.push((src, identity));
+                Ok(())
+            }
+        }
+    }
+
+    /// The identity `src` was learned under, if any.
+    pub fn learned(&self, src: Address) -> Option<K> {
+        self.bindings
+            .iter()
+            .find(|(address, _)| *address == src)
+            .map(|(_, identity)| *identity)
+    }
+
+    /// Forget the identity of `src`, i.e. once a node moved on purpose, such that it is learned anew.
+    pub fn forget(&mut self, src: Address) {
+        self.bindings.retain(|(address, _)| *address != src);
+    }
+
+    /// Forget all identities.
+    pub fn clear(&mut self) {
+        self.bindings.clear();
+    }
+}
+
+impl<K: PartialEq + Copy, const S: usize> Default for SourceBindings<K, S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Reader;
+
+    fn receive<'a>(reader: &'a mut Reader, frame: &Frame) -> FrameRef<'a> {
+        for b in frame.as_slice() {
+            if let Ok(Some(_)) = reader.feed(*b) {
+                break;
+            }
+        }
+        reader.last_frame().unwrap()
+    }
+
+    #[test]
+    fn bound_writer() {
+        let (ours, theirs) = (Address::new(1), Address::new(2));
+        let writer = BoundWriter::new(ours);
+
+        let frame = writer.package(theirs, b"hello").unwrap();
+        let expected = Writer::package(ours, theirs, b"hello").unwrap();
+        assert_eq!(frame.as_slice(), expected.as_slice());
+        let frame = writer.builder(theirs).hop_limit(1).build().unwrap();
+        assert_eq!(frame.header().unwrap().address_src, ours);
+
+        // Frames of others are forwarded, those that claim to be ours are not.
+        let mut reader = Reader::new();
+        let frame = FrameBuilder::new(theirs, Address::new(3))
+            .hop_limit(1)
+            .build()
+            .unwrap();
+        let forwarded = writer.package_forward(&receive(&mut reader, &frame));
+        assert_eq!(
+            forwarded.unwrap().unwrap().header().unwrap().address_src,
+            theirs
+        );
+
+        let frame = FrameBuilder::new(ours, Address::new(3))
+            .hop_limit(1)
+            .build()
+            .unwrap();
+        let forwarded = writer.package_forward(&receive(&mut reader, &frame));
+        assert!(matches!(forwarded, Ok(None)));
+
+        // Frames stay on the bus of the writer.
+        let writer = writer.on_bus(2);
+        assert_eq!(writer.package(theirs, b"hello").unwrap().bus(), Some(2));
+        let mut reader = Reader::<{ crate::MAX_NAKED_LEN }, 2>::default();
+        let frame = FrameBuilder::new(theirs, Address::new(3))
+            .bus(2)
+            .hop_limit(1)
+            .build()
+            .unwrap();
+        let frame = frame
+            .as_slice()
+            .iter()
+            .find_map(|b| reader.feed(*b).ok().flatten().map(|_| ()))
+            .and_then(|()| reader.last_frame())
+            .unwrap();
+        let forwarded = writer.package_forward(&frame).unwrap().unwrap();
+        assert_eq!(forwarded.bus(), Some(2));
+    }
+
+    #[test]
+    fn source_bindings() {
+        let mut bindings = SourceBindings::<usize, 2>::new();
+        assert_eq!(bindings.check(Address::new(1), 0), Ok(()));
+        assert_eq!(bindings.check(Address::new(2), 1), Ok(()));
+        assert_eq!(bindings.check(Address::new(1), 0), Ok(()));
+        assert_eq!(
+            bindings.check(Address::new(1), 1),
+            Err(SourceMismatch {
+                src: Address::new(1),
+                learned: 0
+            })
+        );
+
+        // Addresses beyond the capacity are not checked.
+        assert_eq!(bindings.check(Address::new(3), 0), Ok(()));
+        assert_eq!(bindings.check(Address::new(3), 1), Ok(()));
+        assert_eq!(bindings.learned(Address::new(3)), None);
+
+        bindings.forget(Address::new(1));
+        assert_eq!(bindings.check(Address::new(1), 1), Ok(()));
+        assert_eq!(bindings.learned(Address::new(1)), Some(1));
+    }
+}
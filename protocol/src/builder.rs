@@ -0,0 +1,274 @@
+//! Packaging frames of which any combination of features is used, see `FrameBuilder`.
+
+use crate::{
+    options::{self, TlvOption, MAX_OPTIONS_LEN},
+    Address, Frame, WriteError, Writer,
+};
+
+/// How many options can be added to a frame at most, besides those of the dedicated methods.
+pub const MAX_BUILDER_OPTIONS: usize = 8;
+
+/// Builder of a frame, i.e. `FrameBuilder::new(src, dst).priority(3).ttl(500).payload(buf).build()`.
+///
+/// Fields that are not set are left out of the frame, or are `0` in the case of the bus, hop limit and sequence
+/// number.
+#[derive(Debug, Clone)]
+pub struct FrameBuilder<'a> {
+    bus: u8,
+    src: Address,
+    dst: Address,
+    hop_limit: u8,
+    sequence: u8,
+    priority: Option<u8>,
+    ttl: Option<u16>,
+    ack: Option<u8>,
+    options: heapless::Vec<TlvOption<'a>, MAX_BUILDER_OPTIONS>,
+    /// Whether more options were added than fit.
+    too_many_options: bool,
+    #[cfg(feature = "compression")]
+    compressed: bool,
+    padded_len: Option<usize>,
+    payload: &'a [u8],
+    /// Parts of which the payload consists, instead of `payload`.
+    parts: Option<&'a [&'a [u8]]>,
+}
+
+impl<'a> FrameBuilder<'a> {
+    pub fn new(src: Address, dst: Address) -> Self {
+        Self {
+            bus: 0,
+            src,
+            dst,
+            hop_limit: 0,
+            sequence: 0,
+            priority: None,
+            ttl: None,
+            ack: None,
+            options: heapless::Vec::new(),
+            too_many_options: false,
+            #[cfg(feature = "compression")]
+            compressed: false,
+            padded_len: None,
+            payload: &[],
+            parts: None,
+        }
+    }
+
+    /// The bus the frame is meant for, such that only a `Reader` of that bus accepts it, see `magic_word`.
+    pub fn bus(mut self, bus: u8) -> Self {
+        self.bus = bus;
+        self
+    }
+
+    /// How many times repeaters may forward the frame to another bus segment, at most `MAX_HOP_LIMIT`.
+    pub fn hop_limit(mut self, hop_limit: u8) -> Self {
+        self.hop_limit = hop_limit;
+        self
+    }
+
+    /// Sequence number such that receivers can drop duplicates, see `Writer::package_with_sequence`.
+    pub fn sequence(mut self, sequence: u8) -> Self {
+        self.sequence = sequence;
+        self
+    }
+
+    /// Priority of the frame, higher is more important, see `options::PRIORITY`.
+    pub fn priority(mut self, priority: u8) -> Self {
+        self.priority = Some(priority);
+        self
+    }
+
+    /// How long the frame is relevant in milliseconds, see `options::TTL`.
+    pub fn ttl(mut self, ttl: u16) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Acknowledge the frame of the destination with sequence number `sequence`, see `options::ACK`.
+    ///
+    /// Use this to piggyback an acknowledgement on a frame that is sent anyway, i.e. the answer to a request.
+    pub fn ack(mut self, sequence: u8) -> Self {
+        self.ack = Some(sequence);
+        self
+    }
+
+    /// Add an option of `kind`, of which the value is carried as is.
+    ///
+    /// Adding more than `MAX_BUILDER_OPTIONS` options fails the frame with `TooLong` once it is built.
+    pub fn option(mut self, kind: u8, value: &'a [u8]) -> Self {
+        if self.options.push(TlvOption { kind, value }).is_err() {
+            self.too_many_options = true;
+        }
+        self
+    }
+
+    pub fn options(self, options: &[TlvOption<'a>]) -> Self {
+        options.iter().fold(self, |builder, option| {
+            builder.option(option.kind, option.value)
+        })
+    }
+
+    /// Compress the payload if that makes the frame smaller, see `Writer::package_compressed`.
+    #[cfg(feature = "compression")]
+    pub fn compressed(mut self) -> Self {
+        self.compressed = true;
+        self
+    }
+
+    /// Pad the frame to take up exactly `frame_len` bytes on the bus, see `Writer::package_padded`.
+    pub fn padded(mut self, frame_len: usize) -> Self {
+        self.padded_len = Some(frame_len);
+        self
+    }
+
+    pub fn payload(mut self, payload: &'a [u8]) -> Self {
+        self.payload = payload;
+        self.parts = None;
+        self
+    }
+
+    /// Use the concatenation of `parts` as payload, without concatenating them into a temporary buffer first.
+    pub fn payload_vectored(mut self, parts: &'a [&'a [u8]]) -> Self {
+        self.parts = Some(parts);
+        self
+    }
+
+    pub fn build(&self) -> Result<Frame, WriteError> {
+        self.build_sized()
+    }
+
+    /// Build the frame into a buffer of `N` bytes, failing with `TooLong` if it does not fit.
+    pub fn build_sized<const N: usize>(&self) -> Result<Frame<N>, WriteError> {
+        if self.too_many_options {
+            return Err(WriteError::TooLong);
+        }
+
+        let single = [self.payload];
+        let parts = self.parts.unwrap_or(&single);
+
+        #[cfg(feature = "compression")]
+        if self.compressed {
+            return self.build_compressed(parts);
+        }
+        self.build_parts(parts, false)
+    }
+
+    /// Build the frame with the concatenation of `parts` compressed, if that makes it smaller.
+    ///
+    /// Kept apart from `build_sized`, such that only compressed frames take scratch space, of `N` bytes as the
+    /// payload does not fit in the frame otherwise.
+    #[cfg(feature = "compression")]
+    #[inline(never)]
+    fn build_compressed<const N: usize>(&self, parts: &[&[u8]]) -> Result<Frame<N>, WriteError> {
+        let mut payload = [0u8; N];
+        let mut len = 0;
+        for part in parts {
+            match payload.get_mut(len..len + part.len()) {
+                Some(buf) => buf.copy_from_slice(part),
+                None => return self.build_parts(parts, false),
+            }
+            len += part.len();
+        }
+
+        // The option takes up two bytes, and possibly another one for the length of the options block.
+        let mut compressed = [0u8; N];
+        match crate::compress::compress(&payload[..len], &mut compressed) {
+            Some(compressed_len) if compressed_len + 3 < len => {
+                self.build_parts(&[&compressed[..compressed_len]], true)
+            }
+            _ => self.build_parts(parts, false),
+        }
+    }
+
+    /// Build the frame with `parts` as payload, which are `compressed` or not.
+    fn build_parts<const N: usize>(
+        &self,
+        parts: &[&[u8]],
+        compressed: bool,
+    ) -> Result<Frame<N>, WriteError> {
+        let priority = self.priority.map(|priority| [priority]);
+        let ttl = self.ttl.map(u16::to_be_bytes);
+        let ack = self.ack.map(|sequence| [sequence]);
+        let dedicated = [
+            priority.as_ref().map(|value| TlvOption {
+                kind: options::PRIORITY,
+                value,
+            }),
+            ttl.as_ref().map(|value| TlvOption {
+                kind: options::TTL,
+                value,
+            }),
+            ack.as_ref().map(|value| TlvOption {
+                kind: options::ACK,
+                value,
+            }),
+            compressed.then_some(TlvOption {
+                kind: options::COMPRESSED,
+                value: &[],
+            }),
+        ];
+        let mut all = dedicated
+            .into_iter()
+            .flatten()
+            .chain(self.options.iter().copied())
+            .peekable();
+
+        match all.peek() {
+            Some(_) => self.build_with_options(all, parts),
+            None => self.package(None, parts),
+        }
+    }
+
+    /// Build the frame with `options` encoded in front of `parts`.
+    ///
+    /// Kept apart from `build_parts`, such that frames without options do not take space for an options block.
+    #[inline(never)]
+    fn build_with_options<'b, const N: usize>(
+        &self,
+        options: impl Iterator<Item = TlvOption<'b>>,
+        parts: &[&[u8]],
+    ) -> Result<Frame<N>, WriteError> {
+        let mut buf = [0u8; MAX_OPTIONS_LEN];
+        let len = options::encode(options, &mut buf)?;
+        self.package(Some(&buf[..len]), parts)
+    }
+
+    /// Package the frame with the encoded `options` and `parts`, padded if asked for.
+    fn package<const N: usize>(
+        &self,
+        options: Option<&[u8]>,
+        parts: &[&[u8]],
+    ) -> Result<Frame<N>, WriteError> {
+        let package = |padding| {
+            Writer::package_inner(
+                self.bus,
+                self.src,
+                self.dst,
+                self.hop_limit,
+                self.sequence,
+                options,
+                parts,
+                padding,
+            )
+        };
+
+        let frame_len = match self.padded_len {
+            Some(frame_len) => frame_len,
+            None => return package(0),
+        };
+        let mut padding = 0;
+        loop {
+            let frame = package(padding)?;
+            let len = frame.as_slice().len();
+            if len >= frame_len {
+                return if len == frame_len {
+                    Ok(frame)
+                } else {
+                    Err(WriteError::TooLong)
+                };
+            }
+            // Zeroes take up a byte each once encoded, but might spare a COBS code byte in long frames.
+            padding += frame_len - len;
+        }
+    }
+}
@@ -0,0 +1,196 @@
+//! Compression of payloads with LZSS, for payloads like telemetry that repeat themselves a lot.
+//!
+//! Compressed frames carry the `options::COMPRESSED` option, see `Writer::package_compressed`.
+//! Receivers get the original payload back using `FrameRef::decompress`.
+//!
+//! The compressed data consists of groups of eight items, preceded by a byte of flags. A set flag denotes a
+//! literal byte, a cleared one a reference of two bytes to data that was seen before: 12 bits of distance,
+//! followed by 4 bits of length.
+
+/// How far back references can point.
+const WINDOW_LEN: usize = 4096;
+/// How long a reference should be at least to be worth it.
+const MIN_MATCH_LEN: usize = 3;
+/// How long a reference can be at most.
+const MAX_MATCH_LEN: usize = MIN_MATCH_LEN + 0xF;
+
+/// Why a compressed payload could not be decompressed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DecompressError {
+    /// The options of the frame are broken, see `crate::options::InvalidOptions`.
+    InvalidOptions,
+    /// The compressed data is broken.
+    Corrupt,
+    /// The decompressed payload does not fit in the buffer.
+    TooLong,
+}
+
+impl core::fmt::Display for DecompressError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            DecompressError::InvalidOptions => "invalid options",
+            DecompressError::Corrupt => "corrupt compressed data",
+            DecompressError::TooLong => "decompressed payload does not fit in the buffer",
+        })
+    }
+}
+
+impl core::error::Error for DecompressError {}
+
+/// Compress `input` into `out`, yielding the compressed length, or `None` if it does not fit.
+pub fn compress(input: &[u8], out: &mut [u8]) -> Option<usize> {
+    let mut i = 0;
+    let mut o = 0;
+    let mut flags_at = 0;
+    let mut items = 0;
+
+    while i < input.len() {
+        if items % 8 == 0 {
+            flags_at = o;
+            *out.get_mut(o)? = 0;
+            o += 1;
+        }
+        items += 1;
+
+        let (distance, len) = longest_match(input, i);
+        if len >= MIN_MATCH_LEN {
+            let code = ((distance - 1) << 4) | (len - MIN_MATCH_LEN);
+            out.get_mut(o..o + 2)?
+                .copy_from_slice(&(code as u16).to_be_bytes());
+            o += 2;
+            i += len;
+        } else {
+            out[flags_at] |= 1 << ((items - 1) % 8);
+            *out.get_mut(o)? = input[i];
+            o += 1;
+            i += 1;
+        }
+    }
+    Some(o)
+}
+
+/// The distance and length of the longest earlier occurrence of the data at `at`.
+fn longest_match(input: &[u8], at: usize) -> (usize, usize) {
+    let max_len = MAX_MATCH_LEN.min(input.len() - at);
+    let mut best = (0, 0);
+    for start in at.saturating_sub(WINDOW_LEN)..at {
+        let len = (0..max_len)
+            .take_while(|n| input[start + n] == input[at + n])
+            .count();
+        if len > best.1 {
+            best = (at - start, len);
+            if len == max_len {
+                break;
+            }
+        }
+    }
+    best
+}
+
+/// Decompress `input` into `out`, yielding the decompressed length.
+pub fn decompress(input: &[u8], out: &mut [u8]) -> Result<usize, DecompressError> {
+    use DecompressError::*;
+
+    let mut i = 0;
+    let mut o = 0;
+    while let Some(&flags) = input.get(i) {
+        i += 1;
+        for bit in 0..8 {
+            if i == input.len() {
+                return Ok(o);
+            }
+
+            if flags & (1 << bit) != 0 {
+                *out.get_mut(o).ok_or(TooLong)? = input[i];
+                o += 1;
+                i += 1;
+                continue;
+            }
+
+            let code = match input.get(i..i + 2) {
+                Some(code) => u16::from_be_bytes([code[0], code[1]]) as usize,
+                None => return Err(Corrupt),
+            };
+            i += 2;
+            let distance = (code >> 4) + 1;
+            let len = (code & 0xF) + MIN_MATCH_LEN;
+            if distance > o {
+                return Err(Corrupt);
+            }
+            if o + len > out.len() {
+                return Err(TooLong);
+            }
+            // References may overlap with the data they produce, hence copy byte by byte.
+            for n in o..o + len {
+                out[n] = out[n - distance];
+            }
+            o += len;
+        }
+    }
+    Ok(o)
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate alloc;
+
+    use super::*;
+    use crate::{Address, Reader, Writer, MAX_MESSAGE_LEN};
+    use alloc::vec::Vec;
+
+    const TELEMETRY: &[u8] = br#"[{"sensor":"temperature","value":21.5,"unit":"C"},{"sensor":"temperature","value":21.7,"unit":"C"},{"sensor":"humidity","value":45.0,"unit":"%"},{"sensor":"humidity","value":45.2,"unit":"%"}]"#;
+
+    #[test]
+    fn compress_roundtrip() {
+        let mut compressed = [0u8; 2 * MAX_MESSAGE_LEN];
+        let mut decompressed = [0u8; MAX_MESSAGE_LEN];
+        let incrementing: [u8; 300] = core::array::from_fn(|i| (i * 7 % 251) as u8);
+        for input in [
+            &b""[..],
+            b"a",
+            b"aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa",
+            TELEMETRY,
+            &incrementing,
+        ] {
+            let len = compress(input, &mut compressed).unwrap();
+            let compressed = &compressed[..len];
+            assert_eq!(decompress(compressed, &mut decompressed), Ok(input.len()));
+            assert_eq!(&decompressed[..input.len()], input);
+        }
+
+        let len = compress(TELEMETRY, &mut compressed).unwrap();
+        assert!(len * 2 < TELEMETRY.len());
+        assert_eq!(compress(TELEMETRY, &mut compressed[..len - 1]), None);
+        assert_eq!(
+            decompress(&compressed[..len], &mut decompressed[..10]),
+            Err(DecompressError::TooLong)
+        );
+        // A reference to before the start of the data.
+        assert_eq!(
+            decompress(&[0, 0x10, 0], &mut decompressed),
+            Err(DecompressError::Corrupt)
+        );
+    }
+
+    #[test]
+    fn package_compressed() {
+        let mut reader = Reader::new();
+        let mut buf = [0u8; MAX_MESSAGE_LEN];
+        for (payload, compressed) in [(TELEMETRY, true), (&b"short"[..], false)] {
+            let frame =
+                Writer::package_compressed(Address::new(1), Address::new(2), payload).unwrap();
+            let received = frame
+                .as_slice()
+                .iter()
+                .find_map(|b| match reader.feed(*b) {
+                    Ok(Some(frame)) => {
+                        assert_eq!(frame.header.has_options, compressed);
+                        Some(frame.decompress(&mut buf).map(<[u8]>::to_vec))
+                    }
+                    _ => None,
+                })
+                .unwrap();
+            assert_eq!(received, Ok(Vec::from(payload)));
+        }
+    }
+}
@@ -0,0 +1,442 @@
+//! Wireshark dissector for captures of the bus, generated from the definitions of the protocol.
+//!
+//! The offsets and masks of the header fields are found by packing headers with a single field set, and the
+//! magic words, option kinds and checksum parameters are taken from their constants, such that the dissector
+//! can not drift from the implementation. `contrib/wireshark/kiri.lua` holds the output of `LuaDissector`.
+
+use core::fmt::{self, Display};
+
+use packed_struct::{prelude::*, types::Integer};
+
+use crate::{
+    magic_word, options, Address, Header, CHECKSUM, CHECKSUM_LEN, HEADER_LEN, LEN_EXTENSION_LEN,
+    MAGIC_LEN, MIN_NAKED_LEN,
+};
+
+/// The kinds of options, by the name they are shown with.
+const OPTIONS: [(u8, &str); 7] = [
+    (options::PRIORITY, "Priority"),
+    (options::TTL, "TTL"),
+    (options::FRAGMENT, "Fragment"),
+    (options::AUTH_TAG, "Authentication tag"),
+    (options::COMPRESSED, "Compressed"),
+    (options::MANAGEMENT, "Management"),
+    (options::ACK, "Acknowledgement"),
+];
+
+/// Where a field is in the decoded frame: the offset and width in bytes of the integer it is in, and its mask.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Field {
+    offset: usize,
+    width: usize,
+    mask: u32,
+}
+
+impl Field {
+    /// The bits that are set in `header` when packed.
+    fn of(header: Header) -> Self {
+        let packed = header.pack().unwrap_or_default();
+        let first = packed.iter().position(|b| *b != 0).unwrap_or(0);
+        let last = packed.iter().rposition(|b| *b != 0).unwrap_or(0);
+        let width = match last - first + 1 {
+            3 => 4,
+            width => width,
+        };
+        let mask = packed[first..first + width]
+            .iter()
+            .fold(0, |mask, b| mask << 8 | *b as u32);
+        Self {
+            offset: MAGIC_LEN + first,
+            width,
+            mask,
+        }
+    }
+
+    fn shift(&self) -> u32 {
+        self.mask.trailing_zeros()
+    }
+
+    fn is_whole(&self) -> bool {
+        self.mask == u32::MAX >> (32 - 8 * self.width)
+    }
+
+    /// A `ProtoField` named `name`, of which the value is shown in `base`.
+    fn proto_field<'a>(&'a self, abbrev: &'a str, name: &'a str, base: &'a str) -> ProtoField<'a> {
+        ProtoField {
+            field: self,
+            abbrev,
+            name,
+            base,
+        }
+    }
+}
+
+/// Declaration of a header field in Lua.
+struct ProtoField<'a> {
+    field: &'a Field,
+    abbrev: &'a str,
+    name: &'a str,
+    base: &'a str,
+}
+
+impl Display for ProtoField<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Field { width, mask, .. } = self.field;
+        write!(
+            f,
+            "ProtoField.uint{}(\"kiri.{}\", \"{}\", base.{}",
+            8 * width,
+            self.abbrev,
+            self.name,
+            self.base
+        )?;
+        if !self.field.is_whole() {
+            write!(f, ", nil, 0x{:0w$X}", mask, w = 2 * width)?;
+        }
+        f.write_str(")")
+    }
+}
+
+/// An expression of the value of `field` in the decoded frame `tvb`.
+struct Value<'a>(&'a Field);
+
+impl Display for Value<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Field {
+            offset,
+            width,
+            mask,
+        } = self.0;
+        match (self.0.is_whole(), self.0.shift()) {
+            (true, _) => write!(f, "tvb({}, {}):uint()", offset, width),
+            (false, 0) => write!(
+                f,
+                "bit.band(tvb({}, {}):uint(), 0x{:X})",
+                offset, width, mask
+            ),
+            (false, shift) => write!(
+                f,
+                "bit.rshift(bit.band(tvb({}, {}):uint(), 0x{:X}), {})",
+                offset, width, mask, shift
+            ),
+        }
+    }
+}
+
+/// Header with only the field set by `set` having all of its bits set.
+fn header_with(set: impl FnOnce(&mut Header)) -> Header {
+    let mut header = Header {
+        address_src: Address::new(0),
+        address_dst: Address::new(0),
+        len: Integer::from_primitive(0),
+        hop_limit: Integer::from_primitive(0),
+        sequence: Integer::from_primitive(0),
+        has_options: false,
+    };
+    set(&mut header);
+    header
+}
+
+/// The Lua source of a Wireshark dissector of the captures of `kiri_simulation::pcap`.
+///
+/// Link-type `USER0` carries single bus bytes followed by a flags byte, `USER1` complete COBS encoded frames.
+#[derive(Debug, Clone, Copy)]
+pub struct LuaDissector;
+
+impl Display for LuaDissector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let src = Field::of(header_with(|h| h.address_src = Address::new(u32::MAX)));
+        let dst = Field::of(header_with(|h| h.address_dst = Address::new(u32::MAX)));
+        let len = Field::of(header_with(|h| h.len = Integer::from_primitive(u16::MAX)));
+        let hop_limit = Field::of(header_with(|h| {
+            h.hop_limit = Integer::from_primitive(u8::MAX)
+        }));
+        let sequence = Field::of(header_with(|h| {
+            h.sequence = Integer::from_primitive(u8::MAX)
+        }));
+        let has_options = Field::of(header_with(|h| h.has_options = true));
+        let algorithm = CHECKSUM.algorithm;
+        // The checksum below keeps a 16-bit register that is either reflected for both input and output, or neither.
+        if algorithm.refin != algorithm.refout || algorithm.width != 16 {
+            return Err(fmt::Error);
+        }
+
+        writeln!(
+            f,
+            "-- Wireshark dissector for kiri bus captures, as written by the simulator bus tap.
+--
+-- Interface 0 (LINKTYPE_USER0) carries single bus bytes followed by a flags byte.
+-- Interface 1 (LINKTYPE_USER1) carries complete COBS encoded frames, including the trailing marker.
+--
+-- Generated from the definitions in `kiri-protocol` by `kiri gen-dissector`, do not edit.
+-- Install by copying into your Wireshark personal plugins directory.
+
+local kiri_byte = Proto(\"kiri_byte\", \"Kiri bus byte\")
+local kiri = Proto(\"kiri\", \"Kiri frame\")
+
+local f_byte = ProtoField.uint8(\"kiri_byte.value\", \"Value\", base.HEX)
+local f_error = ProtoField.bool(\"kiri_byte.error\", \"Garbled\", 8, nil, 0x01)
+kiri_byte.fields = {{ f_byte, f_error }}
+
+local f_magic = ProtoField.bytes(\"kiri.magic\", \"Magic\")
+local f_bus = ProtoField.uint8(\"kiri.bus\", \"Bus\", base.DEC)
+local f_src = {}
+local f_dst = {}
+local f_len = {}
+local f_hop_limit = {}
+local f_sequence = {}
+local f_has_options = ProtoField.bool(\"kiri.has_options\", \"Has options\", {}, nil, 0x{:02X})
+local f_len_high = ProtoField.uint8(\"kiri.len_high\", \"Length, high bits\", base.DEC)
+local f_options = ProtoField.bytes(\"kiri.options\", \"Options\")
+local f_option = ProtoField.uint8(\"kiri.option\", \"Option\", base.DEC, {{",
+            src.proto_field("src", "Source", "HEX"),
+            dst.proto_field("dst", "Destination", "HEX"),
+            len.proto_field("len", "Length", "DEC"),
+            hop_limit.proto_field("hop_limit", "Hop limit", "DEC"),
+            sequence.proto_field("sequence", "Sequence", "DEC"),
+            8 * has_options.width,
+            has_options.mask,
+        )?;
+        for (kind, name) in OPTIONS {
+            writeln!(f, "    [{}] = \"{}\",", kind, name)?;
+        }
+        writeln!(
+            f,
+            "}})
+local f_option_value = ProtoField.bytes(\"kiri.option.value\", \"Value\")
+local f_payload = ProtoField.bytes(\"kiri.payload\", \"Payload\")
+local f_padding = ProtoField.bytes(\"kiri.padding\", \"Padding\")
+local f_crc = ProtoField.uint{crc_bits}(\"kiri.crc\", \"CRC\", base.HEX)
+kiri.fields = {{
+    f_magic, f_bus, f_src, f_dst, f_len, f_hop_limit, f_sequence, f_has_options, f_len_high,
+    f_options, f_option, f_option_value, f_payload, f_padding, f_crc,
+}}
+
+function kiri_byte.dissector(buf, pinfo, tree)
+    pinfo.cols.protocol = \"KIRI\"
+    local subtree = tree:add(kiri_byte, buf())
+    subtree:add(f_byte, buf(0, 1))
+    subtree:add(f_error, buf(1, 1))
+end
+
+-- Undo the COBS encoding, dropping the trailing marker.
+local function cobs_decode(bytes)
+    local out = ByteArray.new()
+    local i = 0
+    local len = bytes:len()
+    while i < len do
+        local code = bytes:get_index(i)
+        if code == 0 then
+            break
+        end
+        i = i + 1
+        for _ = 1, code - 1 do
+            if i >= len then
+                return nil
+            end
+            out:append(bytes:subset(i, 1))
+            i = i + 1
+        end
+        if code < 0xFF and i < len and bytes:get_index(i) ~= 0 then
+            out:append(ByteArray.new(\"00\"))
+        end
+    end
+    return out
+end",
+            crc_bits = algorithm.width,
+        )?;
+
+        // Reflected algorithms shift the register right, with the polynomial reversed.
+        let (shift_in, test_bit, shift, poly) = match algorithm.refin {
+            true => (
+                "crc = bit.bxor(crc, byte)",
+                "bit.band(crc, 1) ~= 0",
+                "bit.rshift(crc, 1)",
+                algorithm.poly.reverse_bits(),
+            ),
+            false => (
+                "crc = bit.bxor(crc, bit.lshift(byte, 8))",
+                "bit.band(crc, 0x8000) ~= 0",
+                "bit.band(bit.lshift(crc, 1), 0xFFFF)",
+                algorithm.poly,
+            ),
+        };
+        writeln!(
+            f,
+            "
+-- Checksum with polynomial 0x{poly_normal:04X}, over everything from the magic word up to the checksum.
+local function checksum(tvb, len)
+    local crc = 0x{init:04X}
+    for i = 0, len - 1 do
+        local byte = tvb(i, 1):uint()
+        {shift_in}
+        for _ = 1, 8 do
+            if {test_bit} then
+                crc = bit.bxor({shift}, 0x{poly:04X})
+            else
+                crc = {shift}
+            end
+        end
+    end
+    return bit.bxor(crc, 0x{xorout:04X})
+end",
+            poly_normal = algorithm.poly,
+            init = algorithm.init,
+            xorout = algorithm.xorout,
+        )?;
+
+        writeln!(
+            f,
+            "
+local function dissect_options(tvb, tree)
+    local block_len = tvb(0, 1):uint()
+    local options = tree:add(f_options, tvb(0, block_len + 1))
+    local i = 1
+    while i + 1 < block_len + 1 do
+        local option_len = tvb(i + 1, 1):uint()
+        local option = options:add(f_option, tvb(i, 1))
+        if option_len > 0 then
+            option:add(f_option_value, tvb(i + 2, option_len))
+        end
+        i = i + 2 + option_len
+    end
+    return block_len + 1
+end
+
+function kiri.dissector(buf, pinfo, tree)
+    pinfo.cols.protocol = \"KIRI\"
+
+    local decoded = cobs_decode(buf:bytes())
+    if decoded == nil or decoded:len() < {min_len} then
+        tree:add(kiri, buf(), \"Malformed kiri frame\")
+        return
+    end
+
+    local tvb = decoded:tvb(\"Decoded frame\")
+    local subtree = tree:add(kiri, tvb())
+    subtree:add(f_magic, tvb(0, {magic_len}))
+    local kind = tvb(1, 1):uint()
+    if kind ~= 0x{magic_kind:02X} and kind ~= 0x{magic_kind_extended:02X} then
+        subtree:add_expert_info(PI_MALFORMED, PI_ERROR, \"Invalid magic word\")
+        return
+    end
+    -- The bus is XOR-ed into the first byte of the magic word.
+    subtree:add(f_bus, tvb(0, 1), bit.bxor(tvb(0, 1):uint(), 0x{magic_bus:02X}))
+
+    subtree:add(f_src, tvb({src_offset}, {src_width}))
+    subtree:add(f_dst, tvb({dst_offset}, {dst_width}))
+    subtree:add(f_len, tvb({len_offset}, {len_width}))
+    subtree:add(f_hop_limit, tvb({hop_limit_offset}, {hop_limit_width}))
+    subtree:add(f_sequence, tvb({sequence_offset}, {sequence_width}))
+    subtree:add(f_has_options, tvb({has_options_offset}, {has_options_width}))
+
+    local len = {len_value}
+    local start = {header_end}
+    if kind == 0x{magic_kind_extended:02X} then
+        subtree:add(f_len_high, tvb(start, {len_extension_len}))
+        len = len + bit.lshift(tvb(start, {len_extension_len}):uint(), {len_bits})
+        start = start + {len_extension_len}
+    end
+
+    local crc_start = tvb:len() - {crc_len}
+    if start + len > crc_start then
+        subtree:add_expert_info(PI_MALFORMED, PI_ERROR, \"Length exceeds the frame\")
+        return
+    end
+    local payload_start = start
+    if {has_options_value} ~= 0 then
+        payload_start = start + dissect_options(tvb(start, len), subtree)
+    end
+    if payload_start < start + len then
+        subtree:add(f_payload, tvb(payload_start, start + len - payload_start))
+    end
+    if start + len < crc_start then
+        subtree:add(f_padding, tvb(start + len, crc_start - start - len))
+    end
+
+    local crc = subtree:add(f_crc, tvb(crc_start, {crc_len}))
+    local expected = checksum(tvb, crc_start)
+    if expected == tvb(crc_start, {crc_len}):uint() then
+        crc:append_text(\" [correct]\")
+    else
+        crc:add_expert_info(PI_CHECKSUM, PI_ERROR, string.format(\"Incorrect, should be 0x%04x\", expected))
+    end
+
+    pinfo.cols.info = string.format(\"%08x -> %08x, %d bytes, seq=%d, hops=%d\", {src_value}, {dst_value}, len, {sequence_value}, {hop_limit_value})
+end
+
+local encaps = wtap_encaps or wtap
+local wtap_table = DissectorTable.get(\"wtap_encap\")
+wtap_table:add(encaps.USER0, kiri_byte)
+wtap_table:add(encaps.USER1, kiri)",
+            min_len = MIN_NAKED_LEN,
+            magic_len = MAGIC_LEN,
+            magic_bus = magic_word(0, false)[0],
+            magic_kind = magic_word(0, false)[1],
+            magic_kind_extended = magic_word(0, true)[1],
+            src_offset = src.offset,
+            src_width = src.width,
+            dst_offset = dst.offset,
+            dst_width = dst.width,
+            len_offset = len.offset,
+            len_width = len.width,
+            hop_limit_offset = hop_limit.offset,
+            hop_limit_width = hop_limit.width,
+            sequence_offset = sequence.offset,
+            sequence_width = sequence.width,
+            has_options_offset = has_options.offset,
+            has_options_width = has_options.width,
+            len_value = Value(&len),
+            header_end = MAGIC_LEN + HEADER_LEN,
+            len_extension_len = LEN_EXTENSION_LEN,
+            len_bits = len.mask.count_ones(),
+            crc_len = CHECKSUM_LEN,
+            has_options_value = Value(&has_options),
+            src_value = Value(&src),
+            dst_value = Value(&dst),
+            sequence_value = Value(&sequence),
+            hop_limit_value = Value(&hop_limit),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate alloc;
+
+    use alloc::string::ToString;
+
+    use super::*;
+
+    #[test]
+    fn header_fields() {
+        let len = Field::of(header_with(|h| h.len = Integer::from_primitive(u16::MAX)));
+        assert_eq!(
+            len,
+            Field {
+                offset: 10,
+                width: 2,
+                mask: 0xFFC0
+            }
+        );
+        assert_eq!(
+            Value(&len).to_string(),
+            "bit.rshift(bit.band(tvb(10, 2):uint(), 0xFFC0), 6)"
+        );
+
+        let has_options = Field::of(header_with(|h| h.has_options = true));
+        assert_eq!((has_options.offset, has_options.mask), (11, 0x01));
+        let src = Field::of(header_with(|h| h.address_src = Address::new(u32::MAX)));
+        assert_eq!(Value(&src).to_string(), "tvb(2, 4):uint()");
+    }
+
+    /// Regenerate the dissector with `cargo run --bin kiri -- gen-dissector contrib/wireshark/kiri.lua` if this fails.
+    #[test]
+    fn dissector_is_up_to_date() {
+        let generated = LuaDissector.to_string();
+        assert_eq!(
+            generated.as_str(),
+            include_str!("../../contrib/wireshark/kiri.lua")
+        );
+    }
+}
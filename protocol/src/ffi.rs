@@ -0,0 +1,194 @@
+//! C bindings to the `Reader` and `Writer`, such that firmware written in C can use the same encoder and decoder.
+//!
+//! The header `include/kiri_protocol.h` is generated from this module with `cbindgen --config cbindgen.toml`.
+//! Link the bindings from a `staticlib` crate of your firmware, which also provides the panic handler.
+//!
+//! Nothing is allocated: the caller provides the memory of the reader, see `kiri_reader_new`.
+
+use core::mem::{align_of, size_of};
+
+use crate::{Address, FrameError, Reader, WriteError, Writer};
+
+/// Bytes to reserve for a reader, see `kiri_reader_new`.
+pub const KIRI_READER_SIZE: usize = size_of::<Reader>();
+
+/// Alignment of the memory of a reader, see `kiri_reader_new`.
+pub const KIRI_READER_ALIGN: usize = align_of::<Reader>();
+
+/// Reader of frames, of which the contents are hidden from C.
+pub struct KiriReader(Reader);
+
+/// Outcome of feeding a byte to a reader, see `FrameError`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KiriReadResult {
+    NotYet = 0,
+    FrameOk = 1,
+    Overflow = -1,
+    FrameErrorCobs = -2,
+    FrameErrorMagic = -3,
+    FrameErrorHeader = -4,
+    FrameErrorSize = -5,
+    FrameErrorChecksum = -6,
+}
+
+/// A received frame, of which the contents are valid until the reader is fed again.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct KiriFrame {
+    pub src: u32,
+    pub dst: u32,
+    pub hop_limit: u8,
+    pub sequence: u8,
+    pub contents: *const u8,
+    pub contents_len: usize,
+}
+
+/// The contents do not fit in a frame, or the frame does not fit in the buffer.
+pub const KIRI_WRITE_ERROR_TOO_LONG: isize = -1;
+/// The header could not be encoded.
+pub const KIRI_WRITE_ERROR_HEADER: isize = -2;
+
+/// Bytes to reserve for a reader, which depends on the target and is hence not known to the header.
+#[no_mangle]
+pub extern "C" fn kiri_reader_size() -> usize {
+    KIRI_READER_SIZE
+}
+
+/// Alignment of the memory of a reader.
+#[no_mangle]
+pub extern "C" fn kiri_reader_align() -> usize {
+    KIRI_READER_ALIGN
+}
+
+/// Create a reader in `mem`, which must be valid for `len` bytes and aligned to `kiri_reader_align()`.
+///
+/// Yields null if the memory is too small or misaligned.
+///
+/// # Safety
+///
+/// `mem` must be valid for writes of `len` bytes, and not be used otherwise for as long as the reader is in use.
+#[no_mangle]
+pub unsafe extern "C" fn kiri_reader_new(mem: *mut u8, len: usize) -> *mut KiriReader {
+    if mem.is_null() || len < KIRI_READER_SIZE || !(mem as usize).is_multiple_of(KIRI_READER_ALIGN)
+    {
+        return core::ptr::null_mut();
+    }
+
+    let reader = mem as *mut KiriReader;
+    reader.write(KiriReader(Reader::new()));
+    reader
+}
+
+/// Feed a byte to `reader`, filling `frame` if this results in a correct frame.
+///
+/// # Safety
+///
+/// `reader` must come from `kiri_reader_new`, and `frame` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn kiri_reader_feed(
+    reader: *mut KiriReader,
+    byte: u8,
+    frame: *mut KiriFrame,
+) -> KiriReadResult {
+    match (*reader).0.feed(byte) {
+        Ok(None) => KiriReadResult::NotYet,
+        Err(FrameError::Overflow) => KiriReadResult::Overflow,
+        Err(FrameError::Cobs) => KiriReadResult::FrameErrorCobs,
+        Err(FrameError::Magic) => KiriReadResult::FrameErrorMagic,
+        Err(FrameError::Header) => KiriReadResult::FrameErrorHeader,
+        Err(FrameError::Size) => KiriReadResult::FrameErrorSize,
+        Err(FrameError::Checksum) => KiriReadResult::FrameErrorChecksum,
+        Ok(Some(received)) => {
+            frame.write(KiriFrame {
+                src: received.header.address_src.to_primitive(),
+                dst: received.header.address_dst.to_primitive(),
+                hop_limit: received.hop_limit(),
+                sequence: received.sequence(),
+                contents: received.contents.as_ptr(),
+                contents_len: received.contents.len(),
+            });
+            KiriReadResult::FrameOk
+        }
+    }
+}
+
+/// Package `contents` into a frame in `out`, yielding its length, or a negative `KIRI_WRITE_ERROR_*`.
+///
+/// # Safety
+///
+/// `contents` must be valid for reads of `contents_len` bytes, and `out` for writes of `out_len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn kiri_writer_package(
+    src: u32,
+    dst: u32,
+    contents: *const u8,
+    contents_len: usize,
+    out: *mut u8,
+    out_len: usize,
+) -> isize {
+    let contents = match contents_len {
+        0 => &[],
+        _ => core::slice::from_raw_parts(contents, contents_len),
+    };
+    let frame = match Writer::package(Address::new(src), Address::new(dst), contents) {
+        Ok(frame) => frame,
+        Err(WriteError::FrameErrorHeader) => return KIRI_WRITE_ERROR_HEADER,
+        Err(WriteError::TooLong | WriteError::InvalidFrame) => return KIRI_WRITE_ERROR_TOO_LONG,
+    };
+
+    let frame = frame.as_slice();
+    if frame.len() > out_len {
+        return KIRI_WRITE_ERROR_TOO_LONG;
+    }
+    core::ptr::copy_nonoverlapping(frame.as_ptr(), out, frame.len());
+    frame.len() as isize
+}
+
+#[cfg(test)]
+mod tests {
+    use core::mem::MaybeUninit;
+
+    use super::*;
+
+    #[test]
+    fn ffi_roundtrip() {
+        let contents = b"from C";
+        let mut buf = [0u8; 64];
+        let len = unsafe {
+            kiri_writer_package(
+                1,
+                2,
+                contents.as_ptr(),
+                contents.len(),
+                buf.as_mut_ptr(),
+                buf.len(),
+            )
+        };
+        assert!(len > 0);
+        let too_small = unsafe {
+            kiri_writer_package(1, 2, contents.as_ptr(), contents.len(), buf.as_mut_ptr(), 8)
+        };
+        assert_eq!(too_small, KIRI_WRITE_ERROR_TOO_LONG);
+
+        let mut mem = MaybeUninit::<Reader>::uninit();
+        let mem = mem.as_mut_ptr() as *mut u8;
+        assert!(unsafe { kiri_reader_new(mem, kiri_reader_size() - 1) }.is_null());
+        let reader = unsafe { kiri_reader_new(mem, kiri_reader_size()) };
+        assert!(!reader.is_null());
+
+        let mut frame = MaybeUninit::<KiriFrame>::uninit();
+        let (last, rest) = buf[..len as usize].split_last().unwrap();
+        for b in rest {
+            let result = unsafe { kiri_reader_feed(reader, *b, frame.as_mut_ptr()) };
+            assert_eq!(result, KiriReadResult::NotYet);
+        }
+        let result = unsafe { kiri_reader_feed(reader, *last, frame.as_mut_ptr()) };
+        assert_eq!(result, KiriReadResult::FrameOk);
+
+        let frame = unsafe { frame.assume_init() };
+        assert_eq!((frame.src, frame.dst), (1, 2));
+        let received = unsafe { core::slice::from_raw_parts(frame.contents, frame.contents_len) };
+        assert_eq!(received, contents);
+    }
+}
@@ -0,0 +1,141 @@
+//! Decoding frames from a stream of bytes, such as a capture, a file or a socket.
+
+pub use crate::FrameError;
+use crate::{FrameOwned, Reader, MAX_NAKED_LEN};
+
+/// Feed `byte` to `reader`, yielding the frame or error it completes.
+fn feed<const N: usize>(
+    reader: &mut Reader<N>,
+    byte: u8,
+) -> Option<Result<FrameOwned, FrameError>> {
+    match reader.feed(byte) {
+        Ok(Some(frame)) => Some(frame.try_into().map_err(|_| FrameError::Size)),
+        Ok(None) => None,
+        Err(e) => Some(Err(e)),
+    }
+}
+
+/// Iterator over the frames in `bytes`, yielding every frame or why it could not be decoded.
+///
+/// Bytes of an incomplete frame at the end are ignored.
+///
+/// ```
+/// # use kiri_protocol::{iter::FrameIter, Address, Writer};
+/// let frame = Writer::package(Address::new(1), Address::new(2), b"hello").unwrap();
+/// let frames: Vec<_> = FrameIter::new(frame.as_slice().iter().copied()).collect();
+/// assert_eq!(frames[0].as_ref().unwrap().contents, b"hello");
+/// ```
+pub struct FrameIter<I, const N: usize = MAX_NAKED_LEN> {
+    bytes: I,
+    reader: Reader<N>,
+}
+
+impl<I: Iterator<Item = u8>> FrameIter<I> {
+    /// Decode frames up to the largest possible size.
+    ///
+    /// Use `FrameIter::with_reader` for other buffer sizes.
+    pub fn new(bytes: I) -> Self {
+        Self::with_reader(bytes, Reader::new())
+    }
+}
+
+impl<I: Iterator<Item = u8>, const N: usize> FrameIter<I, N> {
+    pub fn with_reader(bytes: I, reader: Reader<N>) -> Self {
+        Self { bytes, reader }
+    }
+}
+
+impl<I: Iterator<Item = u8>, const N: usize> Iterator for FrameIter<I, N> {
+    type Item = Result<FrameOwned, FrameError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for byte in self.bytes.by_ref() {
+            if let Some(result) = feed(&mut self.reader, byte) {
+                return Some(result);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(feature = "std")]
+pub use read::ReadFramer;
+
+#[cfg(feature = "std")]
+mod read {
+    extern crate std;
+
+    use std::io;
+
+    use super::{feed, FrameError};
+    use crate::{FrameOwned, Reader, MAX_NAKED_LEN};
+
+    /// Iterator over the frames read from `R`, yielding every frame or why it could not be decoded.
+    ///
+    /// Iteration ends at the end of the input, or when reading fails. In the latter case the error is
+    /// available from `ReadFramer::take_error`, after which iteration can continue.
+    pub struct ReadFramer<R, const N: usize = MAX_NAKED_LEN> {
+        input: R,
+        reader: Reader<N>,
+        buf: [u8; 256],
+        pos: usize,
+        len: usize,
+        error: Option<io::Error>,
+    }
+
+    impl<R: io::Read> ReadFramer<R> {
+        /// Decode frames up to the largest possible size.
+        ///
+        /// Use `ReadFramer::with_reader` for other buffer sizes.
+        pub fn new(input: R) -> Self {
+            Self::with_reader(input, Reader::new())
+        }
+    }
+
+    impl<R: io::Read, const N: usize> ReadFramer<R, N> {
+        pub fn with_reader(input: R, reader: Reader<N>) -> Self {
+            Self {
+                input,
+                reader,
+                buf: [0u8; 256],
+                pos: 0,
+                len: 0,
+                error: None,
+            }
+        }
+
+        /// The error that ended the iteration, if any.
+        pub fn take_error(&mut self) -> Option<io::Error> {
+            self.error.take()
+        }
+
+        pub fn into_inner(self) -> R {
+            self.input
+        }
+    }
+
+    impl<R: io::Read, const N: usize> Iterator for ReadFramer<R, N> {
+        type Item = Result<FrameOwned, FrameError>;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            while self.error.is_none() {
+                if self.pos == self.len {
+                    match self.input.read(&mut self.buf) {
+                        Ok(0) => return None,
+                        Ok(len) => (self.pos, self.len) = (0, len),
+                        Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                        Err(e) => self.error = Some(e),
+                    }
+                    continue;
+                }
+
+                let byte = self.buf[self.pos];
+                self.pos += 1;
+                if let Some(result) = feed(&mut self.reader, byte) {
+                    return Some(result);
+                }
+            }
+            None
+        }
+    }
+}
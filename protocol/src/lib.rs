@@ -3,7 +3,22 @@
 use core::fmt::Debug;
 use packed_struct::{prelude::*, types::Integer};
 
-use crc::{Crc, CRC_16_IBM_SDLC};
+use crc::{Crc, Digest, CRC_16_IBM_SDLC};
+use options::{InvalidOptions, Options, TlvOption};
+
+pub mod bound;
+pub mod builder;
+#[cfg(feature = "compression")]
+pub mod compress;
+pub mod dissector;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod iter;
+pub mod options;
+pub mod testvectors;
+
+pub use bound::BoundWriter;
+pub use builder::FrameBuilder;
 
 pub const CHECKSUM: Crc<u16> = Crc::<u16>::new(&CRC_16_IBM_SDLC);
 
@@ -11,29 +26,113 @@ const COBS_MARKER: u8 = 0;
 const CHECKSUM_LEN: usize = 2;
 
 const MAGIC_LEN: usize = 2;
+/// Magic word of regular frames on bus `0`, see `magic_word`.
 const MAGIC_WORD: &[u8; 2] = b"kI";
+/// Magic word of extended frames, of which the header is followed by the high bits of the length.
+const MAGIC_WORD_EXTENDED: &[u8; 2] = b"kL";
+const LEN_EXTENSION_LEN: usize = 1;
 
 /// How much bytes the header uses up.
 pub const HEADER_LEN: usize = 10;
 
+/// The largest hop limit that fits in the header.
+pub const MAX_HOP_LIMIT: u8 = 7;
+
+/// The largest sequence number that fits in the header.
+pub const MAX_SEQUENCE: u8 = 3;
+
 /// How long a message in the frame can be at most, chosen such that `MAX_FRAME_LEN` is at most `1024`.
 pub const MAX_MESSAGE_LEN: usize = 1000;
 
 /// How much bytes the contents of a frame, without COBS encoding, is taking up at most.
-pub const MAX_NAKED_LEN: usize = MAGIC_LEN + HEADER_LEN + MAX_MESSAGE_LEN + CHECKSUM_LEN;
+pub const MAX_NAKED_LEN: usize = max_naked_len(MAX_MESSAGE_LEN);
 
 /// How much bytes the contents of a frame, without COBS encoding, is taking up at least.
 pub const MIN_NAKED_LEN: usize = MAGIC_LEN + HEADER_LEN + CHECKSUM_LEN;
 
 /// How large a frame can be, theoretically.
-pub const MAX_FRAME_LEN: usize = cobs_max_encoding_length(MAX_NAKED_LEN) + 1;
+pub const MAX_FRAME_LEN: usize = max_frame_len(MAX_MESSAGE_LEN);
+
+/// How long a message in an extended frame can be at most, i.e. for firmware updates on fast links.
+///
+/// Messages longer than `MAX_MESSAGE_LEN` are sent in extended frames, which nodes that do not expect them drop.
+pub const MAX_EXTENDED_MESSAGE_LEN: usize = 4096;
+
+/// How large an extended frame can be, to size a `Frame` that fits all of them.
+pub const MAX_EXTENDED_FRAME_LEN: usize = encoded_frame_len(MAX_EXTENDED_MESSAGE_LEN);
+
+/// How large the contents of an extended frame can be without COBS encoding, to size a `Reader` that fits all of them.
+pub const MAX_EXTENDED_NAKED_LEN: usize = max_naked_len(MAX_EXTENDED_MESSAGE_LEN);
+
+/// How large a frame can be when messages are at most `max_message_len` long.
+///
+/// Use this to size a `Frame` for applications that only send small messages.
+pub const fn max_frame_len(max_message_len: usize) -> usize {
+    encoded_frame_len(max_message_len)
+}
+
+/// How large the contents of a frame can be without COBS encoding, when messages are at most `max_message_len` long.
+///
+/// Use this to size a `Reader` for applications that only receive small messages.
+pub const fn max_naked_len(max_message_len: usize) -> usize {
+    let extension_len = if max_message_len > MAX_MESSAGE_LEN {
+        LEN_EXTENSION_LEN
+    } else {
+        0
+    };
+    MAGIC_LEN + HEADER_LEN + extension_len + max_message_len + CHECKSUM_LEN
+}
+
+/// How much bytes a frame with a payload of `payload_len` bytes takes up on the bus at most, including the COBS marker.
+///
+/// Use this to size DMA buffers and queues at compile time.
+pub const fn encoded_frame_len(payload_len: usize) -> usize {
+    let extension_len = if payload_len > MAX_MESSAGE_LEN {
+        LEN_EXTENSION_LEN
+    } else {
+        0
+    };
+    cobs_max_encoding_length(MAGIC_LEN + HEADER_LEN + extension_len + payload_len + CHECKSUM_LEN)
+        + 1
+}
+
+/// Assert at compile time that a payload of `len` bytes fits in a frame, optionally of at most `frame_len` bytes.
+///
+/// ```
+/// kiri_protocol::static_assert_fits!(core::mem::size_of::<[u32; 16]>());
+/// kiri_protocol::static_assert_fits!(64, 128);
+/// ```
+///
+/// ```compile_fail
+/// kiri_protocol::static_assert_fits!(kiri_protocol::MAX_MESSAGE_LEN + 1);
+/// ```
+#[macro_export]
+macro_rules! static_assert_fits {
+    ($len:expr) => {
+        const _: () = assert!(
+            $len <= $crate::MAX_MESSAGE_LEN,
+            "payload does not fit in a frame"
+        );
+    };
+    ($len:expr, $frame_len:expr) => {
+        $crate::static_assert_fits!($len);
+        const _: () = assert!(
+            $crate::encoded_frame_len($len) <= $frame_len,
+            "frame does not fit in the buffer"
+        );
+    };
+}
 
 /// How much bytes cobs will use at most given a specific source length.
 const fn cobs_max_encoding_length(source_len: usize) -> usize {
-    source_len + (source_len / 254) + if source_len % 254 > 0 { 1 } else { 0 }
+    source_len + (source_len / 254) + if source_len.is_multiple_of(254) { 0 } else { 1 }
 }
 
-#[derive(PackedStruct, PartialEq, Clone, Copy)]
+/// The address of a node, or of a group of nodes, which is 32 bits wide.
+///
+/// Every frame carries the full width of both its addresses, such that deployments that span many buses can give
+/// every node a unique address, see `AddressClass` for the ranges. Only the length in the `Header` is 10 bits wide.
+#[derive(PackedStruct, PartialEq, Eq, Clone, Copy)]
 #[packed_struct(bit_numbering = "msb0", endian = "msb")]
 pub struct Address {
     #[packed_field(bits = "0..32")]
@@ -43,7 +142,64 @@ pub struct Address {
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub struct AddressTooLargeError;
 
-const ADDRESS_MULTICAST: u32 = 0xFFFFFFFF;
+impl core::fmt::Display for AddressTooLargeError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("address does not fit in 32 bits")
+    }
+}
+
+impl core::error::Error for AddressTooLargeError {}
+
+/// A string is not an address of 8 hexadecimal digits, see `Address::from_hex_str`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct ParseAddressError;
+
+impl core::fmt::Display for ParseAddressError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("address is not 8 hexadecimal digits")
+    }
+}
+
+impl core::error::Error for ParseAddressError {}
+
+/// What an address refers to, depending on the range it is in.
+///
+/// | Range                       | Class        |
+/// |-----------------------------|--------------|
+/// | `00000000` to `EFFFFFFF`    | `Unicast`    |
+/// | `F0000000` to `FEFFFFFF`    | `Dynamic`    |
+/// | `FF000000` to `FFFEFFFF`    | `Management` |
+/// | `FFFF0000` to `FFFFFFFE`    | `Multicast`  |
+/// | `FFFFFFFF`                  | `Broadcast`  |
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum AddressClass {
+    /// A single node, of which the address is assigned statically.
+    Unicast,
+    /// A single node, of which the address is assigned at runtime.
+    Dynamic,
+    /// A management service that any node may provide, such as the health of a node.
+    Management,
+    /// A group of nodes, which nodes join by listening for it, see `Address::group`.
+    Multicast,
+    /// All nodes.
+    Broadcast,
+}
+
+impl AddressClass {
+    /// Whether frames to addresses of this class might be meant for more than one node.
+    ///
+    /// This includes `Management`, as any node may provide a management service, hence frames to those are acked,
+    /// credited and routed like those to a multicast group.
+    pub fn is_group(&self) -> bool {
+        !matches!(self, AddressClass::Unicast | AddressClass::Dynamic)
+    }
+}
+
+const ADDRESS_DYNAMIC_START: u32 = 0xF0000000;
+const ADDRESS_MANAGEMENT_START: u32 = 0xFF000000;
+const ADDRESS_MULTICAST_START: u32 = 0xFFFF0000;
+const ADDRESS_BROADCAST: u32 = 0xFFFFFFFF;
 
 impl Address {
     pub fn new(addr: u32) -> Self {
@@ -52,21 +208,56 @@ impl Address {
         }
     }
 
+    /// The address of all nodes, see `AddressClass::Broadcast`.
+    pub fn broadcast() -> Address {
+        Self::new(ADDRESS_BROADCAST)
+    }
+
+    /// The all-ones address, which is the same as `broadcast`.
     pub fn multicast() -> Address {
-        Self::new(ADDRESS_MULTICAST)
+        Self::broadcast()
     }
 
+    /// The address of multicast group `group`, of which group `FFFF` is `broadcast`.
+    pub fn group(group: u16) -> Address {
+        Self::new(ADDRESS_MULTICAST_START | group as u32)
+    }
+
+    /// The address of management service `service`, see `AddressClass::Management`.
+    ///
+    /// Yields `None` if `service` is out of the range of management addresses.
+    pub fn management(service: u32) -> Option<Address> {
+        let address = ADDRESS_MANAGEMENT_START.checked_add(service)?;
+        (address < ADDRESS_MULTICAST_START).then(|| Self::new(address))
+    }
+
+    pub fn class(&self) -> AddressClass {
+        match self.to_primitive() {
+            ADDRESS_BROADCAST => AddressClass::Broadcast,
+            ADDRESS_MULTICAST_START.. => AddressClass::Multicast,
+            ADDRESS_MANAGEMENT_START.. => AddressClass::Management,
+            ADDRESS_DYNAMIC_START.. => AddressClass::Dynamic,
+            _ => AddressClass::Unicast,
+        }
+    }
+
+    /// Whether this is the all-ones address of `multicast`, see `is_group` for any address of a group.
     pub fn is_multicast(&self) -> bool {
         self == &Self::multicast()
     }
 
+    /// Whether the address might refer to more than one node, see `AddressClass::is_group`.
+    pub fn is_group(&self) -> bool {
+        self.class().is_group()
+    }
+
     pub fn to_primitive(&self) -> u32 {
         self.inner.to_primitive()
     }
 
-    pub fn from_hex_str(str: &str) -> Result<Self, ()> {
+    pub fn from_hex_str(str: &str) -> Result<Self, ParseAddressError> {
         let mut buf = [0u8; 4];
-        hex::decode_to_slice(str, &mut buf).map_err(|_| ())?;
+        hex::decode_to_slice(str, &mut buf).map_err(|_| ParseAddressError)?;
         Ok(Address::new(u32::from_be_bytes(buf)))
     }
 }
@@ -100,10 +291,18 @@ pub struct Header {
     pub address_src: Address,
     #[packed_field(bits = "32..64")]
     pub address_dst: Address,
+    /// Length of the contents, or its lowest 10 bits for extended frames, see `MAX_EXTENDED_MESSAGE_LEN`.
     #[packed_field(bits = "64..74")]
     pub len: Integer<u16, packed_bits::Bits<10>>,
-    #[packed_field(bits = "74..80")]
-    _reserved: Integer<u8, packed_bits::Bits<6>>,
+    /// How many more times repeaters may forward this frame to another bus segment.
+    #[packed_field(bits = "74..77")]
+    pub hop_limit: Integer<u8, packed_bits::Bits<3>>,
+    /// Sequence number of the sender, used by receivers to drop duplicates. `0` if the frame has none.
+    #[packed_field(bits = "77..79")]
+    pub sequence: Integer<u8, packed_bits::Bits<2>>,
+    /// Whether the contents start with an options block, see `options`.
+    #[packed_field(bits = "79")]
+    pub has_options: bool,
 }
 
 /// A reference to a decoded frame, owned by the Reader.
@@ -116,9 +315,61 @@ pub struct FrameRef<'a> {
     pub contents: &'a [u8],
 }
 
-/// Owned variant of a frame.
+impl FrameRef<'_> {
+    /// How many more times repeaters may forward this frame.
+    pub fn hop_limit(&self) -> u8 {
+        self.header.hop_limit.to_primitive()
+    }
+
+    /// Sequence number of the sender, or `0` if the frame has none.
+    pub fn sequence(&self) -> u8 {
+        self.header.sequence.to_primitive()
+    }
+}
+
+impl<'a> FrameRef<'a> {
+    /// The options of the frame, and the payload that follows them.
+    ///
+    /// Frames without options have no options, and the complete contents as payload.
+    pub fn options(&self) -> Result<(Options<'a>, &'a [u8]), InvalidOptions> {
+        if self.header.has_options {
+            Options::split(self.contents)
+        } else {
+            Ok((Options::empty(), self.contents))
+        }
+    }
+
+    /// The sequence number of the frame of ours that this frame acknowledges, if any, see `FrameBuilder::ack`.
+    pub fn ack(&self) -> Option<u8> {
+        let (options, _) = self.options().ok()?;
+        options.get(options::ACK)?.first().copied()
+    }
+
+    /// The contents of the frame, without any options.
+    pub fn payload(&self) -> Result<&'a [u8], InvalidOptions> {
+        self.options().map(|(_, payload)| payload)
+    }
+
+    /// The payload of the frame, decompressed into `buf` if it is compressed, see `Writer::package_compressed`.
+    #[cfg(feature = "compression")]
+    pub fn decompress<'b>(&self, buf: &'b mut [u8]) -> Result<&'b [u8], compress::DecompressError>
+    where
+        'a: 'b,
+    {
+        let (options, payload) = self
+            .options()
+            .map_err(|InvalidOptions| compress::DecompressError::InvalidOptions)?;
+        if options.get(options::COMPRESSED).is_none() {
+            return Ok(payload);
+        }
+        let len = compress::decompress(payload, buf)?;
+        Ok(&buf[..len])
+    }
+}
+
+/// Owned variant of a frame, for frames that outlive the reader they were received by, i.e. those of a `FrameIter`.
 ///
-/// **TODO**: remove this type as it should be unnecessary.
+/// Copying frames is unnecessary otherwise, see `Reader::last_frame`.
 pub struct FrameOwned {
     pub header: Header,
     pub contents: heapless::Vec<u8, MAX_MESSAGE_LEN>,
@@ -165,6 +416,70 @@ pub enum ReadResult<'a> {
     FrameOK(FrameRef<'a>),
 }
 
+/// Why a frame could not be decoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameError {
+    /// The frame did not fit in the buffer of the reader.
+    Overflow,
+    /// The COBS marker arrived before the last COBS block was complete.
+    Cobs,
+    /// The frame does not start with the magic word of the bus, i.e. it is of another protocol or bus. Checked before
+    /// anything else.
+    Magic,
+    /// The header could not be unpacked.
+    Header,
+    /// The frame is too short, or its length does not match the header.
+    Size,
+    /// The CRC of the frame does not match its contents.
+    Checksum,
+}
+
+impl FrameError {
+    /// The error corresponding to `result`, if it is one.
+    pub fn from_read_result(result: &ReadResult) -> Option<Self> {
+        match result {
+            ReadResult::NotYet | ReadResult::FrameOK(_) => None,
+            ReadResult::Overflow => Some(FrameError::Overflow),
+            ReadResult::FrameErrorCobs => Some(FrameError::Cobs),
+            ReadResult::FrameErrorMagic => Some(FrameError::Magic),
+            ReadResult::FrameErrorHeader => Some(FrameError::Header),
+            ReadResult::FrameErrorSize => Some(FrameError::Size),
+            ReadResult::FrameErrorChecksum => Some(FrameError::Checksum),
+        }
+    }
+}
+
+impl core::fmt::Display for FrameError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            FrameError::Overflow => "frame does not fit in the buffer",
+            FrameError::Cobs => "invalid COBS encoding",
+            FrameError::Magic => "invalid magic word",
+            FrameError::Header => "invalid header",
+            FrameError::Size => "length does not match the header",
+            FrameError::Checksum => "invalid checksum",
+        })
+    }
+}
+
+impl core::error::Error for FrameError {}
+
+/// Compatibility with code written against `ReadResult`, i.e. `ReadResult::from(reader.feed(byte))`.
+impl<'a> From<Result<Option<FrameRef<'a>>, FrameError>> for ReadResult<'a> {
+    fn from(result: Result<Option<FrameRef<'a>>, FrameError>) -> Self {
+        match result {
+            Ok(None) => ReadResult::NotYet,
+            Ok(Some(frame)) => ReadResult::FrameOK(frame),
+            Err(FrameError::Overflow) => ReadResult::Overflow,
+            Err(FrameError::Cobs) => ReadResult::FrameErrorCobs,
+            Err(FrameError::Magic) => ReadResult::FrameErrorMagic,
+            Err(FrameError::Header) => ReadResult::FrameErrorHeader,
+            Err(FrameError::Size) => ReadResult::FrameErrorSize,
+            Err(FrameError::Checksum) => ReadResult::FrameErrorChecksum,
+        }
+    }
+}
+
 impl<'a> ReadResult<'a> {
     pub fn is_error(&self) -> bool {
         match self {
@@ -184,109 +499,536 @@ impl<'a> ReadResult<'a> {
 /// A reader for the protocol.
 ///
 /// We use a separate `ptr` field contrary to a `heapless::Vec` due to lifetimes.
-pub struct Reader {
-    buf: [u8; MAX_FRAME_LEN],
+///
+/// Bytes are COBS decoded and checksummed as they arrive, such that only the contents of a frame are buffered,
+/// without its encoding, padding or checksum.
+/// The buffer is `N` bytes large, which can be reduced using `max_naked_len` if messages are known to be small.
+/// Frames that do not fit result in a single `FrameError::Overflow`, after which the rest of the frame is skipped.
+/// Frames of other protocols are rejected as soon as their magic word arrived, those of which the length is too
+/// large as soon as the header arrived, and those of which the padding is broken as soon as that arrived, without
+/// waiting for the COBS marker. Use `promiscuous` to receive invalid frames completely instead.
+///
+/// Only frames on bus `BUS` are accepted, those of other buses are rejected like those of other protocols, see
+/// `magic_word`.
+pub struct Reader<const N: usize = MAX_NAKED_LEN, const BUS: u8 = 0> {
+    buf: [u8; N],
     ptr: usize,
+    /// How many bytes of the current COBS block are still to come, or `0` if the next byte starts a block.
+    block_left: u8,
+    /// Whether the previous COBS block stands for a zero, which is only known once another block follows it.
+    zero_pending: bool,
+    /// How many of the last decoded bytes, which follow `ptr` in the buffer, are held back from the contents and
+    /// checksum, as they might be the checksum itself.
+    held: usize,
+    /// Checksum of the decoded bytes up to the held back ones.
+    digest: Digest<'static, u16>,
+    /// Where the contents end in the buffer, once the header arrived. Anything beyond is padding.
+    content_end: Option<usize>,
+    /// Skipping the rest of a frame that did not fit, until the next COBS marker.
+    discarding: bool,
+    /// Decoding the header of each frame as it arrives, see `peek_headers`.
+    peek: PeekState,
+    /// The header and where the contents are in the buffer of the frame yielded last, see `last_frame`.
+    last: Option<(Header, core::ops::Range<usize>)>,
+    /// Buffering invalid frames completely instead of rejecting them early, see `promiscuous`.
+    promiscuous: bool,
+    /// How many decoded bytes the frame rejected by the last call to `feed` consists of, see `rejected`.
+    rejected: Option<usize>,
+}
+
+/// `CHECKSUM`, such that a `Reader` can hold on to a `Digest` of it.
+static READER_CHECKSUM: Crc<u16> = CHECKSUM;
+
+/// How far the header of the frame being received is decoded, see `Reader::peek_headers`.
+#[derive(Debug, Clone, PartialEq)]
+enum PeekState {
+    /// Headers are only decoded once the whole frame arrived.
+    Off,
+    /// Not enough of the frame has arrived yet.
+    Pending,
+    /// The header arrived, and was not yet taken.
+    Ready(Header),
+    /// The header was taken, or could not be decoded.
+    Done,
 }
 
+/// How many bytes of a frame the header is encoded in at most, including the COBS code byte that follows it.
+#[cfg(test)]
+const PEEK_LEN: usize = MAGIC_LEN + HEADER_LEN + 2;
+
 impl Reader {
+    /// Create a reader that fits the largest possible frame.
+    ///
+    /// Use `Reader::<N>::default()` for other buffer sizes, or `Reader::<N, BUS>::default()` for other buses.
     pub fn new() -> Self {
-        Reader {
-            buf: [0u8; MAX_FRAME_LEN],
-            ptr: 0,
-        }
+        Self::default()
     }
+}
 
+impl<const N: usize, const BUS: u8> Reader<N, BUS> {
     pub fn clear(&mut self) {
         self.ptr = 0;
+        self.block_left = 0;
+        self.zero_pending = false;
+        self.held = 0;
+        self.digest = READER_CHECKSUM.digest();
+        self.content_end = None;
+        self.discarding = false;
+        self.last = None;
+        self.rejected = None;
+        self.restart_peek();
+    }
+
+    /// Buffer invalid frames up to their end, including any padding, instead of rejecting them as soon as possible.
+    ///
+    /// Use this to inspect frames that do not pass validation using `rejected`, i.e. when sniffing a bus. Frames
+    /// that do not fit in the buffer still result in `FrameError::Overflow`.
+    pub fn promiscuous(&mut self, enabled: bool) {
+        self.promiscuous = enabled;
+    }
+
+    /// The COBS decoded bytes of the frame rejected by the last call to `feed`, if `promiscuous` is enabled.
+    ///
+    /// This includes the magic word, header and checksum, as far as they arrived.
+    pub fn rejected(&self) -> Option<&[u8]> {
+        self.rejected.map(|len| &self.buf[0..len])
+    }
+
+    /// Decode the header of every frame as soon as it arrives, for `take_header` to yield.
+    ///
+    /// Use this to `skip` frames that are of no interest early, without buffering and checking all of them.
+    pub fn peek_headers(&mut self, enabled: bool) {
+        self.peek = match enabled {
+            // Frames that are already on their way are peeked from the next one on.
+            true if self.ptr == 0 && self.held == 0 && !self.discarding => PeekState::Pending,
+            true => PeekState::Done,
+            false => PeekState::Off,
+        };
+    }
+
+    /// The header of the frame being received, once per frame, if `peek_headers` is enabled.
+    ///
+    /// Like `peek_header`, the header is not yet verified by the checksum.
+    pub fn take_header(&mut self) -> Option<Header> {
+        match self.peek {
+            PeekState::Ready(_) => match core::mem::replace(&mut self.peek, PeekState::Done) {
+                PeekState::Ready(header) => Some(header),
+                _ => unreachable!(),
+            },
+            _ => None,
+        }
+    }
+
+    fn restart_peek(&mut self) {
+        if self.peek != PeekState::Off {
+            self.peek = PeekState::Pending;
+        }
+    }
+
+    /// The frame yielded by the last call to `feed`, as long as the reader was not fed or cleared since.
+    ///
+    /// Use this to hand a frame over without copying it, where the borrow of `feed` can not be held on to.
+    pub fn last_frame(&self) -> Option<FrameRef<'_>> {
+        self.last.as_ref().map(|(header, contents)| FrameRef {
+            header: header.clone(),
+            contents: &self.buf[contents.clone()],
+        })
+    }
+
+    /// How many decoded bytes of the current frame have been buffered so far.
+    pub fn buffered_len(&self) -> usize {
+        self.ptr + self.held
     }
 
-    /// Feed a new byte to the reader, and it might result in a correct frame.
+    /// The header of the frame being received, as soon as enough of it has arrived.
     ///
-    /// Do not forget to clear the reader after an error.
-    pub fn feed(&mut self, byte: u8) -> ReadResult {
-        let old_ptr = self.ptr;
-        let new_ptr = (self.ptr + 1).min(self.buf.len());
-        let overflown = old_ptr == new_ptr;
+    /// Use this to `skip` frames that are of no interest early, i.e. based on their destination.
+    /// The header is not yet verified by the checksum, and might hence be broken.
+    pub fn peek_header(&self) -> Option<Header> {
+        match &self.peek {
+            PeekState::Ready(header) => Some(header.clone()),
+            _ => self.prefix().and_then(|naked| decode_header(naked, BUS)),
+        }
+    }
+
+    /// The first `L` decoded bytes of the frame being received, if that many arrived and none of them is padding.
+    fn prefix<const L: usize>(&self) -> Option<&[u8; L]> {
+        if self.content_end.is_some_and(|end| end < L) {
+            return None;
+        }
+        self.buf[0..self.ptr + self.held].first_chunk()
+    }
+
+    /// Where the contents of the frame being received end, once its header and any length extension arrived.
+    ///
+    /// Frames of which the header is broken, or that will not fit, are rejected before they arrived completely.
+    fn decode_content_end(&self) -> Result<usize, FrameError> {
+        let naked: &[u8; MAGIC_LEN + HEADER_LEN + LEN_EXTENSION_LEN] =
+            self.prefix().ok_or(FrameError::Size)?;
+        let header = decode_header(naked.first_chunk().unwrap(), BUS).ok_or(FrameError::Header)?;
+        let len = header.len.to_primitive() as usize;
+        let end = if parse_magic(&naked[..MAGIC_LEN], BUS) == Some(true) {
+            let len = len | (naked[MAGIC_LEN + HEADER_LEN] as usize) << 10;
+            MAGIC_LEN + HEADER_LEN + LEN_EXTENSION_LEN + len
+        } else {
+            MAGIC_LEN + HEADER_LEN + len
+        };
 
-        if overflown {
-            return ReadResult::Overflow;
+        match end + CHECKSUM_LEN <= N {
+            true => Ok(end),
+            false => Err(FrameError::Overflow),
         }
+    }
+
+    /// Skip the rest of the frame being received, up to and including the next COBS marker.
+    pub fn skip(&mut self) {
+        self.clear();
+        self.discarding = true;
+    }
+
+    /// Take a decoded byte, holding it back until it is known not to be part of the checksum.
+    ///
+    /// Padding beyond the contents is only checked, such that padded frames fit in a reader sized for their contents.
+    fn push(&mut self, byte: u8) -> Result<(), FrameError> {
+        if self.held == CHECKSUM_LEN {
+            let held = self.buf[self.ptr];
+            self.digest.update(&[held]);
+
+            if self.content_end == Some(self.ptr) {
+                if held != 0 {
+                    self.skip();
+                    return Err(FrameError::Size);
+                }
+                self.buf
+                    .copy_within(self.ptr + 1..self.ptr + CHECKSUM_LEN, self.ptr);
+                self.buf[self.ptr + CHECKSUM_LEN - 1] = byte;
+                return Ok(());
+            }
 
-        self.buf[self.ptr] = byte;
-        self.ptr = new_ptr;
+            self.ptr += 1;
+            self.held -= 1;
+            if self.ptr == MAGIC_LEN + HEADER_LEN && !self.promiscuous {
+                match self.decode_content_end() {
+                    Ok(end) => self.content_end = Some(end),
+                    Err(e) => {
+                        self.skip();
+                        return Err(e);
+                    }
+                }
+            }
+        }
+
+        match self.buf.get_mut(self.ptr + self.held) {
+            Some(b) => *b = byte,
+            None => {
+                self.skip();
+                return Err(FrameError::Overflow);
+            }
+        }
+        self.held += 1;
+
+        // Frames of other protocols or buses are rejected before anything else.
+        if self.ptr + self.held == MAGIC_LEN
+            && !self.promiscuous
+            && parse_magic(&self.buf[0..MAGIC_LEN], BUS).is_none()
+        {
+            self.skip();
+            return Err(FrameError::Magic);
+        }
+        Ok(())
+    }
+
+    /// Feed a new byte to the reader, yielding the frame it completes, or why that frame is broken.
+    ///
+    /// The reader recovers from errors by itself, starting afresh with the next frame.
+    pub fn feed(&mut self, byte: u8) -> Result<Option<FrameRef<'_>>, FrameError> {
+        // The frame yielded or rejected last is overwritten from now on.
+        self.last = None;
+        self.rejected = None;
+
+        if self.discarding {
+            if byte == COBS_MARKER {
+                self.clear();
+            }
+            return Ok(None);
+        }
 
         // COBS marker detected
         if byte == COBS_MARKER {
+            let result = self.validate();
+            let decoded_len = self.ptr + self.held;
             // Clear frame so that the reader is usable again at error or when FrameRef is dropped.
             self.clear();
 
-            let buf = &mut self.buf[0..old_ptr];
-            let buf = match cobs::decode_in_place(buf) {
-                Ok(len) => &mut buf[0..len],
-                Err(()) => return ReadResult::FrameErrorCobs,
+            return match result {
+                // Reader can not be fed as long as FrameRef is in use.
+                Ok(frame) => {
+                    self.last = Some(frame);
+                    Ok(self.last_frame())
+                }
+                Err(e) => {
+                    if self.promiscuous {
+                        self.rejected = Some(decoded_len);
+                    }
+                    Err(e)
+                }
             };
+        }
 
-            if buf.len() < MIN_NAKED_LEN {
-                return ReadResult::FrameErrorSize;
+        if self.block_left > 0 {
+            self.block_left -= 1;
+            self.push(byte)?;
+        } else {
+            // A block shorter than the maximum stands for a zero, unless it is the last one of the frame.
+            if self.zero_pending {
+                self.push(0)?;
             }
+            self.block_left = byte - 1;
+            self.zero_pending = byte < 0xFF;
+        }
 
-            let (buf, checksum_buf) = buf.split_at(buf.len() - CHECKSUM_LEN);
-            let checksum_at_end = u16::from_be_bytes(checksum_buf.try_into().unwrap());
-            let checksum_of_msg = CHECKSUM.checksum(buf);
-
-            if checksum_at_end != checksum_of_msg {
-                return ReadResult::FrameErrorChecksum;
+        if self.peek == PeekState::Pending {
+            if let Some(naked) = self.prefix() {
+                // Broken headers are left to the checksum to tell once the frame is complete.
+                self.peek = match decode_header(naked, BUS) {
+                    Some(header) => PeekState::Ready(header),
+                    None => PeekState::Done,
+                };
             }
+        }
 
-            let (magic_buf, buf) = buf.split_at(MAGIC_LEN);
-            let (header_buf, content_buf) = buf.split_at(HEADER_LEN);
+        Ok(None)
+    }
 
-            if magic_buf != MAGIC_WORD {
-                return ReadResult::FrameErrorHeader;
-            }
+    /// Check the frame that arrived completely, yielding its header and where its contents are in the buffer.
+    ///
+    /// The magic word is checked first, such that frames of other protocols on the bus are told apart from broken
+    /// ones.
+    fn validate(&mut self) -> Result<(Header, core::ops::Range<usize>), FrameError> {
+        let len = self.ptr;
+        let checksum_of_msg =
+            core::mem::replace(&mut self.digest, READER_CHECKSUM.digest()).finalize();
+
+        let extended = match self.buf[0..len + self.held].first_chunk::<MAGIC_LEN>() {
+            Some(magic_buf) => match parse_magic(magic_buf, BUS) {
+                Some(extended) => extended,
+                None => return Err(FrameError::Magic),
+            },
+            None if self.block_left > 0 => return Err(FrameError::Cobs),
+            None => return Err(FrameError::Size),
+        };
 
-            let header_buf: &[u8; HEADER_LEN] = header_buf.try_into().unwrap();
+        if self.block_left > 0 {
+            return Err(FrameError::Cobs);
+        }
 
-            let header = match Header::unpack(header_buf) {
-                Ok(header) => header,
-                Err(_) => return ReadResult::FrameErrorHeader,
-            };
+        if self.held != CHECKSUM_LEN || len + CHECKSUM_LEN < MIN_NAKED_LEN {
+            return Err(FrameError::Size);
+        }
 
-            if content_buf.len() != header.len.to_primitive() as usize {
-                return ReadResult::FrameErrorSize;
+        let checksum_buf = &self.buf[len..len + CHECKSUM_LEN];
+        let checksum_at_end = u16::from_be_bytes(checksum_buf.try_into().unwrap());
+        if checksum_at_end != checksum_of_msg {
+            return Err(FrameError::Checksum);
+        }
+
+        let (header_buf, content_buf) = self.buf[MAGIC_LEN..len].split_at(HEADER_LEN);
+        let header_buf: &[u8; HEADER_LEN] = header_buf.try_into().unwrap();
+
+        let header = match Header::unpack(header_buf) {
+            Ok(header) => header,
+            Err(_) => return Err(FrameError::Header),
+        };
+
+        let mut len = header.len.to_primitive() as usize;
+        let content_buf = match content_buf.split_first() {
+            Some((len_high, content_buf)) if extended => {
+                len |= (*len_high as usize) << 10;
+                content_buf
             }
+            None if extended => return Err(FrameError::Size),
+            _ => content_buf,
+        };
 
-            // Reader can not be fed as long as FrameRef is in use.
-            ReadResult::FrameOK(FrameRef {
-                header,
-                contents: content_buf,
-            })
-        } else {
-            ReadResult::NotYet
-        }
+        // Anything beyond the length is padding, see `Writer::package_padded`.
+        let content_buf = match content_buf.split_at_checked(len) {
+            Some((content_buf, padding)) if padding.iter().all(|b| *b == 0) => content_buf,
+            _ => return Err(FrameError::Size),
+        };
+
+        let start = match extended {
+            true => MAGIC_LEN + HEADER_LEN + LEN_EXTENSION_LEN,
+            false => MAGIC_LEN + HEADER_LEN,
+        };
+        Ok((header, start..start + content_buf.len()))
+    }
+}
+
+/// The magic word of frames on bus `bus`, which is that of bus `0` with `bus` XOR-ed into its first byte.
+///
+/// Buses of which the wiring might leak into each other are told apart by giving each its own identifier, such that
+/// the `Reader` of one rejects the frames of another. Frames on bus `0` are those of before buses had identifiers.
+pub const fn magic_word(bus: u8, extended: bool) -> [u8; MAGIC_LEN] {
+    let word = if extended {
+        MAGIC_WORD_EXTENDED
+    } else {
+        MAGIC_WORD
+    };
+    [word[0] ^ bus, word[1]]
+}
+
+/// Whether `magic_buf` is the magic word of an extended or a regular frame on bus `bus`, if either.
+fn parse_magic(magic_buf: &[u8], bus: u8) -> Option<bool> {
+    if magic_buf == magic_word(bus, false) {
+        Some(false)
+    } else if magic_buf == magic_word(bus, true) {
+        Some(true)
+    } else {
+        None
     }
 }
 
-impl Default for Reader {
+/// The bus of which `magic_buf` is the magic word, and whether the frame is extended.
+fn parse_magic_of_any_bus(magic_buf: &[u8; MAGIC_LEN]) -> Option<(u8, bool)> {
+    let bus = magic_buf[0] ^ MAGIC_WORD[0];
+    parse_magic(magic_buf, bus).map(|extended| (bus, extended))
+}
+
+/// Decode the header at the start of a frame, if its magic word is that of bus `bus`.
+fn decode_header(naked: &[u8; MAGIC_LEN + HEADER_LEN], bus: u8) -> Option<Header> {
+    let (magic_buf, header_buf) = naked.split_at(MAGIC_LEN);
+    parse_magic(magic_buf, bus)?;
+    Header::unpack(header_buf.try_into().unwrap()).ok()
+}
+
+impl<const N: usize, const BUS: u8> Default for Reader<N, BUS> {
     fn default() -> Self {
-        Self::new()
+        Reader {
+            buf: [0u8; N],
+            ptr: 0,
+            block_left: 0,
+            zero_pending: false,
+            held: 0,
+            digest: READER_CHECKSUM.digest(),
+            content_end: None,
+            discarding: false,
+            peek: PeekState::Off,
+            last: None,
+            promiscuous: false,
+            rejected: None,
+        }
     }
 }
 
-impl Debug for Reader {
+impl<const N: usize, const BUS: u8> Debug for Reader<N, BUS> {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         self.buf[0..self.ptr].fmt(f)
     }
 }
 
-#[derive(Debug)]
-pub struct Frame(pub heapless::Vec<u8, { MAX_FRAME_LEN }>);
+/// An encoded frame, ready to be put on the bus.
+#[derive(Debug, Clone)]
+pub struct Frame<const N: usize = MAX_FRAME_LEN>(pub heapless::Vec<u8, N>);
 
-impl Frame {
+impl<const N: usize> Frame<N> {
     pub fn as_slice(&self) -> &[u8] {
         self.0.as_slice()
     }
+
+    /// The header of the frame, decoded without decoding the rest of it, i.e. to log frames that are sent.
+    pub fn header(&self) -> Option<Header> {
+        let naked = self.naked_prefix()?;
+        let (bus, _) = parse_magic_of_any_bus(naked.first_chunk().unwrap())?;
+        decode_header(naked.first_chunk().unwrap(), bus)
+    }
+
+    /// The bus the frame is meant for, as told by its magic word, see `magic_word`.
+    pub fn bus(&self) -> Option<u8> {
+        let naked = self.naked_prefix()?;
+        parse_magic_of_any_bus(naked.first_chunk().unwrap()).map(|(bus, _)| bus)
+    }
+
+    /// How long the contents of the frame are, including those of extended frames.
+    pub fn contents_len(&self) -> Option<usize> {
+        let naked = self.naked_prefix()?;
+        let (bus, extended) = parse_magic_of_any_bus(naked.first_chunk().unwrap())?;
+        let len = decode_header(naked.first_chunk().unwrap(), bus)?
+            .len
+            .to_primitive() as usize;
+        match extended {
+            true => Some(len | (naked[MAGIC_LEN + HEADER_LEN] as usize) << 10),
+            false => Some(len),
+        }
+    }
+
+    /// COBS decode the magic word, header and length extension, if any, which every frame has room for.
+    fn naked_prefix(&self) -> Option<[u8; MAGIC_LEN + HEADER_LEN + LEN_EXTENSION_LEN]> {
+        let mut naked = [0u8; MAGIC_LEN + HEADER_LEN + LEN_EXTENSION_LEN];
+        let mut len = 0;
+        let mut encoded = self.0.iter().copied();
+        while len < naked.len() {
+            let code = encoded.next().filter(|code| *code != COBS_MARKER)?;
+            for _ in 1..code {
+                if len == naked.len() {
+                    break;
+                }
+                naked[len] = encoded.next().filter(|b| *b != COBS_MARKER)?;
+                len += 1;
+            }
+            // A block shorter than the maximum stands for a zero.
+            if code < 0xFF && len < naked.len() {
+                len += 1;
+            }
+        }
+        Some(naked)
+    }
+
+    /// Replace the addresses in the header of an already encoded frame, and fix up its checksum.
+    ///
+    /// Cheaper than decoding the frame and packaging it again using the `Writer`, for use in repeaters.
+    /// Frames that are broken are rejected instead of being given a valid checksum.
+    pub fn rewrite_addresses(&mut self, src: Address, dst: Address) -> Result<(), WriteError> {
+        use WriteError::*;
+
+        let encoded = match self.0.split_last() {
+            Some((&COBS_MARKER, encoded)) => encoded,
+            _ => return Err(InvalidFrame),
+        };
+
+        let mut naked = [0u8; N];
+        let naked = &mut naked[0..encoded.len()];
+        naked.copy_from_slice(encoded);
+        let naked = match cobs::decode_in_place(naked) {
+            Ok(len) if len >= MIN_NAKED_LEN => &mut naked[0..len],
+            _ => return Err(InvalidFrame),
+        };
+
+        let (buf, checksum_buf) = naked.split_at_mut(naked.len() - CHECKSUM_LEN);
+        if parse_magic_of_any_bus(buf.first_chunk().unwrap()).is_none()
+            || CHECKSUM.checksum(buf) != u16::from_be_bytes([checksum_buf[0], checksum_buf[1]])
+        {
+            return Err(InvalidFrame);
+        }
+
+        // The addresses are the first two fields of the header.
+        buf[MAGIC_LEN..MAGIC_LEN + 4].copy_from_slice(&src.to_primitive().to_be_bytes());
+        buf[MAGIC_LEN + 4..MAGIC_LEN + 8].copy_from_slice(&dst.to_primitive().to_be_bytes());
+        checksum_buf.copy_from_slice(&CHECKSUM.checksum(buf).to_be_bytes());
+
+        // The encoded length might change, as the amount of zeroes might have changed.
+        self.0.resize_default(N).unwrap();
+        let mut cobs = cobs::CobsEncoder::new(self.0.as_mut());
+        let len = match cobs.push(naked).and_then(|()| cobs.finalize()) {
+            Ok(len) if len < N => len,
+            _ => {
+                self.0.clear();
+                return Err(TooLong);
+            }
+        };
+        self.0[len] = COBS_MARKER;
+        self.0.truncate(len + 1);
+        Ok(())
+    }
 }
 
 #[derive(Debug)]
@@ -296,33 +1038,240 @@ pub enum WriteError {
     TooLong,
     /// Tried to encode an invalid header.
     FrameErrorHeader,
+    /// Tried to rewrite a frame that is not a valid encoded frame.
+    InvalidFrame,
 }
 
+impl core::fmt::Display for WriteError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(match self {
+            WriteError::TooLong => "message does not fit in a frame",
+            WriteError::FrameErrorHeader => "invalid header",
+            WriteError::InvalidFrame => "not a valid encoded frame",
+        })
+    }
+}
+
+impl core::error::Error for WriteError {}
+
+/// Shorthands to package frames, see `FrameBuilder` to combine the features of a frame.
 pub struct Writer;
 
 impl Writer {
+    /// Package a frame that fits the largest message.
+    ///
+    /// The frame takes up `MAX_FRAME_LEN` bytes regardless of the length of `contents`, use `package_sized` on
+    /// small stacks.
     pub fn package(src: Address, dst: Address, contents: &[u8]) -> Result<Frame, WriteError> {
+        Self::package_sized(src, dst, contents)
+    }
+
+    /// Package a frame into a buffer of `N` bytes, failing with `TooLong` if it does not fit.
+    ///
+    /// Takes up stack in proportion to `N`, which `max_frame_len` decides for messages of a known length.
+    pub fn package_sized<const N: usize>(
+        src: Address,
+        dst: Address,
+        contents: &[u8],
+    ) -> Result<Frame<N>, WriteError> {
+        Self::package_inner(0, src, dst, 0, 0, None, &[contents], 0)
+    }
+
+    /// Package a frame with contents consisting of the concatenation of `parts`.
+    ///
+    /// Avoids having to concatenate the parts into a temporary buffer first.
+    pub fn package_vectored(
+        src: Address,
+        dst: Address,
+        parts: &[&[u8]],
+    ) -> Result<Frame, WriteError> {
+        Self::package_vectored_sized(src, dst, parts)
+    }
+
+    /// Package a frame with contents of up to `MAX_EXTENDED_MESSAGE_LEN` bytes.
+    ///
+    /// Frames are only extended if the contents do not fit in a regular frame.
+    pub fn package_extended(
+        src: Address,
+        dst: Address,
+        contents: &[u8],
+    ) -> Result<Frame<MAX_EXTENDED_FRAME_LEN>, WriteError> {
+        Self::package_sized(src, dst, contents)
+    }
+
+    /// Combination of `package_sized` and `package_vectored`.
+    pub fn package_vectored_sized<const N: usize>(
+        src: Address,
+        dst: Address,
+        parts: &[&[u8]],
+    ) -> Result<Frame<N>, WriteError> {
+        Self::package_inner(0, src, dst, 0, 0, None, parts, 0)
+    }
+
+    /// Package a frame that repeaters may forward at most `hop_limit` times to another bus segment.
+    ///
+    /// Frames packaged by the other functions are never forwarded, as their hop limit is `0`.
+    /// The hop limit can be at most `MAX_HOP_LIMIT`.
+    pub fn package_with_hop_limit(
+        src: Address,
+        dst: Address,
+        hop_limit: u8,
+        contents: &[u8],
+    ) -> Result<Frame, WriteError> {
+        FrameBuilder::new(src, dst)
+            .hop_limit(hop_limit)
+            .payload(contents)
+            .build()
+    }
+
+    /// Package a frame with a sequence number, such that receivers can drop duplicates.
+    ///
+    /// Senders should cycle through `1..=MAX_SEQUENCE`, as frames with sequence number `0` are never deemed duplicates.
+    pub fn package_with_sequence(
+        src: Address,
+        dst: Address,
+        sequence: u8,
+        contents: &[u8],
+    ) -> Result<Frame, WriteError> {
+        FrameBuilder::new(src, dst)
+            .sequence(sequence)
+            .payload(contents)
+            .build()
+    }
+
+    /// Package a frame of which the contents start with `options`, followed by `payload`.
+    ///
+    /// Receivers get the options and payload back using `FrameRef::options`.
+    pub fn package_with_options(
+        src: Address,
+        dst: Address,
+        options: &[TlvOption],
+        payload: &[u8],
+    ) -> Result<Frame, WriteError> {
+        FrameBuilder::new(src, dst)
+            .options(options)
+            .payload(payload)
+            .build()
+    }
+
+    /// Package a frame that takes up exactly `frame_len` bytes on the bus, including the COBS marker.
+    ///
+    /// The contents are followed by zeroes, which receivers strip using the length in the header.
+    /// Padding all frames to the same length hides which kind of message is sent from those that can listen in.
+    /// Repeaters forward frames without their padding.
+    pub fn package_padded(
+        src: Address,
+        dst: Address,
+        contents: &[u8],
+        frame_len: usize,
+    ) -> Result<Frame, WriteError> {
+        FrameBuilder::new(src, dst)
+            .payload(contents)
+            .padded(frame_len)
+            .build()
+    }
+
+    /// Package a frame of which the payload is compressed, if that makes it smaller.
+    ///
+    /// Receivers get the payload back using `FrameRef::decompress`.
+    #[cfg(feature = "compression")]
+    pub fn package_compressed(
+        src: Address,
+        dst: Address,
+        payload: &[u8],
+    ) -> Result<Frame, WriteError> {
+        FrameBuilder::new(src, dst)
+            .payload(payload)
+            .compressed()
+            .build()
+    }
+
+    /// Package a frame on bus `bus` of which the contents consist of the `options` block, if any, followed by
+    /// `parts`.
+    #[allow(clippy::too_many_arguments)]
+    fn package_inner<const N: usize>(
+        bus: u8,
+        src: Address,
+        dst: Address,
+        hop_limit: u8,
+        sequence: u8,
+        options: Option<&[u8]>,
+        parts: &[&[u8]],
+        padding: usize,
+    ) -> Result<Frame<N>, WriteError> {
         use WriteError::*;
 
-        let len = match contents
-            .len()
-            .try_into()
-            .map_err(|_| ())
-            .and_then(convert_primitive)
-        {
-            Ok(len) => len,
-            Err(_) => return Err(TooLong),
+        let hop_limit = match convert_primitive(hop_limit) {
+            Ok(hop_limit) => hop_limit,
+            Err(_) => return Err(FrameErrorHeader),
+        };
+
+        let sequence = match convert_primitive(sequence) {
+            Ok(sequence) => sequence,
+            Err(_) => return Err(FrameErrorHeader),
         };
 
         let header = Header {
             address_src: src,
             address_dst: dst,
-            len,
-            _reserved: Integer::from_primitive(0),
+            // Filled in by `encode`.
+            len: Integer::from_primitive(0),
+            hop_limit,
+            sequence,
+            has_options: options.is_some(),
         };
 
-        let mut buf = heapless::Vec::<u8, { MAX_FRAME_LEN }>::new();
-        buf.resize_default(MAX_FRAME_LEN).unwrap();
+        Self::encode(bus, &header, options.unwrap_or_default(), parts, padding)
+    }
+
+    /// Package a received frame again to be forwarded by a repeater onto bus `bus`, with its hop limit decremented.
+    ///
+    /// Pass the bus of the `Reader` the frame was received by to keep it on its bus, see `magic_word`.
+    /// Yields `None` if the frame may not be forwarded any further.
+    pub fn forward(frame: &FrameRef, bus: u8) -> Result<Option<Frame>, WriteError> {
+        let hop_limit = frame.header.hop_limit.to_primitive();
+        if hop_limit == 0 {
+            return Ok(None);
+        }
+
+        let mut header = frame.header.clone();
+        header.hop_limit = Integer::from_primitive(hop_limit - 1);
+        Self::encode(bus, &header, &[], &[frame.contents], 0).map(Some)
+    }
+
+    /// Package a frame again with the header and contents of `frame` as is, i.e. to send an owned frame.
+    pub fn repackage(frame: &FrameRef) -> Result<Frame, WriteError> {
+        Self::encode(0, &frame.header, &[], &[frame.contents], 0)
+    }
+
+    /// Encode a frame on bus `bus` with a header of which the length is set to that of the contents.
+    ///
+    /// Contents longer than `MAX_MESSAGE_LEN` result in an extended frame.
+    /// The contents consist of `head` followed by `parts`, and are followed by `padding` zeroes which are not part
+    /// of the length.
+    fn encode<const N: usize>(
+        bus: u8,
+        header: &Header,
+        head: &[u8],
+        parts: &[&[u8]],
+        padding: usize,
+    ) -> Result<Frame<N>, WriteError> {
+        use WriteError::*;
+
+        let len = head.len() + parts.iter().map(|part| part.len()).sum::<usize>();
+        if len > MAX_EXTENDED_MESSAGE_LEN {
+            return Err(TooLong);
+        }
+        let extended = len > MAX_MESSAGE_LEN;
+        let magic_word = magic_word(bus, extended);
+
+        let mut header = header.clone();
+        header.len = Integer::from_primitive((len & 0x3FF) as u16);
+
+        // Only as much of the buffer as the frame might take up is used, such that small frames are cheap to encode.
+        let mut buf = heapless::Vec::<u8, N>::new();
+        buf.resize_default(encoded_frame_len(len + padding).min(N))
+            .unwrap();
 
         let mut cobs = cobs::CobsEncoder::new(buf.as_mut());
         let mut checksum_digest = CHECKSUM.digest();
@@ -332,16 +1281,45 @@ impl Writer {
             Err(_) => return Err(FrameErrorHeader),
         };
 
-        checksum_digest.update(MAGIC_WORD.as_slice());
-        cobs.push(MAGIC_WORD.as_slice()).unwrap(); // Unwrap: can never happen due to buffer size.
+        checksum_digest.update(magic_word.as_slice());
+        match cobs.push(magic_word.as_slice()) {
+            Ok(()) => (),
+            Err(_) => return Err(TooLong), // Only for very small buffers.
+        }
 
         checksum_digest.update(&header_buf);
-        cobs.push(&header_buf).unwrap(); // Unwrap: can never happen due to buffer size.
-
-        checksum_digest.update(contents);
-        match cobs.push(contents) {
+        match cobs.push(&header_buf) {
             Ok(()) => (),
-            Err(_) => return Err(TooLong), // Can definitely happen.
+            Err(_) => return Err(TooLong), // Only for very small buffers.
+        }
+
+        if extended {
+            let len_high = [(len >> 10) as u8];
+            checksum_digest.update(&len_high);
+            match cobs.push(&len_high) {
+                Ok(()) => (),
+                Err(_) => return Err(TooLong),
+            }
+        }
+
+        for part in core::iter::once(&head).chain(parts) {
+            checksum_digest.update(part);
+            match cobs.push(part) {
+                Ok(()) => (),
+                Err(_) => return Err(TooLong), // Can definitely happen.
+            }
+        }
+
+        let zeroes = [0u8; 32];
+        let mut padding = padding;
+        while padding > 0 {
+            let chunk = &zeroes[..padding.min(zeroes.len())];
+            checksum_digest.update(chunk);
+            match cobs.push(chunk) {
+                Ok(()) => (),
+                Err(_) => return Err(TooLong),
+            }
+            padding -= chunk.len();
         }
 
         let crc = checksum_digest.finalize();
@@ -386,7 +1364,7 @@ mod tests {
     extern crate alloc;
 
     use crate::*;
-    use alloc::vec;
+    use alloc::{string::ToString, vec, vec::Vec};
 
     const MSG: &[u8] = b"\0loremipsum\0";
     const ADDR_A: u32 = 0x0f004242;
@@ -409,7 +1387,9 @@ mod tests {
             address_src: Address::new(ADDR_A),
             address_dst: Address::new(ADDR_B),
             len: Integer::from_primitive(800),
-            _reserved: Integer::from_primitive(0),
+            hop_limit: Integer::from_primitive(0),
+            sequence: Integer::from_primitive(0),
+            has_options: false,
         };
 
         assert_eq!(
@@ -419,6 +1399,59 @@ mod tests {
         assert_eq!(Header::unpack(&header.pack().unwrap()).unwrap(), header);
     }
 
+    #[test]
+    fn address_class() {
+        use AddressClass::*;
+
+        for (address, class) in [
+            (0x00000000, Unicast),
+            (0xEFFFFFFF, Unicast),
+            (0xF0000000, Dynamic),
+            (0xFEFFFFFF, Dynamic),
+            (0xFF000000, Management),
+            (0xFFFEFFFF, Management),
+            (0xFFFF0000, Multicast),
+            (0xFFFFFFFE, Multicast),
+            (0xFFFFFFFF, Broadcast),
+        ] {
+            assert_eq!(Address::new(address).class(), class, "{:08X}", address);
+            assert_eq!(Address::new(address).is_group(), class.is_group());
+            assert_eq!(Address::new(address).is_multicast(), class == Broadcast);
+        }
+
+        assert_eq!(Address::group(0xFFFF), Address::broadcast());
+        assert_eq!(Address::group(7).class(), Multicast);
+        assert_eq!(Address::management(1).unwrap().class(), Management);
+        assert_eq!(Address::management(0xFEFFFF).unwrap().class(), Management);
+        assert_eq!(Address::management(0xFF0000), None);
+        assert_eq!(Address::management(u32::MAX), None);
+    }
+
+    #[test]
+    fn address_class_is_group() {
+        use AddressClass::*;
+
+        assert!(!Unicast.is_group());
+        assert!(!Dynamic.is_group());
+        assert!(Management.is_group());
+        assert!(Multicast.is_group());
+        assert!(Broadcast.is_group());
+    }
+
+    #[test]
+    fn error_display() {
+        assert_eq!(
+            Address::from_hex_str("0000000g").unwrap_err().to_string(),
+            "address is not 8 hexadecimal digits"
+        );
+        assert_eq!(
+            WriteError::TooLong.to_string(),
+            "message does not fit in a frame"
+        );
+        let error: &dyn core::error::Error = &FrameError::Checksum;
+        assert_eq!(error.to_string(), "invalid checksum");
+    }
+
     #[test]
     fn writer_reader_ok() {
         let frame = &mut [0u8; 4096];
@@ -430,11 +1463,11 @@ mod tests {
         let mut reader = Reader::new();
         for b in frame_begin {
             let feed_result = reader.feed(*b);
-            assert_eq!(feed_result, ReadResult::NotYet);
+            assert_eq!(feed_result, Ok(None));
         }
 
         let frame = match reader.feed(*frame_last) {
-            ReadResult::FrameOK(frame) => frame,
+            Ok(Some(frame)) => frame,
             e => panic!("Invalid result {:?}", e),
         };
 
@@ -443,6 +1476,827 @@ mod tests {
         assert_eq!(frame.contents, MSG);
     }
 
+    #[test]
+    fn testvectors_golden() {
+        use crate::testvectors::*;
+
+        let vector = TestVector::from_name("00000001-ffffffff-incrementing-2").unwrap();
+        assert_eq!(vector.to_string(), "00000001-ffffffff-incrementing-2");
+        assert_eq!(
+            vector.encode().unwrap().as_slice(),
+            [3, 107, 73, 1, 1, 6, 1, 255, 255, 255, 255, 2, 128, 4, 1, 76, 23, 0]
+        );
+
+        for vector in vectors() {
+            let frame = vector.encode().unwrap();
+            assert_eq!(vector.check_encoded(frame.as_slice()), Ok(()));
+            assert_eq!(TestVector::from_name(&vector.to_string()), Some(vector));
+
+            let mut reader = Reader::new();
+            let (last, init) = frame.as_slice().split_last().unwrap();
+            for b in init {
+                assert_eq!(reader.feed(*b), Ok(None));
+            }
+            let mut buf = [0u8; MAX_MESSAGE_LEN];
+            match reader.feed(*last) {
+                Ok(Some(frame)) => assert_eq!(frame.contents, vector.payload(&mut buf)),
+                e => panic!("Invalid result {:?} for {}", e, vector),
+            }
+        }
+    }
+
+    /// Amount of random cases each property test tries, every case with its own seed.
+    const PROPERTY_CASES: u64 = 256;
+
+    fn random_frame(rng: &mut impl rand::Rng) -> (Address, Address, vec::Vec<u8>) {
+        let len = rng.gen_range(0..=MAX_MESSAGE_LEN);
+        let mut contents = vec![0u8; len];
+        rng.fill_bytes(&mut contents);
+        (Address::new(rng.gen()), Address::new(rng.gen()), contents)
+    }
+
+    #[test]
+    fn property_roundtrip() {
+        use rand::{rngs::StdRng, SeedableRng};
+
+        for seed in 0..PROPERTY_CASES {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let (src, dst, contents) = random_frame(&mut rng);
+            let frame = Writer::package(src, dst, &contents).unwrap();
+            let header = frame.header().unwrap();
+            assert_eq!((header.address_src, header.address_dst), (src, dst));
+            assert_eq!(frame.contents_len(), Some(contents.len()), "seed {}", seed);
+
+            let mut reader = Reader::new();
+            let (last, init) = frame.as_slice().split_last().unwrap();
+            for b in init {
+                assert_eq!(reader.feed(*b), Ok(None), "seed {}", seed);
+            }
+
+            match reader.feed(*last) {
+                Ok(Some(frame)) => {
+                    assert_eq!(frame.header.address_src, src, "seed {}", seed);
+                    assert_eq!(frame.header.address_dst, dst, "seed {}", seed);
+                    assert_eq!(frame.contents, contents.as_slice(), "seed {}", seed);
+                }
+                e => panic!("Invalid result {:?} for seed {}", e, seed),
+            }
+        }
+    }
+
+    #[test]
+    fn property_corruption() {
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+
+        for seed in 0..PROPERTY_CASES {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let (src, dst, contents) = random_frame(&mut rng);
+            let mut frame = Writer::package(src, dst, &contents).unwrap().0;
+
+            for _ in 0..rng.gen_range(1..=3) {
+                let i = rng.gen_range(0..frame.len());
+                frame[i] ^= rng.gen_range(1..=u8::MAX);
+            }
+
+            let mut reader = Reader::new();
+            for b in frame.iter() {
+                let result = reader.feed(*b);
+                if let Ok(Some(decoded)) = &result {
+                    // Only the original frame may ever be decoded.
+                    assert_eq!(decoded.header.address_src, src, "seed {}", seed);
+                    assert_eq!(decoded.header.address_dst, dst, "seed {}", seed);
+                    assert_eq!(decoded.contents, contents.as_slice(), "seed {}", seed);
+                }
+                if result.is_err() {
+                    reader.clear();
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn writer_vectored() {
+        let (a, b) = MSG.split_at(3);
+        let expected = Writer::package(Address::new(ADDR_A), Address::new(ADDR_B), MSG).unwrap();
+
+        for parts in [&[a, b][..], &[&[], a, &[], b, &[]], &[MSG]] {
+            let frame = Writer::package_vectored(Address::new(ADDR_A), Address::new(ADDR_B), parts)
+                .unwrap();
+            assert_eq!(frame.as_slice(), expected.as_slice());
+        }
+
+        let large = [0xFFu8; MAX_MESSAGE_LEN];
+        assert!(matches!(
+            Writer::package_vectored(Address::new(ADDR_A), Address::new(ADDR_B), &[&large, b"x"]),
+            Err(WriteError::TooLong)
+        ));
+    }
+
+    #[test]
+    fn frame_rewrite_addresses() {
+        let addresses = [0, ADDR_A, ADDR_B, 0xFF00FF00, ADDRESS_BROADCAST];
+        for src in addresses {
+            for dst in addresses {
+                let mut frame =
+                    Writer::package(Address::new(ADDR_A), Address::new(ADDR_B), MSG).unwrap();
+                frame
+                    .rewrite_addresses(Address::new(src), Address::new(dst))
+                    .unwrap();
+
+                let expected = Writer::package(Address::new(src), Address::new(dst), MSG).unwrap();
+                assert_eq!(frame.as_slice(), expected.as_slice());
+            }
+        }
+
+        let mut frame = Writer::package(Address::new(ADDR_A), Address::new(ADDR_B), MSG).unwrap();
+        frame.0[4] ^= 1;
+        assert!(matches!(
+            frame.rewrite_addresses(Address::new(ADDR_B), Address::new(ADDR_A)),
+            Err(WriteError::InvalidFrame)
+        ));
+    }
+
+    fn decode(frame: &Frame) -> FrameOwned {
+        let mut reader = Reader::new();
+        let (last, init) = frame.as_slice().split_last().unwrap();
+        for b in init {
+            assert_eq!(reader.feed(*b), Ok(None));
+        }
+        match reader.feed(*last) {
+            Ok(Some(frame)) => frame.try_into().unwrap(),
+            e => panic!("Invalid result {:?}", e),
+        }
+    }
+
+    #[test]
+    fn writer_forward() {
+        let mut frame =
+            Writer::package_with_hop_limit(Address::new(ADDR_A), Address::new(ADDR_B), 2, MSG)
+                .unwrap();
+        assert_eq!(
+            FrameRef::from(&decode(&frame)),
+            FrameRef {
+                header: Header {
+                    address_src: Address::new(ADDR_A),
+                    address_dst: Address::new(ADDR_B),
+                    len: Integer::from_primitive(MSG.len() as u16),
+                    hop_limit: Integer::from_primitive(2),
+                    sequence: Integer::from_primitive(0),
+                    has_options: false,
+                },
+                contents: MSG,
+            }
+        );
+        assert!(matches!(
+            Writer::package_with_hop_limit(
+                Address::new(ADDR_A),
+                Address::new(ADDR_B),
+                MAX_HOP_LIMIT + 1,
+                MSG
+            ),
+            Err(WriteError::FrameErrorHeader)
+        ));
+
+        for hop_limit in [1, 0] {
+            let received = decode(&frame);
+            frame = Writer::forward(&(&received).into(), 0).unwrap().unwrap();
+
+            let forwarded = decode(&frame);
+            assert_eq!(FrameRef::from(&forwarded).hop_limit(), hop_limit);
+            assert_eq!(forwarded.header.address_src, Address::new(ADDR_A));
+            assert_eq!(forwarded.contents, MSG);
+        }
+
+        assert!(Writer::forward(&(&decode(&frame)).into(), 0)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn writer_repackage() {
+        let frame =
+            Writer::package_with_sequence(Address::new(ADDR_A), Address::new(ADDR_B), 2, MSG)
+                .unwrap();
+        let received = decode(&frame);
+        let repackaged = Writer::repackage(&(&received).into()).unwrap();
+        assert_eq!(repackaged.as_slice(), frame.as_slice());
+    }
+
+    #[test]
+    fn writer_forward_bus() {
+        let frame = FrameBuilder::new(Address::new(ADDR_A), Address::new(ADDR_B))
+            .bus(3)
+            .hop_limit(1)
+            .payload(MSG)
+            .build()
+            .unwrap();
+
+        let mut reader = Reader::<MAX_NAKED_LEN, 3>::default();
+        let received: FrameOwned = frame
+            .as_slice()
+            .iter()
+            .find_map(|b| match reader.feed(*b) {
+                Ok(Some(frame)) => frame.try_into().ok(),
+                _ => None,
+            })
+            .unwrap();
+        let forwarded = Writer::forward(&(&received).into(), 3).unwrap().unwrap();
+        assert_eq!(forwarded.bus(), Some(3));
+
+        // Readers of the bus the frame came from still accept it.
+        let forwarded = forwarded
+            .as_slice()
+            .iter()
+            .find_map(|b| match reader.feed(*b) {
+                Ok(Some(frame)) => Some((frame.hop_limit(), frame.contents.to_vec())),
+                _ => None,
+            });
+        assert_eq!(forwarded, Some((0, MSG.to_vec())));
+    }
+
+    #[test]
+    fn writer_sequence() {
+        assert!(matches!(
+            Writer::package_with_sequence(
+                Address::new(ADDR_A),
+                Address::new(ADDR_B),
+                MAX_SEQUENCE + 1,
+                MSG
+            ),
+            Err(WriteError::FrameErrorHeader)
+        ));
+
+        for sequence in 0..=MAX_SEQUENCE {
+            let frame = Writer::package_with_sequence(
+                Address::new(ADDR_A),
+                Address::new(ADDR_B),
+                sequence,
+                MSG,
+            )
+            .unwrap();
+            let received = decode(&frame);
+            assert_eq!(FrameRef::from(&received).sequence(), sequence);
+            assert_eq!(received.contents, MSG);
+        }
+    }
+
+    #[test]
+    fn writer_options() {
+        use options::{PRIORITY, TTL};
+
+        let frame = Writer::package_with_options(
+            Address::new(ADDR_A),
+            Address::new(ADDR_B),
+            &[
+                TlvOption {
+                    kind: PRIORITY,
+                    value: &[3],
+                },
+                TlvOption {
+                    kind: 0xF0,
+                    value: b"unknown",
+                },
+            ],
+            MSG,
+        )
+        .unwrap();
+        let received = decode(&frame);
+        let received = FrameRef::from(&received);
+        assert!(received.header.has_options);
+
+        let (options, payload) = received.options().unwrap();
+        assert_eq!(payload, MSG);
+        assert_eq!(options.get(PRIORITY), Some(&[3][..]));
+        assert_eq!(options.get(0xF0), Some(&b"unknown"[..]));
+        assert_eq!(options.get(TTL), None);
+        assert_eq!(options.iter().count(), 2);
+
+        let frame = Writer::package(Address::new(ADDR_A), Address::new(ADDR_B), MSG).unwrap();
+        assert_eq!(FrameRef::from(&decode(&frame)).payload(), Ok(MSG));
+
+        for broken in [&[][..], &[3, PRIORITY, 1], &[2, PRIORITY, 1, 3]] {
+            assert_eq!(Options::split(broken), Err(InvalidOptions));
+        }
+    }
+
+    #[test]
+    fn frame_builder() {
+        use options::{PRIORITY, TTL};
+
+        let frame = FrameBuilder::new(Address::new(ADDR_A), Address::new(ADDR_B))
+            .hop_limit(2)
+            .sequence(1)
+            .priority(3)
+            .ttl(500)
+            .option(0xF0, b"unknown")
+            .payload_vectored(&[&MSG[..4], &MSG[4..]])
+            .padded(64)
+            .build()
+            .unwrap();
+        assert_eq!(frame.as_slice().len(), 64);
+
+        let received = decode(&frame);
+        let received = FrameRef::from(&received);
+        assert_eq!((received.hop_limit(), received.sequence()), (2, 1));
+        let (options, payload) = received.options().unwrap();
+        assert_eq!(payload, MSG);
+        assert_eq!(options.get(PRIORITY), Some(&[3][..]));
+        assert_eq!(options.get(TTL), Some(&500u16.to_be_bytes()[..]));
+        assert_eq!(options.get(0xF0), Some(&b"unknown"[..]));
+
+        // Without any options, frames are the same as those of the shorthands.
+        let frame = FrameBuilder::new(Address::new(ADDR_A), Address::new(ADDR_B))
+            .payload(MSG)
+            .build()
+            .unwrap();
+        let expected = Writer::package(Address::new(ADDR_A), Address::new(ADDR_B), MSG).unwrap();
+        assert_eq!(frame.as_slice(), expected.as_slice());
+
+        let too_many = (0..=builder::MAX_BUILDER_OPTIONS as u8).fold(
+            FrameBuilder::new(Address::new(ADDR_A), Address::new(ADDR_B)),
+            |builder, kind| builder.option(0x80 + kind, &[]),
+        );
+        assert!(matches!(too_many.build(), Err(WriteError::TooLong)));
+    }
+
+    static_assert_fits!(MSG.len(), encoded_frame_len(MSG.len()));
+
+    #[test]
+    fn encoded_frame_len_bound() {
+        for len in [0, 1, 253, 254, 255, MAX_MESSAGE_LEN] {
+            let frame = Writer::package(
+                Address::new(ADDR_A),
+                Address::new(ADDR_B),
+                &[0xFF; MAX_MESSAGE_LEN][..len],
+            )
+            .unwrap();
+            assert_eq!(frame.as_slice().len(), encoded_frame_len(len));
+        }
+    }
+
+    #[test]
+    fn writer_reader_sized() {
+        const N: usize = max_frame_len(MSG.len());
+
+        let frame =
+            Writer::package_sized::<N>(Address::new(ADDR_A), Address::new(ADDR_B), MSG).unwrap();
+        let full = Writer::package(Address::new(ADDR_A), Address::new(ADDR_B), MSG).unwrap();
+        assert_eq!(frame.as_slice(), full.as_slice());
+        assert!(Writer::package_sized::<{ N - 1 }>(
+            Address::new(ADDR_A),
+            Address::new(ADDR_B),
+            MSG
+        )
+        .is_err());
+        assert!(
+            Writer::package_sized::<4>(Address::new(ADDR_A), Address::new(ADDR_B), &[]).is_err()
+        );
+
+        let mut reader = Reader::<{ max_naked_len(MSG.len()) }>::default();
+        let (last, init) = frame.as_slice().split_last().unwrap();
+        for b in init {
+            assert_eq!(reader.feed(*b), Ok(None));
+        }
+        match reader.feed(*last) {
+            Ok(Some(frame)) => assert_eq!(frame.contents, MSG),
+            e => panic!("Invalid result {:?}", e),
+        }
+
+        // A longer message does not fit in the small reader.
+        let frame =
+            Writer::package(Address::new(ADDR_A), Address::new(ADDR_B), &[1u8; 32]).unwrap();
+        let overflown_at = frame
+            .as_slice()
+            .iter()
+            .position(|b| reader.feed(*b) == Err(FrameError::Overflow))
+            .unwrap();
+        // Which is known as soon as the header arrived.
+        assert!(overflown_at < PEEK_LEN + CHECKSUM_LEN);
+    }
+
+    #[test]
+    fn reader_peek_header() {
+        let frame = Writer::package(Address::new(ADDR_A), Address::new(0x00010000), MSG).unwrap();
+        let mut reader = Reader::new();
+
+        let mut bytes = frame.as_slice().iter();
+        let header = loop {
+            assert_eq!(reader.feed(*bytes.next().unwrap()), Ok(None));
+            if let Some(header) = reader.peek_header() {
+                break header;
+            }
+        };
+        assert_eq!(header.address_src, Address::new(ADDR_A));
+        assert_eq!(header.address_dst, Address::new(0x00010000));
+        assert_eq!(header.len.to_primitive() as usize, MSG.len());
+
+        // Skipping the frame ignores its remainder, and leaves the reader ready for the next one.
+        reader.skip();
+        assert!(bytes.all(|b| reader.feed(*b) == Ok(None)));
+        assert_eq!(reader.peek_header(), None);
+        let received = frame
+            .as_slice()
+            .iter()
+            .filter(|b| matches!(reader.feed(**b), Ok(Some(_))))
+            .count();
+        assert_eq!(received, 1);
+    }
+
+    #[test]
+    fn writer_reader_extended() {
+        let contents: Vec<u8> = (0..MAX_EXTENDED_MESSAGE_LEN).map(|i| i as u8).collect();
+        let src = Address::new(ADDR_A);
+        let dst = Address::new(ADDR_B);
+        assert!(matches!(
+            Writer::package(src, dst, &contents),
+            Err(WriteError::TooLong)
+        ));
+        assert!(matches!(
+            Writer::package_extended(src, dst, &[0; MAX_EXTENDED_MESSAGE_LEN + 1]),
+            Err(WriteError::TooLong)
+        ));
+
+        let mut reader = Reader::<MAX_EXTENDED_NAKED_LEN>::default();
+        for len in [
+            0,
+            MAX_MESSAGE_LEN,
+            MAX_MESSAGE_LEN + 1,
+            2048,
+            MAX_EXTENDED_MESSAGE_LEN,
+        ] {
+            let frame = Writer::package_extended(src, dst, &contents[..len]).unwrap();
+            assert!(frame.as_slice().len() <= encoded_frame_len(len));
+            assert_eq!(frame.contents_len(), Some(len));
+            // Only frames that need it are extended, such that others can still be read by everybody.
+            let mut small_reader = Reader::new();
+            let read = frame
+                .as_slice()
+                .iter()
+                .any(|b| matches!(small_reader.feed(*b), Ok(Some(_))));
+            assert_eq!(read, len <= MAX_MESSAGE_LEN);
+
+            let received = frame
+                .as_slice()
+                .iter()
+                .find_map(|b| match reader.feed(*b) {
+                    Ok(Some(frame)) => Some(frame.contents.to_vec()),
+                    _ => None,
+                })
+                .unwrap();
+            assert_eq!(received, &contents[..len]);
+        }
+    }
+
+    #[test]
+    fn writer_reader_padded() {
+        let src = Address::new(ADDR_A);
+        let dst = Address::new(ADDR_B);
+        let long = [0xAAu8; 600];
+        // Padding is not buffered, such that a reader sized for the longest contents suffices.
+        let mut reader = Reader::<{ max_naked_len(600) }>::default();
+        for contents in [&b""[..], MSG, &long] {
+            for frame_len in [640, 700, MAX_FRAME_LEN] {
+                let frame = Writer::package_padded(src, dst, contents, frame_len).unwrap();
+                assert_eq!(frame.as_slice().len(), frame_len);
+
+                let received = frame
+                    .as_slice()
+                    .iter()
+                    .find_map(|b| match reader.feed(*b) {
+                        Ok(Some(frame)) => Some(frame.contents.to_vec()),
+                        _ => None,
+                    })
+                    .unwrap();
+                // Trailing zeroes of the contents are kept.
+                assert_eq!(received, contents);
+            }
+        }
+
+        assert!(matches!(
+            Writer::package_padded(src, dst, &long, 500),
+            Err(WriteError::TooLong)
+        ));
+        assert!(matches!(
+            Writer::package_padded(src, dst, MSG, MAX_FRAME_LEN + 1),
+            Err(WriteError::TooLong)
+        ));
+    }
+
+    #[test]
+    fn reader_take_header() {
+        let frames = [ADDR_B, 0x00010000]
+            .map(|dst| Writer::package(Address::new(ADDR_A), Address::new(dst), MSG).unwrap());
+        let mut reader = Reader::new();
+        reader.peek_headers(true);
+
+        let mut headers = 0;
+        let mut received = 0;
+        for frame in &frames {
+            for (i, b) in frame.as_slice().iter().enumerate() {
+                if matches!(reader.feed(*b), Ok(Some(_))) {
+                    received += 1;
+                }
+                if let Some(header) = reader.take_header() {
+                    assert!(i < PEEK_LEN);
+                    assert_eq!(header.address_src, Address::new(ADDR_A));
+                    headers += 1;
+                    // Skip frames that end up at the second address.
+                    if header.address_dst == Address::new(0x00010000) {
+                        reader.skip();
+                    }
+                }
+            }
+        }
+        assert_eq!(headers, 2);
+        assert_eq!(received, 1);
+
+        reader.peek_headers(false);
+        for b in frames[0].as_slice() {
+            let _ = reader.feed(*b);
+            assert_eq!(reader.take_header(), None);
+        }
+    }
+
+    #[test]
+    fn reader_last_frame() {
+        let frame = Writer::package(Address::new(ADDR_A), Address::new(ADDR_B), MSG).unwrap();
+        let mut reader = Reader::new();
+        let (last, init) = frame.as_slice().split_last().unwrap();
+        for b in init {
+            assert_eq!(reader.feed(*b), Ok(None));
+            assert_eq!(reader.last_frame(), None);
+        }
+        assert!(matches!(reader.feed(*last), Ok(Some(_))));
+
+        let received = reader.last_frame().unwrap();
+        assert_eq!(received.header.address_src, Address::new(ADDR_A));
+        assert_eq!(received.contents, MSG);
+
+        // Until the reader is fed again.
+        assert_eq!(reader.feed(init[0]), Ok(None));
+        assert_eq!(reader.last_frame(), None);
+    }
+
+    #[test]
+    fn reader_cobs_truncated() {
+        let frame = Writer::package(Address::new(ADDR_A), Address::new(ADDR_B), MSG).unwrap();
+        let (_, encoded) = frame.as_slice().split_last().unwrap();
+        let mut reader = Reader::new();
+
+        // The marker arrives before the last COBS block is complete.
+        for b in &encoded[..encoded.len() - 1] {
+            assert_eq!(reader.feed(*b), Ok(None));
+        }
+        assert_eq!(reader.feed(COBS_MARKER), Err(FrameError::Cobs));
+
+        let received = frame
+            .as_slice()
+            .iter()
+            .filter(|b| matches!(reader.feed(**b), Ok(Some(_))))
+            .count();
+        assert_eq!(received, 1);
+    }
+
+    /// The COBS decoded bytes of `frame`, such that they can be broken before encoding them again with `encode`.
+    fn naked(frame: &Frame) -> Vec<u8> {
+        let (_, encoded) = frame.as_slice().split_last().unwrap();
+        let mut naked = encoded.to_vec();
+        let len = cobs::decode_in_place(&mut naked).unwrap();
+        naked.truncate(len);
+        naked
+    }
+
+    fn encode(naked: &[u8]) -> Vec<u8> {
+        let mut encoded = vec![0; cobs_max_encoding_length(naked.len())];
+        let len = cobs::encode(naked, &mut encoded);
+        encoded.truncate(len);
+        encoded.push(COBS_MARKER);
+        encoded
+    }
+
+    /// Feed `encoded` to `reader`, yielding every error and after how many bytes it occurred.
+    fn errors<const N: usize, const BUS: u8>(
+        reader: &mut Reader<N, BUS>,
+        encoded: &[u8],
+    ) -> Vec<(usize, FrameError)> {
+        encoded
+            .iter()
+            .enumerate()
+            .filter_map(|(i, b)| reader.feed(*b).err().map(|e| (i + 1, e)))
+            .collect()
+    }
+
+    #[test]
+    fn reader_errors() {
+        let frame = Writer::package(Address::new(ADDR_A), Address::new(ADDR_B), MSG).unwrap();
+        let naked = naked(&frame);
+        let mut reader = Reader::new();
+
+        // Frames of other protocols are rejected as soon as the magic word arrived.
+        let mut foreign = naked.clone();
+        foreign[0..MAGIC_LEN].copy_from_slice(b"HI");
+        assert_eq!(
+            errors(&mut reader, &encode(&foreign)),
+            [(3, FrameError::Magic)]
+        );
+
+        // Even if they are too short to be a frame at all.
+        let encoded = encode(&foreign[0..MAGIC_LEN]);
+        assert_eq!(errors(&mut reader, &encoded), [(3, FrameError::Magic)]);
+
+        let encoded = encode(&naked[0..MIN_NAKED_LEN - 1]);
+        let end = encoded.len();
+        assert_eq!(errors(&mut reader, &encoded), [(end, FrameError::Size)]);
+
+        let mut corrupted = naked.clone();
+        corrupted[MAGIC_LEN + HEADER_LEN + 1] ^= 0x01;
+        let encoded = encode(&corrupted);
+        let end = encoded.len();
+        assert_eq!(errors(&mut reader, &encoded), [(end, FrameError::Checksum)]);
+
+        // Contents that are shorter than the header claims.
+        let mut short = naked[0..naked.len() - CHECKSUM_LEN - 1].to_vec();
+        short.extend(CHECKSUM.checksum(&short).to_be_bytes());
+        let encoded = encode(&short);
+        let end = encoded.len();
+        assert_eq!(errors(&mut reader, &encoded), [(end, FrameError::Size)]);
+
+        // Frames that do not fit are rejected as soon as the header arrived.
+        let mut small = Reader::<{ max_naked_len(MSG.len() - 1) }>::default();
+        let errors = errors(&mut small, frame.as_slice());
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].1, FrameError::Overflow);
+        assert!(errors[0].0 < PEEK_LEN + CHECKSUM_LEN);
+
+        // The reader recovered from all of them.
+        assert!(frame
+            .as_slice()
+            .iter()
+            .any(|b| matches!(reader.feed(*b), Ok(Some(_)))));
+    }
+
+    #[test]
+    fn reader_bus() {
+        let src = Address::new(ADDR_A);
+        let dst = Address::new(ADDR_B);
+        let ours = FrameBuilder::new(src, dst)
+            .bus(3)
+            .payload(MSG)
+            .build()
+            .unwrap();
+        let theirs = Writer::package(src, dst, MSG).unwrap();
+        let extended = FrameBuilder::new(src, dst)
+            .bus(3)
+            .payload(&[0; MAX_MESSAGE_LEN + 1])
+            .build()
+            .unwrap();
+        assert_eq!(
+            (ours.bus(), theirs.bus(), extended.bus()),
+            (Some(3), Some(0), Some(3))
+        );
+        assert_eq!(ours.header(), theirs.header());
+        assert_eq!(extended.contents_len(), Some(MAX_MESSAGE_LEN + 1));
+
+        // Frames of other buses are rejected as soon as the magic word arrived.
+        let mut reader = Reader::<MAX_EXTENDED_NAKED_LEN, 3>::default();
+        assert_eq!(
+            errors(&mut reader, theirs.as_slice()),
+            [(3, FrameError::Magic)]
+        );
+        assert_eq!(
+            errors(&mut Reader::new(), ours.as_slice()),
+            [(3, FrameError::Magic)]
+        );
+
+        for frame in [&ours, &extended] {
+            assert!(frame
+                .as_slice()
+                .iter()
+                .any(|b| matches!(reader.feed(*b), Ok(Some(_)))));
+        }
+
+        // Repeaters keep frames on their bus.
+        let mut rewritten = ours.clone();
+        rewritten.rewrite_addresses(dst, src).unwrap();
+        assert_eq!(rewritten.bus(), Some(3));
+        assert!(rewritten
+            .as_slice()
+            .iter()
+            .any(|b| matches!(reader.feed(*b), Ok(Some(_)))));
+    }
+
+    #[test]
+    fn reader_promiscuous() {
+        let frame = Writer::package(Address::new(ADDR_A), Address::new(ADDR_B), MSG).unwrap();
+        let mut foreign = naked(&frame);
+        foreign[0..MAGIC_LEN].copy_from_slice(b"HI");
+        let encoded = encode(&foreign);
+
+        let mut reader = Reader::new();
+        reader.promiscuous(true);
+        let (last, encoded) = encoded.split_last().unwrap();
+        assert_eq!(errors(&mut reader, encoded), []);
+        assert_eq!(reader.rejected(), None);
+
+        // Invalid frames are only rejected once they arrived completely, and can be inspected until the next byte.
+        assert_eq!(reader.feed(*last), Err(FrameError::Magic));
+        assert_eq!(reader.rejected(), Some(foreign.as_slice()));
+        assert_eq!(reader.feed(COBS_MARKER), Err(FrameError::Size));
+        assert_eq!(reader.rejected(), Some(&[][..]));
+
+        // Valid frames are read as usual.
+        let received = frame
+            .as_slice()
+            .iter()
+            .filter(|b| matches!(reader.feed(**b), Ok(Some(_))))
+            .count();
+        assert_eq!(received, 1);
+        assert_eq!(reader.rejected(), None);
+    }
+
+    #[test]
+    fn reader_skips_garbage() {
+        const N: usize = max_naked_len(MSG.len());
+        let frame = Writer::package(Address::new(ADDR_A), Address::new(ADDR_B), MSG).unwrap();
+
+        let mut reader = Reader::<N>::default();
+        for garbage_len in [2 * N, 2 * N + 1, 3 * N] {
+            // Garbage without markers, e.g. from a node with the wrong baudrate, is rejected once.
+            let errors = (0..garbage_len)
+                .map(|i| (i % 255) as u8 + 1)
+                .chain([COBS_MARKER])
+                .filter(|b| reader.feed(*b).is_err())
+                .count();
+            assert_eq!(errors, 1, "garbage of {} bytes", garbage_len);
+
+            // Without clearing the reader, the next frame is received again.
+            let mut received = 0;
+            for b in frame.as_slice() {
+                match reader.feed(*b) {
+                    Ok(None) => (),
+                    Ok(Some(frame)) => {
+                        assert_eq!(frame.contents, MSG);
+                        received += 1;
+                    }
+                    e => panic!("Invalid result {:?}", e),
+                }
+            }
+            assert_eq!(received, 1);
+        }
+
+        // Garbage right in front of a frame loses only that frame, not the one after it.
+        let garbage = [0x55u8; 2 * N];
+        let stream = garbage
+            .iter()
+            .chain(frame.as_slice())
+            .chain(frame.as_slice());
+        let results: Vec<_> = stream
+            .map(|b| match reader.feed(*b) {
+                Ok(Some(_)) => "ok",
+                Ok(None) => "",
+                Err(_) => "error",
+            })
+            .filter(|r| !r.is_empty())
+            .collect();
+        assert_eq!(results, ["error", "ok"]);
+    }
+
+    #[test]
+    fn frame_iter() {
+        let frame = Writer::package(Address::new(ADDR_A), Address::new(ADDR_B), MSG).unwrap();
+        let mut frame_buf = [0u8; MAX_FRAME_LEN];
+        let broken = fill_frame(&mut frame_buf);
+        let broken_len = broken.len();
+        broken[broken_len / 2] = broken[broken_len / 2].wrapping_add(1).max(1);
+
+        let stream = frame
+            .as_slice()
+            .iter()
+            .chain(&frame_buf[..broken_len])
+            .chain(frame.as_slice())
+            .chain(&frame.as_slice()[..5])
+            .copied();
+        let results: Vec<_> = iter::FrameIter::new(stream).collect();
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[0].as_ref().unwrap().contents, MSG);
+        assert!(results[1].is_err());
+        assert_eq!(
+            results[2].as_ref().unwrap().header.address_src,
+            Address::new(ADDR_A)
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn read_framer() {
+        let frame = Writer::package(Address::new(ADDR_A), Address::new(ADDR_B), MSG).unwrap();
+        let capture: Vec<u8> = [frame.as_slice(); 300].concat();
+
+        let mut framer = iter::ReadFramer::new(capture.as_slice());
+        assert_eq!(framer.by_ref().filter(|frame| frame.is_ok()).count(), 300);
+        assert!(framer.take_error().is_none());
+    }
+
     #[test]
     fn writer_reader_noise() {
         let frame = &mut [0u8; MAX_FRAME_LEN];
@@ -461,21 +2315,16 @@ mod tests {
             let mut reader = Reader::new();
             for (j, b) in frame_begin.iter().enumerate() {
                 match reader.feed(*b) {
-                    ReadResult::NotYet => (),
-                    ReadResult::FrameOK(_) => {
+                    Ok(None) => (),
+                    Ok(Some(_)) => {
                         panic!("Frame can not be OK midframe @ {} with error @ {}", j, i)
                     }
-                    ReadResult::Overflow
-                    | ReadResult::FrameErrorCobs
-                    | ReadResult::FrameErrorMagic
-                    | ReadResult::FrameErrorHeader
-                    | ReadResult::FrameErrorSize
-                    | ReadResult::FrameErrorChecksum => continue, // Test OK
+                    Err(_) => continue, // Test OK
                 }
             }
 
             match reader.feed(*frame_last) {
-                ReadResult::FrameOK(_) => panic!("Frame can not be OK with error @ {}", i),
+                Ok(Some(_)) => panic!("Frame can not be OK with error @ {}", i),
                 _ => continue, // Test OK
             };
         }
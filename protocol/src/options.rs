@@ -0,0 +1,124 @@
+//! Options carried at the start of the contents of a frame, if `Header::has_options` is set.
+//!
+//! The options block starts with its length in bytes, followed by the options. Every option consists of
+//! its kind, the length of its value and the value itself. Receivers skip options they do not know.
+
+use crate::WriteError;
+
+/// Priority of the frame, higher is more important.
+pub const PRIORITY: u8 = 1;
+/// How long the frame is relevant, in milliseconds.
+pub const TTL: u8 = 2;
+/// Position of the frame within a larger message that was split into fragments.
+pub const FRAGMENT: u8 = 3;
+/// Authentication tag over the frame.
+pub const AUTH_TAG: u8 = 4;
+/// The payload is compressed, without a value, see `compress`.
+pub const COMPRESSED: u8 = 5;
+/// The frame is a management message of the kind in its value, which nodes answer by themselves.
+pub const MANAGEMENT: u8 = 6;
+/// Acknowledges the frame of the receiver of which the sequence number is in its value, see `Header::sequence`.
+pub const ACK: u8 = 7;
+
+/// How large the options block can be at most, including its length.
+pub const MAX_OPTIONS_LEN: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TlvOption<'a> {
+    pub kind: u8,
+    pub value: &'a [u8],
+}
+
+/// The options block is not encoded correctly.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InvalidOptions;
+
+impl core::fmt::Display for InvalidOptions {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str("invalid options")
+    }
+}
+
+impl core::error::Error for InvalidOptions {}
+
+/// The options of a received frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Options<'a> {
+    options: &'a [u8],
+}
+
+impl<'a> Options<'a> {
+    pub const fn empty() -> Self {
+        Self { options: &[] }
+    }
+
+    /// Split the contents of a frame into its options and the remaining payload.
+    pub fn split(contents: &'a [u8]) -> Result<(Self, &'a [u8]), InvalidOptions> {
+        let (len, rest) = contents.split_first().ok_or(InvalidOptions)?;
+        if rest.len() < *len as usize {
+            return Err(InvalidOptions);
+        }
+
+        let (options, payload) = rest.split_at(*len as usize);
+        let options = Self { options };
+        if options.iter().any(|option| option.is_err()) {
+            return Err(InvalidOptions);
+        }
+        Ok((options, payload))
+    }
+
+    pub fn iter(&self) -> OptionsIter<'a> {
+        OptionsIter { rest: self.options }
+    }
+
+    /// The value of the first option of `kind`.
+    pub fn get(&self, kind: u8) -> Option<&'a [u8]> {
+        self.iter()
+            .filter_map(Result::ok)
+            .find(|option| option.kind == kind)
+            .map(|option| option.value)
+    }
+}
+
+pub struct OptionsIter<'a> {
+    rest: &'a [u8],
+}
+
+impl<'a> Iterator for OptionsIter<'a> {
+    type Item = Result<TlvOption<'a>, InvalidOptions>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.rest {
+            [] => None,
+            [kind, len, rest @ ..] if rest.len() >= *len as usize => {
+                let (value, rest) = rest.split_at(*len as usize);
+                self.rest = rest;
+                Some(Ok(TlvOption { kind: *kind, value }))
+            }
+            _ => {
+                self.rest = &[];
+                Some(Err(InvalidOptions))
+            }
+        }
+    }
+}
+
+/// Encode `options` into an options block in `buf`, yielding its length.
+pub(crate) fn encode<'a>(
+    options: impl IntoIterator<Item = TlvOption<'a>>,
+    buf: &mut [u8; MAX_OPTIONS_LEN],
+) -> Result<usize, WriteError> {
+    let mut len = 1;
+    for option in options {
+        let end = len + 2 + option.value.len();
+        if end > MAX_OPTIONS_LEN {
+            return Err(WriteError::TooLong);
+        }
+        buf[len] = option.kind;
+        buf[len + 1] = option.value.len() as u8;
+        buf[len + 2..end].copy_from_slice(option.value);
+        len = end;
+    }
+    buf[0] = (len - 1) as u8;
+    Ok(len)
+}
@@ -0,0 +1,171 @@
+//! Canonical test vectors, to validate other implementations of the protocol against.
+//!
+//! Every vector is identified by a name of the form `<src>-<dst>-<pattern>-<len>`,
+//! and describes a single frame with a generated payload.
+
+use core::fmt::{self, Display};
+
+use crate::{Address, Frame, WriteError, Writer, MAX_MESSAGE_LEN};
+
+/// How the payload of a test vector is generated.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Pattern {
+    /// All bytes are `0x00`, the COBS marker.
+    Zeros,
+    /// All bytes are `0xFF`.
+    Ones,
+    /// Bytes count up from `0x00`, wrapping around.
+    Incrementing,
+    /// Bytes alternate between `0x55` and `0xAA`.
+    Alternating,
+    /// Bytes are non-zero, except for every 254th byte, to hit COBS block boundaries.
+    SparseZeros,
+}
+
+impl Pattern {
+    pub const ALL: [Pattern; 5] = [
+        Pattern::Zeros,
+        Pattern::Ones,
+        Pattern::Incrementing,
+        Pattern::Alternating,
+        Pattern::SparseZeros,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Pattern::Zeros => "zeros",
+            Pattern::Ones => "ones",
+            Pattern::Incrementing => "incrementing",
+            Pattern::Alternating => "alternating",
+            Pattern::SparseZeros => "sparse-zeros",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|p| p.name() == name)
+    }
+
+    pub fn byte(&self, i: usize) -> u8 {
+        match self {
+            Pattern::Zeros => 0x00,
+            Pattern::Ones => 0xFF,
+            Pattern::Incrementing => i as u8,
+            Pattern::Alternating if i.is_multiple_of(2) => 0x55,
+            Pattern::Alternating => 0xAA,
+            Pattern::SparseZeros if i % 254 == 253 => 0x00,
+            Pattern::SparseZeros => (i % 254) as u8 + 1,
+        }
+    }
+}
+
+/// Address pairs used in the test vector matrix.
+pub const ADDRESSES: [(u32, u32); 4] = [
+    (0x00000000, 0x00000001),
+    (0x00000001, 0xFFFFFFFF),
+    (0x12345678, 0x9ABCDEF0),
+    (0xFFFFFFFE, 0x00000000),
+];
+
+/// Payload lengths used in the test vector matrix, chosen around COBS block boundaries.
+pub const LENGTHS: [usize; 8] = [0, 1, 2, 253, 254, 255, 508, MAX_MESSAGE_LEN];
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct TestVector {
+    pub src: Address,
+    pub dst: Address,
+    pub pattern: Pattern,
+    pub len: usize,
+}
+
+impl TestVector {
+    /// Fill `buf` with the payload of this vector, returning the payload.
+    pub fn payload<'a>(&self, buf: &'a mut [u8; MAX_MESSAGE_LEN]) -> &'a [u8] {
+        let payload = &mut buf[..self.len.min(MAX_MESSAGE_LEN)];
+        for (i, b) in payload.iter_mut().enumerate() {
+            *b = self.pattern.byte(i);
+        }
+        payload
+    }
+
+    /// The canonical encoding of this vector.
+    pub fn encode(&self) -> Result<Frame, WriteError> {
+        let mut buf = [0u8; MAX_MESSAGE_LEN];
+        Writer::package(self.src, self.dst, self.payload(&mut buf))
+    }
+
+    /// Parse a vector from its name, the inverse of its `Display` implementation.
+    pub fn from_name(name: &str) -> Option<Self> {
+        let (src, rest) = name.split_once('-')?;
+        let (dst, rest) = rest.split_once('-')?;
+        let (pattern, len) = rest.rsplit_once('-')?;
+        Some(TestVector {
+            src: Address::from_hex_str(src).ok()?,
+            dst: Address::from_hex_str(dst).ok()?,
+            pattern: Pattern::from_name(pattern)?,
+            len: len.parse().ok().filter(|len| *len <= MAX_MESSAGE_LEN)?,
+        })
+    }
+
+    /// Check whether `encoded` is exactly the canonical encoding of this vector.
+    pub fn check_encoded(&self, encoded: &[u8]) -> Result<(), Mismatch> {
+        let frame = self.encode().map_err(|_| Mismatch::Unencodable)?;
+        let expected = frame.as_slice();
+        if encoded.len() != expected.len() {
+            return Err(Mismatch::Length {
+                expected: expected.len(),
+                actual: encoded.len(),
+            });
+        }
+        match expected.iter().zip(encoded).position(|(a, b)| a != b) {
+            Some(offset) => Err(Mismatch::Byte {
+                offset,
+                expected: expected[offset],
+                actual: encoded[offset],
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Display for TestVector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}-{}-{}-{}",
+            self.src,
+            self.dst,
+            self.pattern.name(),
+            self.len
+        )
+    }
+}
+
+/// How an encoded frame differs from the canonical encoding.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Mismatch {
+    /// The vector itself can not be encoded.
+    Unencodable,
+    /// The encoded frame has the wrong length.
+    Length { expected: usize, actual: usize },
+    /// The encoded frame differs at `offset`.
+    Byte {
+        offset: usize,
+        expected: u8,
+        actual: u8,
+    },
+}
+
+/// The complete matrix of test vectors.
+pub fn vectors() -> impl Iterator<Item = TestVector> {
+    ADDRESSES.iter().flat_map(|(src, dst)| {
+        Pattern::ALL.iter().flat_map(move |pattern| {
+            LENGTHS.iter().map(move |len| TestVector {
+                src: Address::new(*src),
+                dst: Address::new(*dst),
+                pattern: *pattern,
+                len: *len,
+            })
+        })
+    })
+}
@@ -0,0 +1,159 @@
+#![no_std]
+
+//! Publish/subscribe layer on top of multicast frames.
+//!
+//! The contents of a publication start with a 16-bit big endian topic, followed by the message.
+//! Nodes subscribe handlers to the topics they are interested in, and hand all received frames to
+//! `Subscriptions::dispatch`, which ignores any frame that is not a publication.
+
+use kiri_protocol::{Address, Frame, FrameRef, WriteError, Writer};
+
+pub type Topic = u16;
+
+/// How much bytes of the contents the topic uses up.
+pub const TOPIC_LEN: usize = 2;
+
+/// A message published on a topic.
+#[derive(Debug, PartialEq)]
+pub struct Publication<'a> {
+    pub src: Address,
+    pub topic: Topic,
+    pub message: &'a [u8],
+}
+
+impl<'a> Publication<'a> {
+    /// Interpret a frame as publication, if it is sent to multicast and carries a topic.
+    pub fn parse(frame: &FrameRef<'a>) -> Option<Self> {
+        if !frame.header.address_dst.is_multicast() || frame.contents.len() < TOPIC_LEN {
+            return None;
+        }
+
+        let (topic, message) = frame.contents.split_at(TOPIC_LEN);
+        Some(Self {
+            src: frame.header.address_src,
+            topic: Topic::from_be_bytes([topic[0], topic[1]]),
+            message,
+        })
+    }
+}
+
+/// Package a message on `topic` into a multicast frame.
+pub fn publish(src: Address, topic: Topic, message: &[u8]) -> Result<Frame, WriteError> {
+    Writer::package_vectored(src, Address::multicast(), &[&topic.to_be_bytes(), message])
+}
+
+/// Result of `Subscriptions::dispatch`.
+#[derive(Debug, PartialEq)]
+pub enum Dispatch {
+    /// The publication was handed to the handler of its topic.
+    Handled,
+    /// The frame is a publication, but nobody is subscribed to its topic.
+    NotSubscribed,
+    /// The frame is not a publication.
+    NotPublication,
+}
+
+/// Handlers for at most `S` topics.
+pub struct Subscriptions<'a, const S: usize> {
+    handlers: heapless::LinearMap<Topic, &'a mut dyn FnMut(&Publication), S>,
+}
+
+impl<'a, const S: usize> Subscriptions<'a, S> {
+    pub fn new() -> Self {
+        Self {
+            handlers: heapless::LinearMap::new(),
+        }
+    }
+
+    /// Call `handler` for every publication on `topic`, replacing any previous handler.
+    ///
+    /// Yields the handler back if there are already `S` topics subscribed to.
+    pub fn subscribe(
+        &mut self,
+        topic: Topic,
+        handler: &'a mut dyn FnMut(&Publication),
+    ) -> Result<(), &'a mut dyn FnMut(&Publication)> {
+        self.handlers
+            .insert(topic, handler)
+            .map(|_| ())
+            .map_err(|(_, handler)| handler)
+    }
+
+    pub fn unsubscribe(&mut self, topic: Topic) {
+        self.handlers.remove(&topic);
+    }
+
+    pub fn is_subscribed(&self, topic: Topic) -> bool {
+        self.handlers.contains_key(&topic)
+    }
+
+    /// Hand a received frame to the handler of its topic, if it is a publication.
+    pub fn dispatch(&mut self, frame: &FrameRef) -> Dispatch {
+        let publication = match Publication::parse(frame) {
+            Some(publication) => publication,
+            None => return Dispatch::NotPublication,
+        };
+
+        match self.handlers.get_mut(&publication.topic) {
+            Some(handler) => {
+                handler(&publication);
+                Dispatch::Handled
+            }
+            None => Dispatch::NotSubscribed,
+        }
+    }
+}
+
+impl<const S: usize> Default for Subscriptions<'_, S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use kiri_protocol::Reader;
+
+    use super::*;
+
+    fn dispatch<const S: usize>(subscriptions: &mut Subscriptions<S>, frame: &Frame) -> Dispatch {
+        let mut reader = Reader::new();
+        for b in frame.as_slice() {
+            if let Ok(Some(frame)) = reader.feed(*b) {
+                return subscriptions.dispatch(&frame);
+            }
+        }
+        panic!("Frame not received");
+    }
+
+    #[test]
+    fn dispatch_publications() {
+        let mut received = 0;
+        let mut handler = |publication: &Publication| {
+            assert_eq!(publication.src, Address::new(1));
+            assert_eq!(publication.message, b"21.5");
+            received += 1;
+        };
+
+        let mut subscriptions = Subscriptions::<2>::new();
+        subscriptions.subscribe(0x0102, &mut handler).ok().unwrap();
+
+        let frame = publish(Address::new(1), 0x0102, b"21.5").unwrap();
+        assert_eq!(dispatch(&mut subscriptions, &frame), Dispatch::Handled);
+
+        let frame = publish(Address::new(1), 0x0103, b"21.5").unwrap();
+        assert_eq!(
+            dispatch(&mut subscriptions, &frame),
+            Dispatch::NotSubscribed
+        );
+
+        let frame = Writer::package(Address::new(1), Address::new(2), b"\x01\x0221.5").unwrap();
+        assert_eq!(
+            dispatch(&mut subscriptions, &frame),
+            Dispatch::NotPublication
+        );
+
+        drop(subscriptions);
+        assert_eq!(received, 1);
+    }
+}
@@ -0,0 +1,163 @@
+//! Python bindings to encode and decode frames, and to talk to a bus through a serial port.
+//!
+//! Build and install the `kiri` module with `maturin develop`, after which scripts can do:
+//!
+//! ```python
+//! import kiri
+//!
+//! bus = kiri.Bus("/dev/ttyUSB0", baud=115200)
+//! bus.send(0x00000001, 0x00000002, b"ping")
+//! frame = bus.receive(timeout=1.0)
+//! ```
+
+use std::{
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use kiri_csma::{CsmaFrameInProgress, CsmaStrategy, SendReceiveResult, SystemClock};
+use kiri_host::{
+    serial::SerialPort,
+    transceiver::{HostConfig, SerialPortTransceiver},
+};
+use kiri_protocol::{iter::FrameIter, Address, FrameRef, Writer};
+use pyo3::{
+    exceptions::{PyIOError, PyTimeoutError, PyValueError},
+    prelude::*,
+};
+
+type HostStrategy = CsmaStrategy<SerialPortTransceiver, SystemClock, rand::rngs::ThreadRng>;
+
+/// How long to wait between polls of the bus while receiving, to not spin a whole core.
+const POLL_INTERVAL: Duration = Duration::from_micros(100);
+
+/// A decoded frame.
+#[pyclass(get_all, frozen)]
+#[derive(Debug, Clone)]
+struct Frame {
+    src: u32,
+    dst: u32,
+    hop_limit: u8,
+    sequence: u8,
+    contents: Vec<u8>,
+}
+
+#[pymethods]
+impl Frame {
+    fn __repr__(&self) -> String {
+        format!(
+            "Frame(src={}, dst={}, contents={})",
+            Address::new(self.src),
+            Address::new(self.dst),
+            hex::encode(&self.contents)
+        )
+    }
+}
+
+impl From<FrameRef<'_>> for Frame {
+    fn from(frame: FrameRef<'_>) -> Self {
+        Self {
+            src: frame.header.address_src.to_primitive(),
+            dst: frame.header.address_dst.to_primitive(),
+            hop_limit: frame.hop_limit(),
+            sequence: frame.sequence(),
+            contents: frame.contents.to_vec(),
+        }
+    }
+}
+
+/// Package `contents` into an encoded frame, including the COBS marker.
+#[pyfunction]
+fn encode(src: u32, dst: u32, contents: &[u8]) -> PyResult<Vec<u8>> {
+    Writer::package(Address::new(src), Address::new(dst), contents)
+        .map(|frame| frame.as_slice().to_vec())
+        .map_err(|e| PyValueError::new_err(e.to_string()))
+}
+
+/// Decode all frames in `data`, raising `ValueError` for the first broken one.
+///
+/// Bytes of an incomplete frame at the end are ignored.
+#[pyfunction]
+fn decode(data: &[u8]) -> PyResult<Vec<Frame>> {
+    FrameIter::new(data.iter().copied())
+        .map(|result| match result {
+            Ok(frame) => Ok(Frame::from(FrameRef::from(&frame))),
+            Err(e) => Err(PyValueError::new_err(format!("broken frame: {}", e))),
+        })
+        .collect()
+}
+
+/// A serial port attached to the bus, which takes part in collision detection like any other node.
+#[pyclass(unsendable)]
+struct Bus {
+    strategy: HostStrategy,
+}
+
+#[pymethods]
+impl Bus {
+    #[new]
+    #[pyo3(signature = (port, baud = 115200))]
+    fn new(port: &str, baud: u32) -> PyResult<Self> {
+        let serial = SerialPort::open(port, baud, 0)?;
+        let idle = SerialPortTransceiver::idle_duration(baud, Duration::from_millis(2));
+        let transceiver = SerialPortTransceiver::new(serial, idle)?;
+        Ok(Self {
+            strategy: HostStrategy::new::<HostConfig>(transceiver, SystemClock, rand::thread_rng()),
+        })
+    }
+
+    /// Send a frame, raising `TimeoutError` if it could not be sent within `timeout` seconds.
+    ///
+    /// Frames that are received in the meantime are dropped.
+    #[pyo3(signature = (src, dst, contents, timeout = 1.0))]
+    fn send(
+        &mut self,
+        py: Python<'_>,
+        src: u32,
+        dst: u32,
+        contents: &[u8],
+        timeout: f64,
+    ) -> PyResult<()> {
+        let frame = Writer::package(Address::new(src), Address::new(dst), contents)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        let mut frame = CsmaFrameInProgress::new(frame);
+        let deadline = Instant::now() + Duration::from_secs_f64(timeout);
+
+        loop {
+            match self.strategy.send_or_receive_before(&mut frame, deadline) {
+                Ok(SendReceiveResult::SendComplete) => return Ok(()),
+                Ok(SendReceiveResult::Expired) => {
+                    return Err(PyTimeoutError::new_err("frame could not be sent in time"))
+                }
+                Ok(SendReceiveResult::Received(_)) | Err(nb::Error::WouldBlock) => (),
+                Err(nb::Error::Other(e)) => return Err(PyIOError::new_err(e.to_string())),
+            }
+            // Allow scripts to be interrupted.
+            py.check_signals()?;
+        }
+    }
+
+    /// Receive the next frame, or `None` if nothing arrived within `timeout` seconds.
+    #[pyo3(signature = (timeout = 1.0))]
+    fn receive(&mut self, py: Python<'_>, timeout: f64) -> PyResult<Option<Frame>> {
+        let deadline = Instant::now() + Duration::from_secs_f64(timeout);
+        while Instant::now() < deadline {
+            match self.strategy.receive() {
+                Ok(frame) => return Ok(Some(frame.into())),
+                Err(nb::Error::WouldBlock) => sleep(POLL_INTERVAL),
+                Err(nb::Error::Other(e)) => return Err(PyIOError::new_err(e.to_string())),
+            }
+            py.check_signals()?;
+        }
+        Ok(None)
+    }
+}
+
+#[pymodule]
+fn kiri(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Frame>()?;
+    m.add_class::<Bus>()?;
+    m.add_function(wrap_pyfunction!(encode, m)?)?;
+    m.add_function(wrap_pyfunction!(decode, m)?)?;
+    Ok(())
+}
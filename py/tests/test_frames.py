@@ -0,0 +1,26 @@
+import pytest
+
+import kiri
+
+
+def test_roundtrip():
+    encoded = kiri.encode(0x0F004242, 0x00012003, b"\0loremipsum\0")
+    assert encoded[-1] == 0
+
+    (frame,) = kiri.decode(encoded)
+    assert frame.src == 0x0F004242
+    assert frame.dst == 0x00012003
+    assert frame.contents == b"\0loremipsum\0"
+
+
+def test_broken_frame():
+    encoded = bytearray(kiri.encode(1, 2, b"hello"))
+    encoded[5] ^= 0xFF
+    with pytest.raises(ValueError):
+        kiri.decode(bytes(encoded))
+
+
+def test_incomplete_frame_is_ignored():
+    encoded = kiri.encode(1, 2, b"hello")
+    (frame,) = kiri.decode(encoded + encoded[:5])
+    assert frame.contents == b"hello"
@@ -0,0 +1,462 @@
+#![no_std]
+
+//! Store-and-forward router, connecting multiple bus segments.
+//!
+//! Every port of the router is a `CsmaStrategy` on its own bus segment. Frames received on a port
+//! are forwarded to the port its destination is routed to, or to all other ports for group addresses, see
+//! `AddressClass::is_group`.
+//! Frames are only forwarded while their hop limit allows, which prevents them from circulating forever
+//! if the segments are connected in a loop. Frames have to be packaged with a hop limit, see
+//! `FrameBuilder::hop_limit`, to be forwarded at all. All segments are bus `BUS`, see `kiri_protocol::magic_word`.
+
+use kiri_csma::{
+    backoff::UniformBackoff, Clock, CsmaFrameInProgress, CsmaStrategy, SendReceiveResult,
+    Transceiver,
+};
+use kiri_protocol::{Address, Frame, FrameRef, Writer, MAX_FRAME_LEN};
+use rand::RngCore;
+
+/// All destinations within `first..=last` are reachable through `port`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Route {
+    pub first: Address,
+    pub last: Address,
+    pub port: usize,
+}
+
+impl Route {
+    pub fn contains(&self, address: Address) -> bool {
+        (self.first.to_primitive()..=self.last.to_primitive()).contains(&address.to_primitive())
+    }
+}
+
+/// Static routing table of at most `R` routes, in which the first matching route wins.
+#[derive(Debug, Default)]
+pub struct RoutingTable<const R: usize> {
+    routes: heapless::Vec<Route, R>,
+}
+
+impl<const R: usize> RoutingTable<R> {
+    pub fn new() -> Self {
+        Self {
+            routes: heapless::Vec::new(),
+        }
+    }
+
+    /// Add a route, yielding it back if the table is full.
+    pub fn add(&mut self, route: Route) -> Result<(), Route> {
+        self.routes.push(route)
+    }
+
+    pub fn lookup(&self, address: Address) -> Option<usize> {
+        self.routes
+            .iter()
+            .find(|route| route.contains(address))
+            .map(|route| route.port)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct RouterStats {
+    pub forwarded: u64,
+    /// Frames for which the destination is on the segment they were received on.
+    pub local: u64,
+    pub dropped_no_route: u64,
+    pub dropped_hop_limit: u64,
+    pub dropped_queue_full: u64,
+}
+
+/// Error on one of the ports of the router.
+#[derive(Debug)]
+pub struct PortError<E> {
+    pub port: usize,
+    pub error: E,
+}
+
+/// A received frame, as far as the router is concerned, such that it need not be copied.
+struct Incoming {
+    dst: Address,
+    /// The frame to forward, or `None` if its hop limit does not allow that.
+    forwarded: Option<Frame>,
+}
+
+impl Incoming {
+    fn new(frame: &FrameRef, bus: u8) -> Self {
+        Self {
+            dst: frame.header.address_dst,
+            forwarded: Writer::forward(frame, bus).ok().flatten(),
+        }
+    }
+}
+
+/// The strategy of a port on bus `BUS`, which is a `CsmaStrategy` with its defaults otherwise.
+pub type PortStrategy<T, C, R, const BUS: u8 = 0> =
+    CsmaStrategy<T, C, R, MAX_FRAME_LEN, 8, ((), UniformBackoff), 0, BUS>;
+
+struct Port<T: Transceiver, C: Clock, R: RngCore, const Q: usize, const BUS: u8> {
+    strategy: PortStrategy<T, C, R, BUS>,
+    queue: heapless::Deque<Frame, Q>,
+    current: Option<CsmaFrameInProgress>,
+}
+
+/// Router with `P` ports, `R` routes and a queue of `Q` frames per port.
+pub struct Router<
+    T: Transceiver,
+    C: Clock,
+    RNG: RngCore,
+    const P: usize,
+    const R: usize,
+    const Q: usize,
+    const BUS: u8 = 0,
+> {
+    ports: [Port<T, C, RNG, Q, BUS>; P],
+    table: RoutingTable<R>,
+    stats: RouterStats,
+}
+
+impl<
+        T: Transceiver,
+        C: Clock,
+        RNG: RngCore,
+        const P: usize,
+        const R: usize,
+        const Q: usize,
+        const BUS: u8,
+    > Router<T, C, RNG, P, R, Q, BUS>
+{
+    pub fn new(strategies: [PortStrategy<T, C, RNG, BUS>; P], table: RoutingTable<R>) -> Self {
+        Self {
+            ports: strategies.map(|strategy| Port {
+                strategy,
+                queue: heapless::Deque::new(),
+                current: None,
+            }),
+            table,
+            stats: RouterStats::default(),
+        }
+    }
+
+    pub fn stats(&self) -> &RouterStats {
+        &self.stats
+    }
+
+    /// Amount of frames waiting to be sent on `port`, including the one currently being sent.
+    pub fn queue_len(&self, port: usize) -> usize {
+        let port = &self.ports[port];
+        port.queue.len() + port.current.is_some() as usize
+    }
+
+    /// When polling is needed next at the latest, see `CsmaStrategy::next_poll_at`.
+    ///
+    /// Yields `None` if nothing happens until a transceiver signals activity.
+    pub fn next_poll_at(&self) -> Option<C::Instant> {
+        self.ports
+            .iter()
+            .filter_map(|port| match port.current {
+                // The next frame is picked up on the next poll.
+                None if !port.queue.is_empty() => Some(port.strategy.now()),
+                _ => port.strategy.next_poll_at(),
+            })
+            .reduce(|a, b| if b < a { b } else { a })
+    }
+
+    /// Poll all ports once, forwarding any frames that were completely received.
+    ///
+    /// Keep calling this function; it never blocks.
+    pub fn poll(&mut self) -> Result<(), PortError<T::Error>> {
+        for i in 0..P {
+            let port = &mut self.ports[i];
+            if port.current.is_none() {
+                port.current = port.queue.pop_front().map(CsmaFrameInProgress::new);
+            }
+
+            let received = match port.current.as_mut() {
+                Some(frame) => match port.strategy.send_or_receive(frame) {
+                    Ok(SendReceiveResult::SendComplete | SendReceiveResult::Expired) => {
+                        port.current = None;
+                        None
+                    }
+                    Ok(SendReceiveResult::Received(frame)) => Some(Incoming::new(&frame, BUS)),
+                    Err(nb::Error::WouldBlock) => None,
+                    Err(nb::Error::Other(error)) => return Err(PortError { port: i, error }),
+                },
+                None => match port.strategy.receive() {
+                    Ok(frame) => Some(Incoming::new(&frame, BUS)),
+                    Err(nb::Error::WouldBlock) => None,
+                    Err(nb::Error::Other(error)) => return Err(PortError { port: i, error }),
+                },
+            };
+
+            if let Some(incoming) = received {
+                self.route(i, incoming);
+            }
+        }
+        Ok(())
+    }
+
+    fn route(&mut self, ingress: usize, incoming: Incoming) {
+        let dst = incoming.dst;
+        // Groups are flooded, including management services as any node may provide those.
+        let egress = if dst.is_group() {
+            None
+        } else {
+            match self.table.lookup(dst) {
+                Some(port) if port == ingress => {
+                    self.stats.local += 1;
+                    return;
+                }
+                Some(port) if port < P => Some(port),
+                _ => {
+                    self.stats.dropped_no_route += 1;
+                    return;
+                }
+            }
+        };
+
+        let forwarded = match incoming.forwarded {
+            Some(forwarded) => forwarded,
+            None => {
+                self.stats.dropped_hop_limit += 1;
+                return;
+            }
+        };
+
+        for (i, port) in self.ports.iter_mut().enumerate() {
+            if i == ingress || egress.is_some_and(|egress| egress != i) {
+                continue;
+            }
+
+            match port.queue.push_back(forwarded.clone()) {
+                Ok(()) => self.stats.forwarded += 1,
+                Err(_) => self.stats.dropped_queue_full += 1,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate alloc;
+
+    use alloc::{collections::VecDeque, rc::Rc, vec::Vec};
+    use core::cell::{Cell, RefCell};
+
+    use kiri_csma::{Config, ReadError};
+    use kiri_protocol::{FrameBuilder, FrameOwned, Reader};
+    use rand::rngs::mock::StepRng;
+
+    use super::*;
+
+    struct TestClock(Cell<u64>);
+
+    impl Clock for &TestClock {
+        type Instant = u64;
+        type Duration = u64;
+
+        fn now(&self) -> u64 {
+            self.0.get()
+        }
+    }
+
+    struct TestConfig;
+
+    impl Config<&TestClock> for TestConfig {
+        const BUS_MIN_IDLE_DURATION: u64 = 1;
+        const BUS_MAX_IDLE_DURATION: u64 = 5;
+        const ECHO_BYTE_TIMEOUT: u64 = 10;
+        const ECHO_FRAME_TIMEOUT: u64 = 1000;
+    }
+
+    #[derive(Default)]
+    struct WireState {
+        /// Bytes on the bus, which are those of other nodes and those we sent.
+        bus: VecDeque<u8>,
+        /// Every byte we sent.
+        sent: Vec<u8>,
+        /// Keep the bus busy, such that nothing is ever sent.
+        busy: bool,
+    }
+
+    /// Bus segment of a port that loops back what the router sends, and of which the test puts frames of others
+    /// on the bus.
+    #[derive(Clone, Default)]
+    struct Wire(Rc<RefCell<WireState>>);
+
+    impl Wire {
+        fn put(&self, frame: &Frame) {
+            self.0.borrow_mut().bus.extend(frame.as_slice());
+        }
+
+        /// The frames the router sent on this segment.
+        fn sent(&self) -> Vec<FrameOwned> {
+            let mut reader = Reader::new();
+            self.0
+                .borrow()
+                .sent
+                .iter()
+                .filter_map(|b| match reader.feed(*b) {
+                    Ok(Some(frame)) => frame.try_into().ok(),
+                    _ => None,
+                })
+                .collect()
+        }
+    }
+
+    impl Transceiver for Wire {
+        type Error = ();
+
+        fn handle_interrupts(&self) {}
+
+        fn bus_is_idle(&self) -> bool {
+            let state = self.0.borrow();
+            !state.busy && state.bus.is_empty()
+        }
+
+        fn write(&mut self, byte: u8) -> nb::Result<(), Self::Error> {
+            let mut state = self.0.borrow_mut();
+            state.bus.push_back(byte);
+            state.sent.push(byte);
+            Ok(())
+        }
+
+        fn read(&mut self) -> nb::Result<u8, ReadError<Self::Error>> {
+            self.0
+                .borrow_mut()
+                .bus
+                .pop_front()
+                .ok_or(nb::Error::WouldBlock)
+        }
+    }
+
+    type TestRouter<'a, const Q: usize> = Router<Wire, &'a TestClock, StepRng, 3, 4, Q>;
+
+    /// A router of which port `i` reaches addresses `10 * i` up to `10 * i + 9`.
+    fn router<const Q: usize>(clock: &TestClock) -> (TestRouter<'_, Q>, [Wire; 3]) {
+        let wires: [Wire; 3] = Default::default();
+        let mut table = RoutingTable::new();
+        for port in 0..3 {
+            let first = Address::new(10 * port as u32);
+            let last = Address::new(10 * port as u32 + 9);
+            table.add(Route { first, last, port }).unwrap();
+        }
+        let strategies = wires
+            .clone()
+            .map(|wire| CsmaStrategy::new::<TestConfig>(wire, clock, StepRng::new(0, 1)));
+        (Router::new(strategies, table), wires)
+    }
+
+    fn run<const Q: usize>(router: &mut TestRouter<'_, Q>, clock: &TestClock) {
+        for _ in 0..1000 {
+            router.poll().unwrap();
+            clock.0.set(clock.0.get() + 1);
+        }
+    }
+
+    fn frame(src: u32, dst: Address, hop_limit: u8) -> Frame {
+        FrameBuilder::new(Address::new(src), dst)
+            .hop_limit(hop_limit)
+            .payload(b"routed")
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn route_unicast() {
+        let clock = TestClock(Cell::new(0));
+        let (mut router, wires) = router::<4>(&clock);
+
+        wires[0].put(&frame(1, Address::new(15), 2));
+        wires[0].put(&frame(1, Address::new(5), 2));
+        wires[0].put(&frame(1, Address::new(99), 2));
+        run(&mut router, &clock);
+
+        let sent = wires[1].sent();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].header.address_dst, Address::new(15));
+        assert_eq!(FrameRef::from(&sent[0]).hop_limit(), 1);
+        assert_eq!(sent[0].contents, b"routed");
+        assert!(wires[0].sent().is_empty());
+        assert!(wires[2].sent().is_empty());
+
+        let stats = router.stats();
+        assert_eq!(
+            (stats.forwarded, stats.local, stats.dropped_no_route),
+            (1, 1, 1)
+        );
+    }
+
+    #[test]
+    fn hop_limit_exhausted() {
+        let clock = TestClock(Cell::new(0));
+        let (mut router, wires) = router::<4>(&clock);
+
+        wires[0].put(&frame(1, Address::new(15), 0));
+        wires[0].put(&frame(1, Address::broadcast(), 0));
+        run(&mut router, &clock);
+
+        assert!(wires.iter().all(|wire| wire.sent().is_empty()));
+        assert_eq!(router.stats().dropped_hop_limit, 2);
+        assert_eq!(router.stats().forwarded, 0);
+    }
+
+    #[test]
+    fn flood_multicast() {
+        let clock = TestClock(Cell::new(0));
+        let (mut router, wires) = router::<4>(&clock);
+
+        wires[0].put(&frame(1, Address::broadcast(), 1));
+        run(&mut router, &clock);
+
+        // The frame is not echoed back onto the segment it came from.
+        assert!(wires[0].sent().is_empty());
+        for wire in &wires[1..] {
+            let sent = wire.sent();
+            assert_eq!(sent.len(), 1);
+            assert_eq!(sent[0].header.address_src, Address::new(1));
+            assert_eq!(FrameRef::from(&sent[0]).hop_limit(), 0);
+        }
+        assert_eq!(router.stats().forwarded, 2);
+    }
+
+    #[test]
+    fn flood_groups_and_management() {
+        let clock = TestClock(Cell::new(0));
+        let (mut router, wires) = router::<4>(&clock);
+
+        let management = Address::management(1).unwrap();
+        wires[0].put(&frame(1, Address::group(7), 1));
+        wires[0].put(&frame(1, management, 1));
+        run(&mut router, &clock);
+
+        for wire in &wires[1..] {
+            let sent: Vec<_> = wire.sent().iter().map(|f| f.header.address_dst).collect();
+            assert_eq!(sent, [Address::group(7), management]);
+        }
+        assert_eq!(router.stats().forwarded, 4);
+        assert_eq!(router.stats().dropped_no_route, 0);
+    }
+
+    #[test]
+    fn queue_full() {
+        let clock = TestClock(Cell::new(0));
+        let (mut router, wires) = router::<1>(&clock);
+        wires[1].0.borrow_mut().busy = true;
+
+        for _ in 0..3 {
+            wires[0].put(&frame(1, Address::new(15), 1));
+        }
+        run(&mut router, &clock);
+
+        // One frame is being sent, one waits in the queue, and the last one did not fit.
+        assert_eq!(router.queue_len(1), 2);
+        assert_eq!(router.stats().forwarded, 2);
+        assert_eq!(router.stats().dropped_queue_full, 1);
+        assert!(wires[1].sent().is_empty());
+
+        // Once the segment is idle, the frames that were queued are sent.
+        wires[1].0.borrow_mut().busy = false;
+        run(&mut router, &clock);
+        assert_eq!(wires[1].sent().len(), 2);
+        assert_eq!(router.queue_len(1), 0);
+    }
+}
@@ -0,0 +1,184 @@
+//! A node on an STM32G474 that reports how many frames it received to a gateway, once every second.
+//!
+//! The USART interrupt feeds every incoming byte to the receiving half of the strategy, which confirms the frames
+//! being sent as well. A software task hands frames to the sending half and pumps it until they are sent.
+//!
+//! USART1 runs at 115200 baud from the 16 MHz HSI, on PA9 (TX), PA10 (RX) and PA12 (DE).
+
+#![no_std]
+#![no_main]
+
+use kiri_csma::{
+    backoff::UniformBackoff,
+    split::{CsmaCore, CsmaReceiver, CsmaSender},
+    Config, CsmaStrategy,
+};
+use kiri_protocol::MAX_FRAME_LEN;
+use kiri_rtic::{InterruptFree, MonotonicClock, MonotonicTicks};
+use kiri_targets::stm32::Stm32Usart;
+use panic_halt as _;
+use rand::rngs::SmallRng;
+use rtic_monotonics::systick::prelude::*;
+
+const TICK_HZ: u32 = 100_000;
+
+systick_monotonic!(Mono, TICK_HZ);
+
+type NodeClock = MonotonicClock<Mono, TICK_HZ>;
+type TickDuration = fugit::Duration<u64, 1, TICK_HZ>;
+
+/// Timing of a bus at 115200 baud, on which a character takes 87 µs.
+struct NodeConfig;
+
+impl Config<NodeClock> for NodeConfig {
+    const BUS_MIN_IDLE_DURATION: u64 = NodeClock::micros(300);
+    const BUS_MAX_IDLE_DURATION: u64 = NodeClock::millis(2);
+    const ECHO_BYTE_TIMEOUT: u64 = NodeClock::millis(1);
+    const ECHO_FRAME_TIMEOUT: u64 = NodeClock::millis(50);
+}
+
+type Strategy = CsmaStrategy<Stm32Usart, NodeClock, SmallRng>;
+type Core = CsmaCore<
+    InterruptFree,
+    Stm32Usart,
+    NodeClock,
+    SmallRng,
+    MAX_FRAME_LEN,
+    8,
+    ((), UniformBackoff),
+>;
+type Sender = CsmaSender<
+    'static,
+    InterruptFree,
+    Stm32Usart,
+    NodeClock,
+    SmallRng,
+    MAX_FRAME_LEN,
+    8,
+    ((), UniformBackoff),
+>;
+type Receiver = CsmaReceiver<
+    'static,
+    InterruptFree,
+    Stm32Usart,
+    NodeClock,
+    SmallRng,
+    MAX_FRAME_LEN,
+    8,
+    ((), UniformBackoff),
+>;
+
+#[rtic::app(device = stm32g4::stm32g474, dispatchers = [SPI1])]
+mod app {
+    use kiri_csma::ReceiveError;
+    use kiri_protocol::{Address, Writer};
+    use rand::SeedableRng;
+
+    use super::*;
+
+    const ADDRESS: u32 = 0x10;
+    const GATEWAY: u32 = 0x01;
+
+    /// Base address of the 96-bit unique device ID, which seeds the backoff of each node differently.
+    const UID_BASE: usize = 0x1FFF_7590;
+
+    /// How long to wait between polls of the sender, which is shorter than a character such that the
+    /// transmitter does not go idle in the middle of a frame.
+    const POLL_INTERVAL: TickDuration = TickDuration::micros(50);
+    const REPORT_INTERVAL: TickDuration = TickDuration::secs(1);
+
+    #[shared]
+    struct Shared {
+        received: u32,
+    }
+
+    #[local]
+    struct Local {
+        sender: Sender,
+        receiver: Receiver,
+    }
+
+    #[init(local = [core: Option<Core> = None])]
+    fn init(cx: init::Context) -> (Shared, Local) {
+        Mono::start(cx.core.SYST, 16_000_000);
+
+        let dp = cx.device;
+        dp.RCC.ahb2enr.modify(|_, w| w.gpioaen().set_bit());
+        dp.RCC.apb2enr.modify(|_, w| w.usart1en().set_bit());
+        dp.GPIOA
+            .afrh
+            .modify(|_, w| w.afrh9().af7().afrh10().af7().afrh12().af7());
+        dp.GPIOA.moder.modify(|_, w| {
+            w.moder9()
+                .alternate()
+                .moder10()
+                .alternate()
+                .moder12()
+                .alternate()
+        });
+
+        // Safety: USART1 is clocked by the HSI through PCLK2, and not used otherwise.
+        let transceiver = unsafe {
+            Stm32Usart::new(
+                stm32g4::stm32g474::USART1::ptr() as usize,
+                16_000_000,
+                115_200,
+                8,
+            )
+        };
+        // Safety: the unique device ID is always readable.
+        let seed = unsafe { core::ptr::read_volatile(UID_BASE as *const u64) };
+        let strategy = Strategy::new::<NodeConfig>(
+            transceiver,
+            NodeClock::new(MonotonicTicks::new()),
+            SmallRng::seed_from_u64(seed),
+        );
+
+        let core = cx.local.core.insert(Core::new(strategy));
+        let (sender, receiver) = core.split();
+
+        report::spawn().ok();
+        (Shared { received: 0 }, Local { sender, receiver })
+    }
+
+    /// Handle the byte that was received, which is either part of an incoming frame or the echo of our own.
+    #[task(binds = USART1, local = [receiver], shared = [received], priority = 2)]
+    fn usart1(mut cx: usart1::Context) {
+        match cx
+            .local
+            .receiver
+            .receive_with(|frame| frame.header.address_dst == Address::new(ADDRESS))
+        {
+            Ok(true) => cx.shared.received.lock(|received| *received += 1),
+            Ok(false) | Err(nb::Error::WouldBlock) => (),
+            Err(nb::Error::Other(ReceiveError::Dropped(_))) => (),
+            Err(nb::Error::Other(ReceiveError::UnderlyingError(e))) => match e {},
+        }
+    }
+
+    /// Send the number of frames received to the gateway, as soon as the bus allows it.
+    #[task(local = [sender], shared = [received], priority = 1)]
+    async fn report(mut cx: report::Context) {
+        loop {
+            let received = cx.shared.received.lock(|received| *received);
+            if let Ok(frame) = Writer::package(
+                Address::new(ADDRESS),
+                Address::new(GATEWAY),
+                &received.to_le_bytes(),
+            ) {
+                // Can not fail, as the previous frame was sent completely.
+                cx.local.sender.send(frame).ok();
+            }
+
+            loop {
+                match cx.local.sender.poll() {
+                    Ok(()) => break,
+                    Err(nb::Error::WouldBlock) => Mono::delay(POLL_INTERVAL).await,
+                    Err(nb::Error::Other(e)) => match e {},
+                }
+            }
+
+            Mono::delay(REPORT_INTERVAL).await;
+        }
+    }
+}
@@ -0,0 +1,6 @@
+/* STM32G474RE, as on the NUCLEO-G474RE. */
+MEMORY
+{
+  FLASH : ORIGIN = 0x08000000, LENGTH = 512K
+  RAM : ORIGIN = 0x20000000, LENGTH = 128K
+}
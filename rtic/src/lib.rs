@@ -0,0 +1,60 @@
+#![no_std]
+
+//! Integration of kiri in RTIC 2 applications, see `examples/node.rs` for a complete node.
+//!
+//! `MonotonicClock` reads the time from a timer of `rtic-monotonics`, and `InterruptFree` lets the halves of a
+//! split strategy share it between a hardware task receiving and a software task sending.
+
+use core::marker::PhantomData;
+
+use kiri_csma::{
+    shared::CriticalSection,
+    ticks::{TickClock, TickSource},
+};
+use rtic_monotonics::Monotonic;
+
+/// Clock on the ticks of the monotonic timer `M`, which ticks at `HZ`.
+///
+/// The instants and durations of `fugit` can not be `Clock::Instant` and `Clock::Duration` themselves, as they do
+/// not implement `SampleUniform`, which the strategy needs to draw a random backoff. Hence this clock counts plain
+/// ticks instead, and `millis` and `micros` express the durations of a `Config` in ticks.
+pub type MonotonicClock<M, const HZ: u32> = TickClock<MonotonicTicks<M, HZ>, HZ>;
+
+/// Ticks of the monotonic timer `M`, for a `MonotonicClock`.
+///
+/// The timer must have 64-bit instants, i.e. enable `systick-64bit`, as 32-bit instants wrap within days.
+pub struct MonotonicTicks<M, const HZ: u32>(PhantomData<M>);
+
+impl<M, const HZ: u32> MonotonicTicks<M, HZ> {
+    pub const fn new() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<M, const HZ: u32> Default for MonotonicTicks<M, HZ> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<M, const HZ: u32> TickSource for MonotonicTicks<M, HZ>
+where
+    M: Monotonic<Instant = fugit::Instant<u64, 1, HZ>>,
+{
+    fn ticks(&self) -> u64 {
+        M::now().ticks()
+    }
+}
+
+/// Critical section with all interrupts disabled, for the halves of a split strategy to share their core.
+///
+/// The core is not a resource of RTIC, hence the priority ceilings of RTIC do not cover it. Only sound on chips with a
+/// single core, as disabling interrupts does not stop other cores.
+pub struct InterruptFree;
+
+// Safety: interrupts stay disabled while `f` runs, and the Cortex-M has a single core.
+unsafe impl CriticalSection for InterruptFree {
+    fn with<R>(f: impl FnOnce() -> R) -> R {
+        cortex_m::interrupt::free(|_| f())
+    }
+}
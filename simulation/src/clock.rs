@@ -1,15 +1,16 @@
 use std::{
     ops::{Add, Sub},
+    str::FromStr,
     sync::atomic::{AtomicU64, Ordering},
 };
 
 use kiri_csma::Clock;
 use rand::distributions::uniform::{SampleUniform, UniformInt, UniformSampler};
 
-#[derive(PartialEq, PartialOrd, Debug, Clone, Copy)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Copy)]
 pub struct FakeInstant(pub u64);
 
-#[derive(PartialEq, PartialOrd, Debug, Clone, Copy)]
+#[derive(PartialEq, PartialOrd, Debug, Default, Clone, Copy)]
 pub struct FakeDuration(pub u64);
 
 #[derive(Debug)]
@@ -27,6 +28,12 @@ impl FakeClock {
     pub fn increase(&self, duration: u64) {
         self.now.fetch_add(duration, Ordering::Relaxed);
     }
+
+    /// Skip ahead to `at`, which may not be in the past.
+    pub fn skip_to(&self, at: FakeInstant) {
+        let previous = self.now.swap(at.0, Ordering::Relaxed);
+        assert!(previous <= at.0, "Skipping back in time");
+    }
 }
 
 impl Clock for &FakeClock {
@@ -38,6 +45,71 @@ impl Clock for &FakeClock {
     }
 }
 
+/// Clock of a party, which is offset from and drifts relative to the simulation time.
+#[derive(Debug, Clone, Copy)]
+pub struct PartyClock<'a> {
+    simulation: &'a FakeClock,
+    offset: u64,
+    drift_ppm: i64,
+}
+
+impl<'a> PartyClock<'a> {
+    pub fn new(simulation: &'a FakeClock, skew: ClockSkew) -> Self {
+        Self {
+            simulation,
+            offset: skew.offset,
+            drift_ppm: skew.drift_ppm,
+        }
+    }
+}
+
+impl PartyClock<'_> {
+    /// The simulation time at which this clock reads `instant`, at the earliest.
+    pub fn simulation_time(&self, instant: FakeInstant) -> FakeInstant {
+        let since_offset = instant.0.saturating_sub(self.offset) as i128;
+        let rate = 1_000_000 + self.drift_ppm as i128;
+        // Round up, such that the clock has reached `instant` by then.
+        FakeInstant(((since_offset * 1_000_000 + rate - 1) / rate) as u64)
+    }
+}
+
+impl Clock for PartyClock<'_> {
+    type Instant = FakeInstant;
+    type Duration = FakeDuration;
+
+    fn now(&self) -> Self::Instant {
+        let now = self.simulation.now.load(Ordering::Relaxed) as i128;
+        let drift = now * self.drift_ppm as i128 / 1_000_000;
+        FakeInstant(self.offset + (now + drift) as u64)
+    }
+}
+
+/// Offset and drift of the clock of a party, relative to the simulation time.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ClockSkew {
+    pub offset: u64,
+    /// How much faster the clock runs, in parts per million. Negative for a slower clock.
+    pub drift_ppm: i64,
+}
+
+impl FromStr for ClockSkew {
+    type Err = String;
+
+    /// Parse `<offset>:<drift ppm>`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid clock {:?}", s);
+        let (offset, drift_ppm) = s.split_once(':').ok_or_else(invalid)?;
+        let drift_ppm = drift_ppm.parse().map_err(|_| invalid())?;
+        if drift_ppm <= -1_000_000 {
+            return Err(invalid());
+        }
+        Ok(Self {
+            offset: offset.parse().map_err(|_| invalid())?,
+            drift_ppm,
+        })
+    }
+}
+
 impl Add<FakeDuration> for FakeInstant {
     type Output = FakeInstant;
 
@@ -54,6 +126,14 @@ impl Sub<FakeInstant> for FakeInstant {
     }
 }
 
+impl Add<FakeDuration> for FakeDuration {
+    type Output = FakeDuration;
+
+    fn add(self, rhs: FakeDuration) -> Self::Output {
+        FakeDuration(self.0 + rhs.0)
+    }
+}
+
 impl SampleUniform for FakeDuration {
     type Sampler = UniformFakeDuration;
 }
@@ -0,0 +1,132 @@
+//! Polling all parties every tick, either on the main thread or divided over worker threads.
+//!
+//! Parties only share their bus segments, on which bytes written during the same tick are combined regardless of
+//! the order they were written in. Hence polling the parties concurrently yields the same as polling them in turn,
+//! as long as the messages they received are delivered in the order of the parties.
+
+use std::{
+    sync::mpsc::{channel, Receiver, Sender},
+    thread::Scope,
+};
+
+use crate::{Message, Party};
+
+/// Parties are boxed, such that handing them to a worker and back only moves pointers.
+pub type Parties<'a> = Vec<Box<Party<'a>>>;
+
+/// A thread that polls the parties handed to it, and hands them back along with the messages they received.
+struct Worker<'a> {
+    parties: Sender<Parties<'a>>,
+    polled: Receiver<(Parties<'a>, Vec<Message>)>,
+}
+
+/// Polls the parties, on worker threads that live as long as `Scope` if there are any.
+pub struct Executor<'a> {
+    workers: Vec<Worker<'a>>,
+}
+
+impl<'a> Executor<'a> {
+    /// Poll the parties on the calling thread.
+    pub fn sequential() -> Self {
+        Self {
+            workers: Vec::new(),
+        }
+    }
+
+    /// Divide the parties over `threads` workers, which stop once the executor is dropped.
+    pub fn threaded<'scope>(scope: &'scope Scope<'scope, '_>, threads: usize) -> Self
+    where
+        'a: 'scope,
+    {
+        let workers = (0..threads)
+            .map(|_| {
+                let (parties, tasks) = channel::<Parties<'a>>();
+                let (results, polled) = channel();
+                scope.spawn(move || {
+                    for mut parties in tasks {
+                        let messages = parties
+                            .iter_mut()
+                            .filter_map(|party| party.poll())
+                            .collect();
+                        if results.send((parties, messages)).is_err() {
+                            break;
+                        }
+                    }
+                });
+                Worker { parties, polled }
+            })
+            .collect();
+        Self { workers }
+    }
+
+    /// Poll every party once, yielding the messages they received in the order of the parties.
+    pub fn poll(&mut self, parties: &mut Parties<'a>) -> Vec<Message> {
+        if self.workers.is_empty() {
+            return parties
+                .iter_mut()
+                .filter_map(|party| party.poll())
+                .collect();
+        }
+
+        let chunk_len = parties.len().div_ceil(self.workers.len());
+        let mut rest = std::mem::take(parties);
+        for worker in &self.workers {
+            let next = rest.split_off(chunk_len.min(rest.len()));
+            worker.parties.send(rest).expect("Worker stopped");
+            rest = next;
+        }
+
+        // Every tick ends once all workers are done with it, in the same order as the parties were handed out.
+        let mut messages = Vec::new();
+        for worker in &self.workers {
+            let (chunk, received) = worker.polled.recv().expect("Worker panicked");
+            parties.extend(chunk);
+            messages.extend(received);
+        }
+        messages
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, thread};
+
+    use kiri_protocol::Address;
+
+    use super::*;
+    use crate::{
+        clock::{ClockSkew, FakeClock, PartyClock},
+        simulation::{LineProfile, SerialBus},
+        BackoffMode,
+    };
+
+    #[test]
+    fn threaded_keeps_order() {
+        let clock = FakeClock::new();
+        let bus = Arc::new(SerialBus::new());
+        let mut parties: Parties = (0..7)
+            .map(|i| {
+                Box::new(Party::new(
+                    Address::new(i),
+                    bus.clone(),
+                    PartyClock::new(&clock, ClockSkew::default()),
+                    false,
+                    BackoffMode::Uniform,
+                    LineProfile::default(),
+                ))
+            })
+            .collect();
+
+        thread::scope(|scope| {
+            let mut executor = Executor::threaded(scope, 3);
+            for _ in 0..3 {
+                assert!(executor.poll(&mut parties).is_empty());
+                let addresses: Vec<u32> = parties
+                    .iter()
+                    .map(|party| party.address().to_primitive())
+                    .collect();
+                assert_eq!(addresses, [0, 1, 2, 3, 4, 5, 6]);
+            }
+        });
+    }
+}
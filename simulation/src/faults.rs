@@ -0,0 +1,128 @@
+use std::str::FromStr;
+
+/// What happens to a party during a scenario.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Fault {
+    /// The party loses power, forgetting everything it was doing.
+    PowerOff,
+    /// The party starts afresh.
+    PowerOn,
+    /// The party transmits garbage continuously, regardless of the bus.
+    Babble,
+    /// The driver of the party is stuck enabled, garbling every byte on the bus.
+    StuckTx,
+    /// The party stops babbling or driving the bus, and starts afresh.
+    Healthy,
+}
+
+/// A fault happening to `party` at tick `at`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScenarioEvent {
+    pub at: u64,
+    pub party: usize,
+    pub fault: Fault,
+}
+
+impl FromStr for ScenarioEvent {
+    type Err = String;
+
+    /// Parse `<tick>:<party>:<fault>`, with the fault being `off`, `on`, `babble`, `stuck` or `healthy`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid event {:?}", s);
+
+        let mut parts = s.split(':');
+        let at = parts
+            .next()
+            .and_then(|at| at.parse().ok())
+            .ok_or_else(invalid)?;
+        let party = parts
+            .next()
+            .and_then(|party| party.parse().ok())
+            .ok_or_else(invalid)?;
+        let fault = match parts.next() {
+            Some("off") => Fault::PowerOff,
+            Some("on") => Fault::PowerOn,
+            Some("babble") => Fault::Babble,
+            Some("stuck") => Fault::StuckTx,
+            Some("healthy") => Fault::Healthy,
+            _ => return Err(invalid()),
+        };
+        if parts.next().is_some() {
+            return Err(invalid());
+        }
+
+        Ok(Self { at, party, fault })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use kiri_csma::{ReadError, Transceiver};
+
+    use super::*;
+    use crate::simulation::{SerialBus, SerialTransceiver};
+
+    /// How many of `bytes` written on `bus` are read garbled.
+    fn garbled(bus: &Arc<SerialBus>, bytes: usize) -> usize {
+        let mut writer = SerialTransceiver::new(bus.clone());
+        let mut reader = SerialTransceiver::new(bus.clone());
+        (0..bytes)
+            .filter(|_| {
+                writer.write(0x55).unwrap();
+                bus.iterate();
+                let read = reader.read();
+                bus.iterate();
+                matches!(read, Err(nb::Error::Other(ReadError::FrameError)))
+            })
+            .count()
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            "1500:3:stuck".parse(),
+            Ok(ScenarioEvent {
+                at: 1500,
+                party: 3,
+                fault: Fault::StuckTx,
+            })
+        );
+        for (fault, expected) in [
+            ("off", Fault::PowerOff),
+            ("on", Fault::PowerOn),
+            ("babble", Fault::Babble),
+            ("healthy", Fault::Healthy),
+        ] {
+            let event: ScenarioEvent = format!("0:0:{}", fault).parse().unwrap();
+            assert_eq!(event.fault, expected);
+        }
+        for invalid in [
+            "",
+            "10:2",
+            "10:2:melt",
+            "x:2:off",
+            "10:-1:off",
+            "10:2:off:on",
+        ] {
+            assert!(invalid.parse::<ScenarioEvent>().is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn stuck_driver_garbles_everything() {
+        let bus = Arc::new(SerialBus::new());
+        assert_eq!(garbled(&bus, 50), 0);
+
+        bus.set_driver_stuck(true);
+        assert_eq!(garbled(&bus, 50), 50);
+
+        // Only once every stuck driver recovered.
+        bus.set_driver_stuck(true);
+        bus.set_driver_stuck(false);
+        assert_eq!(garbled(&bus, 50), 50);
+        bus.set_driver_stuck(false);
+        assert_eq!(garbled(&bus, 50), 0);
+    }
+}
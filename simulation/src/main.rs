@@ -1,83 +1,500 @@
-use rand::prelude::ThreadRng;
+use rand::{prelude::ThreadRng, rngs::StdRng, SeedableRng};
 use serde_derive::{Deserialize, Serialize};
-use std::{collections::HashSet, rc::Rc};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    ffi::OsString,
+    sync::Arc,
+    thread,
+};
 
-use clock::{FakeClock, FakeDuration};
-use kiri_csma::{Clock, CsmaFrameInProgress, CsmaStrategy, SendReceiveResult};
-use kiri_protocol::{Address, Frame, FrameRef, Writer};
-use simulation::{SerialBus, SerialTransceiver};
+use clock::{ClockSkew, FakeClock, FakeDuration, FakeInstant, PartyClock};
+use executor::{Executor, Parties};
+use faults::{Fault, ScenarioEvent};
+use kiri_csma::{
+    backoff::{AdaptiveBackoff, BackoffSource, UniformBackoff},
+    Clock, CsmaFrameInProgress, CsmaStrategy, ReceiveError, SendReceiveResult, Stats,
+};
+use kiri_protocol::{Address, Frame, FrameBuilder, MAX_FRAME_LEN, MAX_SEQUENCE};
+use kiri_testing::engine::EventQueue;
+use observer::{PartyObserver, Recorded};
+use simulation::{LineProfile, SerialBus, SerialTransceiver};
+use sweep::Sweep;
+use topology::Topology;
+use trace::TraceWriter;
+use traffic::{Arrival, Generator, TrafficModel};
+use view::BusView;
 
 mod clock;
+mod executor;
+mod faults;
+mod observer;
+mod pcap;
+mod replay;
 mod simulation;
+mod sweep;
+mod topology;
+mod trace;
+mod traffic;
+mod view;
+
+/// How long a simulation may run at most by default, in case parties can not get their messages across anymore.
+const MAX_TICKS: u64 = 10_000_000;
+
+const USAGE: &str = "usage: kiri-simulation [--assert-delivery <percentage>%] [--assert-latency <ticks>] [--max-ticks <ticks>]
+                       [--threads <threads>] [--tui] [--replay <capture> [--replay-send <tick>]... [--assert-received <frames>]]
+       kiri-simulation sweep [--parties <parties>,...] [--load <traffic model>,...] [--backoff <backoff>,...]
+                             [--csv <path>] [--plot <directory>] [--max-ticks <ticks>] [--threads <threads>]
+
+Runs the simulation, exiting with a non-zero code if the messages between healthy parties are delivered
+less often than `--assert-delivery`, or any of them later than `--assert-latency`. The simulation fails
+as well if it does not finish within `--max-ticks`.
+
+`--threads` divides the parties over multiple threads, which pays off for hundreds of parties on as many cores.
+
+`--tui` shows a live view of the bus and the parties in the terminal.
+
+`--replay` puts a capture back onto the bus instead, either a pcapng file written by `KIRI_PCAP` or a raw
+dump of a serial port, against a single party that logs what it made of it. The party sends a frame of
+its own at every `--replay-send`, and has to receive at least `--assert-received` frames.
+
+`sweep` simulates every combination of `--parties`, `--load` and `--backoff`, which take the same values as
+`KIRI_PARTIES`, `KIRI_TRAFFIC` and `KIRI_BACKOFF`. It writes the delivery, latency and collisions of each to
+stdout or `--csv` as CSV, and charts them as SVG in `--plot`.";
+
+/// Targets the simulation has to meet, for the messages between parties that are never subject to faults.
+#[derive(Debug, Default)]
+struct Targets {
+    /// Percentage of the messages that has to be delivered at least.
+    delivery: Option<f64>,
+    /// Latency of every message, in ticks.
+    max_latency: Option<u64>,
+    /// Frames the party has to receive during a replay.
+    received: Option<usize>,
+}
+
+/// Options given on the command line.
+struct Args {
+    targets: Targets,
+    max_ticks: u64,
+    /// Threads to poll the parties on.
+    threads: usize,
+    tui: bool,
+    /// Capture to replay instead of simulating the parties.
+    replay: Option<String>,
+    /// Ticks at which the party sends a frame during a replay.
+    replay_sends: Vec<u64>,
+    /// Matrix of parameters to simulate instead.
+    sweep: Option<Sweep>,
+}
+
+fn parse_args() -> Args {
+    fn fail(message: impl std::fmt::Display) -> ! {
+        eprintln!("{}\n\n{}", message, USAGE);
+        std::process::exit(2);
+    }
+
+    let mut targets = Targets::default();
+    let mut max_ticks = MAX_TICKS;
+    let mut threads = 1;
+    let mut tui = false;
+    let mut replay = None;
+    let mut replay_sends = Vec::new();
+    let mut sweep = None;
+
+    /// Parse a comma separated list of values.
+    fn list<T: std::str::FromStr>(value: &str) -> Vec<T>
+    where
+        T::Err: std::fmt::Display,
+    {
+        value
+            .split(',')
+            .map(|item| {
+                item.parse()
+                    .unwrap_or_else(|e| fail(format!("invalid value {:?}: {}", item, e)))
+            })
+            .collect()
+    }
+
+    /// The sweep an option of it applies to, if the `sweep` subcommand was given.
+    fn sweep_only<'s>(sweep: &'s mut Option<Sweep>, arg: &str) -> &'s mut Sweep {
+        match sweep {
+            Some(sweep) => sweep,
+            None => fail(format!("{} is only for sweep", arg)),
+        }
+    }
+
+    let mut args = std::env::args().skip(1).peekable();
+    if args.next_if(|arg| arg == "sweep").is_some() {
+        sweep = Some(Sweep::default());
+    }
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .unwrap_or_else(|| fail(format!("missing value for {}", arg)))
+        };
+        match arg.as_str() {
+            "--parties" => sweep_only(&mut sweep, &arg).parties = list(&value()),
+            "--load" => sweep_only(&mut sweep, &arg).loads = list(&value()),
+            "--backoff" => sweep_only(&mut sweep, &arg).backoffs = list(&value()),
+            "--csv" => sweep_only(&mut sweep, &arg).csv = Some(value().into()),
+            "--plot" => sweep_only(&mut sweep, &arg).plot = Some(value().into()),
+            "--assert-delivery" => {
+                let value = value();
+                let percentage = value.strip_suffix('%').unwrap_or(&value);
+                targets.delivery = Some(
+                    percentage
+                        .parse()
+                        .unwrap_or_else(|e| fail(format!("invalid delivery {:?}: {}", value, e))),
+                );
+            }
+            "--assert-latency" => {
+                let value = value();
+                targets.max_latency = Some(
+                    value
+                        .parse()
+                        .unwrap_or_else(|e| fail(format!("invalid latency {:?}: {}", value, e))),
+                );
+            }
+            "--max-ticks" => {
+                let value = value();
+                max_ticks = value
+                    .parse()
+                    .unwrap_or_else(|e| fail(format!("invalid ticks {:?}: {}", value, e)));
+            }
+            "--threads" => {
+                let value = value();
+                threads = value
+                    .parse()
+                    .ok()
+                    .filter(|threads| *threads > 0)
+                    .unwrap_or_else(|| fail(format!("invalid threads {:?}", value)));
+            }
+            "--tui" => tui = true,
+            "--replay" => replay = Some(value()),
+            "--replay-send" => {
+                let value = value();
+                replay_sends.push(
+                    value
+                        .parse()
+                        .unwrap_or_else(|e| fail(format!("invalid tick {:?}: {}", value, e))),
+                );
+            }
+            "--assert-received" => {
+                let value = value();
+                targets.received = Some(
+                    value
+                        .parse()
+                        .unwrap_or_else(|e| fail(format!("invalid frames {:?}: {}", value, e))),
+                );
+            }
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                std::process::exit(0);
+            }
+            _ => fail(format!("unexpected argument {:?}", arg)),
+        }
+    }
+
+    replay_sends.sort_unstable();
+
+    Args {
+        targets,
+        max_ticks,
+        threads,
+        tui,
+        replay,
+        replay_sends,
+        sweep,
+    }
+}
 
 #[derive(Debug)]
 pub struct BusConf;
 
-impl<'a> kiri_csma::Config<&'a FakeClock> for BusConf {
-    const BUS_MIN_IDLE_DURATION: <&'a FakeClock as Clock>::Duration = FakeDuration(1);
-    const BUS_MAX_IDLE_DURATION: <&'a FakeClock as Clock>::Duration = FakeDuration(32);
+impl<'a> kiri_csma::Config<PartyClock<'a>> for BusConf {
+    const BUS_MIN_IDLE_DURATION: <PartyClock<'a> as Clock>::Duration = FakeDuration(1);
+    const BUS_MAX_IDLE_DURATION: <PartyClock<'a> as Clock>::Duration = FakeDuration(32);
+    const ECHO_BYTE_TIMEOUT: <PartyClock<'a> as Clock>::Duration = FakeDuration(8);
+    const ECHO_FRAME_TIMEOUT: <PartyClock<'a> as Clock>::Duration = FakeDuration(4096);
+    // Gives the other parties the chance to see the bus idle in between frames of the same party.
+    const POST_SEND_GAP: Option<<PartyClock<'a> as Clock>::Duration> = Some(FakeDuration(2));
+    const JAM_LEN: u8 = 2;
+}
+
+/// How parties decide the time to wait once the bus became idle.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BackoffMode {
+    /// Uniformly within the window of `BusConf`.
+    Uniform,
+    /// Within a window that widens as the bus gets busier, see `AdaptiveBackoff`.
+    Adaptive,
+}
+
+impl std::fmt::Display for BackoffMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            BackoffMode::Uniform => "uniform",
+            BackoffMode::Adaptive => "adaptive",
+        })
+    }
+}
+
+impl std::str::FromStr for BackoffMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "uniform" => Ok(BackoffMode::Uniform),
+            "adaptive" => Ok(BackoffMode::Adaptive),
+            _ => Err(format!("unknown backoff {:?}", s)),
+        }
+    }
+}
+
+/// The backoff of a party, as decided by its `BackoffMode`.
+#[derive(Debug, Clone, Copy)]
+pub enum PartyBackoff {
+    Uniform(UniformBackoff),
+    Adaptive(AdaptiveBackoff),
+}
+
+impl PartyBackoff {
+    fn new(mode: BackoffMode) -> Self {
+        match mode {
+            BackoffMode::Uniform => PartyBackoff::Uniform(UniformBackoff),
+            BackoffMode::Adaptive => PartyBackoff::Adaptive(AdaptiveBackoff::new()),
+        }
+    }
+}
+
+impl<C: Clock> BackoffSource<C> for PartyBackoff {
+    fn backoff(
+        &mut self,
+        min: C::Duration,
+        max: C::Duration,
+        rng: &mut impl rand::RngCore,
+    ) -> C::Duration {
+        match self {
+            PartyBackoff::Uniform(backoff) => BackoffSource::<C>::backoff(backoff, min, max, rng),
+            PartyBackoff::Adaptive(backoff) => BackoffSource::<C>::backoff(backoff, min, max, rng),
+        }
+    }
+
+    fn observe_utilization(&mut self, utilization: f32) {
+        match self {
+            PartyBackoff::Uniform(backoff) => {
+                BackoffSource::<C>::observe_utilization(backoff, utilization)
+            }
+            PartyBackoff::Adaptive(backoff) => {
+                BackoffSource::<C>::observe_utilization(backoff, utilization)
+            }
+        }
+    }
+}
+
+/// Delivery of the messages between a class of parties.
+#[derive(Debug, Default)]
+struct Traffic {
+    sent: usize,
+    delivered: usize,
+    total_latency: u64,
+    max_latency: u64,
+}
+
+impl Traffic {
+    /// Percentage of the messages that was delivered.
+    fn delivery(&self) -> f64 {
+        self.delivered as f64 / self.sent.max(1) as f64 * 100.
+    }
+
+    /// Mean latency of the messages that were delivered, in ticks.
+    fn mean_latency(&self) -> u64 {
+        self.total_latency / self.delivered.max(1) as u64
+    }
+
+    fn report(&self, name: &str) {
+        if self.sent == 0 {
+            return;
+        }
+        log::info!(
+            "{}: {}/{} delivered, latency mean {} max {}",
+            name,
+            self.delivered,
+            self.sent,
+            self.mean_latency(),
+            self.max_latency
+        );
+    }
 }
 
 pub struct Mailbox {
-    messages_per_party: usize,
+    generators: Vec<Generator>,
+    /// Messages waiting to be sent by every party.
+    queues: Vec<VecDeque<Message>>,
+    /// Amount of messages generated by every party, including responses.
     send_progress: Vec<usize>,
     receive_progress: Vec<HashSet<usize>>,
+    /// Segment of every party.
+    segments: Vec<usize>,
+    /// Hop limit of frames, to be forwarded by bridges between segments.
+    hop_limit: u8,
+    /// Parties that are running, and hence generate and send messages.
+    active: Vec<bool>,
+    /// Parties that are subject to faults at some point.
+    faulty: Vec<bool>,
+    sent_at: HashMap<(u32, usize), FakeInstant>,
+    local: Traffic,
+    cross_segment: Traffic,
+    /// Messages between parties that are never subject to faults.
+    healthy: Traffic,
+    rng: ThreadRng,
 }
 
 impl Mailbox {
-    pub fn new(messages_per_party: usize, parties: usize) -> Self {
+    /// Every party generates `messages_per_party` messages, according to the models assigned to the parties in turn.
+    pub fn new(messages_per_party: usize, topology: &Topology, models: &[TrafficModel]) -> Self {
+        let parties = topology.party_count();
         Self {
-            messages_per_party,
+            generators: Vec::from_iter(
+                (0..parties).map(|i| Generator::new(models[i % models.len()], messages_per_party)),
+            ),
+            queues: Vec::from_iter((0..parties).map(|_| VecDeque::new())),
             send_progress: Vec::from_iter((0..parties).map(|_| 0)),
             receive_progress: Vec::from_iter((0..parties).map(|_| HashSet::default())),
+            segments: Vec::from_iter(
+                (0..parties).map(|i| topology.segment_of(Address::new(i as u32))),
+            ),
+            hop_limit: topology.diameter() as u8,
+            active: vec![true; parties],
+            faulty: vec![false; parties],
+            sent_at: HashMap::new(),
+            local: Traffic::default(),
+            cross_segment: Traffic::default(),
+            healthy: Traffic::default(),
+            rng: rand::thread_rng(),
         }
     }
 
-    /// Fetch a new message to send.
-    pub fn fetch(&mut self, src: Address) -> Option<Frame> {
-        // TODO maybe wait for messages to be generated.
-        let addr = src.to_primitive() as usize;
-        let parties = self.send_progress.len();
-        let progress = &mut self.send_progress[addr];
-
-        if *progress < self.messages_per_party {
-            let mut dst = *progress % (parties - 1);
-            if dst >= addr {
-                dst += 1;
-            }
-            let dst = Address::new(dst as u32);
-            let message = Message {
-                src: src.to_primitive(),
-                dst: dst.to_primitive(),
-                identifier: *progress,
-            };
+    /// Account a message from `src` to `dst` in all classes of traffic it belongs to.
+    fn account(&mut self, src: u32, dst: u32, f: impl Fn(&mut Traffic)) {
+        let (src, dst) = (src as usize, dst as usize);
+        if self.segments[src] == self.segments[dst] {
+            f(&mut self.local);
+        } else {
+            f(&mut self.cross_segment);
+        }
+        if !self.faulty[src] && !self.faulty[dst] {
+            f(&mut self.healthy);
+        }
+    }
 
-            let frame = match Writer::package(src, dst, &message.to_bytes()) {
-                Ok(frame) => frame,
-                _ => panic!("Writer failed to pack reasonable message"),
-            };
+    /// Note that `party` is subject to faults during the simulation.
+    pub fn set_faulty(&mut self, party: usize) {
+        self.faulty[party] = true;
+    }
 
-            log::info!(
-                "Sending {} -> {}: {}",
-                src.to_primitive(),
-                dst.to_primitive(),
-                progress
-            );
+    /// Pause or resume generating messages for `party`. Any messages it still had to send are lost.
+    pub fn set_active(&mut self, party: Address, active: bool) {
+        let party = party.to_primitive() as usize;
+        self.active[party] = active;
+        if !active {
+            self.queues[party].clear();
+        }
+    }
 
-            *progress += 1;
+    /// When the next message of any active party arrives, see `Generator::next_at`.
+    pub fn next_arrival_at(&self) -> Option<FakeInstant> {
+        (0..self.queues.len())
+            .filter(|party| self.active[*party])
+            .filter_map(|party| self.generators[party].next_at(self.queues[party].len()))
+            .min()
+            .map(FakeInstant)
+    }
 
-            Some(frame)
-        } else {
-            None
+    /// Amount of messages `party` has waiting to be sent.
+    pub fn queue_len(&self, party: Address) -> usize {
+        self.queues[party.to_primitive() as usize].len()
+    }
+
+    /// Generate the messages of all parties that arrive at `now`.
+    pub fn generate(&mut self, now: FakeInstant) {
+        let parties = self.queues.len();
+        for src in 0..parties {
+            if !self.active[src] {
+                continue;
+            }
+            while let Some(arrival) =
+                self.generators[src].poll(now.0, self.queues[src].len(), &mut self.rng)
+            {
+                let progress = self.send_progress[src];
+                let mut dst = progress % (parties - 1);
+                if dst >= src {
+                    dst += 1;
+                }
+                let kind = match arrival {
+                    Arrival::Data => MessageKind::Data,
+                    Arrival::Request => MessageKind::Request,
+                };
+                self.enqueue(src as u32, dst as u32, kind, now);
+            }
         }
     }
 
-    /// Try to deliver a message contained in a frame.
-    pub fn deliver(&mut self, frame: FrameRef) {
-        let message = Message::from_bytes(frame.contents).unwrap();
-        assert_eq!(message.src, frame.header.address_src.to_primitive());
-        assert_eq!(message.dst, frame.header.address_dst.to_primitive());
-        self.receive_progress[message.src as usize].insert(message.identifier);
+    fn enqueue(&mut self, src: u32, dst: u32, kind: MessageKind, now: FakeInstant) {
+        let identifier = self.send_progress[src as usize];
+        self.send_progress[src as usize] += 1;
+
+        self.sent_at.insert((src, identifier), now);
+        self.account(src, dst, |traffic| traffic.sent += 1);
+        self.queues[src as usize].push_back(Message {
+            src,
+            dst,
+            identifier,
+            kind,
+        });
+    }
+
+    /// Fetch a new message to send.
+    pub fn fetch(&mut self, src: Address) -> Option<Frame> {
+        let message = self.queues[src.to_primitive() as usize].pop_front()?;
+        let dst = Address::new(message.dst);
+
+        // Cycle through the non-zero sequence numbers, such that duplicates are dropped.
+        let sequence = (message.identifier % MAX_SEQUENCE as usize) as u8 + 1;
+        let frame = FrameBuilder::new(src, dst)
+            .sequence(sequence)
+            .hop_limit(self.hop_limit)
+            .payload(&message.to_bytes())
+            .build();
+        let frame = match frame {
+            Ok(frame) => frame,
+            _ => panic!("Builder failed to pack reasonable message"),
+        };
+
+        log::info!(
+            "Sending {} -> {}: {}",
+            message.src,
+            message.dst,
+            message.identifier
+        );
+
+        Some(frame)
+    }
+
+    /// Try to deliver a message received by its destination.
+    pub fn deliver(&mut self, message: Message, now: FakeInstant) {
+        if self.receive_progress[message.src as usize].insert(message.identifier) {
+            let latency = (now - self.sent_at[&(message.src, message.identifier)]).0;
+            self.account(message.src, message.dst, |traffic| {
+                traffic.delivered += 1;
+                traffic.total_latency += latency;
+                traffic.max_latency = traffic.max_latency.max(latency);
+            });
+
+            if message.kind == MessageKind::Request {
+                self.enqueue(message.dst, message.src, MessageKind::Response, now);
+            }
+        }
 
         log::info!(
             "Received {} -> {}: {}",
@@ -100,70 +517,287 @@ impl Mailbox {
                 .iter()
                 .map(|set| set.len())
                 .sum::<usize>() as f64
-                / self.messages_per_party as f64
-                / self.receive_progress.len() as f64
+                / self.send_progress.iter().sum::<usize>().max(1) as f64
                 * 100.
         );
+        self.local.report("Local");
+        self.cross_segment.report("Cross-segment");
+        if self.faulty.contains(&true) {
+            self.healthy.report("Healthy");
+        }
+    }
+
+    /// Check whether the messages between healthy parties meet `targets`, logging those that are not met.
+    fn meets(&self, targets: &Targets) -> bool {
+        let delivery = self.healthy.delivery();
+        let mut met = true;
+
+        if let Some(target) = targets.delivery.filter(|target| delivery < *target) {
+            log::error!(
+                "Delivered {:.2}% of the messages, instead of {}%",
+                delivery,
+                target
+            );
+            met = false;
+        }
+        if let Some(target) = targets
+            .max_latency
+            .filter(|target| self.healthy.max_latency > *target)
+        {
+            log::error!(
+                "Delivered a message after {} ticks, instead of {}",
+                self.healthy.max_latency,
+                target
+            );
+            met = false;
+        }
+        met
     }
 
-    /// All messages have been sent successfully, as far as the senders are concerned.
+    /// All messages of the active parties have been sent successfully, as far as the senders are concerned.
     pub fn all_sent(&self) -> bool {
-        self.send_progress
+        self.generators
             .iter()
-            .all(|progress| *progress == self.messages_per_party)
+            .zip(&self.active)
+            .all(|(generator, active)| generator.is_exhausted() || !active)
+            && self.queues.iter().all(VecDeque::is_empty)
     }
 }
 
+/// How a party is behaving.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Condition {
+    Running,
+    PoweredOff,
+    Babbling,
+    StuckTx,
+}
+
+type PartyStrategy<'a> = CsmaStrategy<
+    SerialTransceiver,
+    PartyClock<'a>,
+    StdRng,
+    MAX_FRAME_LEN,
+    8,
+    (PartyObserver, PartyBackoff),
+>;
+
 pub struct Party<'a> {
     address: Address,
-    strategy: CsmaStrategy<SerialTransceiver, &'a FakeClock, ThreadRng, BusConf>,
+    bus: Arc<SerialBus>,
+    clock: PartyClock<'a>,
+    /// Whether the party records what happens to it, to be traced.
+    record: bool,
+    backoff: BackoffMode,
+    line: LineProfile,
+    strategy: PartyStrategy<'a>,
     current_frame: Option<CsmaFrameInProgress>,
+    condition: Condition,
 }
 
 impl<'a> Party<'a> {
     pub fn new(
         address: Address,
-        strategy: CsmaStrategy<SerialTransceiver, &'a FakeClock, ThreadRng, BusConf>,
+        bus: Arc<SerialBus>,
+        clock: PartyClock<'a>,
+        record: bool,
+        backoff: BackoffMode,
+        line: LineProfile,
     ) -> Self {
         Self {
             address,
-            strategy,
+            strategy: Self::strategy(&bus, clock, record, backoff, line),
+            bus,
+            clock,
+            record,
+            backoff,
+            line,
             current_frame: None,
+            condition: Condition::Running,
+        }
+    }
+
+    fn strategy(
+        bus: &Arc<SerialBus>,
+        clock: PartyClock<'a>,
+        record: bool,
+        backoff: BackoffMode,
+        line: LineProfile,
+    ) -> PartyStrategy<'a> {
+        CsmaStrategy::new::<BusConf>(
+            SerialTransceiver::with_line(bus.clone(), line),
+            clock,
+            StdRng::from_entropy(),
+        )
+        .with_observer(PartyObserver::new(record))
+        .with_backoff(PartyBackoff::new(backoff))
+    }
+
+    pub fn address(&self) -> Address {
+        self.address
+    }
+
+    pub fn condition(&self) -> Condition {
+        self.condition
+    }
+
+    /// Whether the party has a frame in progress of being sent.
+    pub fn has_frame(&self) -> bool {
+        self.current_frame.is_some()
+    }
+
+    pub fn stats(&self) -> &Stats<FakeDuration> {
+        self.strategy.stats()
+    }
+
+    pub fn observer(&mut self) -> &mut PartyObserver {
+        self.strategy.observer()
+    }
+
+    /// Apply a fault of a scenario to this party.
+    pub fn apply(&mut self, fault: Fault, mailbox: &mut Mailbox) {
+        log::info!("{:?} {:?}", self.address, fault);
+
+        if self.condition == Condition::StuckTx {
+            self.bus.set_driver_stuck(false);
         }
+
+        self.condition = match (self.condition, fault) {
+            (_, Fault::PowerOff) => Condition::PoweredOff,
+            (Condition::PoweredOff, Fault::PowerOn | Fault::Healthy) => Condition::Running,
+            (condition, Fault::PowerOn) => condition,
+            (_, Fault::Babble) => Condition::Babbling,
+            (_, Fault::StuckTx) => Condition::StuckTx,
+            (_, Fault::Healthy) => Condition::Running,
+        };
+
+        if self.condition == Condition::StuckTx {
+            self.bus.set_driver_stuck(true);
+        }
+
+        // Anything but a healthy party has lost track of what it was doing.
+        let running = self.condition == Condition::Running;
+        mailbox.set_active(self.address, running);
+        if !running {
+            self.current_frame = None;
+            self.strategy =
+                Self::strategy(&self.bus, self.clock, self.record, self.backoff, self.line);
+        }
+
+        let state = match self.condition {
+            Condition::Running => self.observer().state(),
+            Condition::PoweredOff => "PoweredOff",
+            Condition::Babbling => "Babbling",
+            Condition::StuckTx => "StuckTx",
+        };
+        self.observer().record(Recorded::State(state));
     }
 
-    pub fn simulate(&mut self, mailbox: &mut Mailbox) {
+    /// When the party needs to be polled next in simulation time, if not only once the bus changes.
+    pub fn next_poll_at(&self, mailbox: &Mailbox, now: FakeInstant) -> Option<FakeInstant> {
+        match self.condition {
+            Condition::Running => (),
+            Condition::Babbling => return Some(now),
+            Condition::PoweredOff | Condition::StuckTx => return None,
+        }
         if self.current_frame.is_none() {
-            self.current_frame = mailbox
-                .fetch(self.address)
-                .map(|frame| CsmaFrameInProgress::new(frame));
+            // Only receiving, which happens as the bytes come in.
+            return (mailbox.queue_len(self.address) > 0).then_some(now);
+        }
+        self.strategy
+            .next_poll_at()
+            .map(|at| self.clock.simulation_time(at))
+    }
+
+    /// Take the next message to send from `mailbox`, if the party is running and done with the previous one.
+    pub fn fetch(&mut self, mailbox: &mut Mailbox) {
+        if self.condition == Condition::Running && self.current_frame.is_none() {
+            self.current_frame = mailbox.fetch(self.address).map(CsmaFrameInProgress::new);
+        }
+    }
+
+    /// Poll the strategy once, yielding a message addressed to this party if one came in.
+    ///
+    /// Only touches the party itself and its bus segment, such that parties can be polled on different threads.
+    pub fn poll(&mut self) -> Option<Message> {
+        match self.condition {
+            Condition::Running => (),
+            Condition::Babbling => {
+                self.bus.write(rand::random());
+                return None;
+            }
+            Condition::PoweredOff | Condition::StuckTx => return None,
         }
 
-        if let Some(frame) = self.current_frame.as_mut() {
+        let frame = if let Some(frame) = self.current_frame.as_mut() {
             log::trace!("{:?} (S/R) {:?} {:?}", self.address, self.strategy, frame);
             match self.strategy.send_or_receive(frame) {
-                Ok(SendReceiveResult::Received(incoming_frame)) => {
-                    if incoming_frame.header.address_dst == self.address {
-                        mailbox.deliver((&incoming_frame).into())
-                    }
+                Ok(SendReceiveResult::Received(incoming_frame)) => incoming_frame,
+                Ok(SendReceiveResult::SendComplete | SendReceiveResult::Expired) => {
+                    self.current_frame = None;
+                    return None;
                 }
-                Ok(SendReceiveResult::SendComplete) => self.current_frame = None,
-                Err(nb::Error::WouldBlock) => (),
+                Err(nb::Error::WouldBlock) => return None,
                 Err(nb::Error::Other(e)) => panic!("Error: {:?}", e),
             }
         } else {
             log::trace!("{:?} (R) {:?}", self.address, self.strategy);
-            match self.strategy.receive() {
-                Ok(frame) => {
-                    if frame.header.address_dst == self.address {
-                        mailbox.deliver(frame)
-                    }
+            match self.strategy.receive_verbose() {
+                Ok(frame) => frame,
+                Err(nb::Error::WouldBlock) => return None,
+                Err(nb::Error::Other(ReceiveError::Dropped(reason))) => {
+                    log::debug!("{:?} dropped frame: {:?}", self.address, reason);
+                    return None;
                 }
-                Err(nb::Error::WouldBlock) => (),
-                Err(nb::Error::Other(e)) => panic!("Error: {:?}", e),
+                Err(nb::Error::Other(ReceiveError::UnderlyingError(e))) => panic!("Error: {:?}", e),
             }
+        };
+
+        if frame.header.address_dst != self.address {
+            return None;
         }
+        let message = Message::from_bytes(frame.contents).unwrap();
+        assert_eq!(message.src, frame.header.address_src.to_primitive());
+        assert_eq!(message.dst, frame.header.address_dst.to_primitive());
+        Some(message)
+    }
+}
+
+/// What the simulation does at an instant.
+#[derive(Debug)]
+enum Scheduled {
+    /// Apply a fault of the scenario, before stepping.
+    Fault(ScenarioEvent),
+    /// Move the bytes on the buses on, and poll the parties and bridges.
+    Step,
+}
+
+/// When anything happens after `now`, being the next tick as long as there are bytes on any bus.
+fn next_step_at(
+    now: FakeInstant,
+    topology: &Topology,
+    parties: &Parties,
+    mailbox: &Mailbox,
+) -> FakeInstant {
+    let next_tick = now + FakeDuration(1);
+    if !topology.is_idle() {
+        return next_tick;
     }
+    parties
+        .iter()
+        .filter_map(|party| party.next_poll_at(mailbox, now))
+        .chain(topology.next_poll_at())
+        .chain(mailbox.next_arrival_at())
+        .min()
+        // Without anything to wait for, i.e. once all messages are sent, keep on ticking.
+        .map_or(next_tick, |at| at.max(next_tick))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum MessageKind {
+    Data,
+    Request,
+    Response,
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
@@ -171,6 +805,7 @@ pub struct Message {
     src: u32,
     dst: u32,
     identifier: usize,
+    kind: MessageKind,
 }
 
 impl Message {
@@ -178,54 +813,335 @@ impl Message {
         serde_json::to_vec(self).unwrap()
     }
 
+    #[allow(clippy::result_unit_err)]
     pub fn from_bytes(buf: &[u8]) -> Result<Self, ()> {
         serde_json::from_slice(buf).map_err(|_| ())
     }
 }
 
-fn main() {
-    pretty_env_logger::init();
+/// What to simulate, as configured by the `KIRI_*` environment variables.
+#[derive(Debug, Clone)]
+pub struct Scenario {
+    pub party_count: usize,
+    pub segment_count: usize,
+    /// Traffic models, which are assigned to the parties in turn.
+    pub models: Vec<TrafficModel>,
+    pub backoff: BackoffMode,
+    /// Clocks, which are assigned to the parties in turn.
+    pub skews: Vec<ClockSkew>,
+    /// Serial lines, which are assigned to the parties in turn.
+    pub lines: Vec<LineProfile>,
+    /// Faults happening to parties, by the tick they happen at.
+    pub events: Vec<ScenarioEvent>,
+}
+
+impl Scenario {
+    pub fn from_env() -> Self {
+        // Set `KIRI_PARTIES` to simulate more or less than 10 parties.
+        let party_count = std::env::var("KIRI_PARTIES")
+            .map(|parties| parties.parse().expect("Invalid amount of parties"))
+            .unwrap_or(10)
+            .max(2);
+
+        // Set `KIRI_SEGMENTS` to divide the parties over multiple bus segments, connected by bridges.
+        let segment_count = std::env::var("KIRI_SEGMENTS")
+            .map(|segments| segments.parse().expect("Invalid amount of segments"))
+            .unwrap_or(1)
+            .clamp(1, kiri_protocol::MAX_HOP_LIMIT as usize + 1);
+
+        // Set `KIRI_TRAFFIC` to a comma separated list of traffic models, which are assigned to the parties in turn.
+        let models = std::env::var("KIRI_TRAFFIC")
+            .unwrap_or_else(|_| "saturated".to_string())
+            .split(',')
+            .map(|model| model.parse().expect("Invalid traffic model"))
+            .collect();
+
+        // Set `KIRI_BACKOFF` to `uniform` or `adaptive` to decide how parties wait once the bus became idle.
+        let backoff = std::env::var("KIRI_BACKOFF")
+            .map(|backoff| backoff.parse().expect("Invalid backoff"))
+            .unwrap_or(BackoffMode::Uniform);
+
+        // Set `KIRI_CLOCKS` to a comma separated list of `<offset>:<drift ppm>`, which are assigned to the parties in turn.
+        let skews = std::env::var("KIRI_CLOCKS")
+            .unwrap_or_else(|_| "0:0".to_string())
+            .split(',')
+            .map(|skew| skew.parse().expect("Invalid clock"))
+            .collect();
+
+        // Set `KIRI_UART` to a comma separated list of `<framing error rate>:<parity error rate>:<baud error ppm>`,
+        // which are assigned to the parties in turn.
+        let lines = std::env::var("KIRI_UART")
+            .unwrap_or_else(|_| "0:0:0".to_string())
+            .split(',')
+            .map(|line| line.parse().expect("Invalid line"))
+            .collect();
+
+        // Set `KIRI_EVENTS` to a comma separated list of faults happening to parties, see `ScenarioEvent`.
+        let mut events = std::env::var("KIRI_EVENTS")
+            .map(|events| {
+                events
+                    .split(',')
+                    .filter(|event| !event.is_empty())
+                    .map(|event| event.parse().expect("Invalid event"))
+                    .collect::<Vec<ScenarioEvent>>()
+            })
+            .unwrap_or_default();
+        events.sort_by_key(|event| event.at);
+
+        Self {
+            party_count,
+            segment_count,
+            models,
+            backoff,
+            skews,
+            lines,
+            events,
+        }
+    }
+}
+
+/// Where a simulation writes what happens in it, besides the log.
+#[derive(Debug, Default)]
+struct Outputs {
+    /// Path to capture all traffic on the first segment to, as pcapng.
+    pcap: Option<OsString>,
+    /// Path to write what all parties and segments do to, as Chrome trace events.
+    trace: Option<OsString>,
+    /// Whether to show a live view of the bus in the terminal.
+    tui: bool,
+}
 
-    let clock = Rc::new(FakeClock::new());
-    let bus = Rc::new(SerialBus::new());
+/// What came of a simulation.
+pub struct Summary {
+    /// Whether all messages were sent within the maximum amount of ticks.
+    pub finished: bool,
+    pub ticks: u64,
+    pub mailbox: Mailbox,
+    pub collisions: u64,
+    pub retransmissions: u64,
+}
+
+impl Summary {
+    /// Log the outcome to the `log` crate.
+    pub fn report(&self) {
+        log::info!("Done in {:?}", FakeInstant(self.ticks));
+        self.mailbox.report();
+        log::info!(
+            "{} collisions, {} retransmissions",
+            self.collisions,
+            self.retransmissions
+        );
+    }
+}
+
+/// Simulate `scenario` until all messages are sent, or `max_ticks` passed, polling the parties on `threads` threads.
+fn simulate(scenario: &Scenario, mut outputs: Outputs, max_ticks: u64, threads: usize) -> Summary {
+    let clock = FakeClock::new();
 
     let message_count = 100;
-    let party_count = 10;
+    let party_count = scenario.party_count;
+    let segment_count = scenario.segment_count;
     let post_done_length = 32;
 
-    let mut mailbox = Mailbox::new(message_count, party_count);
+    let mut topology = Topology::new(&clock, segment_count, party_count);
+    let mut mailbox = Mailbox::new(message_count, &topology, &scenario.models);
 
-    let mut parties = Vec::new();
-    parties.reserve(party_count);
+    let mut tap = outputs
+        .pcap
+        .take()
+        .map(|path| pcap::BusTap::create(path).expect("Failed to create capture file"));
 
+    let segments = (0..party_count)
+        .map(|i| topology.segment_of(Address::new(i as u32)))
+        .collect::<Vec<_>>();
+    let mut trace = outputs
+        .trace
+        .take()
+        .map(|path| TraceWriter::create(path, &segments).expect("Failed to create trace file"));
+
+    let mut parties: Parties = Vec::with_capacity(party_count);
     for i in 0..party_count {
         let address = Address::new(i as u32);
-        let transceiver = SerialTransceiver::new(bus.clone());
-        let strategy =
-            CsmaStrategy::<_, _, _, BusConf>::new(transceiver, clock.as_ref(), rand::thread_rng());
-        parties.push(Party::new(address, strategy));
+        let segment = topology.segment(topology.segment_of(address));
+        let clock = PartyClock::new(&clock, scenario.skews[i % scenario.skews.len()]);
+        parties.push(Box::new(Party::new(
+            address,
+            segment.clone(),
+            clock,
+            trace.is_some(),
+            scenario.backoff,
+            scenario.lines[i % scenario.lines.len()],
+        )));
+    }
+
+    for event in &scenario.events {
+        assert!(event.party < party_count, "No party {}", event.party);
+        mailbox.set_faulty(event.party);
+    }
+
+    // Faults are due at their tick, and steps whenever anything happens.
+    let mut pending_faults = scenario.events.len();
+    let mut queue = EventQueue::new();
+    for event in &scenario.events {
+        queue.schedule(FakeInstant(event.at), Scheduled::Fault(*event));
     }
+    queue.schedule(FakeInstant(0), Scheduled::Step);
 
-    let mut post_done_count = 0;
-    while !mailbox.all_sent() {
-        bus.iterate();
+    let mut view = outputs.tui.then(|| BusView::new(segment_count));
 
-        for p in parties.iter_mut() {
-            p.simulate(&mut mailbox);
-        }
+    let finished = thread::scope(|scope| {
+        let mut executor = match threads {
+            1 => Executor::sequential(),
+            threads => Executor::threaded(scope, threads),
+        };
+        let mut post_done_count = 0;
+        loop {
+            let now = queue.next_at().expect("No next step");
+            clock.skip_to(now);
+            topology.iterate();
+
+            if let Some(tap) = tap.as_mut() {
+                tap.record((&clock).now(), topology.segment(0).current())
+                    .expect("Failed to write capture");
+            }
+
+            if let Some(trace) = trace.as_mut() {
+                for segment in 0..segment_count {
+                    trace
+                        .record_bus(
+                            (&clock).now().0,
+                            segment,
+                            topology.segment(segment).current(),
+                        )
+                        .expect("Failed to write trace");
+                }
+            }
+
+            // Record who is sending the current bytes, before the parties move on.
+            if let Some(view) = view.as_mut() {
+                view.record(&topology, &mut parties);
+            }
+
+            while let Some((_, scheduled)) = queue.pop_due(now) {
+                if let Scheduled::Fault(event) = scheduled {
+                    parties[event.party].apply(event.fault, &mut mailbox);
+                    pending_faults -= 1;
+                }
+            }
+
+            mailbox.generate(now);
+            for party in parties.iter_mut() {
+                party.fetch(&mut mailbox);
+            }
+            for message in executor.poll(&mut parties) {
+                mailbox.deliver(message, now);
+            }
+            topology.simulate();
+
+            if let Some(trace) = trace.as_mut() {
+                for (i, party) in parties.iter_mut().enumerate() {
+                    trace
+                        .record_party(now.0, i, segments[i], &party.observer().take_recorded())
+                        .expect("Failed to write trace");
+                }
+            }
 
-        clock.increase(1);
+            if let Some(view) = view.as_mut() {
+                view.render(now, &topology, &mut parties, &mailbox);
+            }
 
-        if mailbox.all_sent() {
-            if post_done_count >= post_done_length {
-                break;
-            } else {
-                post_done_count += 1;
+            if now.0 >= max_ticks {
+                log::error!("Giving up after {} ticks", max_ticks);
+                break false;
             }
+
+            if mailbox.all_sent() && topology.is_drained() && pending_faults == 0 {
+                if post_done_count >= post_done_length {
+                    break true;
+                } else {
+                    post_done_count += 1;
+                }
+            }
+
+            // The live view shows every tick, otherwise the ticks in which nothing happens are skipped.
+            let next = match view {
+                Some(_) => now + FakeDuration(1),
+                None => next_step_at(now, &topology, &parties, &mailbox),
+            };
+            queue.schedule(next, Scheduled::Step);
+        }
+    });
+
+    if let Some(tap) = tap.as_mut() {
+        tap.flush().expect("Failed to write capture");
+    }
+    if let Some(trace) = trace.as_mut() {
+        trace
+            .finish((&clock).now().0, &segments)
+            .expect("Failed to write trace");
+    }
+
+    topology.report();
+
+    Summary {
+        finished,
+        ticks: (&clock).now().0,
+        mailbox,
+        collisions: parties.iter().map(|p| p.stats().collisions).sum(),
+        retransmissions: parties.iter().map(|p| p.stats().retransmissions).sum(),
+    }
+}
+
+fn main() {
+    pretty_env_logger::init();
+
+    let Args {
+        targets,
+        max_ticks,
+        threads,
+        tui,
+        replay,
+        replay_sends,
+        sweep,
+    } = parse_args();
+
+    if let Some(path) = replay {
+        let replay = replay::Replay::load(&path).expect("Failed to read capture");
+        let outcome = replay::run(replay, &replay_sends, max_ticks);
+        outcome.report();
+
+        let received = outcome.received.len();
+        if let Some(target) = targets.received.filter(|target| received < *target) {
+            log::error!("Received {} frames, instead of {}", received, target);
+            std::process::exit(1);
         }
+        if !outcome.finished {
+            log::error!("Giving up after {} ticks", max_ticks);
+            std::process::exit(1);
+        }
+        return;
     }
 
-    log::info!("Done in {:?}", clock.as_ref().now());
+    if let Some(sweep) = sweep {
+        let results = sweep.run(&Scenario::from_env(), max_ticks, threads);
+        if let Err(e) = sweep.write(&results) {
+            log::error!("Failed to write sweep results: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let outputs = Outputs {
+        // Set `KIRI_PCAP` to a path to capture all traffic on the first segment.
+        pcap: std::env::var_os("KIRI_PCAP"),
+        // Set `KIRI_TRACE` to a path to write what all parties and segments do as Chrome trace events.
+        trace: std::env::var_os("KIRI_TRACE"),
+        tui,
+    };
+    let summary = simulate(&Scenario::from_env(), outputs, max_ticks, threads);
+    summary.report();
 
-    mailbox.report();
+    if !summary.finished || !summary.mailbox.meets(&targets) {
+        std::process::exit(1);
+    }
 }
@@ -0,0 +1,96 @@
+use kiri_csma::{Clock, CsmaStrategyState, Event, Observer};
+
+/// Short name of a state of the strategy.
+pub fn state_name<C: Clock>(state: &CsmaStrategyState<C>) -> &'static str {
+    match state {
+        CsmaStrategyState::WaitForBusIdle => "WaitForBusIdle",
+        CsmaStrategyState::BusIdleCooldown { .. } => "BusIdleCooldown",
+        CsmaStrategyState::StartSend => "StartSend",
+        CsmaStrategyState::EnablingDriver => "EnablingDriver",
+        CsmaStrategyState::Sending => "Sending",
+        CsmaStrategyState::ConfirmingSendWithoutErrors => "Confirming",
+        CsmaStrategyState::PostSendGap { .. } => "PostSendGap",
+        CsmaStrategyState::Jamming { .. } => "Jamming",
+    }
+}
+
+/// Something that happened to a party, to be traced.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Recorded {
+    /// The party entered a state of its strategy, or a condition like being powered off.
+    State(&'static str),
+    FrameSent,
+    FrameReceived,
+    Collision,
+    EchoTimeout,
+    StateTimeout,
+}
+
+/// Keeps track of what the strategy of a party is doing, to visualise it.
+#[derive(Debug)]
+pub struct PartyObserver {
+    state: &'static str,
+    collisions: u64,
+    /// Events since they were last taken, if they are recorded at all.
+    recorded: Option<Vec<Recorded>>,
+}
+
+impl PartyObserver {
+    pub fn new(record: bool) -> Self {
+        Self {
+            state: "WaitForBusIdle",
+            collisions: 0,
+            recorded: record.then(|| vec![Recorded::State("WaitForBusIdle")]),
+        }
+    }
+
+    /// Record an event that did not come from the strategy itself.
+    pub fn record(&mut self, recorded: Recorded) {
+        if let Some(events) = self.recorded.as_mut() {
+            events.push(recorded);
+        }
+    }
+
+    /// Take the events recorded so far.
+    pub fn take_recorded(&mut self) -> Vec<Recorded> {
+        self.recorded
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    pub fn state(&self) -> &'static str {
+        self.state
+    }
+
+    pub fn collisions(&self) -> u64 {
+        self.collisions
+    }
+
+    /// Whether the party is putting its frame on the bus.
+    pub fn is_sending(&self) -> bool {
+        matches!(self.state, "Sending" | "Confirming" | "Jamming")
+    }
+}
+
+impl<C: Clock> Observer<C> for PartyObserver {
+    fn on_event(&mut self, event: Event<'_, C>) {
+        let recorded = match event {
+            Event::StateChanged(state) => {
+                self.state = state_name(state);
+                Recorded::State(self.state)
+            }
+            Event::FrameSent => Recorded::FrameSent,
+            Event::FrameReceived => Recorded::FrameReceived,
+            Event::CollisionDetected => {
+                self.collisions += 1;
+                Recorded::Collision
+            }
+            Event::EchoTimeout => Recorded::EchoTimeout,
+            Event::StateTimeout => Recorded::StateTimeout,
+            // Parties receive all frames.
+            Event::FrameSkipped => return,
+        };
+        self.record(recorded);
+    }
+}
@@ -0,0 +1,359 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use kiri_protocol::MAX_FRAME_LEN;
+
+use crate::{clock::FakeInstant, simulation::Fragment};
+
+/// Private link-type used for the raw byte stream, `LINKTYPE_USER0`.
+pub const LINKTYPE_KIRI_BYTES: u16 = 147;
+/// Private link-type used for complete COBS encoded frames, `LINKTYPE_USER1`.
+pub const LINKTYPE_KIRI_FRAMES: u16 = 148;
+
+const INTERFACE_BYTES: u32 = 0;
+const INTERFACE_FRAMES: u32 = 1;
+
+const BLOCK_SHB: u32 = 0x0A0D0D0A;
+const BLOCK_IDB: u32 = 0x00000001;
+const BLOCK_EPB: u32 = 0x00000006;
+
+const BYTE_ORDER_MAGIC: u32 = 0x1A2B3C4D;
+
+const OPTION_END: u16 = 0;
+const OPTION_IF_TSRESOL: u16 = 9;
+
+/// Flag set in the second byte of a byte record if the byte was garbled on the bus.
+pub const BYTE_FLAG_ERROR: u8 = 0x01;
+
+/// Minimal pcapng writer, only supporting the blocks required for bus captures.
+///
+/// Timestamps are written with microsecond resolution, such that one simulation tick shows up as one microsecond.
+pub struct PcapngWriter<W: Write> {
+    inner: W,
+}
+
+impl<W: Write> PcapngWriter<W> {
+    pub fn new(mut inner: W) -> io::Result<Self> {
+        // Section header block, with unknown section length.
+        let mut body = Vec::new();
+        body.extend_from_slice(&BYTE_ORDER_MAGIC.to_le_bytes());
+        body.extend_from_slice(&1u16.to_le_bytes());
+        body.extend_from_slice(&0u16.to_le_bytes());
+        body.extend_from_slice(&(-1i64).to_le_bytes());
+        write_block(&mut inner, BLOCK_SHB, &body)?;
+
+        let mut this = Self { inner };
+        this.write_interface(LINKTYPE_KIRI_BYTES)?;
+        this.write_interface(LINKTYPE_KIRI_FRAMES)?;
+        Ok(this)
+    }
+
+    fn write_interface(&mut self, linktype: u16) -> io::Result<()> {
+        let mut body = Vec::new();
+        body.extend_from_slice(&linktype.to_le_bytes());
+        body.extend_from_slice(&0u16.to_le_bytes());
+        body.extend_from_slice(&(MAX_FRAME_LEN as u32).to_le_bytes());
+        // Explicitly note the resolution of 10^-6 seconds.
+        body.extend_from_slice(&OPTION_IF_TSRESOL.to_le_bytes());
+        body.extend_from_slice(&1u16.to_le_bytes());
+        body.extend_from_slice(&[6, 0, 0, 0]);
+        body.extend_from_slice(&OPTION_END.to_le_bytes());
+        body.extend_from_slice(&0u16.to_le_bytes());
+        write_block(&mut self.inner, BLOCK_IDB, &body)
+    }
+
+    fn write_packet(&mut self, interface: u32, timestamp: u64, data: &[u8]) -> io::Result<()> {
+        let mut body = Vec::new();
+        body.extend_from_slice(&interface.to_le_bytes());
+        body.extend_from_slice(&((timestamp >> 32) as u32).to_le_bytes());
+        body.extend_from_slice(&(timestamp as u32).to_le_bytes());
+        body.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        body.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        body.extend_from_slice(data);
+        body.resize(body.len().next_multiple_of(4), 0);
+        write_block(&mut self.inner, BLOCK_EPB, &body)
+    }
+
+    /// Record a single byte as seen on the bus.
+    pub fn write_byte(&mut self, timestamp: u64, byte: u8, error: bool) -> io::Result<()> {
+        let flags = if error { BYTE_FLAG_ERROR } else { 0 };
+        self.write_packet(INTERFACE_BYTES, timestamp, &[byte, flags])
+    }
+
+    /// Record a complete COBS encoded frame, including the trailing marker.
+    pub fn write_frame(&mut self, timestamp: u64, frame: &[u8]) -> io::Result<()> {
+        self.write_packet(INTERFACE_FRAMES, timestamp, frame)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn write_block<W: Write>(w: &mut W, block_type: u32, body: &[u8]) -> io::Result<()> {
+    let total_len = (body.len() + 12) as u32;
+    w.write_all(&block_type.to_le_bytes())?;
+    w.write_all(&total_len.to_le_bytes())?;
+    w.write_all(body)?;
+    w.write_all(&total_len.to_le_bytes())
+}
+
+/// A byte read back from a capture, with the timestamp it was recorded at.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CapturedByte {
+    pub at: u64,
+    pub byte: u8,
+    pub error: bool,
+}
+
+/// Whether `capture` starts like a pcapng file, as opposed to a raw dump of the bus.
+pub fn is_pcapng(capture: &[u8]) -> bool {
+    capture.starts_with(&BLOCK_SHB.to_le_bytes())
+}
+
+/// Read back the bytes recorded by a `BusTap`, skipping the frames recorded alongside them.
+///
+/// Only little-endian sections are supported, which is what `PcapngWriter` writes.
+pub fn read_bytes(mut capture: &[u8]) -> io::Result<Vec<CapturedByte>> {
+    fn invalid(message: &str) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, message)
+    }
+
+    fn take<'a>(input: &mut &'a [u8], len: usize) -> io::Result<&'a [u8]> {
+        if input.len() < len {
+            return Err(invalid("truncated block"));
+        }
+        let (taken, rest) = input.split_at(len);
+        *input = rest;
+        Ok(taken)
+    }
+
+    fn u16_at(body: &[u8], at: usize) -> io::Result<u16> {
+        match body.get(at..at + 2) {
+            Some(bytes) => Ok(u16::from_le_bytes([bytes[0], bytes[1]])),
+            None => Err(invalid("truncated block")),
+        }
+    }
+
+    fn u32_at(body: &[u8], at: usize) -> io::Result<u32> {
+        match body.get(at..at + 4) {
+            Some(bytes) => Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])),
+            None => Err(invalid("truncated block")),
+        }
+    }
+
+    let mut bytes = Vec::new();
+    // Link-type of every interface in the current section, in order of their description.
+    let mut linktypes = Vec::new();
+
+    while !capture.is_empty() {
+        let header = take(&mut capture, 8)?;
+        let block_type = u32_at(header, 0)?;
+        let total_len = u32_at(header, 4)? as usize;
+        if total_len < 12 || !total_len.is_multiple_of(4) {
+            return Err(invalid("invalid block length"));
+        }
+        let body = take(&mut capture, total_len - 12)?;
+        take(&mut capture, 4)?;
+
+        match block_type {
+            BLOCK_SHB => {
+                if u32_at(body, 0)? != BYTE_ORDER_MAGIC {
+                    return Err(invalid("unsupported byte order"));
+                }
+                linktypes.clear();
+            }
+            BLOCK_IDB => linktypes.push(u16_at(body, 0)?),
+            BLOCK_EPB => {
+                let interface = u32_at(body, 0)? as usize;
+                let at = (u32_at(body, 4)? as u64) << 32 | u32_at(body, 8)? as u64;
+                let len = u32_at(body, 12)? as usize;
+                let data = body
+                    .get(20..20 + len)
+                    .ok_or_else(|| invalid("truncated packet"))?;
+                if linktypes.get(interface) != Some(&LINKTYPE_KIRI_BYTES) {
+                    continue;
+                }
+                if let [byte, rest @ ..] = data {
+                    bytes.push(CapturedByte {
+                        at,
+                        byte: *byte,
+                        error: rest
+                            .first()
+                            .is_some_and(|flags| flags & BYTE_FLAG_ERROR != 0),
+                    });
+                }
+            }
+            // Other blocks, i.e. statistics or comments added by other tools, tell nothing about the bus.
+            _ => (),
+        }
+    }
+
+    Ok(bytes)
+}
+
+/// Tap on a `SerialBus` that records all traffic into a pcapng capture.
+///
+/// Bytes are recorded individually, and are also grouped into frames on the COBS marker.
+pub struct BusTap<W: Write> {
+    writer: PcapngWriter<W>,
+    frame_buf: Vec<u8>,
+    frame_start: u64,
+}
+
+impl BusTap<BufWriter<File>> {
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<W: Write> BusTap<W> {
+    pub fn new(inner: W) -> io::Result<Self> {
+        Ok(Self {
+            writer: PcapngWriter::new(inner)?,
+            frame_buf: Vec::new(),
+            frame_start: 0,
+        })
+    }
+
+    /// Record the fragment currently on the bus, if any.
+    pub fn record(&mut self, now: FakeInstant, fragment: Option<Fragment>) -> io::Result<()> {
+        let fragment = match fragment {
+            Some(fragment) => fragment,
+            None => return Ok(()),
+        };
+
+        self.writer
+            .write_byte(now.0, fragment.contents(), fragment.is_error())?;
+
+        if fragment.is_error() {
+            // A garbled byte can never be part of a valid frame.
+            self.frame_buf.clear();
+            return Ok(());
+        }
+
+        if self.frame_buf.is_empty() {
+            self.frame_start = now.0;
+        }
+        self.frame_buf.push(fragment.contents());
+
+        if fragment.contents() == 0 {
+            self.writer.write_frame(self.frame_start, &self.frame_buf)?;
+            self.frame_buf.clear();
+        } else if self.frame_buf.len() > MAX_FRAME_LEN {
+            self.frame_buf.clear();
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Split `capture` into blocks, checking that the length of each is repeated at its end.
+    fn blocks(mut capture: &[u8]) -> Vec<(u32, &[u8])> {
+        let u32_at =
+            |bytes: &[u8], at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+        let mut blocks = Vec::new();
+        while !capture.is_empty() {
+            let total_len = u32_at(capture, 4) as usize;
+            assert_eq!(total_len % 4, 0);
+            assert_eq!(u32_at(capture, total_len - 4) as usize, total_len);
+            blocks.push((u32_at(capture, 0), &capture[8..total_len - 4]));
+            capture = &capture[total_len..];
+        }
+        blocks
+    }
+
+    #[test]
+    fn block_layout() {
+        let mut capture = Vec::new();
+        let mut writer = PcapngWriter::new(&mut capture).unwrap();
+        writer.write_byte(0x1_0000_0002, 0xAB, true).unwrap();
+        writer.write_frame(3, &[1, 2, 3, 4, 0]).unwrap();
+        writer.write_frame(4, &[1, 2, 3, 0]).unwrap();
+
+        let blocks = blocks(&capture);
+        let types: Vec<_> = blocks.iter().map(|(block_type, _)| *block_type).collect();
+        assert_eq!(
+            types,
+            [BLOCK_SHB, BLOCK_IDB, BLOCK_IDB, BLOCK_EPB, BLOCK_EPB, BLOCK_EPB]
+        );
+
+        // Byte order magic, version 1.0 and an unknown section length.
+        assert_eq!(
+            blocks[0].1,
+            [0x4D, 0x3C, 0x2B, 0x1A, 1, 0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]
+        );
+
+        // Link-type, snap length, and `if_tsresol` of 10^-6 padded to 32 bits before the end of the options.
+        let snaplen = (MAX_FRAME_LEN as u32).to_le_bytes();
+        for (block, linktype) in [
+            (&blocks[1], LINKTYPE_KIRI_BYTES),
+            (&blocks[2], LINKTYPE_KIRI_FRAMES),
+        ] {
+            let mut expected = linktype.to_le_bytes().to_vec();
+            expected.extend_from_slice(&[0, 0]);
+            expected.extend_from_slice(&snaplen);
+            expected.extend_from_slice(&[9, 0, 1, 0, 6, 0, 0, 0, 0, 0, 0, 0]);
+            assert_eq!(block.1, expected);
+        }
+
+        // Interface, timestamp in high and low words, captured and original length, and the data padded to 32 bits.
+        assert_eq!(
+            blocks[3].1,
+            [
+                0,
+                0,
+                0,
+                0,
+                1,
+                0,
+                0,
+                0,
+                2,
+                0,
+                0,
+                0,
+                2,
+                0,
+                0,
+                0,
+                2,
+                0,
+                0,
+                0,
+                0xAB,
+                BYTE_FLAG_ERROR,
+                0,
+                0
+            ]
+        );
+        assert_eq!(
+            blocks[4].1,
+            [1, 0, 0, 0, 0, 0, 0, 0, 3, 0, 0, 0, 5, 0, 0, 0, 5, 0, 0, 0, 1, 2, 3, 4, 0, 0, 0, 0]
+        );
+        // Not padded when already aligned.
+        assert_eq!(
+            blocks[5].1,
+            [1, 0, 0, 0, 0, 0, 0, 0, 4, 0, 0, 0, 4, 0, 0, 0, 4, 0, 0, 0, 1, 2, 3, 0]
+        );
+
+        assert_eq!(
+            read_bytes(&capture).unwrap(),
+            [CapturedByte {
+                at: 0x1_0000_0002,
+                byte: 0xAB,
+                error: true,
+            }]
+        );
+    }
+}
@@ -0,0 +1,265 @@
+//! Replaying a captured bus against a single party, to reproduce what a node in the field made of it.
+//!
+//! Captures are either written by the `KIRI_PCAP` tap, or raw dumps of a serial port as read by `kiri sniff`.
+
+use std::{collections::VecDeque, fs, io, path::Path, sync::Arc};
+
+use kiri_csma::{Clock, CsmaFrameInProgress, DropReason, ReceiveError, SendReceiveResult, Stats};
+use kiri_protocol::{Address, FrameBuilder};
+
+use crate::{
+    clock::{ClockSkew, FakeClock, FakeDuration, PartyClock},
+    pcap::{self, CapturedByte},
+    simulation::{LineProfile, SerialBus},
+    BackoffMode, Party,
+};
+
+/// Ticks of silence inserted after every frame of a raw dump, which holds no timing.
+const RAW_FRAME_GAP: u64 = 32;
+
+/// How long to keep simulating once the capture is over, for the party to finish what it was doing.
+const POST_REPLAY_TICKS: u64 = 32;
+
+/// Address the party sends its own frames from, see `run`. The last unicast address, which is unlikely to be in use.
+const PARTY_ADDRESS: u32 = 0xEFFF_FFFF;
+
+/// The bytes of a capture, put back onto a bus at the same pace.
+pub struct Replay {
+    bytes: VecDeque<CapturedByte>,
+    /// Timestamp of the first byte, which is replayed at tick 0.
+    offset: u64,
+}
+
+impl Replay {
+    pub fn new(bytes: Vec<CapturedByte>) -> Self {
+        Self {
+            offset: bytes.first().map_or(0, |byte| byte.at),
+            bytes: bytes.into(),
+        }
+    }
+
+    /// Load a pcapng capture, taking one tick per timestamp unit, or a raw dump with one byte per tick.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let capture = fs::read(path)?;
+        if pcap::is_pcapng(&capture) {
+            return Ok(Self::new(pcap::read_bytes(&capture)?));
+        }
+        Ok(Self::from_raw(&capture))
+    }
+
+    /// Replay a raw dump, with the bytes of a frame back to back and some silence in between frames.
+    pub fn from_raw(dump: &[u8]) -> Self {
+        let mut at = 0;
+        let bytes = dump
+            .iter()
+            .map(|byte| {
+                let captured = CapturedByte {
+                    at,
+                    byte: *byte,
+                    error: false,
+                };
+                at += if *byte == 0 { RAW_FRAME_GAP } else { 1 };
+                captured
+            })
+            .collect();
+        Self::new(bytes)
+    }
+
+    /// Put the bytes that are due at `now` onto `bus`.
+    pub fn simulate(&mut self, bus: &SerialBus, now: u64) {
+        while let Some(captured) = self.bytes.front() {
+            if captured.at - self.offset > now {
+                break;
+            }
+            match captured.error {
+                true => bus.write_garbled(captured.byte),
+                false => bus.write(captured.byte),
+            }
+            self.bytes.pop_front();
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.bytes.is_empty()
+    }
+}
+
+/// A frame the party received during a replay.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReceivedFrame {
+    pub at: u64,
+    pub src: Address,
+    pub dst: Address,
+    pub contents: Vec<u8>,
+}
+
+/// What the party made of a replay.
+#[derive(Debug)]
+pub struct Outcome {
+    pub received: Vec<ReceivedFrame>,
+    /// Frames that were dropped while the party was not sending, and why.
+    pub dropped: Vec<(u64, DropReason)>,
+    pub stats: Stats<FakeDuration>,
+    /// Whether the party was done before `max_ticks`.
+    pub finished: bool,
+}
+
+impl Outcome {
+    /// Log what the party did to the `log` crate.
+    pub fn report(&self) {
+        for frame in &self.received {
+            log::info!(
+                "{}: received {:?} -> {:?}: {:02x?}",
+                frame.at,
+                frame.src,
+                frame.dst,
+                frame.contents
+            );
+        }
+        for (at, reason) in &self.dropped {
+            log::info!("{}: dropped {:?}", at, reason);
+        }
+        log::info!("{:?}", self.stats);
+    }
+}
+
+/// Replay a capture against a single party, which sends a frame of its own at each of `sends` to contend for the bus.
+pub fn run(mut replay: Replay, sends: &[u64], max_ticks: u64) -> Outcome {
+    let clock = FakeClock::new();
+    let bus = Arc::new(SerialBus::new());
+    let mut strategy = Party::strategy(
+        &bus,
+        PartyClock::new(&clock, ClockSkew::default()),
+        false,
+        BackoffMode::Uniform,
+        LineProfile::default(),
+    );
+
+    let mut sends = sends.iter().copied().peekable();
+    let mut current_frame = None;
+    let mut outcome = Outcome {
+        received: Vec::new(),
+        dropped: Vec::new(),
+        stats: Stats::default(),
+        finished: false,
+    };
+    let mut post_done_count = 0;
+
+    loop {
+        bus.iterate();
+        let now = (&clock).now().0;
+        replay.simulate(&bus, now);
+
+        if current_frame.is_none() && sends.next_if(|at| *at <= now).is_some() {
+            let frame = FrameBuilder::new(Address::new(PARTY_ADDRESS), Address::broadcast())
+                .payload(b"replay")
+                .build()
+                .expect("Builder failed to pack reasonable message");
+            current_frame = Some(CsmaFrameInProgress::new(frame));
+        }
+
+        let received = match current_frame.as_mut() {
+            Some(frame) => match strategy.send_or_receive(frame) {
+                Ok(SendReceiveResult::Received(frame)) => Some(frame),
+                Ok(SendReceiveResult::SendComplete | SendReceiveResult::Expired) => {
+                    current_frame = None;
+                    None
+                }
+                Err(nb::Error::WouldBlock) => None,
+                Err(nb::Error::Other(e)) => panic!("Error: {:?}", e),
+            },
+            None => match strategy.receive_verbose() {
+                Ok(frame) => Some(frame),
+                Err(nb::Error::WouldBlock) => None,
+                Err(nb::Error::Other(ReceiveError::Dropped(reason))) => {
+                    outcome.dropped.push((now, reason));
+                    None
+                }
+                Err(nb::Error::Other(ReceiveError::UnderlyingError(e))) => panic!("Error: {:?}", e),
+            },
+        };
+        if let Some(frame) = received {
+            outcome.received.push(ReceivedFrame {
+                at: now,
+                src: frame.header.address_src,
+                dst: frame.header.address_dst,
+                contents: frame.contents.to_vec(),
+            });
+        }
+
+        clock.increase(1);
+
+        if now >= max_ticks {
+            break;
+        }
+
+        if replay.is_done() && current_frame.is_none() && sends.peek().is_none() && bus.is_idle() {
+            if post_done_count >= POST_REPLAY_TICKS {
+                outcome.finished = true;
+                break;
+            }
+            post_done_count += 1;
+        }
+    }
+
+    outcome.stats = strategy.stats().clone();
+    outcome
+}
+
+#[cfg(test)]
+mod tests {
+    use kiri_protocol::Writer;
+
+    use super::*;
+    use crate::pcap::PcapngWriter;
+
+    /// Capture `frames` as the tap would, with a garbled byte in between and some silence after each of them.
+    fn capture(frames: &[&[u8]]) -> Vec<u8> {
+        let mut capture = Vec::new();
+        let mut writer = PcapngWriter::new(&mut capture).unwrap();
+        let mut at = 1000;
+        for frame in frames {
+            for byte in *frame {
+                writer.write_byte(at, *byte, false).unwrap();
+                at += 1;
+            }
+            writer.write_frame(at, frame).unwrap();
+            writer.write_byte(at, 0x55, true).unwrap();
+            at += 50;
+        }
+        capture
+    }
+
+    #[test]
+    fn replays_capture() {
+        let first = Writer::package(Address::new(1), Address::new(2), b"field").unwrap();
+        let second = Writer::package(Address::new(2), Address::new(1), b"bug").unwrap();
+        let capture = capture(&[first.as_slice(), second.as_slice()]);
+
+        assert!(pcap::is_pcapng(&capture));
+        let bytes = pcap::read_bytes(&capture).unwrap();
+        assert_eq!(
+            bytes.len(),
+            first.as_slice().len() + second.as_slice().len() + 2
+        );
+        assert!(bytes[first.as_slice().len()].error);
+
+        let outcome = run(Replay::new(bytes), &[500], 10_000);
+        assert!(outcome.finished);
+        let contents: Vec<&[u8]> = outcome
+            .received
+            .iter()
+            .map(|frame| frame.contents.as_slice())
+            .collect();
+        assert_eq!(contents, [b"field".as_slice(), b"bug"]);
+        assert_eq!(outcome.received[0].src, Address::new(1));
+        assert_eq!(outcome.stats.frame_errors, 2);
+        assert_eq!(outcome.stats.frames_sent, 1);
+
+        // A raw dump of the same frames yields the same.
+        let dump = [first.as_slice(), second.as_slice()].concat();
+        let outcome = run(Replay::from_raw(&dump), &[], 10_000);
+        assert_eq!(outcome.received.len(), 2);
+        assert!(pcap::read_bytes(&dump[..7]).is_err());
+    }
+}
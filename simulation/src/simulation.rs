@@ -1,89 +1,223 @@
-use std::{cell::RefCell, fmt::Debug, rc::Rc};
+use std::{
+    fmt::Debug,
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
 
 use kiri_csma::Transceiver;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+/// Bits a byte takes on the line, being a start bit, 8 data bits and a stop bit.
+const BITS_PER_BYTE: u64 = 10;
+
+/// How far off a byte may be sampled, in parts per million of a bit, before the receiver reads it wrong.
+const MAX_SAMPLE_OFFSET: u64 = 500_000;
 
 #[derive(Debug, Clone, Copy)]
 pub struct Fragment {
     contents: u8,
     error: bool,
+    /// Baud rate error of the writer, see `LineProfile::baud_error_ppm`.
+    baud_error_ppm: i64,
+}
+
+impl Fragment {
+    pub fn contents(&self) -> u8 {
+        self.contents
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.error
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
 pub struct SerialBusState {
     current: Option<Fragment>,
     next: Option<Fragment>,
+    /// Amount of drivers that are stuck enabled.
+    stuck_drivers: usize,
+    /// Amount of times the bus moved on to the next fragment, identifying the current one.
+    ticks: u64,
 }
 
-pub struct SerialBus(RefCell<SerialBusState>);
+/// A bus segment, which parties on different threads may write to during the same tick.
+///
+/// Bytes written during the same tick are combined regardless of their order, such that the order in which the
+/// parties are polled does not matter.
+pub struct SerialBus(Mutex<SerialBusState>);
 
 impl SerialBus {
     pub fn new() -> Self {
-        Self(RefCell::new(SerialBusState {
+        Self(Mutex::new(SerialBusState {
             current: None,
             next: None,
+            stuck_drivers: 0,
+            ticks: 0,
         }))
     }
 
-    pub fn write(&self, mut byte: u8) {
+    pub fn write(&self, byte: u8) {
+        self.write_at_baud(byte, 0)
+    }
+
+    /// Write a byte with a UART of which the baud rate is off by `baud_error_ppm`.
+    pub fn write_at_baud(&self, byte: u8, baud_error_ppm: i64) {
+        self.write_fragment(byte, baud_error_ppm, false)
+    }
+
+    /// Write a byte that is garbled regardless of other writers, i.e. one that was garbled in a capture.
+    pub fn write_garbled(&self, byte: u8) {
+        self.write_fragment(byte, 0, true)
+    }
+
+    fn write_fragment(&self, mut byte: u8, baud_error_ppm: i64, garbled: bool) {
         // If two transceiver write at the same time, the message overlaps?
-        let mut error = false;
+        let mut state = self.0.lock().unwrap();
 
-        let mut state = self.0.borrow_mut();
+        // A stuck driver keeps the line in the idle state, fighting every other driver.
+        let mut error = garbled || state.stuck_drivers > 0;
 
-        match state.next {
-            Some(ref old_fragment) => {
-                byte = byte | old_fragment.contents;
-                error = true;
-            }
-            None => (),
+        if let Some(ref old_fragment) = state.next {
+            byte |= old_fragment.contents;
+            error = true;
         }
 
         let fragment = Fragment {
             contents: byte,
             error,
+            baud_error_ppm,
         };
 
         state.next = Some(fragment);
     }
 
+    /// Have a driver get stuck enabled, or recover from that.
+    pub fn set_driver_stuck(&self, stuck: bool) {
+        let mut state = self.0.lock().unwrap();
+        if stuck {
+            state.stuck_drivers += 1;
+        } else {
+            state.stuck_drivers -= 1;
+        }
+    }
+
     pub fn is_idle(&self) -> bool {
-        let state = self.0.borrow();
+        let state = self.0.lock().unwrap();
         state.current.is_none() && state.next.is_none()
     }
 
-    pub fn is_error(&self) -> bool {
-        let state = self.0.borrow();
-        match state.current {
-            Some(fragment) => fragment.error,
-            None => false,
-        }
+    /// The fragment currently on the bus, regardless of whether it is garbled.
+    pub fn current(&self) -> Option<Fragment> {
+        self.0.lock().unwrap().current
     }
 
-    pub fn read(&self) -> Option<u8> {
-        let state = self.0.borrow();
-        if let Some(current) = state.current {
-            if !current.error {
-                return Some(current.contents);
-            }
-        }
-        None
+    /// Amount of times the bus moved on, which tells fragments with the same contents apart.
+    pub fn ticks(&self) -> u64 {
+        self.0.lock().unwrap().ticks
     }
 
     pub fn iterate(&self) {
-        let mut state = self.0.borrow_mut();
+        let mut state = self.0.lock().unwrap();
 
         state.current = state.next;
         state.next = None;
+        state.ticks += 1;
+    }
+}
+
+/// How well the UART of a party gets bytes across, on top of the collisions on the bus.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct LineProfile {
+    /// Chance of a byte being received without its stop bit, i.e. due to noise on the line.
+    pub framing_error_rate: f64,
+    /// Chance of a byte failing its parity check.
+    pub parity_error_rate: f64,
+    /// How much faster the UART runs than the agreed baud rate, in parts per million. Negative for a slower UART.
+    ///
+    /// A receiver samples every bit a little off from where the writer put it, as far as their baud rates differ.
+    /// The receiver synchronises again on the start bit of a byte following idle time, but without idle time in
+    /// between, the offset accumulates over consecutive bytes until a byte is read wrong.
+    pub baud_error_ppm: i64,
+}
+
+impl FromStr for LineProfile {
+    type Err = String;
+
+    /// Parse `<framing error rate>:<parity error rate>:<baud error ppm>`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid line {:?}", s);
+        let rate = |rate: Option<&str>| {
+            rate.and_then(|rate| rate.parse().ok())
+                .filter(|rate| (0. ..=1.).contains(rate))
+                .ok_or_else(invalid)
+        };
+
+        let mut parts = s.split(':');
+        let framing_error_rate = rate(parts.next())?;
+        let parity_error_rate = rate(parts.next())?;
+        let baud_error_ppm = parts
+            .next()
+            .and_then(|ppm| ppm.parse().ok())
+            .ok_or_else(invalid)?;
+        if parts.next().is_some() {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            framing_error_rate,
+            parity_error_rate,
+            baud_error_ppm,
+        })
     }
 }
 
 pub struct SerialTransceiver {
-    bus: Rc<SerialBus>,
+    bus: Arc<SerialBus>,
+    line: LineProfile,
+    rng: StdRng,
+    /// How far off the current byte is sampled, in parts per million of a bit.
+    sample_offset: u64,
+    /// The tick of the fragment last read, and whether it was read wrong, such that it reads the same every time.
+    last_read: Option<(u64, bool)>,
 }
 
 impl SerialTransceiver {
-    pub fn new(bus: Rc<SerialBus>) -> Self {
-        Self { bus }
+    pub fn new(bus: Arc<SerialBus>) -> Self {
+        Self::with_line(bus, LineProfile::default())
+    }
+
+    pub fn with_line(bus: Arc<SerialBus>, line: LineProfile) -> Self {
+        Self {
+            bus,
+            line,
+            rng: StdRng::from_entropy(),
+            sample_offset: 0,
+            last_read: None,
+        }
+    }
+
+    /// Whether `fragment` is read wrong by the UART, deciding so once for every fragment.
+    fn misreads(&mut self, fragment: &Fragment) -> bool {
+        let tick = self.bus.ticks();
+        if let Some((last_tick, misread)) = self.last_read {
+            if last_tick == tick {
+                return misread;
+            }
+        }
+
+        let mismatch = (self.line.baud_error_ppm - fragment.baud_error_ppm).unsigned_abs();
+        self.sample_offset += mismatch * BITS_PER_BYTE;
+        let mut misread = self.sample_offset > MAX_SAMPLE_OFFSET;
+        if misread {
+            // The receiver will synchronise on whatever edge follows.
+            self.sample_offset = 0;
+        }
+        misread |= self.rng.gen_bool(self.line.framing_error_rate);
+        misread |= self.rng.gen_bool(self.line.parity_error_rate);
+
+        self.last_read = Some((tick, misread));
+        misread
     }
 }
 
@@ -95,18 +229,25 @@ impl Transceiver for SerialTransceiver {
     }
 
     fn write(&mut self, byte: u8) -> nb::Result<(), Self::Error> {
-        self.bus.write(byte);
+        self.bus.write_at_baud(byte, self.line.baud_error_ppm);
         Ok(())
     }
 
     fn read(&mut self) -> nb::Result<u8, kiri_csma::ReadError<Self::Error>> {
-        if self.bus.is_error() {
+        let fragment = match self.bus.current() {
+            Some(fragment) => fragment,
+            None => {
+                // Idle time, after which the receiver synchronises on the next start bit.
+                self.sample_offset = 0;
+                return nb::Result::Err(nb::Error::WouldBlock);
+            }
+        };
+
+        // Framing errors, parity errors and garbled bytes all look the same to the strategy.
+        if fragment.error || self.misreads(&fragment) {
             nb::Result::Err(nb::Error::Other(kiri_csma::ReadError::FrameError))
         } else {
-            match self.bus.read() {
-                Some(b) => Ok(b),
-                None => nb::Result::Err(nb::Error::WouldBlock),
-            }
+            Ok(fragment.contents)
         }
     }
 
@@ -118,3 +259,70 @@ impl Debug for SerialTransceiver {
         f.debug_struct("SerialTransceiver").finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use kiri_csma::ReadError;
+
+    use super::*;
+
+    /// Write `bytes` back to back from `writer`, yielding which of them `reader` reads wrong.
+    fn misread(writer: LineProfile, reader: LineProfile, bytes: usize) -> Vec<bool> {
+        let bus = Arc::new(SerialBus::new());
+        let mut writer = SerialTransceiver::with_line(bus.clone(), writer);
+        let mut reader = SerialTransceiver::with_line(bus.clone(), reader);
+        (0..bytes)
+            .map(|_| {
+                writer.write(0x55).unwrap();
+                bus.iterate();
+                let read = reader.read();
+                // Reading again yields the same.
+                assert_eq!(reader.read().ok(), read.as_ref().ok().copied());
+                matches!(read, Err(nb::Error::Other(ReadError::FrameError)))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn baud_mismatch() {
+        let fast = LineProfile {
+            baud_error_ppm: 20_000,
+            ..Default::default()
+        };
+        let misread = misread(fast, LineProfile::default(), 9);
+        assert_eq!(
+            misread,
+            [false, false, true, false, false, true, false, false, true]
+        );
+
+        // Idle time in between lets the receiver synchronise again.
+        let bus = Arc::new(SerialBus::new());
+        let mut writer = SerialTransceiver::with_line(bus.clone(), fast);
+        let mut reader = SerialTransceiver::new(bus.clone());
+        for _ in 0..9 {
+            writer.write(0x55).unwrap();
+            bus.iterate();
+            assert_eq!(reader.read().ok(), Some(0x55));
+            bus.iterate();
+            assert!(matches!(reader.read(), Err(nb::Error::WouldBlock)));
+        }
+    }
+
+    #[test]
+    fn line_errors() {
+        assert!(misread(LineProfile::default(), LineProfile::default(), 100)
+            .iter()
+            .all(|misread| !misread));
+        let noisy = "1:0:0".parse().unwrap();
+        assert!(misread(LineProfile::default(), noisy, 10)
+            .iter()
+            .all(|misread| *misread));
+        let parity = "0:1:0".parse().unwrap();
+        assert!(misread(LineProfile::default(), parity, 10)
+            .iter()
+            .all(|misread| *misread));
+
+        assert!("2:0:0".parse::<LineProfile>().is_err());
+        assert!("0:0".parse::<LineProfile>().is_err());
+    }
+}
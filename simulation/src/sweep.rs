@@ -0,0 +1,302 @@
+//! Simulating every combination of a matrix of parameters, to compare them by their metrics rather than by log lines.
+//!
+//! Every point of the matrix is simulated on top of the scenario configured by the `KIRI_*` environment variables.
+//! The metrics are written as CSV, and optionally charted as SVG with a line for every load and backoff.
+
+use std::{
+    fmt::Write as _,
+    fs,
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use crate::{simulate, traffic::TrafficModel, BackoffMode, Outputs, Scenario};
+
+/// The parameters to combine, and where to write the results to.
+#[derive(Debug)]
+pub struct Sweep {
+    pub parties: Vec<usize>,
+    pub loads: Vec<TrafficModel>,
+    pub backoffs: Vec<BackoffMode>,
+    /// Path to write the CSV to, instead of stdout.
+    pub csv: Option<PathBuf>,
+    /// Directory to write the charts to.
+    pub plot: Option<PathBuf>,
+}
+
+impl Default for Sweep {
+    fn default() -> Self {
+        Self {
+            parties: vec![2, 5, 10, 20],
+            loads: vec![
+                TrafficModel::Saturated,
+                TrafficModel::Poisson {
+                    mean_interval: 2000,
+                },
+            ],
+            backoffs: vec![BackoffMode::Uniform, BackoffMode::Adaptive],
+            csv: None,
+            plot: None,
+        }
+    }
+}
+
+/// The metrics of a single simulation in the sweep, for the messages between healthy parties.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Point {
+    pub parties: usize,
+    pub load: TrafficModel,
+    pub backoff: BackoffMode,
+    /// Whether the simulation finished within the maximum amount of ticks.
+    pub finished: bool,
+    pub ticks: u64,
+    /// Percentage of the messages that was delivered.
+    pub delivery: f64,
+    pub mean_latency: u64,
+    pub max_latency: u64,
+    pub collisions: u64,
+    pub retransmissions: u64,
+}
+
+/// Picks the metric to chart from a point.
+type Metric = fn(&Point) -> f64;
+
+const CSV_HEADER: &str = "parties,load,backoff,finished,ticks,delivery,mean_latency,max_latency,collisions,retransmissions";
+
+impl Sweep {
+    /// Simulate every combination on top of `base`, in the order of the parties, loads and backoffs.
+    pub fn run(&self, base: &Scenario, max_ticks: u64, threads: usize) -> Vec<Point> {
+        let mut points = Vec::new();
+        for parties in &self.parties {
+            for load in &self.loads {
+                for backoff in &self.backoffs {
+                    let scenario = Scenario {
+                        party_count: (*parties).max(2),
+                        models: vec![*load],
+                        backoff: *backoff,
+                        ..base.clone()
+                    };
+                    let summary = simulate(&scenario, Outputs::default(), max_ticks, threads);
+                    let healthy = &summary.mailbox.healthy;
+                    points.push(Point {
+                        parties: scenario.party_count,
+                        load: *load,
+                        backoff: *backoff,
+                        finished: summary.finished,
+                        ticks: summary.ticks,
+                        delivery: healthy.delivery(),
+                        mean_latency: healthy.mean_latency(),
+                        max_latency: healthy.max_latency,
+                        collisions: summary.collisions,
+                        retransmissions: summary.retransmissions,
+                    });
+                }
+            }
+        }
+        points
+    }
+
+    /// Write `points` as CSV, and chart them if asked to.
+    pub fn write(&self, points: &[Point]) -> io::Result<()> {
+        match &self.csv {
+            Some(path) => write_csv(&mut io::BufWriter::new(fs::File::create(path)?), points)?,
+            None => write_csv(&mut io::stdout().lock(), points)?,
+        }
+
+        if let Some(dir) = &self.plot {
+            fs::create_dir_all(dir)?;
+            let charts: [(&str, &str, Metric); 3] = [
+                ("delivery", "Delivered (%)", |point| point.delivery),
+                ("latency", "Mean latency (ticks)", |point| {
+                    point.mean_latency as f64
+                }),
+                ("collisions", "Collisions", |point| point.collisions as f64),
+            ];
+            for (name, label, metric) in charts {
+                fs::write(
+                    dir.join(format!("{}.svg", name)),
+                    chart(label, points, metric),
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn write_csv(out: &mut impl Write, points: &[Point]) -> io::Result<()> {
+    writeln!(out, "{}", CSV_HEADER)?;
+    for point in points {
+        writeln!(
+            out,
+            "{},{},{},{},{},{:.2},{},{},{},{}",
+            point.parties,
+            point.load,
+            point.backoff,
+            point.finished,
+            point.ticks,
+            point.delivery,
+            point.mean_latency,
+            point.max_latency,
+            point.collisions,
+            point.retransmissions
+        )?;
+    }
+    out.flush()
+}
+
+const WIDTH: f64 = 640.;
+const HEIGHT: f64 = 400.;
+/// Room for the labels left of and below the plot area.
+const MARGIN: f64 = 60.;
+const COLORS: [&str; 6] = [
+    "#1f77b4", "#ff7f0e", "#2ca02c", "#d62728", "#9467bd", "#8c564b",
+];
+
+/// Chart `metric` against the amount of parties as an SVG, with a line for every load and backoff.
+fn chart(label: &str, points: &[Point], metric: Metric) -> String {
+    let mut series: Vec<(String, Vec<(f64, f64)>)> = Vec::new();
+    for point in points {
+        let name = format!("{} {}", point.load, point.backoff);
+        let value = (point.parties as f64, metric(point));
+        match series.iter_mut().find(|(series, _)| *series == name) {
+            Some((_, values)) => values.push(value),
+            None => series.push((name, vec![value])),
+        }
+    }
+
+    let (min_x, max_x) = points
+        .iter()
+        .fold((f64::MAX, f64::MIN), |(min, max), point| {
+            (min.min(point.parties as f64), max.max(point.parties as f64))
+        });
+    let max_y = points.iter().map(metric).fold(0., f64::max).max(1.);
+    let x = |value: f64| MARGIN + (value - min_x) / (max_x - min_x).max(1.) * (WIDTH - 2. * MARGIN);
+    let y = |value: f64| HEIGHT - MARGIN - value / max_y * (HEIGHT - 2. * MARGIN);
+
+    // Writing to a `String` does not fail.
+    let mut svg = String::new();
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}" font-family="sans-serif" font-size="12">"#,
+        w = WIDTH,
+        h = HEIGHT
+    );
+    let _ = writeln!(svg, r#"<rect width="100%" height="100%" fill="white"/>"#);
+    let _ = writeln!(
+        svg,
+        r#"<path d="M{l} {t} V{b} H{r}" fill="none" stroke="black"/>"#,
+        l = MARGIN,
+        t = MARGIN,
+        b = HEIGHT - MARGIN,
+        r = WIDTH - MARGIN
+    );
+    for tick in 0..=4 {
+        let value = max_y * tick as f64 / 4.;
+        let _ = writeln!(
+            svg,
+            r#"<text x="{:.1}" y="{:.1}" text-anchor="end">{:.0}</text>"#,
+            MARGIN - 6.,
+            y(value) + 4.,
+            value
+        );
+    }
+    let mut parties: Vec<usize> = points.iter().map(|point| point.parties).collect();
+    parties.sort_unstable();
+    parties.dedup();
+    for party_count in parties {
+        let _ = writeln!(
+            svg,
+            r#"<text x="{:.1}" y="{:.1}" text-anchor="middle">{}</text>"#,
+            x(party_count as f64),
+            HEIGHT - MARGIN + 18.,
+            party_count
+        );
+    }
+    let _ = writeln!(
+        svg,
+        r#"<text x="{:.1}" y="{:.1}" text-anchor="middle">Parties</text>"#,
+        WIDTH / 2.,
+        HEIGHT - 12.
+    );
+    let _ = writeln!(
+        svg,
+        r#"<text x="16" y="{y}" text-anchor="middle" transform="rotate(-90 16 {y})">{label}</text>"#,
+        y = HEIGHT / 2.,
+        label = label
+    );
+
+    for (i, (name, values)) in series.iter().enumerate() {
+        let color = COLORS[i % COLORS.len()];
+        let line: Vec<String> = values
+            .iter()
+            .map(|(party_count, value)| format!("{:.1},{:.1}", x(*party_count), y(*value)))
+            .collect();
+        let _ = writeln!(
+            svg,
+            r#"<polyline points="{}" fill="none" stroke="{}" stroke-width="2"/>"#,
+            line.join(" "),
+            color
+        );
+        let _ = writeln!(
+            svg,
+            r#"<text x="{:.1}" y="{:.1}" fill="{}">{}</text>"#,
+            MARGIN + 10.,
+            MARGIN + 16. * i as f64,
+            color,
+            name
+        );
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sweep() {
+        let sweep = Sweep {
+            parties: vec![2, 3],
+            loads: vec![TrafficModel::Saturated],
+            ..Default::default()
+        };
+        let base = Scenario {
+            party_count: 10,
+            segment_count: 1,
+            models: vec![TrafficModel::Saturated],
+            backoff: BackoffMode::Uniform,
+            skews: vec![Default::default()],
+            lines: vec![Default::default()],
+            events: Vec::new(),
+        };
+        let points = sweep.run(&base, 1_000_000, 1);
+        let combinations: Vec<_> = points
+            .iter()
+            .map(|point| (point.parties, point.backoff))
+            .collect();
+        assert_eq!(
+            combinations,
+            [
+                (2, BackoffMode::Uniform),
+                (2, BackoffMode::Adaptive),
+                (3, BackoffMode::Uniform),
+                (3, BackoffMode::Adaptive)
+            ]
+        );
+        assert!(points.iter().all(|point| point.finished));
+
+        let mut csv = Vec::new();
+        write_csv(&mut csv, &points).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert_eq!(csv.lines().next(), Some(CSV_HEADER));
+        assert!(csv
+            .lines()
+            .nth(1)
+            .unwrap()
+            .starts_with("2,saturated,uniform,true,"));
+
+        let svg = chart("Delivered (%)", &points, |point| point.delivery);
+        assert_eq!(svg.matches("<polyline").count(), 2);
+    }
+}
@@ -0,0 +1,215 @@
+use std::sync::Arc;
+
+use kiri_csma::CsmaStrategy;
+use kiri_protocol::Address;
+use kiri_router::{Route, Router, RoutingTable};
+use rand::prelude::ThreadRng;
+
+use crate::{
+    clock::{ClockSkew, FakeClock, FakeInstant, PartyClock},
+    simulation::{SerialBus, SerialTransceiver},
+    BusConf,
+};
+
+/// How many frames a bridge queues per segment.
+const BRIDGE_QUEUE_LEN: usize = 16;
+
+type Bridge<'a> = Router<SerialTransceiver, PartyClock<'a>, ThreadRng, 2, 2, BRIDGE_QUEUE_LEN>;
+
+/// Bus segments in a line, with a bridge between every pair of neighbouring segments.
+///
+/// The parties are divided over the segments in order of their address.
+pub struct Topology<'a> {
+    segments: Vec<Arc<SerialBus>>,
+    bridges: Vec<Bridge<'a>>,
+    party_count: usize,
+}
+
+impl<'a> Topology<'a> {
+    pub fn new(clock: &'a FakeClock, segment_count: usize, party_count: usize) -> Self {
+        let segments: Vec<_> = (0..segment_count)
+            .map(|_| Arc::new(SerialBus::new()))
+            .collect();
+
+        let mut this = Self {
+            segments,
+            bridges: Vec::new(),
+            party_count,
+        };
+
+        // Bridges run on the simulation time.
+        let clock = PartyClock::new(clock, ClockSkew::default());
+
+        for i in 1..segment_count {
+            // Everything in front of the bridge is reachable through port 0, everything behind it through port 1.
+            let first_behind = (0..party_count)
+                .find(|party| this.segment_of(Address::new(*party as u32)) >= i)
+                .unwrap_or(party_count) as u32;
+
+            let mut table = RoutingTable::new();
+            let routes = [
+                Route {
+                    first: Address::new(0),
+                    last: Address::new(first_behind - 1),
+                    port: 0,
+                },
+                Route {
+                    first: Address::new(first_behind),
+                    last: Address::new(party_count as u32 - 1),
+                    port: 1,
+                },
+            ];
+            for route in routes {
+                table.add(route).unwrap();
+            }
+
+            let strategy = |segment: &Arc<SerialBus>| {
+                CsmaStrategy::new::<BusConf>(
+                    SerialTransceiver::new(segment.clone()),
+                    clock,
+                    rand::thread_rng(),
+                )
+            };
+            let ports = [strategy(&this.segments[i - 1]), strategy(&this.segments[i])];
+            this.bridges.push(Router::new(ports, table));
+        }
+
+        this
+    }
+
+    /// Amount of bridges a frame has to pass through at most.
+    pub fn diameter(&self) -> usize {
+        self.bridges.len()
+    }
+
+    pub fn party_count(&self) -> usize {
+        self.party_count
+    }
+
+    pub fn segment_of(&self, address: Address) -> usize {
+        address.to_primitive() as usize * self.segments.len() / self.party_count
+    }
+
+    pub fn segment(&self, i: usize) -> &Arc<SerialBus> {
+        &self.segments[i]
+    }
+
+    /// Advance the bytes on all segments.
+    pub fn iterate(&self) {
+        for segment in &self.segments {
+            segment.iterate();
+        }
+    }
+
+    /// Whether no bytes are on any of the segments.
+    pub fn is_idle(&self) -> bool {
+        self.segments.iter().all(|segment| segment.is_idle())
+    }
+
+    /// When any bridge needs to be polled next, see `Router::next_poll_at`.
+    pub fn next_poll_at(&self) -> Option<FakeInstant> {
+        self.bridges
+            .iter()
+            .filter_map(|bridge| bridge.next_poll_at())
+            .min()
+    }
+
+    /// Poll all bridges once, forwarding frames between the segments.
+    pub fn simulate(&mut self) {
+        for bridge in self.bridges.iter_mut() {
+            if let Err(e) = bridge.poll() {
+                panic!("Bridge error: {:?}", e);
+            }
+        }
+    }
+
+    /// Whether no bridge has any frames left to forward.
+    pub fn is_drained(&self) -> bool {
+        self.bridges
+            .iter()
+            .all(|bridge| bridge.queue_len(0) == 0 && bridge.queue_len(1) == 0)
+    }
+
+    /// Log the statistics of all bridges to the `log` crate.
+    pub fn report(&self) {
+        for (i, bridge) in self.bridges.iter().enumerate() {
+            log::info!("Bridge {} <-> {}: {:?}", i, i + 1, bridge.stats());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use kiri_csma::{CsmaFrameInProgress, SendReceiveResult};
+    use kiri_protocol::Writer;
+    use rand::rngs::mock::StepRng;
+
+    use super::*;
+
+    /// Send a frame with `hop_limit` from the first party to the last, yielding whether it arrived.
+    fn reaches_last(segment_count: usize, hop_limit: u8) -> bool {
+        const PARTIES: usize = 9;
+        let clock = FakeClock::new();
+        let mut topology = Topology::new(&clock, segment_count, PARTIES);
+        let party = |segment: usize| {
+            CsmaStrategy::new::<BusConf>(
+                SerialTransceiver::new(topology.segment(segment).clone()),
+                PartyClock::new(&clock, ClockSkew::default()),
+                StepRng::new(0, 7919),
+            )
+        };
+        let mut sender = party(0);
+        let mut receiver = party(segment_count - 1);
+
+        let dst = Address::new(PARTIES as u32 - 1);
+        let frame =
+            Writer::package_with_hop_limit(Address::new(0), dst, hop_limit, b"far").unwrap();
+        let mut frame = CsmaFrameInProgress::new(frame);
+        let mut sent = false;
+        for _ in 0..20_000 {
+            clock.increase(1);
+            topology.iterate();
+            if !sent {
+                match sender.send_or_receive(&mut frame) {
+                    Ok(SendReceiveResult::SendComplete) => sent = true,
+                    Ok(_) | Err(nb::Error::WouldBlock) => (),
+                    Err(nb::Error::Other(e)) => panic!("Sender error: {:?}", e),
+                }
+            }
+            topology.simulate();
+            match receiver.receive() {
+                Ok(frame) if frame.header.address_dst == dst => {
+                    assert_eq!(frame.payload(), Ok(&b"far"[..]));
+                    return true;
+                }
+                Ok(_) | Err(nb::Error::WouldBlock) => (),
+                Err(nb::Error::Other(e)) => panic!("Receiver error: {:?}", e),
+            }
+        }
+        assert!(sent);
+        false
+    }
+
+    #[test]
+    fn segments_by_address() {
+        let clock = FakeClock::new();
+        let topology = Topology::new(&clock, 3, 9);
+        assert_eq!(topology.diameter(), 2);
+        let segments: Vec<_> = (0..9)
+            .map(|party| topology.segment_of(Address::new(party)))
+            .collect();
+        assert_eq!(segments, [0, 0, 0, 1, 1, 1, 2, 2, 2]);
+
+        let single = Topology::new(&clock, 1, 9);
+        assert_eq!(single.diameter(), 0);
+        assert_eq!(single.segment_of(Address::new(8)), 0);
+    }
+
+    #[test]
+    fn reachability() {
+        assert!(reaches_last(1, 0));
+        assert!(reaches_last(3, 2));
+        // Every bridge takes one hop, hence the frame does not make it past the second bridge.
+        assert!(!reaches_last(3, 1));
+    }
+}
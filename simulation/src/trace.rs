@@ -0,0 +1,221 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use serde_json::{json, Value};
+
+use crate::{observer::Recorded, simulation::Fragment};
+
+/// Writes the states of all parties and the occupation of the bus as Chrome trace events, see
+/// <https://docs.google.com/document/d/1JU6C-2wA5D3qbT_O2C3CGsRVoFmjqTjGQIpaXBSpHqI>.
+///
+/// Every segment shows up as a process, with a thread per party and one for the bus itself.
+/// Timestamps are in microseconds, such that one simulation tick shows up as one microsecond, like in captures.
+pub struct TraceWriter<W: Write> {
+    out: W,
+    first: bool,
+    /// Current state of every party, and since when.
+    states: Vec<Option<(&'static str, u64)>>,
+    /// Whether every segment is busy or garbled, and since when.
+    buses: Vec<Option<(&'static str, u64)>>,
+}
+
+impl TraceWriter<BufWriter<File>> {
+    pub fn create(path: impl AsRef<Path>, segments: &[usize]) -> io::Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?), segments)
+    }
+}
+
+impl<W: Write> TraceWriter<W> {
+    /// Write the trace of parties on `segments`, in order of their address.
+    pub fn new(mut out: W, segments: &[usize]) -> io::Result<Self> {
+        out.write_all(b"[\n")?;
+
+        let mut this = Self {
+            out,
+            first: true,
+            states: vec![None; segments.len()],
+            buses: vec![None; segments.iter().max().map_or(0, |max| max + 1)],
+        };
+
+        for segment in 0..this.buses.len() {
+            this.write(json!({
+                "name": "process_name", "ph": "M", "pid": segment,
+                "args": { "name": format!("segment {}", segment) },
+            }))?;
+            this.write(json!({
+                "name": "thread_name", "ph": "M", "pid": segment, "tid": this.bus_tid(),
+                "args": { "name": "bus" },
+            }))?;
+        }
+        for (party, segment) in segments.iter().enumerate() {
+            this.write(json!({
+                "name": "thread_name", "ph": "M", "pid": segment, "tid": party,
+                "args": { "name": format!("party {}", party) },
+            }))?;
+        }
+
+        Ok(this)
+    }
+
+    /// Thread of the bus, following those of the parties.
+    fn bus_tid(&self) -> usize {
+        self.states.len()
+    }
+
+    fn write(&mut self, event: Value) -> io::Result<()> {
+        if !self.first {
+            self.out.write_all(b",\n")?;
+        }
+        self.first = false;
+        serde_json::to_writer(&mut self.out, &event)?;
+        Ok(())
+    }
+
+    fn complete(
+        &mut self,
+        name: &str,
+        cat: &str,
+        pid: usize,
+        tid: usize,
+        start: u64,
+        now: u64,
+    ) -> io::Result<()> {
+        self.write(json!({
+            "name": name, "cat": cat, "ph": "X", "ts": start, "dur": now - start, "pid": pid, "tid": tid,
+        }))
+    }
+
+    /// Record what happened to `party` on `segment` at `now`.
+    pub fn record_party(
+        &mut self,
+        now: u64,
+        party: usize,
+        segment: usize,
+        events: &[Recorded],
+    ) -> io::Result<()> {
+        for event in events {
+            let name = match event {
+                Recorded::State(state) => {
+                    // Staying in the same state continues the same slice.
+                    if matches!(self.states[party], Some((current, _)) if current == *state) {
+                        continue;
+                    }
+                    match self.states[party].replace((state, now)) {
+                        // States that did not last are left out, like the initial state of a party that is rebuilt.
+                        Some((previous, since)) if since < now => {
+                            self.complete(previous, "state", segment, party, since, now)?;
+                        }
+                        _ => (),
+                    }
+                    continue;
+                }
+                Recorded::FrameSent => "FrameSent",
+                Recorded::FrameReceived => "FrameReceived",
+                Recorded::Collision => "Collision",
+                Recorded::EchoTimeout => "EchoTimeout",
+                Recorded::StateTimeout => "StateTimeout",
+            };
+            self.write(json!({
+                "name": name, "cat": "frame", "ph": "i", "s": "t", "ts": now, "pid": segment, "tid": party,
+            }))?;
+        }
+        Ok(())
+    }
+
+    /// Record the byte on `segment` at `now`, if any.
+    pub fn record_bus(
+        &mut self,
+        now: u64,
+        segment: usize,
+        fragment: Option<Fragment>,
+    ) -> io::Result<()> {
+        let occupation = fragment.map(|fragment| match fragment.is_error() {
+            true => "garbled",
+            false => "busy",
+        });
+
+        match self.buses[segment] {
+            Some((previous, _)) if Some(previous) == occupation => (),
+            previous => {
+                if let Some((previous, since)) = previous {
+                    self.complete(previous, "bus", segment, self.bus_tid(), since, now)?;
+                }
+                self.buses[segment] = occupation.map(|occupation| (occupation, now));
+            }
+        }
+        Ok(())
+    }
+
+    /// Close all states that are still going on, and finish the trace.
+    pub fn finish(&mut self, now: u64, segments: &[usize]) -> io::Result<()> {
+        for (party, segment) in segments.iter().enumerate() {
+            if let Some((state, since)) = self.states[party].take() {
+                self.complete(state, "state", *segment, party, since, now)?;
+            }
+        }
+        for segment in 0..self.buses.len() {
+            if let Some((occupation, since)) = self.buses[segment].take() {
+                self.complete(occupation, "bus", segment, self.bus_tid(), since, now)?;
+            }
+        }
+        self.out.write_all(b"\n]\n")?;
+        self.out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::simulation::SerialBus;
+
+    #[test]
+    fn short_run() {
+        let bus = Arc::new(SerialBus::new());
+        let segments = [0, 0];
+        let mut out = Vec::new();
+        let mut trace = TraceWriter::new(&mut out, &segments).unwrap();
+
+        trace
+            .record_party(0, 0, 0, &[Recorded::State("Idle")])
+            .unwrap();
+        trace
+            .record_party(0, 1, 0, &[Recorded::State("Idle")])
+            .unwrap();
+        trace.record_bus(0, 0, None).unwrap();
+
+        bus.write(0x55);
+        bus.iterate();
+        trace.record_bus(4, 0, bus.current()).unwrap();
+        trace
+            .record_party(5, 0, 0, &[Recorded::State("Sending"), Recorded::FrameSent])
+            .unwrap();
+        // Staying idle continues the slice of party 1.
+        trace
+            .record_party(5, 1, 0, &[Recorded::State("Idle")])
+            .unwrap();
+        bus.iterate();
+        trace.record_bus(7, 0, bus.current()).unwrap();
+        trace.finish(10, &segments).unwrap();
+
+        let events: Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(
+            events,
+            json!([
+                { "name": "process_name", "ph": "M", "pid": 0, "args": { "name": "segment 0" } },
+                { "name": "thread_name", "ph": "M", "pid": 0, "tid": 2, "args": { "name": "bus" } },
+                { "name": "thread_name", "ph": "M", "pid": 0, "tid": 0, "args": { "name": "party 0" } },
+                { "name": "thread_name", "ph": "M", "pid": 0, "tid": 1, "args": { "name": "party 1" } },
+                { "name": "Idle", "cat": "state", "ph": "X", "ts": 0, "dur": 5, "pid": 0, "tid": 0 },
+                { "name": "FrameSent", "cat": "frame", "ph": "i", "s": "t", "ts": 5, "pid": 0, "tid": 0 },
+                { "name": "busy", "cat": "bus", "ph": "X", "ts": 4, "dur": 3, "pid": 0, "tid": 2 },
+                { "name": "Sending", "cat": "state", "ph": "X", "ts": 5, "dur": 5, "pid": 0, "tid": 0 },
+                { "name": "Idle", "cat": "state", "ph": "X", "ts": 0, "dur": 10, "pid": 0, "tid": 1 },
+            ])
+        );
+    }
+}
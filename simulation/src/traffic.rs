@@ -0,0 +1,231 @@
+use std::{fmt, str::FromStr};
+
+use rand::Rng;
+
+/// How a party generates new messages.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TrafficModel {
+    /// A new message is ready as soon as the previous one was taken, keeping the party busy all the time.
+    Saturated,
+    /// A new message every `interval` ticks.
+    Constant { interval: u64 },
+    /// Messages arrive independently, on average every `mean_interval` ticks.
+    Poisson { mean_interval: u64 },
+    /// Periods of `on` ticks with a new message every `interval` ticks, alternated by `off` ticks of silence.
+    Bursty { on: u64, off: u64, interval: u64 },
+    /// Requests arrive like `Poisson`, and every receiver of a request answers it with a response.
+    RequestResponse { mean_interval: u64 },
+}
+
+impl FromStr for TrafficModel {
+    type Err = String;
+
+    /// Parse `saturated`, `constant:<interval>`, `poisson:<mean>`, `bursty:<on>:<off>:<interval>` or `request:<mean>`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(':');
+        let name = parts.next().unwrap_or_default();
+        let params = parts
+            .map(|part| part.parse::<u64>().map_err(|e| format!("{}: {}", s, e)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(match (name, params.as_slice()) {
+            ("saturated", []) => TrafficModel::Saturated,
+            ("constant", [interval]) => TrafficModel::Constant {
+                interval: (*interval).max(1),
+            },
+            ("poisson", [mean_interval]) => TrafficModel::Poisson {
+                mean_interval: *mean_interval,
+            },
+            ("bursty", [on, off, interval]) => TrafficModel::Bursty {
+                on: *on,
+                off: *off,
+                interval: (*interval).max(1),
+            },
+            ("request", [mean_interval]) => TrafficModel::RequestResponse {
+                mean_interval: *mean_interval,
+            },
+            _ => return Err(format!("unknown traffic model {:?}", s)),
+        })
+    }
+}
+
+impl fmt::Display for TrafficModel {
+    /// Format the model as `from_str` parses it.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrafficModel::Saturated => write!(f, "saturated"),
+            TrafficModel::Constant { interval } => write!(f, "constant:{}", interval),
+            TrafficModel::Poisson { mean_interval } => write!(f, "poisson:{}", mean_interval),
+            TrafficModel::Bursty { on, off, interval } => {
+                write!(f, "bursty:{}:{}:{}", on, off, interval)
+            }
+            TrafficModel::RequestResponse { mean_interval } => {
+                write!(f, "request:{}", mean_interval)
+            }
+        }
+    }
+}
+
+/// Kind of message a generator yields.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Arrival {
+    Data,
+    Request,
+}
+
+/// Generates the arrivals of messages according to a `TrafficModel`, up to a fixed amount of messages.
+pub struct Generator {
+    model: TrafficModel,
+    remaining: usize,
+    next_at: u64,
+}
+
+impl Generator {
+    pub fn new(model: TrafficModel, messages: usize) -> Self {
+        Self {
+            model,
+            remaining: messages,
+            next_at: 0,
+        }
+    }
+
+    pub fn is_exhausted(&self) -> bool {
+        self.remaining == 0
+    }
+
+    /// When the next message arrives at the earliest, while the party has `queued` messages waiting to be sent.
+    ///
+    /// Yields `None` if no message will arrive, or only once the party took the queued ones.
+    pub fn next_at(&self, queued: usize) -> Option<u64> {
+        match self.model {
+            _ if self.remaining == 0 => None,
+            TrafficModel::Saturated => (queued == 0).then_some(0),
+            _ => Some(self.next_at),
+        }
+    }
+
+    /// Poll for a message arriving at `now`, while the party has `queued` messages waiting to be sent.
+    ///
+    /// Yields at most one message per call; arrivals that are due at the same time follow on the next calls.
+    pub fn poll(&mut self, now: u64, queued: usize, rng: &mut impl Rng) -> Option<Arrival> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        let arrival = match self.model {
+            TrafficModel::Saturated => (queued == 0).then_some(Arrival::Data),
+            _ if now < self.next_at => None,
+            TrafficModel::Constant { interval } => {
+                self.next_at += interval;
+                Some(Arrival::Data)
+            }
+            TrafficModel::Poisson { mean_interval } => {
+                self.next_at += exponential(mean_interval, rng);
+                Some(Arrival::Data)
+            }
+            TrafficModel::Bursty { on, off, interval } => {
+                if now % (on + off).max(1) < on {
+                    self.next_at = now + interval;
+                    Some(Arrival::Data)
+                } else {
+                    // Wait for the next period to start.
+                    self.next_at = now - now % (on + off) + on + off;
+                    None
+                }
+            }
+            TrafficModel::RequestResponse { mean_interval } => {
+                self.next_at += exponential(mean_interval, rng);
+                Some(Arrival::Request)
+            }
+        };
+
+        if arrival.is_some() {
+            self.remaining -= 1;
+        }
+        arrival
+    }
+}
+
+/// Sample the time until the next arrival of a Poisson process with a mean interval of `mean` ticks.
+fn exponential(mean: u64, rng: &mut impl Rng) -> u64 {
+    let u: f64 = rng.gen();
+    (-(1. - u).ln() * mean as f64) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    /// When each of `messages` arrives, polling as soon as the generator says the next one is due.
+    fn arrivals(model: TrafficModel, messages: usize) -> Vec<u64> {
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut generator = Generator::new(model, messages);
+        let mut arrivals = Vec::new();
+        while let Some(at) = generator.next_at(0) {
+            if generator.poll(at, 0, &mut rng).is_some() {
+                arrivals.push(at);
+            }
+        }
+        assert!(generator.is_exhausted());
+        arrivals
+    }
+
+    #[test]
+    fn parse() {
+        for model in [
+            "saturated",
+            "constant:10",
+            "poisson:250",
+            "bursty:100:900:5",
+            "request:40",
+        ] {
+            assert_eq!(model.parse::<TrafficModel>().unwrap().to_string(), model);
+        }
+        assert_eq!(
+            "constant:0".parse(),
+            Ok(TrafficModel::Constant { interval: 1 })
+        );
+        assert!("constant".parse::<TrafficModel>().is_err());
+        assert!("poisson:x".parse::<TrafficModel>().is_err());
+        assert!("uniform:10".parse::<TrafficModel>().is_err());
+    }
+
+    #[test]
+    fn constant_rate() {
+        let arrivals = arrivals(TrafficModel::Constant { interval: 10 }, 5);
+        assert_eq!(arrivals, [0, 10, 20, 30, 40]);
+    }
+
+    #[test]
+    fn poisson_rate() {
+        let model = TrafficModel::Poisson { mean_interval: 100 };
+        let times = arrivals(model, 10_000);
+        let mean = *times.last().unwrap() as f64 / (times.len() - 1) as f64;
+        assert!((95. ..105.).contains(&mean), "mean interval {}", mean);
+        // Same seed, same arrivals.
+        assert_eq!(arrivals(model, 10_000), times);
+    }
+
+    #[test]
+    fn bursty_rate() {
+        let model = TrafficModel::Bursty {
+            on: 20,
+            off: 80,
+            interval: 10,
+        };
+        assert_eq!(arrivals(model, 5), [0, 10, 100, 110, 200]);
+    }
+
+    #[test]
+    fn saturated_waits_for_queue() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut generator = Generator::new(TrafficModel::Saturated, 2);
+        assert_eq!(generator.next_at(1), None);
+        assert_eq!(generator.poll(5, 1, &mut rng), None);
+        assert_eq!(generator.poll(5, 0, &mut rng), Some(Arrival::Data));
+        assert_eq!(generator.poll(5, 0, &mut rng), Some(Arrival::Data));
+        assert_eq!(generator.next_at(0), None);
+    }
+}
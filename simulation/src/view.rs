@@ -0,0 +1,106 @@
+use std::{collections::VecDeque, fmt::Write, time::Duration};
+
+use crate::{clock::FakeInstant, topology::Topology, Mailbox, Party};
+
+/// How many ticks of the bus are shown.
+const WIDTH: usize = 120;
+/// How many ticks pass between updates of the view.
+const REFRESH_TICKS: u64 = 40;
+/// How long every update is shown, such that it can be followed.
+const FRAME_DELAY: Duration = Duration::from_millis(30);
+
+/// Live view of the bus in the terminal, showing a timeline of every segment and the state of every party.
+///
+/// The timeline shows which party holds the bus per tick, using its address in base 36. A `.` is an idle tick,
+/// an `X` a garbled byte and a `#` a byte of a sender that is not a party, like a bridge.
+pub struct BusView {
+    timelines: Vec<VecDeque<char>>,
+}
+
+impl BusView {
+    pub fn new(segments: usize) -> Self {
+        Self {
+            timelines: vec![VecDeque::with_capacity(WIDTH); segments],
+        }
+    }
+
+    /// Record the current tick on all segments.
+    pub fn record(&mut self, topology: &Topology, parties: &mut [Box<Party>]) {
+        for (i, timeline) in self.timelines.iter_mut().enumerate() {
+            let holder = parties
+                .iter_mut()
+                .filter(|party| topology.segment_of(party.address()) == i)
+                .find_map(|party| party.observer().is_sending().then(|| party.address()));
+            let tick = match topology.segment(i).current() {
+                None => '.',
+                Some(fragment) if fragment.is_error() => 'X',
+                Some(_) => holder.map_or('#', |address| symbol(address.to_primitive())),
+            };
+
+            if timeline.len() == WIDTH {
+                timeline.pop_front();
+            }
+            timeline.push_back(tick);
+        }
+    }
+
+    /// Draw the view every so many ticks.
+    pub fn render(
+        &self,
+        now: FakeInstant,
+        topology: &Topology,
+        parties: &mut [Box<Party>],
+        mailbox: &Mailbox,
+    ) {
+        if !now.0.is_multiple_of(REFRESH_TICKS) {
+            return;
+        }
+
+        let mut out = String::new();
+        // Move to the top left, and clear the screen.
+        out.push_str("\x1b[H\x1b[2J");
+        writeln!(out, "tick {}", now.0).unwrap();
+        for (i, timeline) in self.timelines.iter().enumerate() {
+            writeln!(
+                out,
+                "segment {:2} |{}|",
+                i,
+                timeline.iter().collect::<String>()
+            )
+            .unwrap();
+        }
+
+        writeln!(out).unwrap();
+        writeln!(
+            out,
+            "{:>5} {:>3} {:<16} {:>5} {:>6} {:>6} {:>10}  condition",
+            "party", "seg", "state", "queue", "sent", "recv", "collisions"
+        )
+        .unwrap();
+        for party in parties {
+            let address = party.address();
+            let (state, collisions) = (party.observer().state(), party.observer().collisions());
+            writeln!(
+                out,
+                "{:>5} {:>3} {:<16} {:>5} {:>6} {:>6} {:>10}  {:?}",
+                address.to_primitive(),
+                topology.segment_of(address),
+                state,
+                mailbox.queue_len(address) + party.has_frame() as usize,
+                party.stats().frames_sent,
+                party.stats().frames_received,
+                collisions,
+                party.condition(),
+            )
+            .unwrap();
+        }
+
+        print!("{}", out);
+        std::thread::sleep(FRAME_DELAY);
+    }
+}
+
+/// Single character for `address`.
+fn symbol(address: u32) -> char {
+    char::from_digit(address % 36, 36).unwrap()
+}
@@ -0,0 +1,6 @@
+/* The smallest parts we target. Only the sizes matter, as these binaries never run. */
+MEMORY
+{
+  FLASH : ORIGIN = 0x08000000, LENGTH = 64K
+  RAM : ORIGIN = 0x20000000, LENGTH = 8K
+}
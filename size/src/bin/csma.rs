@@ -0,0 +1,49 @@
+//! A complete node that sends frames and receives those of others through a `CsmaStrategy`.
+
+#![no_std]
+#![no_main]
+
+use core::hint::black_box;
+
+use cortex_m_rt::entry;
+use kiri_csma::{CsmaFrameInProgress, CsmaStrategy, SendReceiveResult};
+use kiri_protocol::{Address, Writer};
+use kiri_size::{input, OpaqueClock, OpaqueTransceiver, SizeConfig};
+use panic_halt as _;
+use rand::rngs::mock::StepRng;
+
+type Strategy = CsmaStrategy<OpaqueTransceiver, OpaqueClock, StepRng>;
+
+#[entry]
+fn main() -> ! {
+    static mut STRATEGY: Option<Strategy> = None;
+    static mut FRAME: Option<CsmaFrameInProgress> = None;
+
+    let rng = StepRng::new(input().into(), 7919);
+    let strategy = STRATEGY.insert(Strategy::new::<SizeConfig>(
+        OpaqueTransceiver,
+        OpaqueClock,
+        rng,
+    ));
+
+    loop {
+        let reading = [input(); 8];
+        let frame = match Writer::package(Address::new(1), Address::new(2), &reading) {
+            Ok(frame) => frame,
+            Err(_) => continue,
+        };
+        let frame = FRAME.insert(CsmaFrameInProgress::new(frame));
+
+        loop {
+            match strategy
+                .send_or_receive_with(frame, |incoming| black_box(incoming.contents.len()))
+            {
+                Ok(SendReceiveResult::SendComplete) => break,
+                Ok(SendReceiveResult::Received(len)) => {
+                    black_box(len);
+                }
+                Ok(SendReceiveResult::Expired) | Err(_) => (),
+            }
+        }
+    }
+}
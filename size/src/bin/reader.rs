@@ -0,0 +1,23 @@
+//! Only decoding frames, i.e. for a node that listens to a bus it never sends on.
+
+#![no_std]
+#![no_main]
+
+use core::hint::black_box;
+
+use cortex_m_rt::entry;
+use kiri_protocol::Reader;
+use kiri_size::input;
+use panic_halt as _;
+
+#[entry]
+fn main() -> ! {
+    static mut READER: Option<Reader> = None;
+    let reader = READER.insert(Reader::new());
+
+    loop {
+        if let Ok(Some(frame)) = reader.feed(input()) {
+            black_box(frame.contents);
+        }
+    }
+}
@@ -0,0 +1,23 @@
+//! Only encoding frames, i.e. for a sensor that sends on a bus it never listens to.
+
+#![no_std]
+#![no_main]
+
+use core::hint::black_box;
+
+use cortex_m_rt::entry;
+use kiri_protocol::{Address, Frame, Writer};
+use kiri_size::input;
+use panic_halt as _;
+
+#[entry]
+fn main() -> ! {
+    static mut FRAME: Option<Frame> = None;
+
+    loop {
+        let reading = [input(); 8];
+        if let Ok(frame) = Writer::package(Address::new(1), Address::new(2), &reading) {
+            black_box(FRAME.insert(frame).as_slice());
+        }
+    }
+}
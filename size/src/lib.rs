@@ -0,0 +1,60 @@
+#![no_std]
+
+//! Binaries that link parts of kiri for a Cortex-M, to track how much flash and static RAM they cost.
+//!
+//! These never run: the peripherals they use are stand-ins, of which the compiler cannot see what they yield,
+//! such that nothing is optimized away. The state of every binary lives in statics, such that `cargo size`
+//! reports it under `.bss`.
+
+use core::hint::black_box;
+
+use kiri_csma::{Clock, Config, ReadError, Transceiver};
+
+/// A byte the compiler cannot predict, i.e. from a peripheral.
+pub fn input() -> u8 {
+    black_box(0)
+}
+
+/// Stand-in for a USART with an RS485 transceiver.
+pub struct OpaqueTransceiver;
+
+impl Transceiver for OpaqueTransceiver {
+    type Error = ();
+
+    fn handle_interrupts(&self) {}
+
+    fn bus_is_idle(&self) -> bool {
+        black_box(true)
+    }
+
+    fn write(&mut self, byte: u8) -> nb::Result<(), Self::Error> {
+        black_box(byte);
+        Ok(())
+    }
+
+    fn read(&mut self) -> nb::Result<u8, ReadError<Self::Error>> {
+        black_box(Some(0)).ok_or(nb::Error::WouldBlock)
+    }
+}
+
+/// Stand-in for a timer counting microseconds.
+pub struct OpaqueClock;
+
+impl Clock for OpaqueClock {
+    type Instant = u64;
+    type Duration = u64;
+
+    fn now(&self) -> u64 {
+        black_box(0)
+    }
+}
+
+/// Timing of a bus at 115200 baud.
+pub struct SizeConfig;
+
+impl Config<OpaqueClock> for SizeConfig {
+    const BUS_MIN_IDLE_DURATION: u64 = 100;
+    const BUS_MAX_IDLE_DURATION: u64 = 500;
+    const ECHO_BYTE_TIMEOUT: u64 = 1_000;
+    const ECHO_FRAME_TIMEOUT: u64 = 50_000;
+}
@@ -0,0 +1,33 @@
+#![no_std]
+
+//! Reference `Transceiver` implementations for concrete microcontrollers.
+//!
+//! These work directly on the peripheral registers, such that they do not depend on a specific HAL version.
+//! Configure the clocks and pin muxing with your HAL of choice, and hand the peripheral over afterwards.
+//!
+//! Reception is polled: call `CsmaStrategy::receive` or `send_or_receive` often enough that the receive FIFO
+//! does not overflow, or from the receive interrupt handler. `Transceiver::handle_interrupts` clears
+//! the interrupt flags, so that the interrupt handler does not fire again for the same event.
+
+#[cfg(feature = "rp2040")]
+pub mod rp2040;
+#[cfg(feature = "stm32-usart-v2")]
+pub mod stm32;
+
+/// Minimal volatile register access.
+#[allow(dead_code)]
+mod reg {
+    use core::ptr::{read_volatile, write_volatile};
+
+    pub unsafe fn read(base: usize, offset: usize) -> u32 {
+        read_volatile((base + offset) as *const u32)
+    }
+
+    pub unsafe fn write(base: usize, offset: usize, value: u32) {
+        write_volatile((base + offset) as *mut u32, value)
+    }
+
+    pub unsafe fn modify(base: usize, offset: usize, f: impl FnOnce(u32) -> u32) {
+        write(base, offset, f(read(base, offset)))
+    }
+}
@@ -0,0 +1,146 @@
+//! RP2040 UART (ARM PL011) with a GPIO as RS485 driver enable.
+//!
+//! The PL011 has no hardware driver enable, so the DE pin is controlled through the transmit hooks. The PL011 also has no receiver busy flag, so the bus is deemed idle
+//! if the receive FIFO is empty and the RX pin is at its idle (high) level.
+
+use kiri_csma::{ReadError, Transceiver};
+
+use crate::reg;
+
+/// Base address of `UART0`.
+pub const UART0: usize = 0x4003_4000;
+/// Base address of `UART1`.
+pub const UART1: usize = 0x4003_8000;
+
+const SIO: usize = 0xD000_0000;
+const SIO_GPIO_IN: usize = 0x004;
+const SIO_GPIO_OUT_SET: usize = 0x014;
+const SIO_GPIO_OUT_CLR: usize = 0x018;
+const SIO_GPIO_OE_SET: usize = 0x024;
+
+const UARTDR: usize = 0x000;
+const UARTRSR: usize = 0x004;
+const UARTFR: usize = 0x018;
+const UARTIBRD: usize = 0x024;
+const UARTFBRD: usize = 0x028;
+const UARTLCR_H: usize = 0x02C;
+const UARTCR: usize = 0x030;
+const UARTIMSC: usize = 0x038;
+const UARTICR: usize = 0x044;
+
+const DR_ERRORS: u32 = 0xF << 8;
+
+const FR_BUSY: u32 = 1 << 3;
+const FR_RXFE: u32 = 1 << 4;
+const FR_TXFF: u32 = 1 << 5;
+
+const LCR_H_FEN: u32 = 1 << 4;
+const LCR_H_WLEN_8: u32 = 0b11 << 5;
+
+const CR_UARTEN: u32 = 1 << 0;
+const CR_TXE: u32 = 1 << 8;
+const CR_RXE: u32 = 1 << 9;
+
+/// Receive and receive timeout interrupts.
+const IMSC_RX: u32 = (1 << 4) | (1 << 6);
+
+#[derive(Debug)]
+pub struct Rp2040Uart {
+    base: usize,
+    rx_pin: u32,
+    de_pin: u32,
+}
+
+impl Rp2040Uart {
+    /// Take over the UART at `base`, configuring it for 8N1 at `baud` with FIFOs enabled.
+    ///
+    /// `rx_pin` is the GPIO muxed to the UART RX, `de_pin` a GPIO muxed to SIO.
+    ///
+    /// # Safety
+    /// `base` must be `UART0` or `UART1`, clocked by `clk_peri_hz` and not in use otherwise.
+    /// The DE GPIO must not be used otherwise.
+    pub unsafe fn new(base: usize, clk_peri_hz: u32, baud: u32, rx_pin: u32, de_pin: u32) -> Self {
+        let div = 8 * clk_peri_hz / baud;
+        let (ibrd, fbrd) = match div >> 7 {
+            0 => (1, 0),
+            ibrd if ibrd >= 65535 => (65535, 0),
+            ibrd => (ibrd, (div & 0x7F).div_ceil(2)),
+        };
+
+        reg::write(base, UARTCR, 0);
+        reg::write(base, UARTIBRD, ibrd);
+        reg::write(base, UARTFBRD, fbrd);
+        // Writing LCR_H latches the baud rate divisors.
+        reg::write(base, UARTLCR_H, LCR_H_WLEN_8 | LCR_H_FEN);
+        reg::write(base, UARTIMSC, IMSC_RX);
+        reg::write(base, UARTCR, CR_UARTEN | CR_TXE | CR_RXE);
+
+        reg::write(SIO, SIO_GPIO_OUT_CLR, 1 << de_pin);
+        reg::write(SIO, SIO_GPIO_OE_SET, 1 << de_pin);
+
+        Self {
+            base,
+            rx_pin,
+            de_pin,
+        }
+    }
+
+    fn fr(&self) -> u32 {
+        unsafe { reg::read(self.base, UARTFR) }
+    }
+}
+
+impl Transceiver for Rp2040Uart {
+    /// The UART itself never fails, all errors are frame errors.
+    type Error = core::convert::Infallible;
+
+    fn handle_interrupts(&self) {
+        // Receive interrupts are cleared by draining the FIFO, the timeout interrupt needs explicit clearing.
+        unsafe { reg::write(self.base, UARTICR, IMSC_RX) }
+    }
+
+    fn bus_is_idle(&self) -> bool {
+        let rx_high = unsafe { reg::read(SIO, SIO_GPIO_IN) } & (1 << self.rx_pin) != 0;
+        rx_high && self.fr() & (FR_RXFE | FR_BUSY) == FR_RXFE
+    }
+
+    fn write(&mut self, byte: u8) -> nb::Result<(), Self::Error> {
+        if self.fr() & FR_TXFF != 0 {
+            return Err(nb::Error::WouldBlock);
+        }
+        unsafe { reg::write(self.base, UARTDR, byte as u32) }
+        Ok(())
+    }
+
+    fn read(&mut self) -> nb::Result<u8, ReadError<Self::Error>> {
+        if self.fr() & FR_RXFE != 0 {
+            return Err(nb::Error::WouldBlock);
+        }
+
+        // Error flags are reported alongside each byte in the FIFO.
+        let dr = unsafe { reg::read(self.base, UARTDR) };
+        if dr & DR_ERRORS != 0 {
+            return Err(nb::Error::Other(ReadError::FrameError));
+        }
+        Ok(dr as u8)
+    }
+
+    fn recover(&mut self) {
+        unsafe {
+            // Drain the receive FIFO, and clear any sticky error flags.
+            while self.fr() & FR_RXFE == 0 {
+                let _ = reg::read(self.base, UARTDR);
+            }
+            reg::write(self.base, UARTRSR, 0);
+        }
+    }
+
+    fn start_transmit(&mut self) -> nb::Result<(), Self::Error> {
+        unsafe { reg::write(SIO, SIO_GPIO_OUT_SET, 1 << self.de_pin) }
+        Ok(())
+    }
+
+    fn end_transmit(&mut self) {
+        unsafe { reg::write(SIO, SIO_GPIO_OUT_CLR, 1 << self.de_pin) }
+    }
+}
@@ -0,0 +1,129 @@
+//! STM32 "v2" USART, as found in the F0, F3, F7, G0, G4, L0 and L4 families.
+//!
+//! Uses the hardware driver enable of the USART, which asserts the DE pin during transmission.
+//! Mux the DE pin to the USART (`USARTx_DE` or `USARTx_RTS` alternate function) to use it.
+
+use kiri_csma::{ReadError, Transceiver};
+
+use crate::reg;
+
+const CR1: usize = 0x00;
+const CR3: usize = 0x08;
+const BRR: usize = 0x0C;
+const ISR: usize = 0x1C;
+const RQR: usize = 0x18;
+const ICR: usize = 0x20;
+const RDR: usize = 0x24;
+const TDR: usize = 0x28;
+
+const CR1_UE: u32 = 1 << 0;
+const CR1_RE: u32 = 1 << 2;
+const CR1_TE: u32 = 1 << 3;
+const CR1_RXNEIE: u32 = 1 << 5;
+const CR1_DEDT_SHIFT: u32 = 16;
+const CR1_DEAT_SHIFT: u32 = 21;
+const CR3_DEM: u32 = 1 << 14;
+
+const RQR_RXFRQ: u32 = 1 << 3;
+
+const ISR_PE: u32 = 1 << 0;
+const ISR_FE: u32 = 1 << 1;
+const ISR_NE: u32 = 1 << 2;
+const ISR_ORE: u32 = 1 << 3;
+const ISR_IDLE: u32 = 1 << 4;
+const ISR_RXNE: u32 = 1 << 5;
+const ISR_TXE: u32 = 1 << 7;
+const ISR_BUSY: u32 = 1 << 16;
+
+const ISR_ERRORS: u32 = ISR_PE | ISR_FE | ISR_NE | ISR_ORE;
+
+#[derive(Debug)]
+pub struct Stm32Usart {
+    base: usize,
+}
+
+impl Stm32Usart {
+    /// Take over the USART at `base`, configuring it for 8N1 at `baud` with hardware driver enable.
+    ///
+    /// `de_time` is the driver enable assertion and deassertion time, in sample time units (at most 31).
+    ///
+    /// # Safety
+    /// `base` must be the address of a v2 USART, which is clocked by `pclk_hz` and not in use otherwise.
+    pub unsafe fn new(base: usize, pclk_hz: u32, baud: u32, de_time: u8) -> Self {
+        let de_time = (de_time as u32) & 0x1F;
+
+        reg::write(base, CR1, 0);
+        reg::write(base, BRR, pclk_hz / baud);
+        reg::write(base, CR3, CR3_DEM);
+        reg::write(
+            base,
+            CR1,
+            (de_time << CR1_DEAT_SHIFT)
+                | (de_time << CR1_DEDT_SHIFT)
+                | CR1_RXNEIE
+                | CR1_TE
+                | CR1_RE
+                | CR1_UE,
+        );
+
+        Self { base }
+    }
+
+    fn isr(&self) -> u32 {
+        unsafe { reg::read(self.base, ISR) }
+    }
+}
+
+impl Transceiver for Stm32Usart {
+    /// The USART itself never fails, all errors are frame errors.
+    type Error = core::convert::Infallible;
+
+    fn handle_interrupts(&self) {
+        // RXNE is cleared by reading RDR, errors are reported through `read`.
+        unsafe { reg::write(self.base, ICR, ISR_IDLE) }
+    }
+
+    fn bus_is_idle(&self) -> bool {
+        self.isr() & (ISR_BUSY | ISR_RXNE) == 0
+    }
+
+    fn write(&mut self, byte: u8) -> nb::Result<(), Self::Error> {
+        if self.isr() & ISR_TXE == 0 {
+            return Err(nb::Error::WouldBlock);
+        }
+        unsafe { reg::write(self.base, TDR, byte as u32) }
+        Ok(())
+    }
+
+    fn read(&mut self) -> nb::Result<u8, ReadError<Self::Error>> {
+        let isr = self.isr();
+        if isr & ISR_ERRORS != 0 {
+            unsafe {
+                // Drop the broken byte, and clear the error flags.
+                let _ = reg::read(self.base, RDR);
+                reg::write(self.base, ICR, ISR_ERRORS);
+            }
+            return Err(nb::Error::Other(ReadError::FrameError));
+        }
+
+        if isr & ISR_RXNE == 0 {
+            return Err(nb::Error::WouldBlock);
+        }
+        Ok(unsafe { reg::read(self.base, RDR) } as u8)
+    }
+
+    fn recover(&mut self) {
+        unsafe {
+            // Flush the receive data register, and clear any sticky error flags.
+            reg::write(self.base, RQR, RQR_RXFRQ);
+            reg::write(self.base, ICR, ISR_ERRORS);
+        }
+    }
+}
+
+/// Disable the peripheral when dropped, releasing the bus.
+impl Drop for Stm32Usart {
+    fn drop(&mut self) {
+        unsafe { reg::modify(self.base, CR1, |cr1| cr1 & !CR1_UE) }
+    }
+}
@@ -0,0 +1,229 @@
+//! Discrete-event engine, which moves time straight to the next thing that happens instead of in fixed steps.
+//!
+//! Strategies tell when they need to be polled next through `CsmaStrategy::next_poll_at`, and a `PipeMonitor` tells
+//! when the bus changes. Scheduling polls at those instants skips the time in between, no matter how long the bus
+//! stays quiet.
+
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+use kiri_csma::Clock;
+
+use crate::TestClock;
+
+/// Events keyed by the instant they are due at, taken earliest first.
+///
+/// Events due at the same instant are taken in the order they were scheduled in.
+#[derive(Debug)]
+pub struct EventQueue<I: Ord, E> {
+    heap: BinaryHeap<Reverse<Scheduled<I, E>>>,
+    /// How many events were scheduled so far, which orders events due at the same instant.
+    scheduled: u64,
+}
+
+#[derive(Debug)]
+struct Scheduled<I, E> {
+    at: I,
+    order: u64,
+    event: E,
+}
+
+impl<I: Ord, E> PartialEq for Scheduled<I, E> {
+    fn eq(&self, other: &Self) -> bool {
+        (&self.at, self.order) == (&other.at, other.order)
+    }
+}
+
+impl<I: Ord, E> Eq for Scheduled<I, E> {}
+
+impl<I: Ord, E> PartialOrd for Scheduled<I, E> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<I: Ord, E> Ord for Scheduled<I, E> {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (&self.at, self.order).cmp(&(&other.at, other.order))
+    }
+}
+
+impl<I: Ord + Copy, E> EventQueue<I, E> {
+    pub fn new() -> Self {
+        Self {
+            heap: BinaryHeap::new(),
+            scheduled: 0,
+        }
+    }
+
+    pub fn schedule(&mut self, at: I, event: E) {
+        self.heap.push(Reverse(Scheduled {
+            at,
+            order: self.scheduled,
+            event,
+        }));
+        self.scheduled += 1;
+    }
+
+    /// When the earliest event is due.
+    pub fn next_at(&self) -> Option<I> {
+        self.heap.peek().map(|Reverse(scheduled)| scheduled.at)
+    }
+
+    /// Take the earliest event, along with the instant it is due at.
+    pub fn pop(&mut self) -> Option<(I, E)> {
+        self.heap
+            .pop()
+            .map(|Reverse(scheduled)| (scheduled.at, scheduled.event))
+    }
+
+    /// Take the earliest event, if it is due at `now` or before.
+    pub fn pop_due(&mut self, now: I) -> Option<(I, E)> {
+        match self.next_at() {
+            Some(at) if at <= now => self.pop(),
+            _ => None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+}
+
+impl<I: Ord + Copy, E> Default for EventQueue<I, E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Takes events from an `EventQueue` in order, moving a `TestClock` to the instant of every event taken.
+#[derive(Debug)]
+pub struct Engine<E> {
+    clock: TestClock,
+    queue: EventQueue<u64, E>,
+}
+
+impl<E> Engine<E> {
+    pub fn new(clock: &TestClock) -> Self {
+        Self {
+            clock: clock.clone(),
+            queue: EventQueue::new(),
+        }
+    }
+
+    /// Schedule `event` at `at`, or right away if that has passed already.
+    pub fn schedule(&mut self, at: u64, event: E) {
+        self.queue.schedule(at.max(self.clock.now()), event);
+    }
+
+    /// Schedule `event` `after` microseconds from now.
+    pub fn schedule_in(&mut self, after: u64, event: E) {
+        self.schedule(self.clock.now() + after, event);
+    }
+
+    /// When the next event is due.
+    pub fn next_at(&self) -> Option<u64> {
+        self.queue.next_at()
+    }
+
+    /// Move the clock to the next event and take it, unless there is none before `end`.
+    pub fn step(&mut self, end: u64) -> Option<E> {
+        if self.queue.next_at()? >= end {
+            return None;
+        }
+        let (at, event) = self.queue.pop()?;
+        self.clock.set(at);
+        Some(event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use kiri_csma::{CsmaFrameInProgress, SendReceiveResult};
+    use kiri_protocol::{Address, Writer};
+
+    use super::*;
+    use crate::{pipe, strategy, PipeConfig};
+
+    #[test]
+    fn order() {
+        let mut queue = EventQueue::new();
+        queue.schedule(20, 'c');
+        queue.schedule(10, 'a');
+        queue.schedule(20, 'd');
+        queue.schedule(10, 'b');
+
+        assert_eq!(queue.next_at(), Some(10));
+        assert_eq!(queue.pop_due(5), None);
+        let taken: Vec<_> = std::iter::from_fn(|| queue.pop_due(20)).collect();
+        assert_eq!(taken, [(10, 'a'), (10, 'b'), (20, 'c'), (20, 'd')]);
+        assert!(queue.is_empty());
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum Event {
+        Send,
+        Poll,
+    }
+
+    #[test]
+    fn skip_idle_time() {
+        let clock = TestClock::new();
+        let config = PipeConfig {
+            latency: 50,
+            ..Default::default()
+        };
+        let (a, b) = pipe(&clock, config);
+        let monitors = [a.monitor(), b.monitor()];
+        let mut nodes = [strategy(a, &clock, 1), strategy(b, &clock, 2)];
+
+        let mut engine = Engine::new(&clock);
+        engine.schedule(10_000_000, Event::Send);
+        engine.schedule(0, Event::Poll);
+
+        let mut frame = None;
+        let mut received = Vec::new();
+        let mut polls = 0;
+        while let Some(event) = engine.step(60_000_000) {
+            if event == Event::Send {
+                let hello = Writer::package(Address::new(1), Address::new(2), b"hello").unwrap();
+                frame = Some(CsmaFrameInProgress::new(hello));
+            }
+
+            polls += 1;
+            match frame.as_mut() {
+                Some(in_progress) => {
+                    if let Ok(SendReceiveResult::SendComplete) =
+                        nodes[0].send_or_receive(in_progress)
+                    {
+                        frame = None;
+                    }
+                }
+                None => while nodes[0].receive().is_ok() {},
+            }
+            while let Ok(frame) = nodes[1].receive() {
+                received.push(frame.contents.to_vec());
+            }
+
+            // A byte takes at least a microsecond, hence polls that are due right away happen on the next one.
+            let wake = nodes
+                .iter()
+                .zip(&monitors)
+                .flat_map(|(node, monitor)| [node.next_poll_at(), monitor.next_change_at()])
+                .flatten()
+                .min();
+            if let Some(at) = wake {
+                engine.schedule(at.max(clock.now() + 1), Event::Poll);
+            }
+        }
+
+        assert!(frame.is_none());
+        assert_eq!(received, [b"hello"]);
+        assert!(clock.now() > 10_000_000);
+        // Polling every 10 microseconds, as the other tests do, takes a million polls for the same.
+        assert!(polls < 10_000, "{} polls", polls);
+    }
+}
@@ -0,0 +1,321 @@
+//! Harness to test complete send and receive flows of `CsmaStrategy`s, without running the simulator.
+//!
+//! A `pipe` connects two `PipeTransceiver`s in memory, which deliver the bytes of one side to the other after a
+//! latency, and lose a fraction of them. Time only passes when the test advances the shared `TestClock`, and
+//! losses and backoffs are decided by seeds, such that every run of a test behaves the same.
+//!
+//! Rather than advancing the clock in fixed steps, tests can skip to the next thing that happens using an
+//! `engine::Engine`.
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::VecDeque,
+    convert::Infallible,
+    rc::Rc,
+};
+
+use kiri_csma::{Clock, Config, CsmaStrategy, ReadError, Transceiver};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+pub mod engine;
+
+/// Clock counting microseconds, of which time only passes when told to.
+///
+/// Clones share the same time.
+#[derive(Debug, Clone, Default)]
+pub struct TestClock(Rc<Cell<u64>>);
+
+impl TestClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn advance(&self, us: u64) {
+        self.0.set(self.0.get() + us);
+    }
+
+    pub fn set(&self, now: u64) {
+        self.0.set(now);
+    }
+}
+
+impl Clock for TestClock {
+    type Instant = u64;
+    type Duration = u64;
+
+    fn now(&self) -> u64 {
+        self.0.get()
+    }
+}
+
+/// Timing of a bus at 115200 baud, in microseconds of a `TestClock`.
+pub struct TestConfig;
+
+impl Config<TestClock> for TestConfig {
+    const BUS_MIN_IDLE_DURATION: u64 = 100;
+    const BUS_MAX_IDLE_DURATION: u64 = 500;
+    const ECHO_BYTE_TIMEOUT: u64 = 1_000;
+    const ECHO_FRAME_TIMEOUT: u64 = 50_000;
+}
+
+/// Strategy of a node on a pipe, of which the backoff is decided by a seed.
+pub type TestStrategy = CsmaStrategy<PipeTransceiver, TestClock, StdRng>;
+
+/// Create a strategy configured by `TestConfig` on `transceiver`, of which the backoff is decided by `seed`.
+pub fn strategy(transceiver: PipeTransceiver, clock: &TestClock, seed: u64) -> TestStrategy {
+    TestStrategy::new::<TestConfig>(transceiver, clock.clone(), StdRng::seed_from_u64(seed))
+}
+
+/// How bytes travel through a `pipe`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PipeConfig {
+    /// Microseconds a byte takes to be sent, i.e. 87 for 10 bits at 115200 baud.
+    pub byte_duration: u64,
+    /// Microseconds it takes for a sent byte to arrive at the other side.
+    pub latency: u64,
+    /// Fraction of the bytes that does not arrive at the other side, from `0.0` to `1.0`.
+    pub loss: f64,
+    /// Seed that decides which bytes are lost.
+    pub seed: u64,
+}
+
+impl Default for PipeConfig {
+    fn default() -> Self {
+        Self {
+            byte_duration: 87,
+            latency: 0,
+            loss: 0.0,
+            seed: 0,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct End {
+    /// Bytes and the time they arrive at, earliest first. Bytes that overlapped on the bus are garbled.
+    inbox: VecDeque<(u64, Option<u8>)>,
+    /// Until when the bus is seen busy because of bytes that were sent or received.
+    busy_until: u64,
+    /// When the byte being sent is sent completely.
+    sending_until: u64,
+}
+
+#[derive(Debug)]
+struct Pipe {
+    config: PipeConfig,
+    rng: StdRng,
+    ends: [End; 2],
+}
+
+/// One side of a `pipe`, that loops back the bytes it sends just like an RS485 transceiver.
+///
+/// Bytes of both sides that overlap on the bus are garbled, and yield a frame error instead.
+#[derive(Debug)]
+pub struct PipeTransceiver {
+    pipe: Rc<RefCell<Pipe>>,
+    side: usize,
+    clock: TestClock,
+}
+
+/// Create two transceivers that are connected to each other.
+pub fn pipe(clock: &TestClock, config: PipeConfig) -> (PipeTransceiver, PipeTransceiver) {
+    let pipe = Rc::new(RefCell::new(Pipe {
+        config,
+        rng: StdRng::seed_from_u64(config.seed),
+        ends: Default::default(),
+    }));
+    let end = |side| PipeTransceiver {
+        pipe: pipe.clone(),
+        side,
+        clock: clock.clone(),
+    };
+    (end(0), end(1))
+}
+
+/// Watches one side of a `pipe`, of which the transceiver is owned by a strategy.
+#[derive(Debug, Clone)]
+pub struct PipeMonitor {
+    pipe: Rc<RefCell<Pipe>>,
+    side: usize,
+    clock: TestClock,
+}
+
+impl PipeMonitor {
+    /// When the bus changes next as seen from this side, being a byte arriving or the bus becoming idle.
+    pub fn next_change_at(&self) -> Option<u64> {
+        let now = self.clock.now();
+        let pipe = self.pipe.borrow();
+        let end = &pipe.ends[self.side];
+        let arrival = end.inbox.front().map(|(at, _)| *at);
+        let idle = (end.busy_until > now).then_some(end.busy_until);
+        arrival.into_iter().chain(idle).min()
+    }
+}
+
+impl PipeTransceiver {
+    pub fn monitor(&self) -> PipeMonitor {
+        PipeMonitor {
+            pipe: self.pipe.clone(),
+            side: self.side,
+            clock: self.clock.clone(),
+        }
+    }
+}
+
+impl End {
+    /// Receive `byte` at `at`, garbling it and any byte it overlaps with on the bus.
+    fn deliver(&mut self, at: u64, byte: u8, byte_duration: u64) {
+        let mut byte = Some(byte);
+        for (other, other_byte) in self.inbox.iter_mut() {
+            if other.abs_diff(at) < byte_duration {
+                *other_byte = None;
+                byte = None;
+            }
+        }
+
+        let i = self.inbox.partition_point(|(other, _)| *other <= at);
+        self.inbox.insert(i, (at, byte));
+    }
+}
+
+impl Transceiver for PipeTransceiver {
+    type Error = Infallible;
+
+    fn handle_interrupts(&self) {}
+
+    fn bus_is_idle(&self) -> bool {
+        let now = self.clock.now();
+        let pipe = self.pipe.borrow();
+        let end = &pipe.ends[self.side];
+        // A byte is on the bus while it is being sent, before it arrives.
+        let receiving = end
+            .inbox
+            .front()
+            .is_some_and(|(at, _)| *at <= now + pipe.config.byte_duration);
+        !receiving && now >= end.busy_until
+    }
+
+    fn write(&mut self, byte: u8) -> nb::Result<(), Self::Error> {
+        let now = self.clock.now();
+        let mut pipe = self.pipe.borrow_mut();
+        let Pipe { config, rng, ends } = &mut *pipe;
+        if now < ends[self.side].sending_until {
+            return Err(nb::Error::WouldBlock);
+        }
+
+        let sent_at = now + config.byte_duration;
+        let end = &mut ends[self.side];
+        end.sending_until = sent_at;
+        end.busy_until = end.busy_until.max(sent_at);
+        end.deliver(sent_at, byte, config.byte_duration);
+        if !rng.gen_bool(config.loss) {
+            ends[1 - self.side].deliver(sent_at + config.latency, byte, config.byte_duration);
+        }
+        Ok(())
+    }
+
+    fn read(&mut self) -> nb::Result<u8, ReadError<Self::Error>> {
+        let now = self.clock.now();
+        let mut pipe = self.pipe.borrow_mut();
+        let end = &mut pipe.ends[self.side];
+        match end.inbox.front() {
+            Some((at, _)) if *at <= now => {
+                let (at, byte) = end.inbox.pop_front().unwrap();
+                end.busy_until = end.busy_until.max(at);
+                byte.ok_or(nb::Error::Other(ReadError::FrameError))
+            }
+            _ => Err(nb::Error::WouldBlock),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use kiri_csma::{CsmaFrameInProgress, SendReceiveResult};
+    use kiri_protocol::{Address, Writer};
+
+    use super::*;
+
+    /// Let `a` send a frame to `b`, yielding what `b` received within a second.
+    fn exchange(config: PipeConfig) -> (Vec<Vec<u8>>, TestStrategy) {
+        let clock = TestClock::new();
+        let (a, b) = pipe(&clock, config);
+        let mut a = strategy(a, &clock, 1);
+        let mut b = strategy(b, &clock, 2);
+
+        let frame = Writer::package(Address::new(1), Address::new(2), b"hello").unwrap();
+        let mut frame = CsmaFrameInProgress::new(frame);
+        let mut sent = false;
+        let mut received = Vec::new();
+        while clock.now() < 1_000_000 {
+            if !sent {
+                sent = matches!(
+                    a.send_or_receive(&mut frame),
+                    Ok(SendReceiveResult::SendComplete)
+                );
+            }
+            if let Ok(frame) = b.receive() {
+                received.push(frame.contents.to_vec());
+            }
+            clock.advance(10);
+        }
+        assert!(sent);
+        (received, b)
+    }
+
+    #[test]
+    fn deliver() {
+        let config = PipeConfig {
+            latency: 50,
+            ..Default::default()
+        };
+        let (received, b) = exchange(config);
+        assert_eq!(received, [b"hello"]);
+        assert_eq!(b.stats().frames_received, 1);
+    }
+
+    #[test]
+    fn lose_bytes() {
+        let config = PipeConfig {
+            loss: 1.0,
+            ..Default::default()
+        };
+        let (received, b) = exchange(config);
+        assert!(received.is_empty());
+        assert_eq!(b.stats().bytes_received, 0);
+    }
+
+    #[test]
+    fn collide() {
+        let clock = TestClock::new();
+        let config = PipeConfig {
+            latency: 500,
+            ..Default::default()
+        };
+        let (a, b) = pipe(&clock, config);
+        let mut nodes = [strategy(a, &clock, 1), strategy(b, &clock, 2)];
+        let mut frames = [Address::new(2), Address::new(1)].map(|dst| {
+            let frame = Writer::package(Address::new(0), dst, b"simultaneous").unwrap();
+            Some(CsmaFrameInProgress::new(frame))
+        });
+
+        // Both start sending before they see the other, and send again until both frames got through.
+        while clock.now() < 1_000_000 {
+            for (node, frame) in nodes.iter_mut().zip(&mut frames) {
+                match frame {
+                    Some(in_progress) => {
+                        if let Ok(SendReceiveResult::SendComplete) =
+                            node.send_or_receive(in_progress)
+                        {
+                            *frame = None;
+                        }
+                    }
+                    None => while node.receive().is_ok() {},
+                }
+            }
+            clock.advance(10);
+        }
+        assert!(frames.iter().all(Option::is_none));
+        assert!(nodes.iter().all(|node| node.stats().collisions > 0));
+    }
+}
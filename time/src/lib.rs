@@ -0,0 +1,348 @@
+#![no_std]
+
+//! Time synchronisation between the nodes on the bus.
+//!
+//! A `Master` periodically broadcasts beacons with its clock value. A `Slave` estimates the offset of its own
+//! clock to the master from these beacons, and the skew between both clocks from successive estimates.
+//! As beacons are delayed by the bus, slaves should occasionally measure this delay using a SNTP-style
+//! exchange of a `Slave::request` and the answer of the master.
+//!
+//! Times on the bus are in microseconds since the `Master` was created.
+
+use kiri_csma::Clock;
+use kiri_protocol::{Address, Frame, FrameRef, WriteError, Writer};
+
+const TAG_BEACON: u8 = 0x01;
+const TAG_REQUEST: u8 = 0x02;
+const TAG_RESPONSE: u8 = 0x03;
+
+/// Estimates are only used to calculate the skew if they are at least this far apart, in microseconds.
+const MIN_SKEW_SPAN: u64 = 1_000_000;
+
+/// Durations that can be expressed in microseconds, to exchange times over the bus.
+pub trait Micros {
+    fn as_micros(&self) -> u64;
+}
+
+impl Micros for core::time::Duration {
+    fn as_micros(&self) -> u64 {
+        core::time::Duration::as_micros(self) as u64
+    }
+}
+
+impl Micros for u64 {
+    fn as_micros(&self) -> u64 {
+        *self
+    }
+}
+
+/// A clock with a fixed epoch, counting microseconds.
+struct LocalTime<C: Clock> {
+    clock: C,
+    epoch: C::Instant,
+}
+
+impl<C: Clock> LocalTime<C>
+where
+    C::Duration: Micros,
+{
+    fn new(clock: C) -> Self {
+        let epoch = clock.now();
+        Self { clock, epoch }
+    }
+
+    fn now(&self) -> u64 {
+        (self.clock.now() - self.epoch).as_micros()
+    }
+}
+
+/// Time synchronisation message, as carried in the contents of a frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Message {
+    /// Time of the master when it packaged the beacon.
+    Beacon { master_time: u64 },
+    /// Request of a slave, sent at `t1` on the clock of the slave.
+    Request { t1: u64 },
+    /// Answer of the master, which received the request at `t2` and answered at `t3`.
+    Response { t1: u64, t2: u64, t3: u64 },
+}
+
+impl Message {
+    pub fn parse(contents: &[u8]) -> Option<Self> {
+        let (tag, rest) = contents.split_first()?;
+        let time = |i: usize| u64::from_be_bytes(rest[i * 8..(i + 1) * 8].try_into().unwrap());
+        match (*tag, rest.len()) {
+            (TAG_BEACON, 8) => Some(Message::Beacon {
+                master_time: time(0),
+            }),
+            (TAG_REQUEST, 8) => Some(Message::Request { t1: time(0) }),
+            (TAG_RESPONSE, 24) => Some(Message::Response {
+                t1: time(0),
+                t2: time(1),
+                t3: time(2),
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn package(&self, src: Address, dst: Address) -> Result<Frame, WriteError> {
+        let mut buf = [0u8; 25];
+        let len = match *self {
+            Message::Beacon { master_time } => {
+                buf[0] = TAG_BEACON;
+                buf[1..9].copy_from_slice(&master_time.to_be_bytes());
+                9
+            }
+            Message::Request { t1 } => {
+                buf[0] = TAG_REQUEST;
+                buf[1..9].copy_from_slice(&t1.to_be_bytes());
+                9
+            }
+            Message::Response { t1, t2, t3 } => {
+                buf[0] = TAG_RESPONSE;
+                buf[1..9].copy_from_slice(&t1.to_be_bytes());
+                buf[9..17].copy_from_slice(&t2.to_be_bytes());
+                buf[17..25].copy_from_slice(&t3.to_be_bytes());
+                25
+            }
+        };
+        Writer::package(src, dst, &buf[..len])
+    }
+}
+
+/// The node that all other nodes synchronise their time with.
+pub struct Master<C: Clock> {
+    time: LocalTime<C>,
+    address: Address,
+}
+
+impl<C: Clock> Master<C>
+where
+    C::Duration: Micros,
+{
+    pub fn new(clock: C, address: Address) -> Self {
+        Self {
+            time: LocalTime::new(clock),
+            address,
+        }
+    }
+
+    pub fn now(&self) -> u64 {
+        self.time.now()
+    }
+
+    /// A beacon to broadcast, i.e. once every few seconds.
+    pub fn beacon(&self) -> Result<Frame, WriteError> {
+        Message::Beacon {
+            master_time: self.now(),
+        }
+        .package(self.address, Address::multicast())
+    }
+
+    /// Handle a received frame, yielding the answer to send if it is a request of a slave.
+    ///
+    /// Call this as soon as the frame is received, as the time of receipt is part of the answer.
+    pub fn handle(&self, frame: &FrameRef) -> Option<Result<Frame, WriteError>> {
+        if frame.header.address_dst != self.address {
+            return None;
+        }
+
+        match Message::parse(frame.contents)? {
+            Message::Request { t1 } => {
+                let t2 = self.now();
+                let response = Message::Response {
+                    t1,
+                    t2,
+                    t3: self.now(),
+                };
+                Some(response.package(self.address, frame.header.address_src))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Estimate of the clock of the master, at a moment on the local clock.
+#[derive(Debug, Clone, Copy)]
+struct Estimate {
+    local: u64,
+    /// Time of the master minus the local time.
+    offset: i64,
+}
+
+/// A node that synchronises its time with the master.
+pub struct Slave<C: Clock> {
+    time: LocalTime<C>,
+    address: Address,
+    master: Address,
+    /// Delay of a frame from the master to us, as measured by the last exchange.
+    delay: u64,
+    latest: Option<Estimate>,
+    /// Estimate that the skew is measured against.
+    reference: Option<Estimate>,
+    /// How much faster the clock of the master runs than ours, in parts per billion.
+    skew_ppb: i64,
+    skew_measured: bool,
+}
+
+impl<C: Clock> Slave<C>
+where
+    C::Duration: Micros,
+{
+    pub fn new(clock: C, address: Address, master: Address) -> Self {
+        Self {
+            time: LocalTime::new(clock),
+            address,
+            master,
+            delay: 0,
+            latest: None,
+            reference: None,
+            skew_ppb: 0,
+            skew_measured: false,
+        }
+    }
+
+    /// A request to the master, to measure the delay of the bus. Send this every now and then.
+    pub fn request(&self) -> Result<Frame, WriteError> {
+        Message::Request {
+            t1: self.time.now(),
+        }
+        .package(self.address, self.master)
+    }
+
+    /// Handle a received frame, updating the estimates if it is a beacon or answer of the master.
+    ///
+    /// Call this as soon as the frame is received, as the time of receipt is used for the estimates.
+    pub fn handle(&mut self, frame: &FrameRef) {
+        let t4 = self.time.now();
+        if frame.header.address_src != self.master {
+            return;
+        }
+
+        match Message::parse(frame.contents) {
+            Some(Message::Beacon { master_time }) => {
+                let offset = master_time as i64 + (self.delay / 2) as i64 - t4 as i64;
+                self.estimate(t4, offset);
+            }
+            Some(Message::Response { t1, t2, t3 }) if frame.header.address_dst == self.address => {
+                // Discard answers to requests from before a restart.
+                if t1 > t4 {
+                    return;
+                }
+                let (t1, t2, t3, t4) = (t1 as i64, t2 as i64, t3 as i64, t4 as i64);
+                self.delay = ((t4 - t1) - (t3 - t2)).max(0) as u64;
+                self.estimate(t4 as u64, ((t2 - t1) + (t3 - t4)) / 2);
+            }
+            _ => (),
+        }
+    }
+
+    fn estimate(&mut self, local: u64, offset: i64) {
+        let estimate = Estimate { local, offset };
+        self.latest = Some(estimate);
+
+        match self.reference {
+            Some(reference) if local >= reference.local + MIN_SKEW_SPAN => {
+                let span = (local - reference.local) as i128;
+                let skew = (offset - reference.offset) as i128 * 1_000_000_000 / span;
+                self.skew_ppb = if self.skew_measured {
+                    ((self.skew_ppb as i128 * 3 + skew) / 4) as i64
+                } else {
+                    skew as i64
+                };
+                self.skew_measured = true;
+                self.reference = Some(estimate);
+            }
+            Some(_) => (),
+            None => self.reference = Some(estimate),
+        }
+    }
+
+    /// How much faster the clock of the master runs than ours, in parts per billion.
+    pub fn skew_ppb(&self) -> i64 {
+        self.skew_ppb
+    }
+
+    /// Delay of a frame on the bus, as measured by the last exchange with the master.
+    pub fn delay(&self) -> u64 {
+        self.delay
+    }
+
+    /// The current time on the clock of the master, if it has been heard from yet.
+    pub fn synchronized_now(&self) -> Option<u64> {
+        let latest = self.latest?;
+        let local = self.time.now();
+        let elapsed = local.saturating_sub(latest.local) as i128;
+        let drift = elapsed * self.skew_ppb as i128 / 1_000_000_000;
+        Some((local as i64 + latest.offset + drift as i64).max(0) as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::cell::Cell;
+
+    use kiri_protocol::Reader;
+
+    use super::*;
+
+    struct TestClock(Cell<u64>);
+
+    impl Clock for &TestClock {
+        type Instant = u64;
+        type Duration = u64;
+
+        fn now(&self) -> u64 {
+            self.0.get()
+        }
+    }
+
+    fn deliver(frame: &Frame, f: impl FnOnce(&FrameRef)) {
+        let mut reader = Reader::new();
+        for b in frame.as_slice() {
+            if let Ok(Some(frame)) = reader.feed(*b) {
+                return f(&frame);
+            }
+        }
+        panic!("Frame not received");
+    }
+
+    #[test]
+    fn offset_and_skew() {
+        let master_clock = TestClock(Cell::new(5_000_000));
+        let slave_clock = TestClock(Cell::new(0));
+        let master = Master::new(&master_clock, Address::new(1));
+        let mut slave = Slave::new(&slave_clock, Address::new(2), Address::new(1));
+
+        // The clock of the master runs 100 ppm faster, and every frame takes 2 ms.
+        let advance = |micros: u64| {
+            slave_clock.0.set(slave_clock.0.get() + micros);
+            master_clock
+                .0
+                .set(master_clock.0.get() + micros + micros / 10_000);
+        };
+
+        advance(10_000);
+        assert_eq!(slave.synchronized_now(), None);
+
+        let request = slave.request().unwrap();
+        advance(2_000);
+        let mut response = None;
+        deliver(&request, |frame| response = master.handle(frame));
+        advance(2_000);
+        deliver(&response.unwrap().unwrap(), |frame| slave.handle(frame));
+        assert!(slave.delay().abs_diff(4_000) < 10);
+
+        for _ in 0..10 {
+            advance(1_000_000);
+            let beacon = master.beacon().unwrap();
+            advance(2_000);
+            deliver(&beacon, |frame| slave.handle(frame));
+        }
+        assert!(slave.skew_ppb().abs_diff(100_000) < 1_000);
+
+        advance(500_000);
+        let error = slave.synchronized_now().unwrap().abs_diff(master.now());
+        assert!(error < 1_000, "error of {} us", error);
+    }
+}
@@ -0,0 +1,114 @@
+//! WebAssembly bindings to encode, decode and verify frames, i.e. for a decoder in the browser.
+//!
+//! Build with `wasm-pack build --target web`, after which pages can do:
+//!
+//! ```js
+//! import init, { decodeHex } from "./pkg/kiri_wasm.js";
+//!
+//! await init();
+//! for (const frame of decodeHex("05 6b 49 0f 00 ...")) {
+//!     console.log(frame.src, frame.dst, frame.contents);
+//! }
+//! ```
+
+use kiri_protocol::{iter::FrameIter, Address, FrameOwned, Writer};
+use wasm_bindgen::prelude::*;
+
+/// A decoded frame, or why it could not be decoded.
+#[wasm_bindgen]
+pub struct DecodedFrame {
+    frame: Result<FrameOwned, String>,
+}
+
+#[wasm_bindgen]
+impl DecodedFrame {
+    /// Whether the frame is correct, otherwise see `error`.
+    #[wasm_bindgen(getter)]
+    pub fn ok(&self) -> bool {
+        self.frame.is_ok()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn error(&self) -> Option<String> {
+        self.frame.as_ref().err().cloned()
+    }
+
+    /// The source address as 8 hexadecimal digits.
+    #[wasm_bindgen(getter)]
+    pub fn src(&self) -> Option<String> {
+        self.frame
+            .as_ref()
+            .ok()
+            .map(|frame| frame.header.address_src.to_string())
+    }
+
+    /// The destination address as 8 hexadecimal digits.
+    #[wasm_bindgen(getter)]
+    pub fn dst(&self) -> Option<String> {
+        self.frame
+            .as_ref()
+            .ok()
+            .map(|frame| frame.header.address_dst.to_string())
+    }
+
+    #[wasm_bindgen(getter, js_name = hopLimit)]
+    pub fn hop_limit(&self) -> Option<u8> {
+        self.frame
+            .as_ref()
+            .ok()
+            .map(|frame| frame.header.hop_limit.to_primitive())
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn sequence(&self) -> Option<u8> {
+        self.frame
+            .as_ref()
+            .ok()
+            .map(|frame| frame.header.sequence.to_primitive())
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn contents(&self) -> Option<Vec<u8>> {
+        self.frame
+            .as_ref()
+            .ok()
+            .map(|frame| frame.contents.to_vec())
+    }
+}
+
+/// Package `contents` into an encoded frame, with the addresses as 8 hexadecimal digits.
+#[wasm_bindgen]
+pub fn encode(src: &str, dst: &str, contents: &[u8]) -> Result<Vec<u8>, JsError> {
+    let src = Address::from_hex_str(src).map_err(|e| JsError::new(&format!("source {}", e)))?;
+    let dst =
+        Address::from_hex_str(dst).map_err(|e| JsError::new(&format!("destination {}", e)))?;
+    Writer::package(src, dst, contents)
+        .map(|frame| frame.as_slice().to_vec())
+        .map_err(|e| JsError::new(&e.to_string()))
+}
+
+/// Decode all frames in `data`, including those that are broken.
+///
+/// Bytes of an incomplete frame at the end are ignored.
+#[wasm_bindgen]
+pub fn decode(data: &[u8]) -> Vec<DecodedFrame> {
+    FrameIter::new(data.iter().copied())
+        .map(|frame| DecodedFrame {
+            frame: frame.map_err(|e| e.to_string()),
+        })
+        .collect()
+}
+
+/// Decode all frames in a hex dump, which may contain whitespace.
+#[wasm_bindgen(js_name = decodeHex)]
+pub fn decode_hex(dump: &str) -> Result<Vec<DecodedFrame>, JsError> {
+    let dump: String = dump.split_whitespace().collect();
+    let data = hex::decode(dump).map_err(|e| JsError::new(&e.to_string()))?;
+    Ok(decode(&data))
+}
+
+/// Whether `data` consists of correct frames only.
+#[wasm_bindgen]
+pub fn verify(data: &[u8]) -> bool {
+    FrameIter::new(data.iter().copied()).all(|frame| frame.is_ok())
+}